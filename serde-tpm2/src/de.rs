@@ -46,7 +46,7 @@ macro_rules! define_parse {
             /// Parse $T (u8, u16, ..., i8, i16, ...) from big-endian
             fn [<parse_ $T>] (&mut self) -> Result<$T> {
                 let len = mem::size_of::<$T>();
-                let buffer = self.input[..len].try_into().map_err(|_| Error::Eof)?;
+                let buffer = self.input.get(..len).ok_or(Error::Eof)?.try_into().unwrap();
                 self.input = &self.input[len..];
                 Ok($T::from_be_bytes(buffer))
             }
@@ -631,6 +631,13 @@ fn test_u64_i64() {
     }
 }
 
+#[test]
+fn test_eof() {
+    assert!(matches!(from_bytes::<u8>(b""), Err(Error::Eof)));
+    assert!(matches!(from_bytes::<u32>(b"\xde\xad"), Err(Error::Eof)));
+    assert!(matches!(from_bytes::<u64>(b"\xde\xad\xbe\xef"), Err(Error::Eof)));
+}
+
 #[test]
 fn test_u128_i128() {
     let bytes = [
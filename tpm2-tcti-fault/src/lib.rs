@@ -0,0 +1,141 @@
+pub mod lib {
+    use std::time::Duration;
+
+    use tss2_tcti::config::config::TctiConfig;
+    use tss2_tcti::define_api_symbols;
+    use tss2_tcti::fault::fault::{FaultConfig, FaultTcti};
+    use tss2_tcti::tcti::error::TctiError;
+    use tss2_tcti::tcti::tcti::{Api, Info, PendingResponse, State, Tcti, TctiLib};
+    use tss2_tcti::telemetry::telemetry::CommandSpan;
+    use tss2_tcti_sys::tpm2_tss;
+
+    /// [FaultTcti] for C, the child tcti is opened via
+    /// [open()](tss2_tcti::registry::registry::open).
+    #[repr(C)]
+    #[derive(Debug)]
+    pub struct TctiFault {
+        api: Api,
+        state: State,
+        pending_response: PendingResponse,
+        command_span: CommandSpan,
+        fault_tcti: Option<FaultTcti<Box<dyn Tcti>>>,
+    }
+
+    impl TctiFault {
+        fn get_fault_tcti(&mut self) -> Result<&mut FaultTcti<Box<dyn Tcti>>, TctiError> {
+            self.fault_tcti.as_mut().ok_or(TctiError::BadSequence)
+        }
+    }
+
+    impl TctiLib for TctiFault {
+        const INFO: Info<'static> = Info {
+            name: b"tpm2_tcti-fault\0",
+            description: b"Tcti injecting faults between the caller and the child tcti.\0",
+            config_help: <FaultConfig as TctiConfig>::CONFIG_HELP,
+        };
+        const MAGIC: u64 = 0x6661756c74746369;
+
+        fn new(conf: &str) -> Result<Self, TctiError> {
+            let mut tcti = Self {
+                api: Self::get_api_static(),
+                state: State::NotInitialized,
+                pending_response: None,
+                command_span: CommandSpan::default(),
+                fault_tcti: None,
+            };
+
+            tcti.init(conf)?;
+
+            Ok(tcti)
+        }
+
+        fn init_inner(&mut self, conf: &str) -> Result<(), TctiError> {
+            self.api = Self::get_api_static();
+            self.fault_tcti = Some(FaultTcti::new(conf)?);
+            self.state = State::Transmit;
+            Ok(())
+        }
+
+        fn transmit_inner(&mut self, command: &[u8]) -> Result<(), TctiError> {
+            self.get_fault_tcti()?.transmit(command)
+        }
+
+        fn receive_inner(&mut self, timeout: Duration) -> Result<Vec<u8>, TctiError> {
+            self.get_fault_tcti()?.receive_with_timeout(timeout)
+        }
+
+        fn finalize_inner(&mut self) {
+            self.fault_tcti = None;
+        }
+
+        fn cancel_inner(&mut self) -> Result<(), TctiError> {
+            self.get_fault_tcti()?.cancel()
+        }
+
+        fn get_poll_handles_inner(
+            &mut self,
+        ) -> Result<Vec<tpm2_tss::TSS2_TCTI_POLL_HANDLE>, TctiError> {
+            self.get_fault_tcti()?.get_poll_handles()
+        }
+
+        fn set_locality_inner(&mut self, locality: u8) -> Result<(), TctiError> {
+            self.get_fault_tcti()?.set_locality(locality)
+        }
+
+        fn make_sticky_inner(&mut self, handle: &mut u32, sticky: bool) -> Result<(), TctiError> {
+            self.get_fault_tcti()?.make_sticky(handle, sticky)
+        }
+
+        fn get_state(&self) -> Option<State> {
+            Some(self.state)
+        }
+        fn set_state(&mut self, state: State) {
+            self.state = state;
+        }
+        fn get_pending_response(&mut self) -> Option<&mut PendingResponse> {
+            Some(&mut self.pending_response)
+        }
+
+        fn get_command_span(&mut self) -> Option<&mut CommandSpan> {
+            Some(&mut self.command_span)
+        }
+    }
+
+    define_api_symbols!(TctiFault);
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use tss2_tcti::conformance::conformance::check_tcti;
+        use tss2_tcti::registry::registry::register_tcti;
+        use tss2_tcti::testing::testing::Echo;
+
+        const COMMAND: &[u8] = b"\x80\x01\x00\x00\x00\x0c\x00\x00\x01\x7b\x00\x08";
+
+        #[test]
+        fn test_child() {
+            register_tcti::<Echo>("echo");
+
+            let mut tcti = <TctiFault as Tcti>::new("echo,seed=1").unwrap();
+            Tcti::transmit(&mut tcti, COMMAND).unwrap();
+            assert_eq!(Tcti::receive(&mut tcti).unwrap(), COMMAND);
+
+            let mut tcti = <TctiFault as Tcti>::new("echo,seed=1,tpm_warning=1").unwrap();
+            Tcti::transmit(&mut tcti, COMMAND).unwrap();
+            let size = Tcti::response_size(&mut tcti, Duration::ZERO).unwrap();
+            assert_eq!(size, 10);
+            assert_ne!(Tcti::receive(&mut tcti).unwrap()[6..10], [0; 4]);
+
+            assert_eq!(
+                <TctiFault as Tcti>::new("echo,truncate=2").unwrap_err(),
+                TctiError::BadValue
+            );
+        }
+
+        #[test]
+        fn test_conformance() {
+            register_tcti::<Echo>("echo");
+            check_tcti::<TctiFault>("echo", COMMAND);
+        }
+    }
+}
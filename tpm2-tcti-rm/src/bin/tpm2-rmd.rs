@@ -0,0 +1,179 @@
+//! Resource manager daemon, see [serve_with()](tpm2_tcti_rm::daemon::daemon::serve_with).
+
+use std::io;
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::UnixListener;
+use std::process::ExitCode;
+use std::time::Duration;
+
+use tpm2_tcti_rm::daemon::daemon::{serve_with, DEFAULT_SOCKET};
+use tpm2_tcti_rm::manager::manager::{Quotas, ResourceManager};
+use tpm2_tcti_rm::ownership::ownership::Ownership;
+use tpm2_tcti_rm::policy::policy::Policy;
+use tpm2_tcti_rm::scheduler::scheduler::{Priority, Timeouts};
+use tss2_tcti::registry::registry::open;
+use tss2_tcti::telemetry::telemetry::{init_subscriber, LOG_ENV, LOG_FILE_ENV};
+
+const USAGE: &str = "\
+Usage: tpm2-rmd [OPTIONS]
+
+Options:
+  --tcti <NAME:CONF>       Child tcti [default: device:/dev/tpm0]
+  --socket <PATH>          Socket to listen on [default: /run/tpm2-rmd.sock]
+  --priority-socket <PATH> Additional socket whose clients' commands are
+                           executed first
+  --timeout <SECONDS>      Cancel commands after this time [default: 20]
+  --long-timeout <SECONDS> Cancel key generation and self tests after this
+                           time [default: 300]
+  --max-objects <N>        Maximum number of transient objects per client
+  --max-sessions <N>       Maximum number of sessions per client
+  --ownership <PATH>       File storing which user owns which persistent
+                           objects and NV indices
+  --max-persistent <N>     Maximum number of persistent objects per user
+  --max-nv-indices <N>     Maximum number of NV indices per user
+  --restricted             Deny hierarchy-level commands and the platform and
+                           lockout hierarchies to clients
+  --log <FILTER>           Log filter, e.g. debug or tpm2_tcti_rm=debug
+                           [default: $TSS2_TCTI_LOG or info]
+  --log-file <PATH>        Log to this file instead of stderr
+                           [default: $TSS2_TCTI_LOG_FILE]
+  --help                   Print this help";
+
+struct Args {
+    tcti: String,
+    socket: String,
+    priority_socket: Option<String>,
+    ownership: Option<String>,
+    log: String,
+    log_file: Option<String>,
+    timeouts: Timeouts,
+    quotas: Quotas,
+    policy: Policy,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut parsed = Args {
+        tcti: String::from("device:/dev/tpm0"),
+        socket: String::from(DEFAULT_SOCKET),
+        priority_socket: None,
+        ownership: None,
+        log: std::env::var(LOG_ENV).unwrap_or_else(|_| String::from("info")),
+        log_file: std::env::var(LOG_FILE_ENV).ok(),
+        timeouts: Timeouts::default(),
+        quotas: Quotas::default(),
+        policy: Policy::default(),
+    };
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--help" {
+            println!("{USAGE}");
+            std::process::exit(0);
+        }
+        if arg == "--restricted" {
+            parsed.policy = Policy::restricted();
+            continue;
+        }
+
+        let value = args
+            .next()
+            .ok_or_else(|| format!("Missing value for {arg}"))?;
+        let number = || {
+            value
+                .parse::<usize>()
+                .map_err(|_| format!("Bad value for {arg}: {value}"))
+        };
+
+        match arg.as_str() {
+            "--tcti" => parsed.tcti = value.clone(),
+            "--socket" => parsed.socket = value.clone(),
+            "--priority-socket" => parsed.priority_socket = Some(value.clone()),
+            "--timeout" => parsed.timeouts.default = Duration::from_secs(number()? as u64),
+            "--long-timeout" => parsed.timeouts.long = Duration::from_secs(number()? as u64),
+            "--max-objects" => parsed.quotas.max_transients = number()?,
+            "--max-sessions" => parsed.quotas.max_sessions = number()?,
+            "--ownership" => parsed.ownership = Some(value.clone()),
+            "--max-persistent" => parsed.quotas.max_persistent = number()?,
+            "--max-nv-indices" => parsed.quotas.max_nv_indices = number()?,
+            "--log" => parsed.log = value.clone(),
+            "--log-file" => parsed.log_file = Some(value.clone()),
+            _ => return Err(format!("Unknown argument: {arg}")),
+        }
+    }
+
+    Ok(parsed)
+}
+
+/// Removes the socket of a previous run. Other files are left alone, i.e.
+/// binding fails for them.
+fn remove_stale_socket(path: &str) -> io::Result<()> {
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => std::fs::remove_file(path),
+        Ok(_) => Ok(()),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(error) => Err(error),
+    }
+}
+
+fn main() -> ExitCode {
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(error) => {
+            eprintln!("{error}\n\n{USAGE}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    if let Err(error) = init_subscriber(&args.log, args.log_file.as_deref()) {
+        eprintln!("Could not set up logging: {error}");
+        return ExitCode::FAILURE;
+    }
+
+    let child_tcti = match open(&args.tcti) {
+        Ok(child_tcti) => child_tcti,
+        Err(error) => {
+            eprintln!("Could not open tcti {:?}: {error}", args.tcti);
+            return ExitCode::FAILURE;
+        }
+    };
+    let mut rm = ResourceManager::new(child_tcti);
+    rm.set_quotas(args.quotas);
+    rm.set_default_policy(args.policy);
+    if let Some(path) = args.ownership {
+        match Ownership::open(&path) {
+            Ok(ownership) => rm.set_ownership(ownership),
+            Err(error) => {
+                eprintln!("Could not read ownership from {path:?}: {error}");
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+
+    let mut sockets = vec![(args.socket, Priority::Normal)];
+    if let Some(priority_socket) = args.priority_socket {
+        sockets.push((priority_socket, Priority::High));
+    }
+
+    let mut listeners = Vec::new();
+    for (socket, priority) in sockets {
+        if let Err(error) = remove_stale_socket(&socket) {
+            eprintln!("Could not remove {socket:?}: {error}");
+            return ExitCode::FAILURE;
+        }
+        match UnixListener::bind(&socket) {
+            Ok(listener) => listeners.push((listener, priority)),
+            Err(error) => {
+                eprintln!("Could not listen on {socket:?}: {error}");
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+
+    match serve_with(listeners, rm, args.timeouts) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("{error}");
+            ExitCode::FAILURE
+        }
+    }
+}
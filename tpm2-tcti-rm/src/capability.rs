@@ -0,0 +1,244 @@
+/// Rewriting of `TPM2_GetCapability` responses, such that every client of
+/// the resource manager only sees its own resources.
+pub mod capability {
+    use serde::{Deserialize, Serialize};
+    use serde_tpm2::{de::from_bytes, se::to_bytes};
+    use tpm2_types::constants::{Capability, CommandCode, PropertyTag};
+    use tpm2_types::handles::Handle;
+    use tpm2_types::selectables::Capabilities;
+    use tpm2_types::structs::TaggedProperty;
+    use tracing::warn;
+    use tss2_tcti::tpm::tpm::{self, command_code, response_code, TPM_RC_SUCCESS};
+
+    use crate::manager::manager::{HR_HMAC_SESSION, HR_POLICY_SESSION, HR_TRANSIENT};
+    use crate::rm::rm::{register_command_attributes, TPM_HEADER_SIZE, TPM_ST_SESSIONS};
+
+    /// Parameters of a `TPM2_GetCapability` command.
+    #[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+    pub struct GetCapabilityCommand {
+        pub capability: Capability,
+        pub property: u32,
+        pub property_count: u32,
+    }
+
+    impl GetCapabilityCommand {
+        /// Returns `None` if `command` is not a `TPM2_GetCapability`.
+        pub fn parse(command: &[u8]) -> Option<Self> {
+            if command_code(command)? != u32::from(CommandCode::GetCapability) {
+                return None;
+            }
+            // an authorization area (e.g. for audit) precedes the parameters
+            if command[0..2] == TPM_ST_SESSIONS.to_be_bytes() {
+                warn!("Not virtualizing GetCapability with sessions");
+                return None;
+            }
+
+            from_bytes(&command[TPM_HEADER_SIZE..])
+                .map_err(|error| warn!("Malformed GetCapability command: {error}"))
+                .ok()
+        }
+    }
+
+    /// Parameters of a `TPM2_GetCapability` response.
+    #[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+    pub struct GetCapabilityResponse {
+        pub more_data: bool,
+        pub capability_data: Capabilities,
+    }
+
+    /// Resources of a single client, as reported by `TPM2_GetCapability`.
+    ///
+    /// Handles are the (virtual) handles the client uses. Sessions are
+    /// always reported as loaded, since the resource manager loads them on
+    /// demand. The `*_avail` properties are limited by the quotas of the
+    /// client, `None` leaves the value reported by the TPM.
+    #[derive(Debug, Clone, Default, PartialEq)]
+    pub struct VirtualView {
+        pub transients: Vec<u32>,
+        pub sessions: Vec<u32>,
+        pub transients_avail: Option<u32>,
+        pub sessions_avail: Option<u32>,
+    }
+
+    impl VirtualView {
+        /// Rewrite the response of the TPM to `cmd`. Returns `None` if the
+        /// response is left as it is.
+        pub fn virtualize(&self, cmd: &GetCapabilityCommand, response: &[u8]) -> Option<Vec<u8>> {
+            if response_code(response)? != TPM_RC_SUCCESS {
+                return None;
+            }
+
+            let mut parameters: GetCapabilityResponse = from_bytes(&response[TPM_HEADER_SIZE..])
+                .map_err(|error| warn!("Malformed GetCapability response: {error}"))
+                .ok()?;
+
+            match &mut parameters.capability_data {
+                Capabilities::Handles(handles) => {
+                    let (virtual_handles, more_data) = self.handles(cmd)?;
+                    *handles = virtual_handles;
+                    parameters.more_data = more_data;
+                }
+                Capabilities::TpmProperties(properties) => self.properties(properties),
+                Capabilities::Commands(attributes) => {
+                    // learn the handle counts of vendor specific commands
+                    register_command_attributes(attributes);
+                    return None;
+                }
+                _ => return None,
+            }
+
+            match to_bytes(&parameters) {
+                Ok(bytes) => Some(tpm::response(TPM_RC_SUCCESS, &bytes)),
+                Err(error) => {
+                    warn!("Could not serialize GetCapability response: {error}");
+                    None
+                }
+            }
+        }
+
+        /// Handles of the client instead of the handles of the TPM, and
+        /// whether there are more.
+        fn handles(&self, cmd: &GetCapabilityCommand) -> Option<(Vec<Handle>, bool)> {
+            let handle_type = cmd.property >> 24;
+            let mut handles: Vec<u32> = match handle_type {
+                HR_TRANSIENT => self.transients.clone(),
+                HR_HMAC_SESSION | HR_POLICY_SESSION => self
+                    .sessions
+                    .iter()
+                    .copied()
+                    .filter(|handle| handle >> 24 == handle_type)
+                    .collect(),
+                _ => return None,
+            };
+            handles.retain(|&handle| handle >= cmd.property);
+            handles.sort_unstable();
+
+            let more_data = handles.len() > cmd.property_count as usize;
+            handles.truncate(cmd.property_count as usize);
+
+            let handles = handles
+                .into_iter()
+                .filter_map(|handle| Handle::try_from(handle).ok())
+                .collect();
+            Some((handles, more_data))
+        }
+
+        /// Handle related properties of the client instead of the TPM.
+        fn properties(&self, properties: &mut [TaggedProperty]) {
+            let sessions = Some(self.sessions.len() as u32);
+
+            for property in properties.iter_mut() {
+                let value = match property.property {
+                    PropertyTag::HRTransientAvail => self.transients_avail,
+                    PropertyTag::HRLoaded | PropertyTag::HRActive => sessions,
+                    PropertyTag::HRLoadedAvail | PropertyTag::HRActiveAvail => self.sessions_avail,
+                    _ => None,
+                };
+                if let Some(value) = value {
+                    property.value = value;
+                }
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use tss2_tcti::tpm::tpm::command;
+
+        fn get_capability(capability: Capability, property: u32, count: u32) -> Vec<u8> {
+            let body = [capability as u32, property, count];
+            let body: Vec<u8> = body.iter().flat_map(|value| value.to_be_bytes()).collect();
+            command(CommandCode::GetCapability, &body)
+        }
+
+        fn response(parameters: &GetCapabilityResponse) -> Vec<u8> {
+            tpm::response(TPM_RC_SUCCESS, &to_bytes(parameters).unwrap())
+        }
+
+        fn parameters(response: &[u8]) -> GetCapabilityResponse {
+            from_bytes(&response[TPM_HEADER_SIZE..]).unwrap()
+        }
+
+        #[test]
+        fn test_handles() {
+            let view = VirtualView {
+                transients: vec![0x80ff0002, 0x80ff0000, 0x80ff0001],
+                sessions: vec![0x03000005, 0x02000001],
+                ..Default::default()
+            };
+            let tpm_response = response(&GetCapabilityResponse {
+                more_data: false,
+                capability_data: Capabilities::Handles(vec![
+                    Handle::try_from(0x80000000).unwrap(),
+                    Handle::try_from(0x80000001).unwrap(),
+                ]),
+            });
+
+            let cmd =
+                GetCapabilityCommand::parse(&get_capability(Capability::Handles, 0x80ff0001, 1))
+                    .unwrap();
+            let virtualized = view.virtualize(&cmd, &tpm_response).unwrap();
+            assert_eq!(
+                parameters(&virtualized),
+                GetCapabilityResponse {
+                    more_data: true,
+                    capability_data: Capabilities::Handles(vec![
+                        Handle::try_from(0x80ff0001).unwrap()
+                    ]),
+                }
+            );
+
+            let cmd =
+                GetCapabilityCommand::parse(&get_capability(Capability::Handles, 0x03000000, 8))
+                    .unwrap();
+            let virtualized = view.virtualize(&cmd, &tpm_response).unwrap();
+            assert_eq!(
+                parameters(&virtualized).capability_data,
+                Capabilities::Handles(vec![Handle::try_from(0x03000005).unwrap()])
+            );
+
+            // errors are not rewritten
+            assert_eq!(view.virtualize(&cmd, &tpm::response(0x101, &[])), None);
+            assert_eq!(
+                GetCapabilityCommand::parse(&command(CommandCode::ReadPublic, &[0; 12])),
+                None
+            );
+        }
+
+        #[test]
+        fn test_properties() {
+            let view = VirtualView {
+                sessions: vec![0x03000005],
+                transients_avail: Some(1),
+                ..Default::default()
+            };
+            let tagged = |property, value| TaggedProperty { property, value };
+            let tpm_response = response(&GetCapabilityResponse {
+                more_data: false,
+                capability_data: Capabilities::TpmProperties(vec![
+                    tagged(PropertyTag::HRLoaded, 3),
+                    tagged(PropertyTag::HRLoadedAvail, 61),
+                    tagged(PropertyTag::HRTransientAvail, 3),
+                ]),
+            });
+
+            let cmd = GetCapabilityCommand::parse(&get_capability(
+                Capability::TpmProperties,
+                PropertyTag::HRLoaded as u32,
+                8,
+            ))
+            .unwrap();
+            let virtualized = view.virtualize(&cmd, &tpm_response).unwrap();
+            assert_eq!(
+                parameters(&virtualized).capability_data,
+                Capabilities::TpmProperties(vec![
+                    tagged(PropertyTag::HRLoaded, 1),
+                    // no session quota
+                    tagged(PropertyTag::HRLoadedAvail, 61),
+                    tagged(PropertyTag::HRTransientAvail, 1),
+                ])
+            );
+        }
+    }
+}
@@ -0,0 +1,525 @@
+/// Resource manager daemon serving many clients over a UNIX socket (like
+/// tpm2-abrmd, but without D-Bus).
+///
+/// Clients send plain TPM commands and receive plain TPM responses, i.e. the
+/// framing is given by the size field of the TPM header. See the
+/// `tpm2-tcti-rmd` tcti for the client side.
+///
+/// Commands are executed in the order given by a
+/// [Scheduler](crate::scheduler::scheduler::Scheduler).
+///
+/// On Linux, the [Owner] of a client is its user (`uid:<uid>`), as told by
+/// the kernel.
+pub mod daemon {
+    use std::io::{self, Read, Write};
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+    use std::thread;
+
+    use tracing::{info, warn};
+    use tss2_tcti::tcti::error::TctiError;
+    use tss2_tcti::tcti::rc::{BaseRc, Layer, Tss2Rc};
+    use tss2_tcti::tpm::tpm;
+
+    use crate::manager::manager::{ClientId, ResourceManager};
+    use crate::ownership::ownership::Owner;
+    use crate::rm::rm::TPM_HEADER_SIZE;
+    use crate::scheduler::scheduler::{execute, Job, Priority, Scheduler, Timeouts, POLL_INTERVAL};
+
+    /// Default socket of the resource manager daemon.
+    pub const DEFAULT_SOCKET: &str = "/run/tpm2-rmd.sock";
+
+    /// Upper bound for the size of client commands.
+    pub const MAX_COMMAND_SIZE: usize = 4096;
+
+    enum Request {
+        Connect {
+            priority: Priority,
+            owner: Option<Owner>,
+            client: Sender<ClientId>,
+        },
+        Execute {
+            client: ClientId,
+            command: Vec<u8>,
+            response: Sender<Vec<u8>>,
+        },
+        Disconnect(ClientId),
+    }
+
+    /// Accept clients on `listener` and forward their commands to `rm`.
+    /// Only returns if accepting a connection fails.
+    ///
+    /// The calling thread owns the resource manager and executes the
+    /// commands in the order they arrive. Every client waits for its
+    /// response before sending the next command, i.e. no client can starve
+    /// the others.
+    pub fn serve(listener: UnixListener, rm: ResourceManager) -> io::Result<()> {
+        serve_with(vec![(listener, Priority::Normal)], rm, Timeouts::default())
+    }
+
+    /// Like [serve()], but clients of each listener get the given priority
+    /// class, e.g. to prefer the commands of a privileged socket. Commands
+    /// exceeding `timeouts` or of clients which disconnect are cancelled.
+    pub fn serve_with(
+        listeners: Vec<(UnixListener, Priority)>,
+        rm: ResourceManager,
+        timeouts: Timeouts,
+    ) -> io::Result<()> {
+        let (requests, receiver) = mpsc::channel();
+        let acceptors: Vec<_> = listeners
+            .into_iter()
+            .map(|(listener, priority)| {
+                let requests = requests.clone();
+                thread::spawn(move || accept(listener, priority, requests))
+            })
+            .collect();
+        // the worker stops once all acceptors stopped
+        drop(requests);
+
+        Worker::new(rm, timeouts).run(receiver);
+
+        for acceptor in acceptors {
+            acceptor
+                .join()
+                .unwrap_or_else(|_| Err(io::Error::other("Acceptor panicked")))?;
+        }
+        Ok(())
+    }
+
+    fn accept(
+        listener: UnixListener,
+        priority: Priority,
+        requests: Sender<Request>,
+    ) -> io::Result<()> {
+        for stream in listener.incoming() {
+            let stream = stream?;
+            let requests = requests.clone();
+            thread::spawn(move || connection(stream, priority, requests));
+        }
+
+        Ok(())
+    }
+
+    struct Worker {
+        rm: ResourceManager,
+        scheduler: Scheduler<Sender<Vec<u8>>>,
+        /// clients to remove from the resource manager once no command is
+        /// in flight
+        disconnected: Vec<ClientId>,
+    }
+
+    impl Worker {
+        fn new(rm: ResourceManager, timeouts: Timeouts) -> Self {
+            Self {
+                rm,
+                scheduler: Scheduler::new(timeouts),
+                disconnected: Vec::new(),
+            }
+        }
+
+        fn run(mut self, requests: Receiver<Request>) {
+            loop {
+                if self.scheduler.is_empty() {
+                    match requests.recv() {
+                        Ok(request) => self.handle(request),
+                        Err(_) => return,
+                    }
+                }
+                while let Ok(request) = requests.try_recv() {
+                    self.handle(request);
+                }
+                self.disconnect_clients();
+
+                if let Some(job) = self.scheduler.pop() {
+                    self.execute(job, &requests);
+                }
+            }
+        }
+
+        fn handle(&mut self, request: Request) {
+            match request {
+                Request::Connect {
+                    priority,
+                    owner,
+                    client,
+                } => {
+                    let id = self.rm.connect();
+                    self.scheduler.set_priority(id, priority);
+                    if let Some(owner) = owner {
+                        let _ = self.rm.set_owner(id, owner);
+                    }
+                    let _ = client.send(id);
+                }
+                Request::Execute {
+                    client,
+                    command,
+                    response,
+                } => self.scheduler.push(Job {
+                    client,
+                    command,
+                    reply: response,
+                }),
+                Request::Disconnect(client) => {
+                    // queued commands are dropped
+                    self.scheduler.remove_client(client);
+                    self.disconnected.push(client);
+                }
+            }
+        }
+
+        fn disconnect_clients(&mut self) {
+            for client in std::mem::take(&mut self.disconnected) {
+                if let Err(error) = self.rm.disconnect(client) {
+                    warn!("Could not clean up client {client}: {error}");
+                }
+            }
+        }
+
+        /// Execute the command of `job`, cancelling it if its client
+        /// disconnects meanwhile. Requests arriving meanwhile are handled
+        /// afterwards.
+        fn execute(&mut self, job: Job<Sender<Vec<u8>>>, requests: &Receiver<Request>) {
+            let client = job.client;
+            let timeout = self.scheduler.timeout(&job.command);
+
+            let mut pending = Vec::new();
+            let result = execute(&mut self.rm, client, &job.command, timeout, || {
+                pending.extend(requests.try_iter());
+                pending
+                    .iter()
+                    .any(|request| matches!(request, Request::Disconnect(c) if *c == client))
+            });
+
+            let response = result.unwrap_or_else(|error| {
+                warn!("Could not execute command of client {client}: {error}");
+                error_response(error)
+            });
+            let _ = job.reply.send(response);
+
+            for request in pending {
+                self.handle(request);
+            }
+        }
+    }
+
+    /// Response to a command which could not be executed. Like tpm2-abrmd,
+    /// the response code is one of the resource manager layer, clients must
+    /// not take it for an error of their own tcti.
+    fn error_response(error: TctiError) -> Vec<u8> {
+        let base = Tss2Rc::from(error).base().unwrap_or(BaseRc::GeneralFailure);
+        tpm::response(u32::from(Tss2Rc::from_base(Layer::ResMgr, base)), &[])
+    }
+
+    /// Read a single command. Returns `None` if the client closed the
+    /// connection.
+    fn read_command(stream: &mut UnixStream) -> io::Result<Option<Vec<u8>>> {
+        let mut command = vec![0u8; TPM_HEADER_SIZE];
+        match stream.read_exact(&mut command) {
+            Ok(()) => (),
+            Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(error) => return Err(error),
+        }
+
+        let size = u32::from_be_bytes(command[2..6].try_into().unwrap()) as usize;
+        if !(TPM_HEADER_SIZE..=MAX_COMMAND_SIZE).contains(&size) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Bad command size: {size}"),
+            ));
+        }
+
+        command.resize(size, 0);
+        stream.read_exact(&mut command[TPM_HEADER_SIZE..])?;
+        Ok(Some(command))
+    }
+
+    /// Whether the client closed the connection. Clients must not send a
+    /// command before they received the previous response.
+    fn closed(stream: &mut UnixStream) -> io::Result<bool> {
+        stream.set_nonblocking(true)?;
+        let result = stream.read(&mut [0u8; 1]);
+        stream.set_nonblocking(false)?;
+
+        match result {
+            Ok(0) => Ok(true),
+            Ok(_) => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Command sent before response was received",
+            )),
+            Err(error) if error.kind() == io::ErrorKind::WouldBlock => Ok(false),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Wait for the response to the command of the client. Returns `None`
+    /// if the client closed the connection meanwhile.
+    fn wait_response(
+        stream: &mut UnixStream,
+        receiver: &Receiver<Vec<u8>>,
+    ) -> io::Result<Option<Vec<u8>>> {
+        loop {
+            match receiver.recv_timeout(POLL_INTERVAL) {
+                Ok(response) => return Ok(Some(response)),
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Ok(Some(error_response(TctiError::GeneralFailure)))
+                }
+                Err(RecvTimeoutError::Timeout) => {
+                    if closed(stream)? {
+                        return Ok(None);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Owner of the process at the other end of `stream`.
+    #[cfg(target_os = "linux")]
+    fn peer_owner(stream: &UnixStream) -> Option<Owner> {
+        use std::os::unix::io::AsRawFd;
+
+        let mut cred = libc::ucred {
+            pid: 0,
+            uid: 0,
+            gid: 0,
+        };
+        let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+        // SAFETY: cred and len outlive the call and len is the size of cred
+        let ret = unsafe {
+            libc::getsockopt(
+                stream.as_raw_fd(),
+                libc::SOL_SOCKET,
+                libc::SO_PEERCRED,
+                &mut cred as *mut libc::ucred as *mut libc::c_void,
+                &mut len,
+            )
+        };
+        if ret != 0 {
+            warn!(
+                "Could not get credentials of client: {}",
+                io::Error::last_os_error()
+            );
+            return None;
+        }
+
+        Some(format!("uid:{}", cred.uid))
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn peer_owner(_stream: &UnixStream) -> Option<Owner> {
+        None
+    }
+
+    fn connection(mut stream: UnixStream, priority: Priority, requests: Sender<Request>) {
+        let (sender, receiver) = mpsc::channel();
+        let request = Request::Connect {
+            priority,
+            owner: peer_owner(&stream),
+            client: sender,
+        };
+        if requests.send(request).is_err() {
+            return;
+        }
+        let client = match receiver.recv() {
+            Ok(client) => client,
+            Err(_) => return,
+        };
+        info!("Client {client} connected");
+
+        loop {
+            let command = match read_command(&mut stream) {
+                Ok(Some(command)) => command,
+                Ok(None) => break,
+                Err(error) => {
+                    warn!("Could not read command of client {client}: {error}");
+                    break;
+                }
+            };
+
+            let (sender, receiver) = mpsc::channel();
+            let request = Request::Execute {
+                client,
+                command,
+                response: sender,
+            };
+            if requests.send(request).is_err() {
+                break;
+            }
+            let response = match wait_response(&mut stream, &receiver) {
+                Ok(Some(response)) => response,
+                Ok(None) => break,
+                Err(error) => {
+                    warn!("Connection to client {client} failed: {error}");
+                    break;
+                }
+            };
+
+            if let Err(error) = stream.write_all(&response) {
+                warn!("Could not send response to client {client}: {error}");
+                break;
+            }
+        }
+
+        info!("Client {client} disconnected");
+        let _ = requests.send(Request::Disconnect(client));
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use std::path::PathBuf;
+        use std::time::Duration;
+
+        use super::*;
+        use crate::fake_tpm::fake_tpm::*;
+        use crate::manager::manager::*;
+        use tpm2_types::constants::CommandCode;
+        use tss2_tcti::tpm::tpm::{command, TPM_RC_SUCCESS};
+
+        fn start_daemon(name: &str, quotas: Quotas, timeouts: Timeouts) -> PathBuf {
+            let path = std::env::temp_dir().join(format!("{name}-{}.sock", std::process::id()));
+            let _ = std::fs::remove_file(&path);
+            let listener = UnixListener::bind(&path).unwrap();
+
+            thread::spawn(move || {
+                let mut rm = ResourceManager::new(Box::new(FakeTpm::default()));
+                rm.set_quotas(quotas);
+                serve_with(vec![(listener, Priority::Normal)], rm, timeouts)
+            });
+
+            path
+        }
+
+        fn execute(stream: &mut UnixStream, command: &[u8]) -> Vec<u8> {
+            stream.write_all(command).unwrap();
+            read_command(stream).unwrap().unwrap()
+        }
+
+        #[test]
+        fn test_read_command() {
+            let (mut client, mut server) = UnixStream::pair().unwrap();
+            let create_primary = command(CommandCode::CreatePrimary, &TPM_RH_OWNER.to_be_bytes());
+            client.write_all(&create_primary).unwrap();
+            assert_eq!(read_command(&mut server).unwrap(), Some(create_primary));
+
+            // size smaller than the header
+            client
+                .write_all(&[0x80, 0x01, 0, 0, 0, 4, 0, 0, 0, 0])
+                .unwrap();
+            assert_eq!(
+                read_command(&mut server).unwrap_err().kind(),
+                io::ErrorKind::InvalidData
+            );
+
+            drop(client);
+            assert_eq!(read_command(&mut server).unwrap(), None);
+        }
+
+        #[test]
+        fn test_closed() {
+            let (mut client, mut server) = UnixStream::pair().unwrap();
+            assert!(!closed(&mut server).unwrap());
+
+            // next command before the response
+            client.write_all(&[0x80]).unwrap();
+            assert_eq!(
+                closed(&mut server).unwrap_err().kind(),
+                io::ErrorKind::InvalidData
+            );
+
+            drop(client);
+            assert!(closed(&mut server).unwrap());
+        }
+
+        #[test]
+        fn test_error_response() {
+            let rc =
+                u32::from_be_bytes(error_response(TctiError::IoError)[6..].try_into().unwrap());
+            assert_eq!(Tss2Rc::from(rc).layer(), Layer::ResMgr);
+            assert_eq!(Tss2Rc::from(rc).base(), Some(BaseRc::IoError));
+        }
+
+        #[test]
+        fn test_clients_isolated() {
+            let path = start_daemon(
+                "test_clients_isolated",
+                Quotas::default(),
+                Timeouts::default(),
+            );
+            let mut client_a = UnixStream::connect(&path).unwrap();
+            let mut client_b = UnixStream::connect(&path).unwrap();
+
+            let create_primary = command(CommandCode::CreatePrimary, &TPM_RH_OWNER.to_be_bytes());
+            let response = execute(&mut client_a, &create_primary);
+            assert_eq!(rc(&response), TPM_RC_SUCCESS);
+            let handle = u32_at(&response, TPM_HEADER_SIZE);
+
+            let read_public = command(CommandCode::ReadPublic, &handle.to_be_bytes());
+            let response = execute(&mut client_b, &read_public);
+            assert_eq!(rc(&response), TPM_RC_HANDLE | TPM_RC_N(1));
+            let response = execute(&mut client_a, &read_public);
+            assert_eq!(rc(&response), TPM_RC_SUCCESS);
+
+            // virtual handles are per client
+            let response = execute(&mut client_b, &create_primary);
+            assert_eq!(u32_at(&response, TPM_HEADER_SIZE), handle);
+            let response = execute(&mut client_b, &read_public);
+            assert_eq!(u32_at(&response, TPM_HEADER_SIZE), 2);
+        }
+
+        #[test]
+        fn test_quota() {
+            let quotas = Quotas {
+                max_transients: 1,
+                max_sessions: 1,
+                ..Default::default()
+            };
+            let path = start_daemon("test_quota", quotas, Timeouts::default());
+            let mut client = UnixStream::connect(&path).unwrap();
+
+            let create_primary = command(CommandCode::CreatePrimary, &TPM_RH_OWNER.to_be_bytes());
+            let response = execute(&mut client, &create_primary);
+            assert_eq!(rc(&response), TPM_RC_SUCCESS);
+            let response = execute(&mut client, &create_primary);
+            assert_eq!(rc(&response), TPM_RC_OBJECT_MEMORY);
+        }
+
+        #[test]
+        fn test_timeout() {
+            let timeouts = Timeouts {
+                default: Duration::from_secs(20),
+                long: Duration::from_millis(100),
+            };
+            let path = start_daemon("test_timeout", Quotas::default(), timeouts);
+            let mut client = UnixStream::connect(&path).unwrap();
+
+            // never completes unless cancelled
+            let response = execute(&mut client, &command(CommandCode::SelfTest, &[1]));
+            assert_eq!(rc(&response), TPM_RC_CANCELED);
+
+            let create_primary = command(CommandCode::CreatePrimary, &TPM_RH_OWNER.to_be_bytes());
+            let response = execute(&mut client, &create_primary);
+            assert_eq!(rc(&response), TPM_RC_SUCCESS);
+        }
+
+        #[test]
+        fn test_cancel_on_disconnect() {
+            let path = start_daemon(
+                "test_cancel_on_disconnect",
+                Quotas::default(),
+                Timeouts::default(),
+            );
+            let mut client_a = UnixStream::connect(&path).unwrap();
+            let mut client_b = UnixStream::connect(&path).unwrap();
+
+            // never completes unless cancelled
+            client_a
+                .write_all(&command(CommandCode::SelfTest, &[1]))
+                .unwrap();
+            let create_primary = command(CommandCode::CreatePrimary, &TPM_RH_OWNER.to_be_bytes());
+            client_b.write_all(&create_primary).unwrap();
+            drop(client_a);
+
+            let response = read_command(&mut client_b).unwrap().unwrap();
+            assert_eq!(rc(&response), TPM_RC_SUCCESS);
+        }
+    }
+}
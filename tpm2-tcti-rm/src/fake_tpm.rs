@@ -0,0 +1,288 @@
+/// TPM stand-in for tests.
+pub mod fake_tpm {
+    use std::collections::{HashMap, HashSet};
+    use std::thread;
+    use std::time::Duration;
+
+    use tpm2_types::constants::CommandCode;
+    use tss2_tcti::tcti::error::TctiError;
+    use tss2_tcti::tcti::tcti::Tcti;
+    use tss2_tcti::tpm::tpm::{
+        self, command, response, response_code, TPM_RC_CONTEXT_GAP, TPM_RC_SUCCESS,
+    };
+
+    use crate::manager::manager::*;
+    use crate::rm::rm::TPM_HEADER_SIZE;
+
+    pub const TPM_RC_NV_DEFINED: u32 = 0x14c;
+    pub const TPM_RC_CANCELED: u32 = 0x909;
+    pub const TPM_RC_REFERENCE_H0: u32 = 0x910;
+    pub const TPM_RH_OWNER: u32 = 0x40000001;
+    pub const TPM_RH_NULL: u32 = 0x40000007;
+    pub const TPM_RS_PW: u32 = 0x40000009;
+    pub const MAX_GAP: u64 = 4;
+    pub const TPM_CAP_HANDLES: u32 = 0x00000001;
+    pub const TPM_CAP_TPM_PROPERTIES: u32 = 0x00000006;
+    pub const TPM_PT_HR_LOADED: u32 = 0x00000203;
+    pub const TPM_PT_HR_TRANSIENT_AVAIL: u32 = 0x00000207;
+
+    #[derive(Default)]
+    pub struct FakeSession {
+        pub loaded: bool,
+        pub sequence: u64,
+    }
+
+    /// TPM with three object and three session slots which knows just
+    /// enough commands to test virtualization. Objects are identified by
+    /// an id which is returned by ReadPublic. The context gap is tiny.
+    /// SelfTest never completes, unless it is cancelled. Persistent objects
+    /// and NV indices are just handles.
+    #[derive(Default)]
+    pub struct FakeTpm {
+        objects: HashMap<u32, u32>,
+        nv: HashSet<u32>,
+        sessions: HashMap<u32, FakeSession>,
+        next_id: u32,
+        context_counter: u64,
+        response: Option<Vec<u8>>,
+        self_test: bool,
+    }
+
+    impl FakeTpm {
+        fn define(&mut self, handle: u32) -> Vec<u8> {
+            match self.nv.insert(handle) {
+                true => response(TPM_RC_SUCCESS, &[]),
+                false => response(TPM_RC_NV_DEFINED, &[]),
+            }
+        }
+
+        fn undefine(&mut self, handle: u32, n: u32) -> Vec<u8> {
+            match self.nv.remove(&handle) {
+                true => response(TPM_RC_SUCCESS, &[]),
+                false => response(TPM_RC_HANDLE | TPM_RC_N(n), &[]),
+            }
+        }
+
+        fn load(&mut self, id: u32) -> Vec<u8> {
+            match (0x80000000..0x80000003).find(|handle| !self.objects.contains_key(handle)) {
+                Some(handle) => {
+                    self.objects.insert(handle, id);
+                    response(TPM_RC_SUCCESS, &handle.to_be_bytes())
+                }
+                None => response(TPM_RC_OBJECT_MEMORY, &[]),
+            }
+        }
+
+        fn start_auth_session(&mut self) -> Vec<u8> {
+            if self
+                .sessions
+                .values()
+                .filter(|session| session.loaded)
+                .count()
+                >= 3
+            {
+                return response(TPM_RC_SESSION_MEMORY, &[]);
+            }
+
+            self.next_id += 1;
+            let handle = (HR_POLICY_SESSION << 24) | self.next_id;
+            self.sessions.insert(
+                handle,
+                FakeSession {
+                    loaded: true,
+                    sequence: 0,
+                },
+            );
+            // sessionHandle, nonceTPM
+            response(
+                TPM_RC_SUCCESS,
+                &[&handle.to_be_bytes()[..], &0u16.to_be_bytes()].concat(),
+            )
+        }
+
+        fn context_save(&mut self, handle: u32) -> Vec<u8> {
+            let id = match (self.objects.get(&handle), self.sessions.get(&handle)) {
+                (Some(id), _) => *id,
+                (None, Some(session)) if session.loaded => {
+                    let oldest = self
+                        .sessions
+                        .values()
+                        .filter(|session| !session.loaded)
+                        .map(|session| session.sequence)
+                        .min();
+                    if let Some(oldest) = oldest {
+                        if self.context_counter + 1 - oldest > MAX_GAP {
+                            return response(TPM_RC_CONTEXT_GAP, &[]);
+                        }
+                    }
+
+                    self.context_counter += 1;
+                    let session = self.sessions.get_mut(&handle).unwrap();
+                    session.loaded = false;
+                    session.sequence = self.context_counter;
+                    0
+                }
+                _ => return response(TPM_RC_HANDLE | TPM_RC_N(1), &[]),
+            };
+            let sequence = match self.sessions.get(&handle) {
+                Some(session) => session.sequence,
+                None => 0,
+            };
+
+            // TPMS_CONTEXT: sequence, savedHandle, hierarchy, TPM2B with id
+            response(
+                TPM_RC_SUCCESS,
+                &[
+                    &sequence.to_be_bytes()[..],
+                    &handle.to_be_bytes(),
+                    &TPM_RH_OWNER.to_be_bytes(),
+                    &4u16.to_be_bytes(),
+                    &id.to_be_bytes(),
+                ]
+                .concat(),
+            )
+        }
+
+        /// Only knows `TPM_CAP_HANDLES` for transient objects and the handle
+        /// related `TPM_CAP_TPM_PROPERTIES`, regardless of the requested
+        /// property.
+        fn get_capability(&self, capability: u32) -> Vec<u8> {
+            let loaded = self
+                .sessions
+                .values()
+                .filter(|session| session.loaded)
+                .count() as u32;
+
+            let data: Vec<u32> = match capability {
+                TPM_CAP_HANDLES => self.objects.keys().copied().collect(),
+                TPM_CAP_TPM_PROPERTIES => vec![
+                    TPM_PT_HR_TRANSIENT_AVAIL,
+                    3 - self.objects.len() as u32,
+                    TPM_PT_HR_LOADED,
+                    loaded,
+                ],
+                _ => unimplemented!(),
+            };
+            let count = match capability {
+                TPM_CAP_HANDLES => data.len(),
+                _ => data.len() / 2,
+            } as u32;
+
+            // moreData, capability, count, TPMU_CAPABILITIES
+            let mut body = vec![0];
+            for value in [capability, count].iter().chain(data.iter()) {
+                body.extend_from_slice(&value.to_be_bytes());
+            }
+            response(TPM_RC_SUCCESS, &body)
+        }
+
+        fn context_load(&mut self, context: &[u8]) -> Vec<u8> {
+            let sequence = u64::from_be_bytes(context[0..8].try_into().unwrap());
+            let saved_handle = u32_at(context, 8);
+
+            if !is_session(saved_handle) {
+                return self.load(u32_at(context, 18));
+            }
+
+            match self.sessions.get_mut(&saved_handle) {
+                Some(session) if !session.loaded && session.sequence == sequence => {
+                    session.loaded = true;
+                    response(TPM_RC_SUCCESS, &saved_handle.to_be_bytes())
+                }
+                _ => response(TPM_RC_HANDLE | TPM_RC_P | TPM_RC_N(1), &[]),
+            }
+        }
+    }
+
+    /// `NV_DefineSpace` of `nv_index` with empty auth.
+    pub fn nv_define_space(nv_index: u32) -> Vec<u8> {
+        let mut body = TPM_RH_OWNER.to_be_bytes().to_vec();
+        body.extend_from_slice(&[0, 0, 0, 4]);
+        body.extend_from_slice(&nv_index.to_be_bytes());
+        command(CommandCode::NVDefineSpace, &body)
+    }
+
+    pub fn u32_at(buf: &[u8], offset: usize) -> u32 {
+        tpm::u32_at(buf, offset).unwrap()
+    }
+
+    impl Tcti for FakeTpm {
+        fn new(_conf: &str) -> Result<Self, TctiError> {
+            Ok(Default::default())
+        }
+
+        fn transmit(&mut self, command: &[u8]) -> Result<(), TctiError> {
+            // first handle or parameter
+            let handle = match command.len() >= TPM_HEADER_SIZE + 4 {
+                true => u32_at(command, TPM_HEADER_SIZE),
+                false => 0,
+            };
+
+            let response = match CommandCode::try_from(u32_at(command, 6)).unwrap() {
+                CommandCode::CreatePrimary => {
+                    self.next_id += 1;
+                    self.load(self.next_id)
+                }
+                CommandCode::StartAuthSession => self.start_auth_session(),
+                CommandCode::ContextLoad => self.context_load(&command[TPM_HEADER_SIZE..]),
+                CommandCode::ContextSave => self.context_save(handle),
+                CommandCode::FlushContext => {
+                    match (self.objects.remove(&handle), self.sessions.remove(&handle)) {
+                        (None, None) => response(TPM_RC_HANDLE | TPM_RC_P | TPM_RC_N(1), &[]),
+                        _ => response(TPM_RC_SUCCESS, &[]),
+                    }
+                }
+                CommandCode::ReadPublic => match self.objects.get(&handle) {
+                    Some(id) => response(TPM_RC_SUCCESS, &id.to_be_bytes()),
+                    None => response(TPM_RC_HANDLE | TPM_RC_N(1), &[]),
+                },
+                CommandCode::SelfTest => {
+                    self.self_test = true;
+                    return Ok(());
+                }
+                CommandCode::GetCapability => self.get_capability(handle),
+                CommandCode::EvictControl => match u32_at(command, TPM_HEADER_SIZE + 4) {
+                    object if object >> 24 == 0x81 => self.undefine(object, 2),
+                    _ => self.define(u32_at(command, TPM_HEADER_SIZE + 8)),
+                },
+                CommandCode::NVDefineSpace => {
+                    let auth_size = u16::from_be_bytes(command[14..16].try_into().unwrap());
+                    self.define(u32_at(command, 18 + auth_size as usize))
+                }
+                CommandCode::NVUndefineSpace => {
+                    self.undefine(u32_at(command, TPM_HEADER_SIZE + 4), 2)
+                }
+                CommandCode::PolicyGetDigest => match self.sessions.get(&handle) {
+                    Some(session) if session.loaded => {
+                        response(TPM_RC_SUCCESS, &handle.to_be_bytes())
+                    }
+                    _ => response(TPM_RC_REFERENCE_H0, &[]),
+                },
+                _ => unimplemented!(),
+            };
+            self.response = Some(response);
+
+            Ok(())
+        }
+
+        fn receive_with_timeout(&mut self, timeout: Duration) -> Result<Vec<u8>, TctiError> {
+            if self.self_test {
+                thread::sleep(timeout.min(Duration::from_millis(1)));
+                return Err(TctiError::TryAgain);
+            }
+            self.response.take().ok_or(TctiError::BadSequence)
+        }
+
+        fn cancel(&mut self) -> Result<(), TctiError> {
+            if self.self_test {
+                self.self_test = false;
+                self.response = Some(response(TPM_RC_CANCELED, &[]));
+            }
+            Ok(())
+        }
+    }
+
+    pub fn rc(response: &[u8]) -> u32 {
+        response_code(response).unwrap()
+    }
+}
@@ -1,46 +1,65 @@
 pub mod bytes;
+pub mod capability;
+pub mod daemon;
+#[cfg(test)]
+pub mod fake_tpm;
+pub mod manager;
 pub mod mu;
+pub mod ownership;
+pub mod policy;
 pub mod rm;
+pub mod scheduler;
 
 pub mod lib {
-    use core::panic;
+    use std::time::Duration;
+    use tracing::{debug, warn};
     use tss2_tcti::define_api_symbols;
+    use tss2_tcti::registry::registry::open;
     use tss2_tcti::tcti::error::TctiError;
-    use tss2_tcti::tcti::tcti::{Api, Info, State, Tcti, TctiLib};
-    use tss2_tcti::tctildr::tcti_loader::TctiLoader;
+    use tss2_tcti::tcti::tcti::{Api, Info, PendingResponse, State, TctiLib};
+    use tss2_tcti::telemetry::telemetry::CommandSpan;
     use tss2_tcti_sys::tpm2_tss;
 
+    use crate::manager::manager::{ClientId, ResourceManager};
     use crate::rm::rm::{Command, Response};
 
+    /// Tcti which gives its user a [ResourceManager] of its own, on top of
+    /// the child tcti opened from the conf string.
     #[repr(C)]
     #[derive(Debug)]
-    pub struct TctiFoobar {
+    pub struct TctiRm {
         api: Api,
         state: State,
-        child_tcti: Option<TctiLoader>,
+        pending_response: PendingResponse,
+        command_span: CommandSpan,
+        resource_manager: Option<ResourceManager>,
+        client: ClientId,
         last_command_code: Option<u32>,
     }
 
-    impl TctiFoobar {
-        fn get_child_tcti(&mut self) -> &mut TctiLoader {
-            match &mut self.child_tcti {
-                Some(child_tcti) => child_tcti,
-                None => panic!("Do not call this on uninitialized context."),
+    impl TctiRm {
+        fn get_resource_manager(&mut self) -> Result<(&mut ResourceManager, ClientId), TctiError> {
+            match &mut self.resource_manager {
+                Some(resource_manager) => Ok((resource_manager, self.client)),
+                None => {
+                    warn!("Tcti context is not initialized");
+                    Err(TctiError::BadSequence)
+                }
             }
         }
 
-        fn get_last_command_code(&mut self) -> u32 {
-            match self.last_command_code {
-                Some(last_command_code) => last_command_code,
-                None => panic!("Do not call this before the first command was transmitted."),
-            }
+        fn get_last_command_code(&mut self) -> Result<u32, TctiError> {
+            self.last_command_code.ok_or_else(|| {
+                warn!("No command was transmitted yet");
+                TctiError::BadSequence
+            })
         }
 
         fn process_command(&mut self, buf: &[u8]) -> Result<Vec<u8>, TctiError> {
             let cmd = Command::new(buf)?;
             self.last_command_code = Some(cmd.cc);
 
-            println!("{}", cmd);
+            debug!("{cmd}");
 
             for handle in cmd.handles.iter() {
                 match *handle {
@@ -49,9 +68,12 @@ pub mod lib {
                     h if h >> 24 == 0x02 => (), // HMAC session
                     h if h >> 24 == 0x03 => (), // policy session
                     h if h >> 24 == 0x40 => (), // permanent values
-                    h if h >> 24 == 0x80 => (), // transient objects
+                    h if h >> 24 == 0x80 => (), // transient objects, virtualized by the resource manager
                     h if h >> 24 == 0x81 => (), // persistent objects
-                    h => panic!("Unknown handle type: {:08x}", h),
+                    h => {
+                        warn!("Unknown handle type: {:08x}", h);
+                        return Err(TctiError::BadValue);
+                    }
                 }
             }
 
@@ -59,19 +81,19 @@ pub mod lib {
         }
 
         fn process_response(&mut self, buf: &[u8]) -> Result<Vec<u8>, TctiError> {
-            let rsp = Response::new(buf, self.get_last_command_code())?;
+            let rsp = Response::new(buf, self.get_last_command_code()?)?;
 
-            println!("{}", rsp);
+            debug!("{rsp}");
 
             Ok(buf.to_vec())
         }
     }
 
-    impl TctiLib for TctiFoobar {
+    impl TctiLib for TctiRm {
         const INFO: Info<'static> = Info {
-            name: b"tpm2_tcti-foobar\0",
-            description: b"A demo tcti written in Rust.\0",
-            config_help: b"Child TCTI config string as expected by TctiLdr.\0",
+            name: b"tpm2_tcti-rm\0",
+            description: b"In-process resource manager on top of a child tcti.\0",
+            config_help: b"Child tcti as <name>:<conf> or a stack of layers and a tcti, e.g. mssim:port=2321.\0",
         };
         const MAGIC: u64 = 0x72657372736d6772;
 
@@ -79,7 +101,10 @@ pub mod lib {
             let mut tcti = Self {
                 api: Self::get_api_static(),
                 state: State::NotInitialized,
-                child_tcti: None,
+                pending_response: None,
+                command_span: CommandSpan::default(),
+                resource_manager: None,
+                client: 0,
                 last_command_code: None,
             };
 
@@ -89,8 +114,10 @@ pub mod lib {
         }
 
         fn init_inner(&mut self, conf: &str) -> Result<(), TctiError> {
-            self.api = TctiFoobar::get_api_static();
-            self.child_tcti = Some(<TctiLoader as Tcti>::new(conf)?);
+            self.api = TctiRm::get_api_static();
+            let mut resource_manager = ResourceManager::new(open(conf)?);
+            self.client = resource_manager.connect();
+            self.resource_manager = Some(resource_manager);
             self.state = State::Transmit;
             Ok(())
         }
@@ -98,13 +125,33 @@ pub mod lib {
         fn transmit_inner(&mut self, command: &[u8]) -> Result<(), TctiError> {
             let command = self.process_command(&command)?;
 
-            self.get_child_tcti().transmit(&command)
+            let (resource_manager, client) = self.get_resource_manager()?;
+            resource_manager.transmit(client, &command)
+        }
+
+        fn receive_inner(&mut self, timeout: Duration) -> Result<Vec<u8>, TctiError> {
+            let (resource_manager, client) = self.get_resource_manager()?;
+            let response = resource_manager.receive(client, timeout)?;
+
+            self.process_response(&response)
+        }
+
+        fn get_poll_handles_inner(
+            &mut self,
+        ) -> Result<Vec<tpm2_tss::TSS2_TCTI_POLL_HANDLE>, TctiError> {
+            let (resource_manager, _) = self.get_resource_manager()?;
+            resource_manager.get_child_tcti().get_poll_handles()
         }
 
-        fn receive_inner(&mut self) -> Result<Vec<u8>, TctiError> {
-            let mut response = self.get_child_tcti().receive()?;
+        fn cancel_inner(&mut self) -> Result<(), TctiError> {
+            let (resource_manager, client) = self.get_resource_manager()?;
+            resource_manager.cancel(client)
+        }
 
-            self.process_response(&mut response)
+        fn make_sticky_inner(&mut self, _handle: &mut u32, _sticky: bool) -> Result<(), TctiError> {
+            // the handles are virtual, the child does not know them
+            warn!("Sticky objects are not supported by the resource manager");
+            Err(TctiError::NotImplemented)
         }
 
         fn get_state(&self) -> Option<State> {
@@ -113,7 +160,63 @@ pub mod lib {
         fn set_state(&mut self, state: State) {
             self.state = state;
         }
+        fn get_pending_response(&mut self) -> Option<&mut PendingResponse> {
+            Some(&mut self.pending_response)
+        }
+
+        fn get_command_span(&mut self) -> Option<&mut CommandSpan> {
+            Some(&mut self.command_span)
+        }
     }
 
-    define_api_symbols!(TctiFoobar);
+    define_api_symbols!(TctiRm);
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::fake_tpm::fake_tpm::{FakeTpm, TPM_CAP_HANDLES};
+        use tpm2_types::constants::CommandCode;
+        use tss2_tcti::conformance::conformance::check_tcti;
+        use tss2_tcti::registry::registry::register_tcti;
+        use tss2_tcti::tcti::tcti::Tcti;
+        use tss2_tcti::tpm::tpm::command;
+
+        #[test]
+        fn test_misuse() {
+            register_tcti::<FakeTpm>("fake");
+            let mut tcti = <TctiRm as Tcti>::new("fake").unwrap();
+
+            // no command was transmitted yet
+            assert_eq!(
+                tcti.process_response(&[0x80, 0x01, 0, 0, 0, 10, 0, 0, 0, 0]),
+                Err(TctiError::BadSequence)
+            );
+            assert!(Tcti::transmit(&mut tcti, &[0x80, 0x01, 0x00]).is_err());
+            let unknown_handle = command(CommandCode::ReadPublic, &0x7f000000u32.to_be_bytes());
+            assert_eq!(
+                Tcti::transmit(&mut tcti, &unknown_handle),
+                Err(TctiError::BadValue)
+            );
+
+            assert_eq!(
+                Tcti::make_sticky(&mut tcti, &mut 0x80ff0000, true),
+                Err(TctiError::NotImplemented)
+            );
+
+            tcti.resource_manager = None;
+            let read_public = command(CommandCode::ReadPublic, &0x80000000u32.to_be_bytes());
+            assert_eq!(
+                Tcti::transmit(&mut tcti, &read_public),
+                Err(TctiError::BadSequence)
+            );
+        }
+
+        #[test]
+        fn test_conformance() {
+            register_tcti::<FakeTpm>("fake");
+            let body = [TPM_CAP_HANDLES, 0x80000000, 1];
+            let body: Vec<u8> = body.iter().flat_map(|value| value.to_be_bytes()).collect();
+            check_tcti::<TctiRm>("fake", &command(CommandCode::GetCapability, &body));
+        }
+    }
 }
@@ -0,0 +1,1375 @@
+pub mod manager {
+    use std::collections::BTreeMap;
+    use std::fmt;
+    use std::time::Duration;
+
+    use thiserror::Error;
+    use tpm2_types::constants::CommandCode;
+    use tracing::warn;
+    use tss2_tcti::tcti::error::TctiError;
+    use tss2_tcti::tcti::tcti::{Tcti, TIMEOUT_BLOCK};
+    use tss2_tcti::tpm::tpm::{self, response_code, TPM_RC_CONTEXT_GAP, TPM_RC_SUCCESS};
+
+    use crate::capability::capability::{GetCapabilityCommand, VirtualView};
+    use crate::ownership::ownership::{
+        is_nv_index, is_persistent, Owner, Ownership, OwnershipChange, DEFAULT_OWNER,
+        TPM_RC_NV_SPACE,
+    };
+    use crate::policy::policy::Policy;
+    use crate::rm::rm::{write_handle, Command, Response, TPM_HEADER_SIZE};
+
+    pub const TPM_RC_HANDLE: u32 = 0x08b;
+    pub const TPM_RC_OBJECT_MEMORY: u32 = 0x902;
+    pub const TPM_RC_SESSION_MEMORY: u32 = 0x903;
+    /// Error refers to a parameter (instead of a handle), see [TPM_RC_N()].
+    pub const TPM_RC_P: u32 = 0x040;
+    /// Error refers to a session (instead of a handle), see [TPM_RC_N()].
+    pub const TPM_RC_S: u32 = 0x800;
+
+    /// Returns the number `n` (1-based) of the handle, parameter or session
+    /// an error response code refers to.
+    #[allow(non_snake_case)]
+    pub const fn TPM_RC_N(n: u32) -> u32 {
+        n << 8
+    }
+
+    /// Most significant octet of HMAC session handles.
+    pub const HR_HMAC_SESSION: u32 = 0x02;
+    /// Most significant octet of policy session handles.
+    pub const HR_POLICY_SESSION: u32 = 0x03;
+    /// Most significant octet of transient object handles.
+    pub const HR_TRANSIENT: u32 = 0x80;
+
+    /// First virtual handle handed out to clients. Virtual handles are
+    /// distinct from the handles the TPM uses (0x80000000, 0x80000001, ...).
+    pub const VIRTUAL_TRANSIENT_FIRST: u32 = 0x80ff0000;
+
+    pub fn is_transient(handle: u32) -> bool {
+        handle >> 24 == HR_TRANSIENT
+    }
+
+    pub fn is_session(handle: u32) -> bool {
+        matches!(handle >> 24, HR_HMAC_SESSION | HR_POLICY_SESSION)
+    }
+
+    /// `sequence` of a saved context (TPMS_CONTEXT).
+    fn context_sequence(context: &[u8]) -> u64 {
+        match context.get(0..8) {
+            Some(sequence) => u64::from_be_bytes(sequence.try_into().unwrap()),
+            None => 0,
+        }
+    }
+
+    #[derive(Debug, Error)]
+    pub enum RmError {
+        #[error(transparent)]
+        Tcti(#[from] TctiError),
+
+        #[error("TPM returned error {0:#x}")]
+        Tpm(u32),
+    }
+
+    /// Identifies a client of the [ResourceManager], see
+    /// [connect()](ResourceManager::connect).
+    pub type ClientId = u64;
+
+    /// Resources of a single client, i.e. its transient objects, its
+    /// [Policy] and its [Owner].
+    ///
+    /// Transient objects are only loaded into the TPM for the duration of a
+    /// command. In between commands, only their saved context is kept.
+    #[derive(Debug)]
+    pub struct ClientResources {
+        /// virtual handle -> saved context (TPMS_CONTEXT)
+        transients: BTreeMap<u32, Vec<u8>>,
+        next_virtual_transient: u32,
+        policy: Policy,
+        owner: Owner,
+    }
+
+    impl Default for ClientResources {
+        fn default() -> Self {
+            Self {
+                transients: BTreeMap::new(),
+                next_virtual_transient: 0,
+                policy: Policy::default(),
+                owner: DEFAULT_OWNER.to_string(),
+            }
+        }
+    }
+
+    impl ClientResources {
+        pub fn new() -> Self {
+            Default::default()
+        }
+
+        pub fn policy(&self) -> &Policy {
+            &self.policy
+        }
+
+        pub fn owner(&self) -> &str {
+            &self.owner
+        }
+
+        /// Virtual handles of all transient objects of this client.
+        pub fn transient_handles(&self) -> impl Iterator<Item = u32> + '_ {
+            self.transients.keys().copied()
+        }
+
+        fn allocate_virtual_transient(&mut self) -> u32 {
+            loop {
+                let handle = VIRTUAL_TRANSIENT_FIRST | (self.next_virtual_transient & 0xffff);
+                self.next_virtual_transient = self.next_virtual_transient.wrapping_add(1);
+
+                if !self.transients.contains_key(&handle) {
+                    return handle;
+                }
+            }
+        }
+    }
+
+    /// Limits per client, see [set_quotas()](ResourceManager::set_quotas).
+    /// Unlimited by default.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub struct Quotas {
+        /// Maximum number of transient objects.
+        pub max_transients: usize,
+        /// Maximum number of sessions.
+        pub max_sessions: usize,
+        /// Maximum number of persistent objects, per [Owner].
+        pub max_persistent: usize,
+        /// Maximum number of NV indices, per [Owner].
+        pub max_nv_indices: usize,
+    }
+
+    impl Default for Quotas {
+        fn default() -> Self {
+            Self {
+                max_transients: usize::MAX,
+                max_sessions: usize::MAX,
+                max_persistent: usize::MAX,
+                max_nv_indices: usize::MAX,
+            }
+        }
+    }
+
+    /// HMAC or policy session. Unlike transient objects, session handles are
+    /// not virtualized since the TPM keeps them when saving and loading.
+    #[derive(Debug)]
+    struct Session {
+        owner: ClientId,
+        /// `sequence` of the saved context, i.e. its age
+        sequence: u64,
+        /// saved context (TPMS_CONTEXT), `None` while loaded
+        context: Option<Vec<u8>>,
+    }
+
+    /// Resources loaded into the TPM for a command.
+    #[derive(Debug, Default)]
+    struct Loaded {
+        /// (virtual, physical)
+        transients: Vec<(u32, u32)>,
+        sessions: Vec<u32>,
+    }
+
+    /// Command which was sent to the TPM.
+    #[derive(Debug)]
+    struct Forwarded {
+        cc: u32,
+        loaded: Loaded,
+        /// session flushed by the command
+        flush_session: Option<u32>,
+        /// parameters of GetCapability, the response is rewritten to the
+        /// view of the client
+        get_capability: Option<GetCapabilityCommand>,
+        /// recorded if the command succeeds
+        ownership_change: Option<OwnershipChange>,
+    }
+
+    #[derive(Debug)]
+    enum InFlight {
+        /// Command was sent to the TPM.
+        Forwarded {
+            client: ClientId,
+            command: Forwarded,
+        },
+        /// Command was answered by the resource manager itself.
+        Synthesized { client: ClientId, response: Vec<u8> },
+    }
+
+    impl InFlight {
+        fn client(&self) -> ClientId {
+            match self {
+                InFlight::Forwarded { client, .. } | InFlight::Synthesized { client, .. } => {
+                    *client
+                }
+            }
+        }
+    }
+
+    /// Resource manager virtualizing transient objects and sessions, which
+    /// hides the limited number of TPM object and session slots from its
+    /// clients (like tpm2-abrmd).
+    ///
+    /// Every client works on virtual handles. Before a command is sent to
+    /// the TPM, the objects and sessions it references are loaded
+    /// (`ContextLoad`) and object handles are replaced. After the response
+    /// was received, all objects are saved (`ContextSave`) and flushed
+    /// (`FlushContext`) again, all sessions are saved.
+    ///
+    /// Saved sessions count towards the context gap of the TPM. If saving a
+    /// session fails with `TPM_RC_CONTEXT_GAP`, the oldest saved session is
+    /// loaded and saved again (regap).
+    ///
+    /// Persistent objects and NV indices are not virtualized, but the
+    /// [Owner] creating them is recorded, see [Ownership]. Clients must not
+    /// remove those of other owners.
+    pub struct ResourceManager {
+        child_tcti: Box<dyn Tcti>,
+        clients: BTreeMap<ClientId, ClientResources>,
+        /// session handle -> session, shared by all clients
+        sessions: BTreeMap<u32, Session>,
+        next_client: ClientId,
+        in_flight: Option<InFlight>,
+        quotas: Quotas,
+        /// policy of new clients
+        default_policy: Policy,
+        ownership: Ownership,
+    }
+
+    impl fmt::Debug for ResourceManager {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_struct("ResourceManager")
+                .field("clients", &self.clients)
+                .field("sessions", &self.sessions)
+                .field("in_flight", &self.in_flight)
+                .field("quotas", &self.quotas)
+                .field("ownership", &self.ownership)
+                .finish_non_exhaustive()
+        }
+    }
+
+    impl ResourceManager {
+        pub fn new(child_tcti: Box<dyn Tcti>) -> Self {
+            Self {
+                child_tcti,
+                clients: BTreeMap::new(),
+                sessions: BTreeMap::new(),
+                next_client: 0,
+                in_flight: None,
+                quotas: Quotas::default(),
+                default_policy: Policy::default(),
+                ownership: Ownership::default(),
+            }
+        }
+
+        /// Limit the resources of every client. Commands which would exceed
+        /// the quota of a client fail with `TPM_RC_OBJECT_MEMORY`,
+        /// `TPM_RC_SESSION_MEMORY` or `TPM_RC_NV_SPACE`, respectively.
+        pub fn set_quotas(&mut self, quotas: Quotas) {
+            self.quotas = quotas;
+        }
+
+        /// Replace the (in-memory) owners of persistent objects and NV
+        /// indices, e.g. by [Ownership::open()].
+        pub fn set_ownership(&mut self, ownership: Ownership) {
+            self.ownership = ownership;
+        }
+
+        pub fn ownership(&self) -> &Ownership {
+            &self.ownership
+        }
+
+        pub fn get_child_tcti(&mut self) -> &mut dyn Tcti {
+            self.child_tcti.as_mut()
+        }
+
+        /// Set the [Policy] of clients which connect afterwards.
+        pub fn set_default_policy(&mut self, policy: Policy) {
+            self.default_policy = policy;
+        }
+
+        /// Set the [Policy] of `client`.
+        pub fn set_policy(&mut self, client: ClientId, policy: Policy) -> Result<(), TctiError> {
+            match self.clients.get_mut(&client) {
+                Some(resources) => {
+                    resources.policy = policy;
+                    Ok(())
+                }
+                None => {
+                    warn!("Unknown client {client}");
+                    Err(TctiError::BadValue)
+                }
+            }
+        }
+
+        /// Set the [Owner] of `client`, [DEFAULT_OWNER] by default.
+        pub fn set_owner(&mut self, client: ClientId, owner: Owner) -> Result<(), TctiError> {
+            match self.clients.get_mut(&client) {
+                Some(resources) => {
+                    resources.owner = owner;
+                    Ok(())
+                }
+                None => {
+                    warn!("Unknown client {client}");
+                    Err(TctiError::BadValue)
+                }
+            }
+        }
+
+        /// Register a new client with empty resources and the default
+        /// policy.
+        pub fn connect(&mut self) -> ClientId {
+            let client = self.next_client;
+            self.next_client += 1;
+
+            let resources = ClientResources {
+                policy: self.default_policy.clone(),
+                ..Default::default()
+            };
+            self.clients.insert(client, resources);
+            client
+        }
+
+        pub fn get_client(&self, client: ClientId) -> Option<&ClientResources> {
+            self.clients.get(&client)
+        }
+
+        /// Remove a client, flushing all of its sessions and forgetting its
+        /// objects. A command of the client which is still in flight is
+        /// cancelled and completed first.
+        pub fn disconnect(&mut self, client: ClientId) -> Result<(), TctiError> {
+            if matches!(&self.in_flight, Some(in_flight) if in_flight.client() == client) {
+                match self.cancel(client) {
+                    Ok(()) | Err(TctiError::NotImplemented) => (),
+                    Err(error) => warn!("Could not cancel command of client {client}: {error}"),
+                }
+                if let Err(error) = self.receive(client, TIMEOUT_BLOCK) {
+                    warn!("Could not complete command of client {client}: {error}");
+                    self.in_flight = None;
+                }
+            }
+
+            // objects are saved only, i.e. they do not occupy the TPM
+            self.take_client(client)?;
+
+            let sessions: Vec<u32> = self.session_handles(client).collect();
+            for handle in sessions {
+                self.sessions.remove(&handle);
+                if let Err(error) = self.flush_context(handle) {
+                    warn!("Could not flush session {handle:08x}: {error}");
+                }
+            }
+
+            Ok(())
+        }
+
+        /// Handles of all sessions of `client`.
+        pub fn session_handles(&self, client: ClientId) -> impl Iterator<Item = u32> + '_ {
+            self.sessions
+                .iter()
+                .filter(move |(_, session)| session.owner == client)
+                .map(|(handle, _)| *handle)
+        }
+
+        fn take_client(&mut self, client: ClientId) -> Result<ClientResources, TctiError> {
+            self.clients.remove(&client).ok_or_else(|| {
+                warn!("Unknown client {client}");
+                TctiError::BadValue
+            })
+        }
+
+        /// Transmit a client command to the TPM, replacing virtual handles.
+        pub fn transmit(&mut self, client: ClientId, command: &[u8]) -> Result<(), TctiError> {
+            if self.in_flight.is_some() {
+                return Err(TctiError::BadSequence);
+            }
+
+            let mut resources = self.take_client(client)?;
+            let result = self.prepare_command(client, &mut resources, command);
+            self.clients.insert(client, resources);
+
+            let in_flight = match result {
+                Ok(in_flight) => in_flight,
+                Err(RmError::Tpm(rc)) => InFlight::Synthesized {
+                    client,
+                    response: tpm::response(rc, &[]),
+                },
+                Err(RmError::Tcti(error)) => return Err(error),
+            };
+            self.in_flight = Some(in_flight);
+
+            Ok(())
+        }
+
+        /// Receive the response to the last command of `client`, replacing
+        /// handles of new transient objects by virtual ones.
+        pub fn receive(
+            &mut self,
+            client: ClientId,
+            timeout: Duration,
+        ) -> Result<Vec<u8>, TctiError> {
+            match &self.in_flight {
+                Some(in_flight) if in_flight.client() == client => (),
+                _ => return Err(TctiError::BadSequence),
+            }
+
+            let command = match self.in_flight.take() {
+                Some(InFlight::Forwarded { command, .. }) => command,
+                Some(InFlight::Synthesized { response, .. }) => return Ok(response),
+                None => unreachable!(),
+            };
+
+            let mut resources = self.take_client(client)?;
+            let result = self.receive_response(client, &mut resources, command, timeout);
+            self.clients.insert(client, resources);
+
+            result
+        }
+
+        /// Cancel the command of `client` which is in flight. The response,
+        /// usually `TPM_RC_CANCELED`, still has to be received.
+        pub fn cancel(&mut self, client: ClientId) -> Result<(), TctiError> {
+            match &self.in_flight {
+                Some(InFlight::Forwarded { client: c, .. }) if *c == client => {
+                    self.child_tcti.cancel()
+                }
+                Some(InFlight::Synthesized { client: c, .. }) if *c == client => Ok(()),
+                _ => Err(TctiError::BadSequence),
+            }
+        }
+
+        /// Transmit a client command and wait for the response.
+        pub fn execute(&mut self, client: ClientId, command: &[u8]) -> Result<Vec<u8>, TctiError> {
+            self.transmit(client, command)?;
+            self.receive(client, TIMEOUT_BLOCK)
+        }
+
+        fn owns_session(&self, client: ClientId, handle: u32) -> bool {
+            matches!(self.sessions.get(&handle), Some(session) if session.owner == client)
+        }
+
+        fn prepare_command(
+            &mut self,
+            client: ClientId,
+            resources: &mut ClientResources,
+            command: &[u8],
+        ) -> Result<InFlight, RmError> {
+            let cmd = Command::new(command)?;
+            resources.policy.check(&cmd).map_err(RmError::Tpm)?;
+
+            let ownership_change = OwnershipChange::parse(&cmd, command);
+            if let Some(change) = ownership_change {
+                self.check_ownership(client, resources, change)?;
+            }
+
+            let mut flush_session = None;
+
+            let flush_handle = match cmd.cc == u32::from(CommandCode::FlushContext) {
+                true => tpm::u32_at(command, TPM_HEADER_SIZE),
+                false => None,
+            };
+            if let Some(flush_handle) = flush_handle {
+                // the object is not loaded, i.e. flushing means forgetting its context
+                if is_transient(flush_handle) {
+                    return match resources.transients.remove(&flush_handle) {
+                        Some(_) => Ok(InFlight::Synthesized {
+                            client,
+                            response: tpm::response(TPM_RC_SUCCESS, &[]),
+                        }),
+                        None => Err(RmError::Tpm(TPM_RC_HANDLE | TPM_RC_P | TPM_RC_N(1))),
+                    };
+                }
+
+                // saved sessions are flushed by the TPM directly
+                if is_session(flush_handle) {
+                    if !self.owns_session(client, flush_handle) {
+                        return Err(RmError::Tpm(TPM_RC_HANDLE | TPM_RC_P | TPM_RC_N(1)));
+                    }
+                    flush_session = Some(flush_handle);
+                }
+            }
+
+            let mut command = command.to_vec();
+            let mut loaded = Loaded::default();
+
+            let mut result = self.load(client, resources, &cmd, &mut command, &mut loaded);
+            if result.is_ok() {
+                result = self.child_tcti.transmit(&command).map_err(RmError::from);
+            }
+            if let Err(error) = result {
+                self.unload(resources, loaded);
+                return Err(error);
+            }
+
+            Ok(InFlight::Forwarded {
+                client,
+                command: Forwarded {
+                    cc: cmd.cc,
+                    loaded,
+                    flush_session,
+                    get_capability: GetCapabilityCommand::parse(&command),
+                    ownership_change,
+                },
+            })
+        }
+
+        /// Deny removing persistent objects and NV indices of other owners,
+        /// and creating more of them than the quota allows. Those without an
+        /// owner may be removed by every client.
+        fn check_ownership(
+            &self,
+            client: ClientId,
+            resources: &ClientResources,
+            change: OwnershipChange,
+        ) -> Result<(), RmError> {
+            match change {
+                OwnershipChange::Insert(handle) => {
+                    let (used, max) = if is_persistent(handle) {
+                        (
+                            self.ownership.count(&resources.owner, is_persistent),
+                            self.quotas.max_persistent,
+                        )
+                    } else if is_nv_index(handle) {
+                        (
+                            self.ownership.count(&resources.owner, is_nv_index),
+                            self.quotas.max_nv_indices,
+                        )
+                    } else {
+                        // rejected by the TPM
+                        return Ok(());
+                    };
+                    if used >= max {
+                        warn!("Client {client} exceeds its quota, denying {handle:08x}");
+                        return Err(RmError::Tpm(TPM_RC_NV_SPACE));
+                    }
+                }
+                OwnershipChange::Remove { handle, n } => match self.ownership.owner(handle) {
+                    Some(owner) if owner != resources.owner => {
+                        warn!("Client {client} does not own {handle:08x}, owner is {owner}");
+                        return Err(RmError::Tpm(TPM_RC_HANDLE | TPM_RC_N(n)));
+                    }
+                    _ => (),
+                },
+            }
+
+            Ok(())
+        }
+
+        /// Load all objects and sessions referenced by `cmd`, replacing
+        /// virtual handles in `command`.
+        fn load(
+            &mut self,
+            client: ClientId,
+            resources: &ClientResources,
+            cmd: &Command,
+            command: &mut [u8],
+            loaded: &mut Loaded,
+        ) -> Result<(), RmError> {
+            for (i, &handle) in cmd.handles.iter().enumerate() {
+                let rc_unknown = TPM_RC_HANDLE | TPM_RC_N(i as u32 + 1);
+
+                if is_transient(handle) {
+                    let physical = match loaded
+                        .transients
+                        .iter()
+                        .find(|(virtual_handle, _)| *virtual_handle == handle)
+                    {
+                        // same object used twice in one command
+                        Some((_, physical)) => *physical,
+                        None => {
+                            let context = resources
+                                .transients
+                                .get(&handle)
+                                .ok_or(RmError::Tpm(rc_unknown))?;
+                            let physical = self.context_load(context)?;
+                            loaded.transients.push((handle, physical));
+                            physical
+                        }
+                    };
+
+                    write_handle(command, i, physical);
+                } else if is_session(handle) {
+                    self.load_session(client, handle, loaded, rc_unknown)?;
+                }
+            }
+
+            for (i, &handle) in cmd.sessions.iter().enumerate() {
+                if is_session(handle) {
+                    let rc_unknown = TPM_RC_HANDLE | TPM_RC_S | TPM_RC_N(i as u32 + 1);
+                    self.load_session(client, handle, loaded, rc_unknown)?;
+                }
+            }
+
+            Ok(())
+        }
+
+        fn load_session(
+            &mut self,
+            client: ClientId,
+            handle: u32,
+            loaded: &mut Loaded,
+            rc_unknown: u32,
+        ) -> Result<(), RmError> {
+            if loaded.sessions.contains(&handle) {
+                return Ok(());
+            }
+
+            let context = match self.sessions.get_mut(&handle) {
+                Some(session) if session.owner == client => session.context.take(),
+                _ => None,
+            };
+            let context = context.ok_or(RmError::Tpm(rc_unknown))?;
+
+            match self.context_load(&context) {
+                Ok(_) => {
+                    loaded.sessions.push(handle);
+                    Ok(())
+                }
+                Err(error) => {
+                    if let Some(session) = self.sessions.get_mut(&handle) {
+                        session.context = Some(context);
+                    }
+                    Err(error)
+                }
+            }
+        }
+
+        fn receive_response(
+            &mut self,
+            client: ClientId,
+            resources: &mut ClientResources,
+            mut command: Forwarded,
+            timeout: Duration,
+        ) -> Result<Vec<u8>, TctiError> {
+            let mut response = match self.child_tcti.receive_with_timeout(timeout) {
+                Ok(response) => response,
+                Err(TctiError::TryAgain) => {
+                    // response is not ready yet, keep resources loaded
+                    self.in_flight = Some(InFlight::Forwarded { client, command });
+                    return Err(TctiError::TryAgain);
+                }
+                Err(error) => {
+                    self.unload(resources, command.loaded);
+                    return Err(error);
+                }
+            };
+
+            let result = self.virtualize_response(
+                client,
+                resources,
+                command.cc,
+                &mut response,
+                &mut command.loaded,
+                command.flush_session,
+            );
+            self.unload(resources, command.loaded);
+
+            if let Some(rc) = result? {
+                return Ok(tpm::response(rc, &[]));
+            }
+            if let Some(change) = command.ownership_change {
+                if response_code(&response) == Some(TPM_RC_SUCCESS) {
+                    match change {
+                        OwnershipChange::Insert(handle) => {
+                            self.ownership.insert(handle, &resources.owner)
+                        }
+                        OwnershipChange::Remove { handle, .. } => self.ownership.remove(handle),
+                    }
+                }
+            }
+            if let Some(get_capability) = &command.get_capability {
+                let view = self.virtual_view(client, resources);
+                if let Some(virtualized) = view.virtualize(get_capability, &response) {
+                    response = virtualized;
+                }
+            }
+
+            Ok(response)
+        }
+
+        /// Resources of `client` as it sees them.
+        fn virtual_view(&self, client: ClientId, resources: &ClientResources) -> VirtualView {
+            let transients: Vec<u32> = resources.transient_handles().collect();
+            let sessions: Vec<u32> = self.session_handles(client).collect();
+            let avail = |max: usize, used: usize| match max {
+                usize::MAX => None,
+                max => Some(u32::try_from(max.saturating_sub(used)).unwrap_or(u32::MAX)),
+            };
+
+            VirtualView {
+                transients_avail: avail(self.quotas.max_transients, transients.len()),
+                sessions_avail: avail(self.quotas.max_sessions, sessions.len()),
+                transients,
+                sessions,
+            }
+        }
+
+        /// Replace handles of new objects by virtual ones and register new
+        /// sessions. Returns the response code to answer with instead if the
+        /// client exceeds its quota.
+        fn virtualize_response(
+            &mut self,
+            client: ClientId,
+            resources: &mut ClientResources,
+            cc: u32,
+            response: &mut [u8],
+            loaded: &mut Loaded,
+            flush_session: Option<u32>,
+        ) -> Result<Option<u32>, TctiError> {
+            let rsp = Response::new(response, cc)?;
+
+            if let (TPM_RC_SUCCESS, Some(handle)) = (rsp.rc, flush_session) {
+                self.sessions.remove(&handle);
+            }
+
+            if let Some(rc) = self.exceeded_quota(client, resources, &rsp.handles) {
+                warn!("Client {client} exceeds its quota, flushing new handles");
+                for &handle in rsp.handles.iter() {
+                    if is_transient(handle) || is_session(handle) {
+                        if let Err(error) = self.flush_context(handle) {
+                            warn!("Could not flush {handle:08x}: {error}");
+                        }
+                    }
+                }
+                return Ok(Some(rc));
+            }
+
+            for (i, &handle) in rsp.handles.iter().enumerate() {
+                if is_transient(handle) {
+                    let virtual_handle = resources.allocate_virtual_transient();
+                    // placeholder until the object is saved
+                    resources.transients.insert(virtual_handle, Vec::new());
+                    loaded.transients.push((virtual_handle, handle));
+
+                    write_handle(response, i, virtual_handle);
+                } else if is_session(handle) {
+                    self.sessions.insert(
+                        handle,
+                        Session {
+                            owner: client,
+                            sequence: 0,
+                            context: None,
+                        },
+                    );
+                    if !loaded.sessions.contains(&handle) {
+                        loaded.sessions.push(handle);
+                    }
+                }
+            }
+
+            Ok(None)
+        }
+
+        /// Response code if the new `handles` would exceed the quota of
+        /// `client`.
+        fn exceeded_quota(
+            &self,
+            client: ClientId,
+            resources: &ClientResources,
+            handles: &[u32],
+        ) -> Option<u32> {
+            let new_transients = handles.iter().filter(|h| is_transient(**h)).count();
+            let new_sessions = handles
+                .iter()
+                .filter(|h| is_session(**h) && !self.sessions.contains_key(h))
+                .count();
+
+            if new_transients > 0
+                && resources.transients.len() + new_transients > self.quotas.max_transients
+            {
+                return Some(TPM_RC_OBJECT_MEMORY);
+            }
+            if new_sessions > 0
+                && self.session_handles(client).count() + new_sessions > self.quotas.max_sessions
+            {
+                return Some(TPM_RC_SESSION_MEMORY);
+            }
+
+            None
+        }
+
+        /// Save and flush all loaded objects, save all loaded sessions.
+        fn unload(&mut self, resources: &mut ClientResources, loaded: Loaded) {
+            for (virtual_handle, physical) in loaded.transients {
+                match self.context_save(physical) {
+                    Ok(context) => {
+                        resources.transients.insert(virtual_handle, context);
+                    }
+                    Err(error) => {
+                        // e.g. sequence objects are flushed by the TPM on completion
+                        warn!("Could not save object {virtual_handle:08x}, dropping it: {error}");
+                        resources.transients.remove(&virtual_handle);
+                        continue;
+                    }
+                }
+
+                if let Err(error) = self.flush_context(physical) {
+                    warn!("Could not flush object {physical:08x}: {error}");
+                }
+            }
+
+            for handle in loaded.sessions {
+                if let Err(error) = self.save_session(handle) {
+                    // e.g. sessions without continueSession are flushed by the TPM
+                    warn!("Could not save session {handle:08x}, dropping it: {error}");
+                    self.sessions.remove(&handle);
+                    // in case it is still loaded
+                    let _ = self.flush_context(handle);
+                }
+            }
+        }
+
+        fn save_session(&mut self, handle: u32) -> Result<(), RmError> {
+            // every regap makes another session the newest, i.e. this terminates
+            for _ in 0..=self.sessions.len() {
+                match self.context_save(handle) {
+                    Ok(context) => {
+                        let session = self
+                            .sessions
+                            .get_mut(&handle)
+                            .ok_or(RmError::Tcti(TctiError::GeneralFailure))?;
+                        session.sequence = context_sequence(&context);
+                        session.context = Some(context);
+                        return Ok(());
+                    }
+                    Err(RmError::Tpm(TPM_RC_CONTEXT_GAP)) => self.regap(handle)?,
+                    Err(error) => return Err(error),
+                }
+            }
+
+            Err(RmError::Tpm(TPM_RC_CONTEXT_GAP))
+        }
+
+        /// Load and save the oldest saved session (except `exclude`) to
+        /// reduce the context gap.
+        fn regap(&mut self, exclude: u32) -> Result<(), RmError> {
+            let oldest = self
+                .sessions
+                .iter()
+                .filter(|(handle, session)| **handle != exclude && session.context.is_some())
+                .min_by_key(|(_, session)| session.sequence)
+                .map(|(handle, _)| *handle);
+            let handle = oldest.ok_or(RmError::Tpm(TPM_RC_CONTEXT_GAP))?;
+
+            let session = self.sessions.get_mut(&handle).unwrap();
+            let context = session.context.take().unwrap();
+            if let Err(error) = self.context_load(&context) {
+                warn!("Could not load session {handle:08x} for regap: {error}");
+                self.sessions.remove(&handle);
+                return Err(error);
+            }
+
+            match self.context_save(handle) {
+                Ok(context) => {
+                    let session = self.sessions.get_mut(&handle).unwrap();
+                    session.sequence = context_sequence(&context);
+                    session.context = Some(context);
+                    Ok(())
+                }
+                Err(error) => {
+                    warn!("Could not save session {handle:08x} for regap: {error}");
+                    self.sessions.remove(&handle);
+                    Err(error)
+                }
+            }
+        }
+
+        /// Execute a command of the resource manager itself.
+        fn execute_child(&mut self, command: &[u8]) -> Result<Vec<u8>, RmError> {
+            self.child_tcti.transmit(command)?;
+            let response = self.child_tcti.receive()?;
+
+            match response_code(&response) {
+                Some(TPM_RC_SUCCESS) => Ok(response),
+                Some(rc) => Err(RmError::Tpm(rc)),
+                None => {
+                    warn!("Child tcti returned malformed response: {response:02x?}");
+                    Err(TctiError::GeneralFailure.into())
+                }
+            }
+        }
+
+        fn context_load(&mut self, context: &[u8]) -> Result<u32, RmError> {
+            let response = self.execute_child(&tpm::command(CommandCode::ContextLoad, context))?;
+            tpm::u32_at(&response, TPM_HEADER_SIZE).ok_or(TctiError::GeneralFailure.into())
+        }
+
+        fn context_save(&mut self, handle: u32) -> Result<Vec<u8>, RmError> {
+            let response = self.execute_child(&tpm::command(
+                CommandCode::ContextSave,
+                &handle.to_be_bytes(),
+            ))?;
+            Ok(response[TPM_HEADER_SIZE..].to_vec())
+        }
+
+        fn flush_context(&mut self, handle: u32) -> Result<(), RmError> {
+            self.execute_child(&tpm::command(
+                CommandCode::FlushContext,
+                &handle.to_be_bytes(),
+            ))?;
+            Ok(())
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::fake_tpm::fake_tpm::*;
+        use tss2_tcti::fault::fault::{FaultConfig, FaultTcti};
+        use tss2_tcti::tpm::tpm::command;
+
+        fn start_auth_session(rm: &mut ResourceManager, client: ClientId) -> u32 {
+            let body = [
+                &TPM_RH_NULL.to_be_bytes()[..],
+                &TPM_RH_NULL.to_be_bytes(),
+                // nonceCaller, encryptedSalt, sessionType, symmetric, authHash
+                &[0, 0, 0, 0, 0x01, 0x00, 0x10, 0x00, 0x0b],
+            ]
+            .concat();
+            let response = rm
+                .execute(client, &command(CommandCode::StartAuthSession, &body))
+                .unwrap();
+            assert_eq!(rc(&response), TPM_RC_SUCCESS);
+            u32_at(&response, TPM_HEADER_SIZE)
+        }
+
+        #[test]
+        fn test_virtualize_transients() {
+            let mut rm = ResourceManager::new(Box::new(FakeTpm::default()));
+            let client = rm.connect();
+
+            // more objects than the TPM has slots
+            let handles: Vec<u32> = (0..5)
+                .map(|_| {
+                    let response = rm
+                        .execute(
+                            client,
+                            &command(CommandCode::CreatePrimary, &TPM_RH_OWNER.to_be_bytes()),
+                        )
+                        .unwrap();
+                    assert_eq!(rc(&response), TPM_RC_SUCCESS);
+                    u32_at(&response, TPM_HEADER_SIZE)
+                })
+                .collect();
+            assert_eq!(
+                handles,
+                (VIRTUAL_TRANSIENT_FIRST..VIRTUAL_TRANSIENT_FIRST + 5).collect::<Vec<u32>>()
+            );
+            assert_eq!(
+                rm.get_client(client)
+                    .unwrap()
+                    .transient_handles()
+                    .collect::<Vec<u32>>(),
+                handles
+            );
+
+            for (id, handle) in (1..).zip(handles.iter().rev()) {
+                let response = rm
+                    .execute(
+                        client,
+                        &command(CommandCode::ReadPublic, &handle.to_be_bytes()),
+                    )
+                    .unwrap();
+                assert_eq!(u32_at(&response, TPM_HEADER_SIZE), 6 - id);
+            }
+        }
+
+        #[test]
+        fn test_flush_and_unknown_handle() {
+            let mut rm = ResourceManager::new(Box::new(FakeTpm::default()));
+            let client = rm.connect();
+
+            let response = rm
+                .execute(
+                    client,
+                    &command(CommandCode::CreatePrimary, &TPM_RH_OWNER.to_be_bytes()),
+                )
+                .unwrap();
+            let handle = u32_at(&response, TPM_HEADER_SIZE);
+
+            let response = rm
+                .execute(
+                    client,
+                    &command(CommandCode::FlushContext, &handle.to_be_bytes()),
+                )
+                .unwrap();
+            assert_eq!(rc(&response), TPM_RC_SUCCESS);
+            assert_eq!(
+                rm.get_client(client).unwrap().transient_handles().count(),
+                0
+            );
+
+            let response = rm
+                .execute(
+                    client,
+                    &command(CommandCode::ReadPublic, &handle.to_be_bytes()),
+                )
+                .unwrap();
+            assert_eq!(rc(&response), TPM_RC_HANDLE | TPM_RC_N(1));
+
+            let response = rm
+                .execute(
+                    client,
+                    &command(CommandCode::FlushContext, &handle.to_be_bytes()),
+                )
+                .unwrap();
+            assert_eq!(rc(&response), TPM_RC_HANDLE | TPM_RC_P | TPM_RC_N(1));
+        }
+
+        #[test]
+        fn test_session_regap() {
+            let mut rm = ResourceManager::new(Box::new(FakeTpm::default()));
+            let client_a = rm.connect();
+            let client_b = rm.connect();
+
+            let session_a = start_auth_session(&mut rm, client_a);
+            let session_b = start_auth_session(&mut rm, client_b);
+
+            // every use of session_b saves it again, i.e. session_a ages
+            for _ in 0..(2 * MAX_GAP) {
+                let response = rm
+                    .execute(
+                        client_b,
+                        &command(CommandCode::PolicyGetDigest, &session_b.to_be_bytes()),
+                    )
+                    .unwrap();
+                assert_eq!(rc(&response), TPM_RC_SUCCESS);
+            }
+
+            let response = rm
+                .execute(
+                    client_a,
+                    &command(CommandCode::PolicyGetDigest, &session_a.to_be_bytes()),
+                )
+                .unwrap();
+            assert_eq!(rc(&response), TPM_RC_SUCCESS);
+            assert_eq!(
+                rm.session_handles(client_a).collect::<Vec<u32>>(),
+                [session_a]
+            );
+        }
+
+        #[test]
+        fn test_session_ownership() {
+            let mut rm = ResourceManager::new(Box::new(FakeTpm::default()));
+            let client_a = rm.connect();
+            let client_b = rm.connect();
+
+            let session = start_auth_session(&mut rm, client_a);
+
+            let response = rm
+                .execute(
+                    client_b,
+                    &command(CommandCode::PolicyGetDigest, &session.to_be_bytes()),
+                )
+                .unwrap();
+            assert_eq!(rc(&response), TPM_RC_HANDLE | TPM_RC_N(1));
+
+            // session in the authorization area
+            let mut read_public = command(
+                CommandCode::ReadPublic,
+                &[
+                    &TPM_RH_OWNER.to_be_bytes()[..],
+                    &9u32.to_be_bytes(),
+                    &session.to_be_bytes(),
+                    &[0, 0, 0x01, 0, 0],
+                ]
+                .concat(),
+            );
+            read_public[0..2].copy_from_slice(&0x8002u16.to_be_bytes());
+            let response = rm.execute(client_b, &read_public).unwrap();
+            assert_eq!(rc(&response), TPM_RC_HANDLE | TPM_RC_S | TPM_RC_N(1));
+
+            // password sessions are not managed, i.e. the command reaches the TPM
+            read_public[18..22].copy_from_slice(&TPM_RS_PW.to_be_bytes());
+            let response = rm.execute(client_b, &read_public).unwrap();
+            assert_eq!(rc(&response), TPM_RC_HANDLE | TPM_RC_N(1));
+
+            let response = rm
+                .execute(
+                    client_b,
+                    &command(CommandCode::FlushContext, &session.to_be_bytes()),
+                )
+                .unwrap();
+            assert_eq!(rc(&response), TPM_RC_HANDLE | TPM_RC_P | TPM_RC_N(1));
+
+            let response = rm
+                .execute(
+                    client_a,
+                    &command(CommandCode::FlushContext, &session.to_be_bytes()),
+                )
+                .unwrap();
+            assert_eq!(rc(&response), TPM_RC_SUCCESS);
+            assert_eq!(rm.session_handles(client_a).count(), 0);
+
+            let response = rm
+                .execute(
+                    client_a,
+                    &command(CommandCode::PolicyGetDigest, &session.to_be_bytes()),
+                )
+                .unwrap();
+            assert_eq!(rc(&response), TPM_RC_HANDLE | TPM_RC_N(1));
+        }
+
+        #[test]
+        fn test_quotas() {
+            let mut rm = ResourceManager::new(Box::new(FakeTpm::default()));
+            rm.set_quotas(Quotas {
+                max_transients: 1,
+                max_sessions: 1,
+                ..Default::default()
+            });
+            let client = rm.connect();
+
+            let create_primary = command(CommandCode::CreatePrimary, &TPM_RH_OWNER.to_be_bytes());
+            let response = rm.execute(client, &create_primary).unwrap();
+            assert_eq!(rc(&response), TPM_RC_SUCCESS);
+            let response = rm.execute(client, &create_primary).unwrap();
+            assert_eq!(rc(&response), TPM_RC_OBJECT_MEMORY);
+            assert_eq!(
+                rm.get_client(client).unwrap().transient_handles().count(),
+                1
+            );
+
+            start_auth_session(&mut rm, client);
+            let body = [
+                &TPM_RH_NULL.to_be_bytes()[..],
+                &TPM_RH_NULL.to_be_bytes(),
+                &[0, 0, 0, 0, 0x01, 0x00, 0x10, 0x00, 0x0b],
+            ]
+            .concat();
+            let response = rm
+                .execute(client, &command(CommandCode::StartAuthSession, &body))
+                .unwrap();
+            assert_eq!(rc(&response), TPM_RC_SESSION_MEMORY);
+            assert_eq!(rm.session_handles(client).count(), 1);
+
+            // the quota is per client
+            let other_client = rm.connect();
+            let response = rm.execute(other_client, &create_primary).unwrap();
+            assert_eq!(rc(&response), TPM_RC_SUCCESS);
+        }
+
+        #[test]
+        fn test_disconnect() {
+            let mut rm = ResourceManager::new(Box::new(FakeTpm::default()));
+            let client = rm.connect();
+            let other_client = rm.connect();
+
+            rm.execute(
+                client,
+                &command(CommandCode::CreatePrimary, &TPM_RH_OWNER.to_be_bytes()),
+            )
+            .unwrap();
+            let session = start_auth_session(&mut rm, client);
+            rm.transmit(
+                client,
+                &command(CommandCode::PolicyGetDigest, &session.to_be_bytes()),
+            )
+            .unwrap();
+
+            rm.disconnect(client).unwrap();
+            assert!(rm.get_client(client).is_none());
+            assert_eq!(rm.session_handles(client).count(), 0);
+            assert_eq!(
+                rm.execute(client, &command(CommandCode::ReadPublic, &[0; 4])),
+                Err(TctiError::BadValue)
+            );
+            assert_eq!(rm.disconnect(client), Err(TctiError::BadValue));
+
+            let response = rm
+                .execute(
+                    other_client,
+                    &command(CommandCode::CreatePrimary, &TPM_RH_OWNER.to_be_bytes()),
+                )
+                .unwrap();
+            assert_eq!(rc(&response), TPM_RC_SUCCESS);
+        }
+
+        #[test]
+        fn test_get_capability() {
+            let mut rm = ResourceManager::new(Box::new(FakeTpm::default()));
+            rm.set_quotas(Quotas {
+                max_transients: 2,
+                ..Default::default()
+            });
+            let client = rm.connect();
+            let other_client = rm.connect();
+
+            let create_primary = command(CommandCode::CreatePrimary, &TPM_RH_OWNER.to_be_bytes());
+            rm.execute(client, &create_primary).unwrap();
+            rm.execute(other_client, &create_primary).unwrap();
+            rm.execute(other_client, &create_primary).unwrap();
+            start_auth_session(&mut rm, other_client);
+
+            let get_capability = |capability: u32, property: u32| {
+                let body: Vec<u8> = [capability, property, 8]
+                    .iter()
+                    .flat_map(|value| value.to_be_bytes())
+                    .collect();
+                command(CommandCode::GetCapability, &body)
+            };
+
+            // only the handles of the client are reported
+            let response = rm
+                .execute(client, &get_capability(TPM_CAP_HANDLES, 0x80000000))
+                .unwrap();
+            assert_eq!(rc(&response), TPM_RC_SUCCESS);
+            assert_eq!(u32_at(&response, TPM_HEADER_SIZE + 5), 1);
+            assert_eq!(
+                u32_at(&response, TPM_HEADER_SIZE + 9),
+                VIRTUAL_TRANSIENT_FIRST
+            );
+
+            // TPM_PT_HR_TRANSIENT_AVAIL is limited by the quota
+            let response = rm
+                .execute(client, &get_capability(TPM_CAP_TPM_PROPERTIES, 0))
+                .unwrap();
+            assert_eq!(
+                u32_at(&response, TPM_HEADER_SIZE + 9),
+                TPM_PT_HR_TRANSIENT_AVAIL
+            );
+            assert_eq!(u32_at(&response, TPM_HEADER_SIZE + 13), 1);
+            assert_eq!(u32_at(&response, TPM_HEADER_SIZE + 17), TPM_PT_HR_LOADED);
+            assert_eq!(u32_at(&response, TPM_HEADER_SIZE + 21), 0);
+
+            let response = rm
+                .execute(other_client, &get_capability(TPM_CAP_TPM_PROPERTIES, 0))
+                .unwrap();
+            assert_eq!(u32_at(&response, TPM_HEADER_SIZE + 13), 0);
+            assert_eq!(u32_at(&response, TPM_HEADER_SIZE + 21), 1);
+        }
+
+        #[test]
+        fn test_policy() {
+            use crate::policy::policy::TPM_RC_HIERARCHY;
+            use tpm2_types::handles::Handle;
+
+            let mut rm = ResourceManager::new(Box::new(FakeTpm::default()));
+            let mut policy = Policy::restricted();
+            policy.denied_hierarchies.push(Handle::Owner);
+            rm.set_default_policy(policy);
+            let client = rm.connect();
+
+            let create_primary = command(CommandCode::CreatePrimary, &TPM_RH_OWNER.to_be_bytes());
+            let response = rm.execute(client, &create_primary).unwrap();
+            assert_eq!(rc(&response), TPM_RC_HIERARCHY | TPM_RC_N(1));
+            assert_eq!(
+                rm.get_client(client).unwrap().transient_handles().count(),
+                0
+            );
+
+            rm.set_policy(client, Policy::default()).unwrap();
+            let response = rm.execute(client, &create_primary).unwrap();
+            assert_eq!(rc(&response), TPM_RC_SUCCESS);
+        }
+
+        #[test]
+        fn test_ownership() {
+            let mut rm = ResourceManager::new(Box::new(FakeTpm::default()));
+            rm.set_quotas(Quotas {
+                max_persistent: 1,
+                ..Default::default()
+            });
+            let alice = rm.connect();
+            rm.set_owner(alice, "alice".to_string()).unwrap();
+            let bob = rm.connect();
+            rm.set_owner(bob, "bob".to_string()).unwrap();
+
+            let create_primary = command(CommandCode::CreatePrimary, &TPM_RH_OWNER.to_be_bytes());
+            let response = rm.execute(alice, &create_primary).unwrap();
+            let object = u32_at(&response, TPM_HEADER_SIZE);
+            let evict_control = |object: u32, persistent: u32| {
+                let body = [TPM_RH_OWNER, object, persistent];
+                let body: Vec<u8> = body.iter().flat_map(|value| value.to_be_bytes()).collect();
+                command(CommandCode::EvictControl, &body)
+            };
+
+            let response = rm
+                .execute(alice, &evict_control(object, 0x81000001))
+                .unwrap();
+            assert_eq!(rc(&response), TPM_RC_SUCCESS);
+            assert_eq!(rm.ownership().owner(0x81000001), Some("alice"));
+            // quota exceeded
+            let response = rm
+                .execute(alice, &evict_control(object, 0x81000002))
+                .unwrap();
+            assert_eq!(rc(&response), TPM_RC_NV_SPACE);
+            // not owned
+            let evict = evict_control(0x81000001, 0x81000001);
+            let response = rm.execute(bob, &evict).unwrap();
+            assert_eq!(rc(&response), TPM_RC_HANDLE | TPM_RC_N(2));
+            let response = rm.execute(alice, &evict).unwrap();
+            assert_eq!(rc(&response), TPM_RC_SUCCESS);
+            assert_eq!(rm.ownership().owner(0x81000001), None);
+
+            // failing commands do not change ownership
+            let response = rm.execute(bob, &nv_define_space(0x01c00002)).unwrap();
+            assert_eq!(rc(&response), TPM_RC_SUCCESS);
+            let response = rm.execute(alice, &nv_define_space(0x01c00002)).unwrap();
+            assert_eq!(rc(&response), TPM_RC_NV_DEFINED);
+            assert_eq!(rm.ownership().owner(0x01c00002), Some("bob"));
+
+            let nv_undefine_space = command(
+                CommandCode::NVUndefineSpace,
+                &[TPM_RH_OWNER.to_be_bytes(), 0x01c00002u32.to_be_bytes()].concat(),
+            );
+            let response = rm.execute(alice, &nv_undefine_space).unwrap();
+            assert_eq!(rc(&response), TPM_RC_HANDLE | TPM_RC_N(2));
+            let response = rm.execute(bob, &nv_undefine_space).unwrap();
+            assert_eq!(rc(&response), TPM_RC_SUCCESS);
+            assert_eq!(rm.ownership().count("bob", is_nv_index), 0);
+        }
+
+        #[test]
+        fn test_faulty_child() {
+            let config = FaultConfig::parse(
+                "seed=1,try_again=0.1,io_error=0.1,truncate=0.1,oversize=0.1,bad_size=0.1,\
+                 tpm_warning=0.1",
+            )
+            .unwrap();
+            let child = FaultTcti::with_child(FakeTpm::default(), config);
+            let mut rm = ResourceManager::new(Box::new(child));
+            let clients = [rm.connect(), rm.connect()];
+
+            let start_auth_session = [
+                &TPM_RH_NULL.to_be_bytes()[..],
+                &TPM_RH_NULL.to_be_bytes(),
+                &[0, 0, 0, 0, 0x01, 0x00, 0x10, 0x00, 0x0b],
+            ]
+            .concat();
+            let commands = [
+                command(CommandCode::CreatePrimary, &TPM_RH_OWNER.to_be_bytes()),
+                command(CommandCode::StartAuthSession, &start_auth_session),
+                command(
+                    CommandCode::ReadPublic,
+                    &VIRTUAL_TRANSIENT_FIRST.to_be_bytes(),
+                ),
+                command(
+                    CommandCode::FlushContext,
+                    &VIRTUAL_TRANSIENT_FIRST.to_be_bytes(),
+                ),
+                command(
+                    CommandCode::GetCapability,
+                    &[TPM_CAP_HANDLES, 0x80000000, 16]
+                        .iter()
+                        .flat_map(|x| x.to_be_bytes())
+                        .collect::<Vec<u8>>(),
+                ),
+            ];
+
+            // no panics, every command is answered or fails
+            for i in 0..500 {
+                let client = clients[i % clients.len()];
+                if rm.transmit(client, &commands[i % commands.len()]).is_err() {
+                    continue;
+                }
+                while let Err(TctiError::TryAgain) = rm.receive(client, Duration::ZERO) {}
+            }
+            for client in clients {
+                let _ = rm.disconnect(client);
+            }
+        }
+    }
+}
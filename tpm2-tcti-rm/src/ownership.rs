@@ -0,0 +1,273 @@
+/// Ownership of persistent objects and NV indices. Unlike transient objects
+/// and sessions, these outlive the clients of the resource manager and the
+/// resource manager itself, i.e. their owners are identified by a name
+/// (e.g. the user of the client) and are stored on disk.
+pub mod ownership {
+    use std::collections::BTreeMap;
+    use std::fs;
+    use std::io::{self, Write};
+    use std::path::PathBuf;
+
+    use tpm2_types::constants::CommandCode;
+    use tracing::warn;
+
+    use crate::rm::rm::Command;
+
+    /// Identity of a client which persists across connections, e.g. its
+    /// user, see [set_owner()](crate::manager::manager::ResourceManager::set_owner).
+    pub type Owner = String;
+
+    /// Owner of clients for which no owner was set.
+    pub const DEFAULT_OWNER: &str = "default";
+
+    /// Most significant octet of NV index handles.
+    pub const HR_NV_INDEX: u32 = 0x01;
+    /// Most significant octet of persistent object handles.
+    pub const HR_PERSISTENT: u32 = 0x81;
+
+    pub fn is_nv_index(handle: u32) -> bool {
+        handle >> 24 == HR_NV_INDEX
+    }
+
+    pub fn is_persistent(handle: u32) -> bool {
+        handle >> 24 == HR_PERSISTENT
+    }
+
+    /// Insufficient space for NV allocation, i.e. the owner exceeds its
+    /// quota.
+    pub const TPM_RC_NV_SPACE: u32 = 0x14b;
+
+    /// Change of ownership if a command succeeds.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum OwnershipChange {
+        /// `EvictControl` making an object persistent, `NV_DefineSpace`
+        Insert(u32),
+        /// `EvictControl` removing a persistent object, `NV_UndefineSpace`,
+        /// `NV_UndefineSpaceSpecial`. `n` is the number (1-based) of the
+        /// handle in the handle area.
+        Remove { handle: u32, n: u32 },
+    }
+
+    impl OwnershipChange {
+        /// Returns `None` if `cmd` neither creates nor removes persistent
+        /// objects or NV indices.
+        pub fn parse(cmd: &Command, command: &[u8]) -> Option<Self> {
+            let u16_at = |offset: usize| {
+                Some(u16::from_be_bytes(
+                    command.get(offset..offset + 2)?.try_into().unwrap(),
+                ))
+            };
+            let u32_at = |offset: usize| {
+                Some(u32::from_be_bytes(
+                    command.get(offset..offset + 4)?.try_into().unwrap(),
+                ))
+            };
+
+            let change = match CommandCode::try_from(cmd.cc).ok()? {
+                // auth, objectHandle; persistentHandle
+                CommandCode::EvictControl => match cmd.handles.get(1)? {
+                    &handle if is_persistent(handle) => Self::Remove { handle, n: 2 },
+                    _ => Self::Insert(u32_at(cmd.parameters)?),
+                },
+                // authHandle; auth (TPM2B_AUTH), publicInfo (TPM2B_NV_PUBLIC)
+                CommandCode::NVDefineSpace => {
+                    let auth_size = u16_at(cmd.parameters)? as usize;
+                    Self::Insert(u32_at(cmd.parameters + 2 + auth_size + 2)?)
+                }
+                // authHandle, nvIndex
+                CommandCode::NVUndefineSpace => Self::Remove {
+                    handle: *cmd.handles.get(1)?,
+                    n: 2,
+                },
+                // nvIndex, platform
+                CommandCode::NVUndefineSpaceSpecial => Self::Remove {
+                    handle: *cmd.handles.first()?,
+                    n: 1,
+                },
+                _ => return None,
+            };
+            Some(change)
+        }
+    }
+
+    /// Owners of persistent objects and NV indices. Handles without an owner
+    /// were created without the resource manager (e.g. the EK) and are not
+    /// restricted.
+    ///
+    /// If opened from a file, every change is written back immediately. The
+    /// file has one line per handle: `<handle> <owner>`, e.g.
+    /// `81000001 uid:1000`.
+    #[derive(Debug, Default, PartialEq)]
+    pub struct Ownership {
+        owners: BTreeMap<u32, Owner>,
+        path: Option<PathBuf>,
+    }
+
+    impl Ownership {
+        /// Ownership stored in `path`. The file is created on the first
+        /// change if it does not exist.
+        pub fn open(path: impl Into<PathBuf>) -> io::Result<Self> {
+            let path = path.into();
+            let owners = match fs::read_to_string(&path) {
+                Ok(content) => Self::parse(&content)?,
+                Err(error) if error.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+                Err(error) => return Err(error),
+            };
+
+            Ok(Self {
+                owners,
+                path: Some(path),
+            })
+        }
+
+        fn parse(content: &str) -> io::Result<BTreeMap<u32, Owner>> {
+            let invalid = |line: &str| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Bad ownership entry: {line:?}"),
+                )
+            };
+
+            let mut owners = BTreeMap::new();
+            for line in content.lines().filter(|line| !line.trim().is_empty()) {
+                let (handle, owner) = line.split_once(' ').ok_or_else(|| invalid(line))?;
+                let handle = u32::from_str_radix(handle, 16).map_err(|_| invalid(line))?;
+                owners.insert(handle, owner.to_string());
+            }
+            Ok(owners)
+        }
+
+        fn save(&self) -> io::Result<()> {
+            let path = match &self.path {
+                Some(path) => path,
+                None => return Ok(()),
+            };
+
+            // replace atomically, i.e. never leave a truncated file behind
+            let mut temp_path = path.clone().into_os_string();
+            temp_path.push(".tmp");
+            let mut file = fs::File::create(&temp_path)?;
+            for (handle, owner) in self.owners.iter() {
+                writeln!(file, "{handle:08x} {owner}")?;
+            }
+            file.sync_all()?;
+            fs::rename(&temp_path, path)
+        }
+
+        pub fn owner(&self, handle: u32) -> Option<&str> {
+            self.owners.get(&handle).map(String::as_str)
+        }
+
+        /// Number of handles of `owner` for which `filter` returns `true`,
+        /// e.g. [is_persistent()].
+        pub fn count(&self, owner: &str, filter: impl Fn(u32) -> bool) -> usize {
+            self.owners
+                .iter()
+                .filter(|(&handle, handle_owner)| handle_owner.as_str() == owner && filter(handle))
+                .count()
+        }
+
+        /// Record that `owner` created `handle`. Failing to store the change
+        /// is logged only, since the TPM already executed the command.
+        pub fn insert(&mut self, handle: u32, owner: &str) {
+            self.owners.insert(handle, owner.to_string());
+            if let Err(error) = self.save() {
+                warn!("Could not store owner of {handle:08x}: {error}");
+            }
+        }
+
+        /// Record that `handle` was removed, see [insert()](Ownership::insert).
+        pub fn remove(&mut self, handle: u32) {
+            if self.owners.remove(&handle).is_some() {
+                if let Err(error) = self.save() {
+                    warn!("Could not store removal of {handle:08x}: {error}");
+                }
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use tss2_tcti::tpm::tpm::command;
+
+        #[test]
+        fn test_parse() {
+            let parse = |cc: CommandCode, body: &[u32]| {
+                let body: Vec<u8> = body.iter().flat_map(|value| value.to_be_bytes()).collect();
+                let command = command(cc, &body);
+                OwnershipChange::parse(&Command::new(&command).unwrap(), &command)
+            };
+
+            assert_eq!(
+                parse(
+                    CommandCode::EvictControl,
+                    &[0x40000001, 0x80ff0000, 0x81000001]
+                ),
+                Some(OwnershipChange::Insert(0x81000001))
+            );
+            assert_eq!(
+                parse(
+                    CommandCode::EvictControl,
+                    &[0x40000001, 0x81000001, 0x81000001]
+                ),
+                Some(OwnershipChange::Remove {
+                    handle: 0x81000001,
+                    n: 2
+                })
+            );
+            // empty auth, size of publicInfo
+            assert_eq!(
+                parse(
+                    CommandCode::NVDefineSpace,
+                    &[0x40000001, 0x0000_000e, 0x01c00002]
+                ),
+                Some(OwnershipChange::Insert(0x01c00002))
+            );
+            assert_eq!(
+                parse(
+                    CommandCode::NVUndefineSpaceSpecial,
+                    &[0x01c00002, 0x4000000c]
+                ),
+                Some(OwnershipChange::Remove {
+                    handle: 0x01c00002,
+                    n: 1
+                })
+            );
+            assert_eq!(parse(CommandCode::ReadPublic, &[0x81000001]), None);
+            // truncated
+            assert_eq!(
+                parse(CommandCode::EvictControl, &[0x40000001, 0x80ff0000]),
+                None
+            );
+        }
+
+        #[test]
+        fn test_persisted() {
+            let path = std::env::temp_dir().join(format!(
+                "test_ownership_persisted-{}.txt",
+                std::process::id()
+            ));
+            let _ = fs::remove_file(&path);
+
+            let mut ownership = Ownership::open(&path).unwrap();
+            ownership.insert(0x81000001, "uid:1000");
+            ownership.insert(0x01c00002, "uid:1000");
+            ownership.insert(0x81000002, "uid:0");
+            ownership.remove(0x81000002);
+
+            let ownership = Ownership::open(&path).unwrap();
+            assert_eq!(ownership.owner(0x81000001), Some("uid:1000"));
+            assert_eq!(ownership.owner(0x81000002), None);
+            assert_eq!(ownership.count("uid:1000", is_persistent), 1);
+            assert_eq!(ownership.count("uid:1000", is_nv_index), 1);
+
+            fs::write(&path, "81000001\n").unwrap();
+            assert_eq!(
+                Ownership::open(&path).unwrap_err().kind(),
+                io::ErrorKind::InvalidData
+            );
+            fs::remove_file(&path).unwrap();
+        }
+    }
+}
@@ -0,0 +1,190 @@
+pub mod policy {
+    use std::ops::RangeInclusive;
+
+    use tpm2_types::constants::CommandCode;
+    use tpm2_types::handles::Handle;
+    use tracing::warn;
+
+    use crate::manager::manager::TPM_RC_N;
+    use crate::rm::rm::Command;
+
+    pub const TPM_RC_HIERARCHY: u32 = 0x085;
+    pub const TPM_RC_COMMAND_CODE: u32 = 0x143;
+    pub const TPM_RC_NV_AUTHORIZATION: u32 = 0x149;
+
+    /// Commands which affect the whole TPM, i.e. all tenants. Denied by
+    /// [Policy::restricted()].
+    pub const PRIVILEGED_COMMANDS: &[CommandCode] = &[
+        CommandCode::Clear,
+        CommandCode::ClearControl,
+        CommandCode::ChangeEPS,
+        CommandCode::ChangePPS,
+        CommandCode::HierarchyControl,
+        CommandCode::HierarchyChangeAuth,
+        CommandCode::SetPrimaryPolicy,
+        CommandCode::DictionaryAttackParameters,
+        CommandCode::DictionaryAttackLockReset,
+        CommandCode::PCRAllocate,
+        CommandCode::PPCommands,
+        CommandCode::SetAlgorithmSet,
+        CommandCode::ClockRateAdjust,
+        CommandCode::FieldUpgradeStart,
+        CommandCode::FieldUpgradeData,
+    ];
+
+    /// Access control of a client of the resource manager, see
+    /// [ResourceManager::set_policy()](crate::manager::manager::ResourceManager::set_policy).
+    ///
+    /// Commands violating the policy are not sent to the TPM but answered
+    /// with a synthesized response code:
+    ///
+    ///  * `TPM_RC_COMMAND_CODE` if the command is not allowed
+    ///  * `TPM_RC_HIERARCHY + TPM_RC_N(n)` if the `n`th handle is a denied
+    ///    hierarchy
+    ///  * `TPM_RC_NV_AUTHORIZATION` if a handle is a denied NV index
+    ///
+    /// Only the handle area is checked, i.e. hierarchies given as parameter
+    /// (e.g. of `LoadExternal`) are not denied.
+    ///
+    /// The default policy allows everything.
+    #[derive(Debug, Clone, Default, PartialEq)]
+    pub struct Policy {
+        /// Commands the client may issue, all if `None`.
+        pub allowed_commands: Option<Vec<CommandCode>>,
+        /// Commands the client must not issue. Takes precedence over
+        /// `allowed_commands`.
+        pub denied_commands: Vec<CommandCode>,
+        /// Hierarchies (e.g. [Handle::Platform]) the client must not use.
+        pub denied_hierarchies: Vec<Handle>,
+        /// NV indices the client must not use.
+        pub denied_nv_indices: Vec<RangeInclusive<u32>>,
+    }
+
+    impl Policy {
+        /// Policy for unprivileged tenants: denies [PRIVILEGED_COMMANDS] and
+        /// the platform and lockout hierarchies.
+        ///
+        /// # Examples
+        /// ```
+        /// use tpm2_types::handles::Handle;
+        /// use tpm2_tcti_rm::policy::policy::Policy;
+        ///
+        /// let mut policy = Policy::restricted();
+        /// // tenants must not create primary keys in the owner hierarchy either
+        /// policy.denied_hierarchies.push(Handle::Owner);
+        /// // nor touch the platform NV indices
+        /// policy.denied_nv_indices.push(0x01400000..=0x017fffff);
+        /// ```
+        pub fn restricted() -> Self {
+            Self {
+                allowed_commands: None,
+                denied_commands: PRIVILEGED_COMMANDS.to_vec(),
+                denied_hierarchies: vec![Handle::Platform, Handle::Lockout],
+                denied_nv_indices: Vec::new(),
+            }
+        }
+
+        fn is_allowed(&self, cc: u32) -> bool {
+            let contains = |commands: &[CommandCode]| {
+                commands.iter().any(|&command| u32::from(command) == cc)
+            };
+
+            let allowed = match &self.allowed_commands {
+                Some(allowed_commands) => contains(allowed_commands),
+                None => true,
+            };
+            allowed && !contains(&self.denied_commands)
+        }
+
+        /// Returns the response code to answer `cmd` with if it violates
+        /// this policy.
+        pub fn check(&self, cmd: &Command) -> Result<(), u32> {
+            if !self.is_allowed(cmd.cc) {
+                warn!("Command {:#010x} denied by policy", cmd.cc);
+                return Err(TPM_RC_COMMAND_CODE);
+            }
+
+            for (i, &handle) in cmd.handles.iter().enumerate() {
+                if let Ok(hierarchy) = Handle::try_from(handle) {
+                    if self.denied_hierarchies.contains(&hierarchy) {
+                        warn!("Handle {handle:08x} denied by policy");
+                        return Err(TPM_RC_HIERARCHY | TPM_RC_N(i as u32 + 1));
+                    }
+                }
+
+                if self
+                    .denied_nv_indices
+                    .iter()
+                    .any(|indices| indices.contains(&handle))
+                {
+                    warn!("NV index {handle:08x} denied by policy");
+                    return Err(TPM_RC_NV_AUTHORIZATION);
+                }
+            }
+
+            Ok(())
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use tss2_tcti::tpm::tpm::command;
+
+        const TPM_RH_OWNER: u32 = 0x40000001;
+        const TPM_RH_PLATFORM: u32 = 0x4000000c;
+
+        fn check(policy: &Policy, cc: CommandCode, handles: &[u32]) -> Result<(), u32> {
+            let body: Vec<u8> = handles
+                .iter()
+                .flat_map(|handle| handle.to_be_bytes())
+                .collect();
+            policy.check(&Command::new(&command(cc, &body)).unwrap())
+        }
+
+        #[test]
+        fn test_restricted() {
+            let policy = Policy::restricted();
+
+            assert_eq!(
+                check(&policy, CommandCode::Clear, &[TPM_RH_PLATFORM]),
+                Err(TPM_RC_COMMAND_CODE)
+            );
+            assert_eq!(
+                check(&policy, CommandCode::CreatePrimary, &[TPM_RH_PLATFORM]),
+                Err(TPM_RC_HIERARCHY | TPM_RC_N(1))
+            );
+            assert_eq!(
+                check(&policy, CommandCode::CreatePrimary, &[TPM_RH_OWNER]),
+                Ok(())
+            );
+            assert_eq!(
+                check(&Policy::default(), CommandCode::Clear, &[TPM_RH_PLATFORM]),
+                Ok(())
+            );
+        }
+
+        #[test]
+        fn test_allowlist_and_nv() {
+            let policy = Policy {
+                allowed_commands: Some(vec![CommandCode::NVRead, CommandCode::GetRandom]),
+                denied_nv_indices: vec![0x01c00000..=0x01c07fff],
+                ..Default::default()
+            };
+
+            assert_eq!(check(&policy, CommandCode::GetRandom, &[]), Ok(()));
+            assert_eq!(
+                check(&policy, CommandCode::NVWrite, &[0x01000000, 0x01000000]),
+                Err(TPM_RC_COMMAND_CODE)
+            );
+            assert_eq!(
+                check(&policy, CommandCode::NVRead, &[0x01000000, 0x01000000]),
+                Ok(())
+            );
+            assert_eq!(
+                check(&policy, CommandCode::NVRead, &[TPM_RH_OWNER, 0x01c00002]),
+                Err(TPM_RC_NV_AUTHORIZATION)
+            );
+        }
+    }
+}
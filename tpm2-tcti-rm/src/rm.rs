@@ -1,19 +1,68 @@
-// from dataclasses import fields
-// from tpmstream.spec.commands.commands_handles import command_handle_types
-// from tpmstream.spec.commands.responses_handles import response_handle_types
-// print("\n".join(f"0x{int(k):08x}: {len(fields(v))}" for k, v in command_handle_types.items()))
-// print("\n".join(f"0x{int(k):08x}: {len(fields(v))}" for k, v in response_handle_types.items()))
-
 pub mod rm {
-    use std::{collections::HashMap, fmt};
+    use std::collections::BTreeMap;
+    use std::fmt;
+    use std::sync::{PoisonError, RwLock};
 
-    use log::warn;
     use std::io::Read;
+    use tpm2_types::bitfields::CommandCodeAttributes;
+    use tpm2_types::constants::CommandCode;
+    use tracing::warn;
     use tss2_tcti::tcti::error::TctiError;
 
-    use crate::bytes::bytes::Stream;
+    pub const TPM_HEADER_SIZE: usize = 10;
+    pub const TPM_ST_SESSIONS: u16 = 0x8002;
+
+    /// Overwrite the `index`th handle of the handle area of a command or response.
+    pub fn write_handle(buf: &mut [u8], index: usize, handle: u32) {
+        let offset = TPM_HEADER_SIZE + index * 4;
+        buf[offset..offset + 4].copy_from_slice(&handle.to_be_bytes());
+    }
+
+    /// TPMA_CC: commandIndex
+    pub const TPMA_CC_COMMAND_INDEX: u32 = 0x0000ffff;
+    /// TPMA_CC: cHandles
+    pub const TPMA_CC_C_HANDLES: u32 = 0x0e000000;
+    /// TPMA_CC: rHandle
+    pub const TPMA_CC_R_HANDLE: u32 = 0x10000000;
+    /// TPMA_CC: V, also set in the command code of vendor specific commands
+    pub const TPMA_CC_V: u32 = 0x20000000;
+
+    /// Attributes of vendor specific commands by command code.
+    static VENDOR_COMMANDS: RwLock<BTreeMap<u32, CommandCodeAttributes>> =
+        RwLock::new(BTreeMap::new());
+
+    /// Make vendor specific commands known, e.g. from the response to
+    /// `TPM2_GetCapability(TPM_CAP_COMMANDS)`. Attributes of commands defined
+    /// by the TCG are ignored, these are known from [CommandCode].
+    pub fn register_command_attributes(attributes: &[CommandCodeAttributes]) {
+        let mut vendor_commands = VENDOR_COMMANDS
+            .write()
+            .unwrap_or_else(PoisonError::into_inner);
+        for &attributes in attributes.iter().filter(|&a| a & TPMA_CC_V != 0) {
+            let cc = attributes & (TPMA_CC_COMMAND_INDEX | TPMA_CC_V);
+            vendor_commands.insert(cc, attributes);
+        }
+    }
+
+    /// Number of handles of the command and of its response, `None` if `cc`
+    /// is unknown.
+    pub fn handle_counts(cc: u32) -> Option<(usize, usize)> {
+        if let Ok(command_code) = CommandCode::try_from(cc) {
+            return Some((
+                command_code.handle_count(),
+                command_code.response_handle_count(),
+            ));
+        }
 
-    const TPM_HEADER_SIZE: usize = 10;
+        let attributes = *VENDOR_COMMANDS
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(&cc)?;
+        Some((
+            ((attributes & TPMA_CC_C_HANDLES) >> TPMA_CC_C_HANDLES.trailing_zeros()) as usize,
+            (attributes & TPMA_CC_R_HANDLE != 0) as usize,
+        ))
+    }
 
     #[derive(Debug)]
     pub struct Handle {
@@ -23,168 +72,106 @@ pub mod rm {
         pub handles: Vec<u32>,
     }
 
+    fn read_u16_at(buf: &[u8], offset: usize) -> Result<u16, TctiError> {
+        match buf.get(offset..offset + 2) {
+            Some(bytes) => Ok(u16::from_be_bytes(bytes.try_into().unwrap())),
+            None => Err(TctiError::BadValue),
+        }
+    }
+
+    fn read_u32_at(buf: &[u8], offset: usize) -> Result<u32, TctiError> {
+        match buf.get(offset..offset + 4) {
+            Some(bytes) => Ok(u32::from_be_bytes(bytes.try_into().unwrap())),
+            None => Err(TctiError::BadValue),
+        }
+    }
+
+    /// Session handles of the authorization area starting at `offset`.
+    fn parse_sessions(buf: &[u8], offset: usize) -> Result<Vec<u32>, TctiError> {
+        let auth_size = read_u32_at(buf, offset)? as usize;
+        let end = offset + 4 + auth_size;
+        if buf.len() < end {
+            warn!("Authorization area exceeds command: {} bytes", auth_size);
+            return Err(TctiError::BadValue);
+        }
+
+        let mut sessions = Vec::new();
+        let mut offset = offset + 4;
+        while offset < end {
+            // sessionHandle, nonce (TPM2B), sessionAttributes, hmac (TPM2B)
+            sessions.push(read_u32_at(buf, offset)?);
+            offset += 4;
+            offset += 2 + read_u16_at(buf, offset)? as usize;
+            offset += 1;
+            offset += 2 + read_u16_at(buf, offset)? as usize;
+        }
+        if offset != end {
+            warn!("Malformed authorization area");
+            return Err(TctiError::BadValue);
+        }
+
+        Ok(sessions)
+    }
+
     #[derive(Debug)]
     pub struct Command {
         pub tag: u16,
         pub size: u32,
         pub cc: u32,
         pub handles: Vec<u32>,
+        /// Handles of the sessions in the authorization area.
+        pub sessions: Vec<u32>,
+        /// Offset of the parameter area, i.e. after the handle and
+        /// authorization areas.
+        pub parameters: usize,
     }
 
     impl Command {
         pub fn new(buf: &[u8]) -> Result<Command, TctiError> {
-            let mut stream = Stream::new(buf);
-            println!("{:?}", stream.read_u16().unwrap());
-            println!("{:?}", stream.read_u32().unwrap());
-            println!("{:?}", stream.read_u32().unwrap());
-
             if buf.len() < TPM_HEADER_SIZE {
                 return Err(TctiError::GeneralFailure);
             }
 
-            let nr_handles_cmd: HashMap<u32, u8> = [
-                (0x0000011f, 2),
-                (0x00000120, 2),
-                (0x00000121, 1),
-                (0x00000122, 2),
-                (0x00000124, 1),
-                (0x00000125, 1),
-                (0x00000126, 1),
-                (0x00000127, 1),
-                (0x00000128, 1),
-                (0x00000129, 1),
-                (0x0000012a, 1),
-                (0x0000012b, 1),
-                (0x0000012c, 1),
-                (0x0000012d, 1),
-                (0x0000012e, 1),
-                (0x0000012f, 2),
-                (0x00000130, 1),
-                (0x00000131, 1),
-                (0x00000132, 1),
-                (0x00000133, 2),
-                (0x00000134, 2),
-                (0x00000135, 2),
-                (0x00000136, 2),
-                (0x00000137, 2),
-                (0x00000138, 2),
-                (0x00000139, 1),
-                (0x0000013a, 1),
-                (0x0000013b, 1),
-                (0x0000013c, 1),
-                (0x0000013d, 1),
-                (0x0000013e, 1),
-                (0x0000013f, 1),
-                (0x00000140, 1),
-                (0x00000141, 0),
-                (0x00000142, 0),
-                (0x00000143, 0),
-                (0x00000144, 0),
-                (0x00000145, 0),
-                (0x00000146, 0),
-                (0x00000147, 2),
-                (0x00000148, 2),
-                (0x00000149, 3),
-                (0x0000014a, 2),
-                (0x0000014b, 2),
-                (0x0000014c, 2),
-                (0x0000014d, 3),
-                (0x0000014e, 2),
-                (0x0000014f, 2),
-                (0x00000150, 2),
-                (0x00000151, 2),
-                (0x00000152, 2),
-                (0x00000153, 1),
-                (0x00000154, 1),
-                (0x00000155, 1),
-                (0x00000156, 1),
-                (0x00000157, 1),
-                (0x00000158, 1),
-                (0x00000159, 1),
-                (0x0000015b, 1),
-                (0x0000015c, 1),
-                (0x0000015d, 1),
-                (0x0000015e, 1),
-                (0x00000160, 2),
-                (0x00000161, 0),
-                (0x00000162, 1),
-                (0x00000163, 1),
-                (0x00000164, 1),
-                (0x00000165, 1),
-                (0x00000167, 0),
-                (0x00000168, 1),
-                (0x00000169, 1),
-                (0x0000016a, 1),
-                (0x0000016b, 1),
-                (0x0000016c, 1),
-                (0x0000016d, 1),
-                (0x0000016e, 1),
-                (0x0000016f, 1),
-                (0x00000170, 1),
-                (0x00000171, 1),
-                (0x00000172, 1),
-                (0x00000173, 1),
-                (0x00000174, 1),
-                (0x00000176, 2),
-                (0x00000177, 1),
-                (0x00000178, 0),
-                (0x00000179, 0),
-                (0x0000017a, 0),
-                (0x0000017b, 0),
-                (0x0000017c, 0),
-                (0x0000017d, 0),
-                (0x0000017e, 0),
-                (0x0000017f, 1),
-                (0x00000180, 1),
-                (0x00000181, 0),
-                (0x00000182, 1),
-                (0x00000183, 1),
-                (0x00000184, 3),
-                (0x00000185, 2),
-                (0x00000186, 0),
-                (0x00000187, 1),
-                (0x00000188, 1),
-                (0x00000189, 1),
-                (0x0000018a, 0),
-                (0x0000018b, 1),
-                (0x0000018c, 1),
-                (0x0000018d, 1),
-                (0x0000018e, 0),
-                (0x0000018f, 1),
-                (0x00000190, 1),
-                (0x00000191, 1),
-                (0x00000192, 3),
-                (0x00000193, 1),
-                (0x00000194, 1),
-                (0x00000195, 3),
-                (0x00000196, 1),
-                (0x00000197, 2),
-                (0x00000198, 1),
-            ]
-            .iter()
-            .cloned()
-            .collect();
-
             let tag = u16::from_be_bytes(buf[0..2].try_into().unwrap());
             let size = u32::from_be_bytes(buf[2..6].try_into().unwrap());
             let cc = u32::from_be_bytes(buf[6..10].try_into().unwrap());
-            let nr_handles = match nr_handles_cmd.get(&cc) {
-                Some(nr_handles) => *nr_handles,
+            let nr_handles = match handle_counts(cc) {
+                Some((nr_handles, _)) => nr_handles,
                 None => {
                     warn!("Unknown command code {:08x}. Do not process command.", cc);
                     0
                 }
-            } as usize;
+            };
+
+            if buf.len() < TPM_HEADER_SIZE + nr_handles * 4 {
+                warn!(
+                    "Buffer too short for {} handles: {} bytes",
+                    nr_handles,
+                    buf.len()
+                );
+                return Err(TctiError::BadValue);
+            }
 
             let handles = (0..nr_handles)
                 .map(|i| u32::from_be_bytes(buf[(10 + i * 4)..(14 + i * 4)].try_into().unwrap()))
                 .collect::<Vec<_>>();
 
+            let handles_end = TPM_HEADER_SIZE + nr_handles * 4;
+            let (sessions, parameters) = match tag {
+                TPM_ST_SESSIONS => (
+                    parse_sessions(buf, handles_end)?,
+                    handles_end + 4 + read_u32_at(buf, handles_end)? as usize,
+                ),
+                _ => (Vec::new(), handles_end),
+            };
+
             Ok(Command {
                 tag,
                 size,
                 cc,
                 handles,
+                sessions,
+                parameters,
             })
         }
     }
@@ -205,6 +192,15 @@ pub mod rm {
                     .collect::<Vec<String>>()
                     .join(", ")
             )?;
+            write!(
+                f,
+                "    sessions: {}\n",
+                self.sessions
+                    .iter()
+                    .map(|&h| format!("{:08x}", h))
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            )?;
             write!(f, "}}\n")?;
 
             Ok(())
@@ -226,139 +222,27 @@ pub mod rm {
                 return Err(TctiError::GeneralFailure);
             }
 
-            let nr_handles_rsp: HashMap<u32, u8> = [
-                (0x0000011f, 0),
-                (0x00000120, 0),
-                (0x00000121, 0),
-                (0x00000122, 0),
-                (0x00000124, 0),
-                (0x00000125, 0),
-                (0x00000126, 0),
-                (0x00000127, 0),
-                (0x00000128, 0),
-                (0x00000129, 0),
-                (0x0000012a, 0),
-                (0x0000012b, 0),
-                (0x0000012c, 0),
-                (0x0000012d, 0),
-                (0x0000012e, 0),
-                (0x0000012f, 0),
-                (0x00000130, 0),
-                (0x00000131, 1),
-                (0x00000132, 0),
-                (0x00000133, 0),
-                (0x00000134, 0),
-                (0x00000135, 0),
-                (0x00000136, 0),
-                (0x00000137, 0),
-                (0x00000138, 0),
-                (0x00000139, 0),
-                (0x0000013a, 0),
-                (0x0000013b, 0),
-                (0x0000013c, 0),
-                (0x0000013d, 0),
-                (0x0000013e, 0),
-                (0x0000013f, 0),
-                (0x00000140, 0),
-                (0x00000141, 0),
-                (0x00000142, 0),
-                (0x00000143, 0),
-                (0x00000144, 0),
-                (0x00000145, 0),
-                (0x00000146, 0),
-                (0x00000147, 0),
-                (0x00000148, 0),
-                (0x00000149, 0),
-                (0x0000014a, 0),
-                (0x0000014b, 0),
-                (0x0000014c, 0),
-                (0x0000014d, 0),
-                (0x0000014e, 0),
-                (0x0000014f, 0),
-                (0x00000150, 0),
-                (0x00000151, 0),
-                (0x00000152, 0),
-                (0x00000153, 0),
-                (0x00000154, 0),
-                (0x00000155, 0),
-                (0x00000156, 0),
-                (0x00000157, 1),
-                (0x00000158, 0),
-                (0x00000159, 0),
-                (0x0000015b, 1),
-                (0x0000015c, 0),
-                (0x0000015d, 0),
-                (0x0000015e, 0),
-                (0x00000160, 0),
-                (0x00000161, 1),
-                (0x00000162, 0),
-                (0x00000163, 0),
-                (0x00000164, 0),
-                (0x00000165, 0),
-                (0x00000167, 1),
-                (0x00000168, 0),
-                (0x00000169, 0),
-                (0x0000016a, 0),
-                (0x0000016b, 0),
-                (0x0000016c, 0),
-                (0x0000016d, 0),
-                (0x0000016e, 0),
-                (0x0000016f, 0),
-                (0x00000170, 0),
-                (0x00000171, 0),
-                (0x00000172, 0),
-                (0x00000173, 0),
-                (0x00000174, 0),
-                (0x00000176, 1),
-                (0x00000177, 0),
-                (0x00000178, 0),
-                (0x00000179, 0),
-                (0x0000017a, 0),
-                (0x0000017b, 0),
-                (0x0000017c, 0),
-                (0x0000017d, 0),
-                (0x0000017e, 0),
-                (0x0000017f, 0),
-                (0x00000180, 0),
-                (0x00000181, 0),
-                (0x00000182, 0),
-                (0x00000183, 0),
-                (0x00000184, 0),
-                (0x00000185, 0),
-                (0x00000186, 1),
-                (0x00000187, 0),
-                (0x00000188, 0),
-                (0x00000189, 0),
-                (0x0000018a, 0),
-                (0x0000018b, 0),
-                (0x0000018c, 0),
-                (0x0000018d, 0),
-                (0x0000018e, 0),
-                (0x0000018f, 0),
-                (0x00000190, 0),
-                (0x00000191, 1),
-                (0x00000192, 0),
-                (0x00000193, 0),
-                (0x00000194, 0),
-                (0x00000195, 0),
-                (0x00000196, 0),
-                (0x00000197, 0),
-                (0x00000198, 0),
-            ]
-            .iter()
-            .cloned()
-            .collect();
-
             let tag = u16::from_be_bytes(buf[0..2].try_into().unwrap());
             let size = u32::from_be_bytes(buf[2..6].try_into().unwrap());
             let rc = u32::from_be_bytes(buf[6..10].try_into().unwrap());
-            let nr_handles = match nr_handles_rsp.get(&cc) {
-                Some(nr_handles) => *nr_handles,
+            let nr_handles = match handle_counts(cc) {
+                // error responses do not contain handles
+                Some(_) if rc != 0 => 0,
+                Some((_, nr_handles)) => nr_handles,
                 None => {
                     warn!("Unknown command code {:08x}. Do not process command.", cc);
                     0
                 }
-            } as usize;
+            };
+
+            if buf.len() < TPM_HEADER_SIZE + nr_handles * 4 {
+                warn!(
+                    "Buffer too short for {} handles: {} bytes",
+                    nr_handles,
+                    buf.len()
+                );
+                return Err(TctiError::BadValue);
+            }
 
             let handles = (0..nr_handles)
                 .map(|i| u32::from_be_bytes(buf[(10 + i * 4)..(14 + i * 4)].try_into().unwrap()))
@@ -395,4 +279,31 @@ pub mod rm {
             Ok(())
         }
     }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use tss2_tcti::tpm::tpm::command;
+
+        #[test]
+        fn test_handle_counts() {
+            let cmd = Command::new(&command(CommandCode::PolicyNV, &[0; 12])).unwrap();
+            assert_eq!(cmd.handles.len(), 3);
+
+            let response = [0x80, 0x01, 0, 0, 0, 14, 0, 0, 0, 0, 0x80, 0, 0, 0];
+            let rsp = Response::new(&response, CommandCode::Load as u32).unwrap();
+            assert_eq!(rsp.handles, vec![0x80000000]);
+
+            // vendor specific command with two handles and a response handle
+            let cc = TPMA_CC_V | 0x0123;
+            let mut vendor = command(CommandCode::Startup, &[0; 8]);
+            vendor[6..10].copy_from_slice(&cc.to_be_bytes());
+            assert_eq!(handle_counts(cc), None);
+            assert_eq!(Command::new(&vendor).unwrap().handles.len(), 0);
+
+            register_command_attributes(&[0x00000131, cc | (2 << 25) | TPMA_CC_R_HANDLE]);
+            assert_eq!(handle_counts(cc), Some((2, 1)));
+            assert_eq!(Command::new(&vendor).unwrap().handles.len(), 2);
+        }
+    }
 }
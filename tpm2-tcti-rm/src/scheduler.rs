@@ -0,0 +1,250 @@
+/// Scheduling of the commands of many clients of the resource manager, see
+/// [Scheduler].
+pub mod scheduler {
+    use std::collections::{BTreeMap, VecDeque};
+    use std::time::{Duration, Instant};
+
+    use tpm2_types::constants::CommandCode;
+    use tracing::warn;
+    use tss2_tcti::tcti::error::TctiError;
+    use tss2_tcti::tcti::tcti::TIMEOUT_BLOCK;
+
+    use crate::manager::manager::{ClientId, ResourceManager};
+
+    /// Interval in which a command being executed is checked for
+    /// cancellation.
+    pub const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+    /// Commands which take [Timeouts::long], e.g. because of RSA key
+    /// generation.
+    pub const LONG_COMMANDS: &[CommandCode] = &[
+        CommandCode::CreatePrimary,
+        CommandCode::Create,
+        CommandCode::CreateLoaded,
+        CommandCode::SelfTest,
+    ];
+
+    /// Priority class of a client. Commands of a higher class are always
+    /// executed first, commands of the same class in the order they arrive.
+    #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+    pub enum Priority {
+        High,
+        #[default]
+        Normal,
+        Low,
+    }
+
+    /// Time after which a command is cancelled.
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct Timeouts {
+        pub default: Duration,
+        /// timeout of [LONG_COMMANDS]
+        pub long: Duration,
+    }
+
+    impl Default for Timeouts {
+        fn default() -> Self {
+            Self {
+                default: Duration::from_secs(20),
+                long: Duration::from_secs(300),
+            }
+        }
+    }
+
+    impl Timeouts {
+        pub fn timeout(&self, cc: u32) -> Duration {
+            if LONG_COMMANDS.iter().any(|&command| u32::from(command) == cc) {
+                self.long
+            } else {
+                self.default
+            }
+        }
+    }
+
+    /// Command of a client waiting to be executed. `reply` is whatever is
+    /// needed to deliver the response.
+    #[derive(Debug)]
+    pub struct Job<T> {
+        pub client: ClientId,
+        pub command: Vec<u8>,
+        pub reply: T,
+    }
+
+    /// Queues of the commands of all clients, one per [Priority].
+    #[derive(Debug)]
+    pub struct Scheduler<T> {
+        queues: [VecDeque<Job<T>>; 3],
+        priorities: BTreeMap<ClientId, Priority>,
+        timeouts: Timeouts,
+    }
+
+    impl<T> Scheduler<T> {
+        pub fn new(timeouts: Timeouts) -> Self {
+            Self {
+                queues: Default::default(),
+                priorities: BTreeMap::new(),
+                timeouts,
+            }
+        }
+
+        /// Set the priority class of `client`, [Priority::Normal] by
+        /// default. Affects commands queued afterwards.
+        pub fn set_priority(&mut self, client: ClientId, priority: Priority) {
+            self.priorities.insert(client, priority);
+        }
+
+        pub fn priority(&self, client: ClientId) -> Priority {
+            self.priorities.get(&client).copied().unwrap_or_default()
+        }
+
+        pub fn push(&mut self, job: Job<T>) {
+            let priority = self.priority(job.client);
+            self.queues[priority as usize].push_back(job);
+        }
+
+        /// Next command to execute.
+        pub fn pop(&mut self) -> Option<Job<T>> {
+            self.queues.iter_mut().find_map(|queue| queue.pop_front())
+        }
+
+        pub fn is_empty(&self) -> bool {
+            self.queues.iter().all(|queue| queue.is_empty())
+        }
+
+        /// Forget `client`, returning its queued commands.
+        pub fn remove_client(&mut self, client: ClientId) -> Vec<Job<T>> {
+            self.priorities.remove(&client);
+
+            let mut removed = Vec::new();
+            for queue in self.queues.iter_mut() {
+                let (jobs, others): (VecDeque<_>, VecDeque<_>) =
+                    queue.drain(..).partition(|job| job.client == client);
+                *queue = others;
+                removed.extend(jobs);
+            }
+            removed
+        }
+
+        /// Timeout of `command`, see [Timeouts].
+        pub fn timeout(&self, command: &[u8]) -> Duration {
+            match command.get(6..10) {
+                Some(cc) => self
+                    .timeouts
+                    .timeout(u32::from_be_bytes(cc.try_into().unwrap())),
+                None => self.timeouts.default,
+            }
+        }
+    }
+
+    /// Execute `command` of `client`. The command is cancelled if it takes
+    /// longer than `timeout` or if `cancelled()`, which is polled every
+    /// [POLL_INTERVAL], returns `true`.
+    ///
+    /// A cancelled command still returns the response of the TPM, usually
+    /// `TPM_RC_CANCELED`. If the child tcti cannot cancel commands, the
+    /// command is completed regularly.
+    pub fn execute(
+        rm: &mut ResourceManager,
+        client: ClientId,
+        command: &[u8],
+        timeout: Duration,
+        mut cancelled: impl FnMut() -> bool,
+    ) -> Result<Vec<u8>, TctiError> {
+        rm.transmit(client, command)?;
+        // no deadline for TIMEOUT_BLOCK
+        let deadline = Instant::now().checked_add(timeout);
+
+        loop {
+            match rm.receive(client, POLL_INTERVAL) {
+                Err(TctiError::TryAgain) => (),
+                result => return result,
+            }
+
+            let expired = deadline.is_some_and(|deadline| Instant::now() >= deadline);
+            if expired || cancelled() {
+                if expired {
+                    warn!("Command of client {client} exceeded its timeout of {timeout:?}");
+                }
+                if let Err(error) = rm.cancel(client) {
+                    warn!("Could not cancel command of client {client}: {error}");
+                }
+                return rm.receive(client, TIMEOUT_BLOCK);
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::fake_tpm::fake_tpm::*;
+        use tss2_tcti::tpm::tpm::{command, TPM_RC_SUCCESS};
+
+        #[test]
+        fn test_priorities() {
+            let mut scheduler = Scheduler::new(Timeouts::default());
+            scheduler.set_priority(1, Priority::Low);
+            scheduler.set_priority(2, Priority::High);
+            for (client, reply) in [(0, "a"), (1, "b"), (2, "c"), (0, "d"), (2, "e")] {
+                scheduler.push(Job {
+                    client,
+                    command: Vec::new(),
+                    reply,
+                });
+            }
+
+            let removed = scheduler.remove_client(0);
+            assert_eq!(
+                removed.iter().map(|job| job.reply).collect::<Vec<_>>(),
+                vec!["a", "d"]
+            );
+            assert_eq!(scheduler.priority(0), Priority::Normal);
+
+            let mut order = Vec::new();
+            while let Some(job) = scheduler.pop() {
+                order.push(job.reply);
+            }
+            assert_eq!(order, vec!["c", "e", "b"]);
+            assert!(scheduler.is_empty());
+        }
+
+        #[test]
+        fn test_timeouts() {
+            let timeouts = Timeouts::default();
+            assert_eq!(timeouts.timeout(u32::from(CommandCode::CreatePrimary)), timeouts.long);
+            assert_eq!(timeouts.timeout(u32::from(CommandCode::ReadPublic)), timeouts.default);
+
+            // the fake TPM does not complete self tests until cancelled
+            let scheduler = Scheduler::<()>::new(Timeouts {
+                default: Duration::from_secs(1),
+                long: Duration::from_millis(50),
+            });
+            let self_test = command(CommandCode::SelfTest, &[1]);
+            let timeout = scheduler.timeout(&self_test);
+            assert_eq!(timeout, Duration::from_millis(50));
+
+            let mut rm = ResourceManager::new(Box::new(FakeTpm::default()));
+            let client = rm.connect();
+            let response = execute(&mut rm, client, &self_test, timeout, || false).unwrap();
+            assert_eq!(rc(&response), TPM_RC_CANCELED);
+
+            // cancelled by the caller
+            let mut polled = 0;
+            let response = execute(&mut rm, client, &self_test, TIMEOUT_BLOCK, || {
+                polled += 1;
+                polled == 3
+            })
+            .unwrap();
+            assert_eq!(rc(&response), TPM_RC_CANCELED);
+
+            let response = execute(
+                &mut rm,
+                client,
+                &command(CommandCode::CreatePrimary, &TPM_RH_OWNER.to_be_bytes()),
+                timeout,
+                || true,
+            )
+            .unwrap();
+            assert_eq!(rc(&response), TPM_RC_SUCCESS);
+        }
+    }
+}
@@ -0,0 +1,320 @@
+/// Tcti connecting to the resource manager daemon of `tpm2-tcti-rm`, i.e.
+/// `tpm2-rmd`.
+pub mod lib {
+    use std::io::{self, Read, Write};
+    use std::os::fd::AsRawFd;
+    use std::os::unix::net::UnixStream;
+    use std::time::{Duration, Instant};
+
+    use tracing::warn;
+    use tss2_tcti::config::config::{reject_conf, TctiConfig};
+    use tss2_tcti::define_api_symbols;
+    use tss2_tcti::tcti::error::TctiError;
+    use tss2_tcti::tcti::tcti::{Api, Info, PendingResponse, State, TctiLib, TIMEOUT_BLOCK};
+    use tss2_tcti::telemetry::telemetry::CommandSpan;
+    use tss2_tcti::tpm::tpm::TPM_HEADER_SIZE;
+    use tss2_tcti_sys::tpm2_tss;
+
+    /// Default socket of the resource manager daemon.
+    pub const DEFAULT_SOCKET: &str = "/run/tpm2-rmd.sock";
+
+    /// Data to read from a file descriptor.
+    const POLLIN: i16 = 0x001;
+
+    #[derive(TctiConfig, Debug, PartialEq)]
+    pub struct ClientConfig {
+        /// Path of the socket of the resource manager daemon.
+        #[tcti_config(positional)]
+        pub path: String,
+    }
+
+    impl Default for ClientConfig {
+        fn default() -> Self {
+            Self {
+                path: String::from(DEFAULT_SOCKET),
+            }
+        }
+    }
+
+    fn io_error(error: io::Error) -> TctiError {
+        match error.kind() {
+            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => TctiError::TryAgain,
+            _ => {
+                warn!("Connection to resource manager failed: {error}");
+                TctiError::IoError
+            }
+        }
+    }
+
+    /// Client of the resource manager daemon. The daemon frames commands
+    /// and responses by the size field of the TPM header only.
+    #[repr(C)]
+    #[derive(Debug)]
+    pub struct TctiRmClient {
+        api: Api,
+        state: State,
+        pending_response: PendingResponse,
+        command_span: CommandSpan,
+        stream: Option<UnixStream>,
+        /// response received so far, if the last receive timed out
+        partial: Option<Vec<u8>>,
+    }
+
+    impl TctiRmClient {
+        fn get_stream(&mut self) -> Result<&mut UnixStream, TctiError> {
+            self.stream.as_mut().ok_or(TctiError::BadSequence)
+        }
+
+        /// Read into `buf` until it is full, keeping what was read so far if
+        /// the timeout expires. The read timeout is shrunk to what is left
+        /// until `deadline` before each read, if there is a deadline.
+        fn read_until_full(
+            stream: &mut UnixStream,
+            buf: &mut Vec<u8>,
+            size: usize,
+            deadline: Option<Instant>,
+        ) -> Result<(), TctiError> {
+            let mut chunk = [0u8; 1024];
+            while buf.len() < size {
+                if let Some(deadline) = deadline {
+                    let remaining = deadline.saturating_duration_since(Instant::now());
+                    if remaining.is_zero() {
+                        return Err(TctiError::TryAgain);
+                    }
+                    stream.set_read_timeout(Some(remaining)).map_err(io_error)?;
+                }
+
+                let max = (size - buf.len()).min(chunk.len());
+                match stream.read(&mut chunk[..max]) {
+                    Ok(0) => {
+                        warn!("Resource manager closed the connection");
+                        return Err(TctiError::IoError);
+                    }
+                    Ok(n) => buf.extend_from_slice(&chunk[..n]),
+                    Err(error) if error.kind() == io::ErrorKind::Interrupted => (),
+                    Err(error) => return Err(io_error(error)),
+                }
+            }
+            Ok(())
+        }
+    }
+
+    impl TctiLib for TctiRmClient {
+        const INFO: Info<'static> = Info {
+            name: b"tpm2_tcti-rmd\0",
+            description: b"Client of the Rust resource manager daemon.\0",
+            config_help: <ClientConfig as TctiConfig>::CONFIG_HELP,
+        };
+        const MAGIC: u64 = 0x9b3c1f0e6d2a4785;
+
+        fn new(conf: &str) -> Result<Self, TctiError> {
+            let mut tcti = Self {
+                api: Self::get_api_static(),
+                state: State::NotInitialized,
+                pending_response: None,
+                command_span: CommandSpan::default(),
+                stream: None,
+                partial: None,
+            };
+
+            tcti.init(conf)?;
+
+            Ok(tcti)
+        }
+
+        fn init_inner(&mut self, conf: &str) -> Result<(), TctiError> {
+            let config = ClientConfig::from_conf(conf).map_err(reject_conf)?;
+
+            self.api = Self::get_api_static();
+            self.stream = Some(UnixStream::connect(&config.path).map_err(|error| {
+                warn!("Could not connect to {:?}: {error}", config.path);
+                TctiError::IoError
+            })?);
+            self.state = State::Transmit;
+            Ok(())
+        }
+
+        fn transmit_inner(&mut self, command: &[u8]) -> Result<(), TctiError> {
+            self.partial = None;
+            self.get_stream()?.write_all(command).map_err(io_error)
+        }
+
+        fn receive_inner(&mut self, timeout: Duration) -> Result<Vec<u8>, TctiError> {
+            let mut response = self.partial.take().unwrap_or_default();
+            let stream = self.get_stream()?;
+
+            // one deadline for all reads, not a timeout per read
+            let deadline = match timeout {
+                TIMEOUT_BLOCK => {
+                    stream.set_read_timeout(None).map_err(io_error)?;
+                    None
+                }
+                Duration::ZERO => {
+                    stream.set_nonblocking(true).map_err(io_error)?;
+                    None
+                }
+                timeout => Instant::now().checked_add(timeout),
+            };
+
+            let mut result =
+                Self::read_until_full(stream, &mut response, TPM_HEADER_SIZE, deadline);
+            if result.is_ok() {
+                let size = u32::from_be_bytes(response[2..6].try_into().unwrap()) as usize;
+                result = Self::read_until_full(stream, &mut response, size, deadline);
+            }
+
+            stream.set_nonblocking(false).map_err(io_error)?;
+            match result {
+                Ok(()) => Ok(response),
+                Err(TctiError::TryAgain) => {
+                    self.partial = Some(response);
+                    Err(TctiError::TryAgain)
+                }
+                Err(error) => Err(error),
+            }
+        }
+
+        fn finalize_inner(&mut self) {
+            self.stream = None;
+        }
+
+        fn get_poll_handles_inner(
+            &mut self,
+        ) -> Result<Vec<tpm2_tss::TSS2_TCTI_POLL_HANDLE>, TctiError> {
+            Ok(vec![tpm2_tss::TSS2_TCTI_POLL_HANDLE {
+                fd: self.get_stream()?.as_raw_fd(),
+                events: POLLIN,
+                revents: 0,
+            }])
+        }
+
+        fn get_state(&self) -> Option<State> {
+            Some(self.state)
+        }
+        fn set_state(&mut self, state: State) {
+            self.state = state;
+        }
+        fn get_pending_response(&mut self) -> Option<&mut PendingResponse> {
+            Some(&mut self.pending_response)
+        }
+
+        fn get_command_span(&mut self) -> Option<&mut CommandSpan> {
+            Some(&mut self.command_span)
+        }
+    }
+
+    define_api_symbols!(TctiRmClient);
+
+    #[cfg(test)]
+    mod tests {
+        use std::os::unix::net::UnixListener;
+        use std::path::{Path, PathBuf};
+        use std::thread;
+
+        use super::*;
+        use tss2_tcti::conformance::conformance::check_tcti;
+        use tss2_tcti::tcti::tcti::Tcti;
+
+        const STARTUP: &[u8] = b"\x80\x01\x00\x00\x00\x0c\x00\x00\x01\x44\x00\x00";
+
+        /// Echoes every command of every client, sending the response in
+        /// parts of `part` bytes with `delay` in between.
+        fn start_daemon(name: &str, part: usize, delay: Duration) -> PathBuf {
+            let path = std::env::temp_dir().join(format!("{name}-{}.sock", std::process::id()));
+            let _ = std::fs::remove_file(&path);
+            let listener = UnixListener::bind(&path).unwrap();
+
+            thread::spawn(move || {
+                for stream in listener.incoming() {
+                    let mut stream = stream.unwrap();
+                    thread::spawn(move || loop {
+                        let mut command = vec![0u8; TPM_HEADER_SIZE];
+                        if stream.read_exact(&mut command).is_err() {
+                            return;
+                        }
+                        let size = u32::from_be_bytes(command[2..6].try_into().unwrap());
+                        command.resize(size as usize, 0);
+                        stream.read_exact(&mut command[TPM_HEADER_SIZE..]).unwrap();
+
+                        for (i, part) in command.chunks(part).enumerate() {
+                            if i > 0 {
+                                thread::sleep(delay);
+                            }
+                            stream.write_all(part).unwrap();
+                        }
+                    });
+                }
+            });
+
+            path
+        }
+
+        fn connect(path: &Path) -> TctiRmClient {
+            <TctiRmClient as Tcti>::new(path.to_str().unwrap()).unwrap()
+        }
+
+        #[test]
+        fn test_execute() {
+            let path = start_daemon("test_execute", 4, Duration::ZERO);
+            let mut client = connect(&path);
+
+            Tcti::transmit(&mut client, STARTUP).unwrap();
+            assert_eq!(Tcti::receive(&mut client).unwrap(), STARTUP);
+            Tcti::transmit(&mut client, STARTUP).unwrap();
+            assert_eq!(Tcti::receive(&mut client).unwrap(), STARTUP);
+        }
+
+        #[test]
+        fn test_timeout() {
+            let path = start_daemon("test_timeout", 8, Duration::from_millis(200));
+            let mut client = connect(&path);
+
+            Tcti::transmit(&mut client, STARTUP).unwrap();
+            assert_eq!(
+                Tcti::receive_with_timeout(&mut client, Duration::from_millis(20)),
+                Err(TctiError::TryAgain)
+            );
+            // the part received so far is kept
+            assert_eq!(
+                Tcti::receive_with_timeout(&mut client, Duration::from_secs(5)).unwrap(),
+                STARTUP
+            );
+
+            // the timeout is for the whole response, not for every part
+            let path = start_daemon("test_timeout_parts", 1, Duration::from_millis(20));
+            let mut client = connect(&path);
+
+            Tcti::transmit(&mut client, STARTUP).unwrap();
+            assert_eq!(
+                Tcti::receive_with_timeout(&mut client, Duration::from_millis(100)),
+                Err(TctiError::TryAgain)
+            );
+            assert_eq!(
+                Tcti::receive_with_timeout(&mut client, Duration::from_secs(5)).unwrap(),
+                STARTUP
+            );
+        }
+
+        #[test]
+        fn test_config() {
+            assert_eq!(
+                ClientConfig::from_conf("").unwrap().path,
+                DEFAULT_SOCKET.to_string()
+            );
+            assert_eq!(
+                ClientConfig::from_conf("/tmp/rmd.sock").unwrap().path,
+                "/tmp/rmd.sock".to_string()
+            );
+            assert!(matches!(
+                <TctiRmClient as Tcti>::new("/does/not/exist.sock"),
+                Err(TctiError::IoError)
+            ));
+        }
+
+        #[test]
+        fn test_conformance() {
+            let path = start_daemon("test_conformance", 4, Duration::ZERO);
+            check_tcti::<TctiRmClient>(path.to_str().unwrap(), STARTUP);
+        }
+    }
+}
@@ -0,0 +1,293 @@
+/// `TPM2_GetCapability` for the capabilities describing the simulator.
+pub mod capability {
+    use tpm2_types::alg::{Alg, EccCurve};
+    use tpm2_types::bitfields::AlgorithAttributes;
+    use tpm2_types::commands::{GetCapability, GetCapabilityResponse};
+    use tpm2_types::constants::{Capability, CommandCode, PropertyTag, ReturnCode};
+    use tpm2_types::handles::Handle;
+    use tpm2_types::selectables::Capabilities;
+    use tpm2_types::structs::{AlgorithmProperty, TaggedProperty};
+
+    use crate::keys::keys::MAX_DIGEST_BUFFER;
+    use crate::nv::nv::{MAX_NV_BUFFER_SIZE, MAX_NV_INDEX_SIZE, MAX_NV_INDICES};
+    use crate::pcr::pcr::{PCR_COUNT, PCR_SELECT_SIZE};
+    use crate::rc::rc::*;
+    use crate::session::session::SessionSlot;
+    use crate::simulator::simulator::{
+        handle_type, parse, Reply, Simulator, COMMANDS, FIRMWARE_VERSION, MANUFACTURER,
+        MAX_ACTIVE_SESSIONS, MAX_LOADED_OBJECTS, MAX_LOADED_SESSIONS, PERMANENT_HANDLES,
+        TPM_HT_HMAC_SESSION, TPM_HT_NV_INDEX, TPM_HT_PCR, TPM_HT_PERMANENT, TPM_HT_TRANSIENT,
+    };
+
+    pub const TPMA_ALGORITHM_ASYMMETRIC: AlgorithAttributes = 1 << 0;
+    pub const TPMA_ALGORITHM_SYMMETRIC: AlgorithAttributes = 1 << 1;
+    pub const TPMA_ALGORITHM_HASH: AlgorithAttributes = 1 << 2;
+    pub const TPMA_ALGORITHM_OBJECT: AlgorithAttributes = 1 << 3;
+    pub const TPMA_ALGORITHM_SIGNING: AlgorithAttributes = 1 << 8;
+    pub const TPMA_ALGORITHM_ENCRYPTING: AlgorithAttributes = 1 << 9;
+
+    pub const TPMA_CC_FLUSHED: u32 = 1 << 24;
+    pub const TPMA_CC_C_HANDLES_SHIFT: u32 = 25;
+    pub const TPMA_CC_R_HANDLE: u32 = 1 << 28;
+
+    /// Maximum size of the command and response buffers.
+    pub const MAX_COMMAND_SIZE: u32 = 4096;
+    /// Maximum number of entries returned by a single `TPM2_GetCapability`.
+    pub const MAX_CAP_ENTRIES: usize = 64;
+
+    /// Algorithms implemented (or accepted in templates) by the simulator.
+    pub const ALGORITHMS: [(Alg, AlgorithAttributes); 14] = [
+        (Alg::RSA, TPMA_ALGORITHM_ASYMMETRIC | TPMA_ALGORITHM_OBJECT),
+        (Alg::SHA1, TPMA_ALGORITHM_HASH),
+        (Alg::HMAC, TPMA_ALGORITHM_HASH | TPMA_ALGORITHM_SIGNING),
+        (Alg::AES, TPMA_ALGORITHM_SYMMETRIC),
+        (Alg::KeyedHash, TPMA_ALGORITHM_HASH | TPMA_ALGORITHM_OBJECT),
+        (Alg::SHA256, TPMA_ALGORITHM_HASH),
+        (Alg::SHA384, TPMA_ALGORITHM_HASH),
+        (Alg::SHA512, TPMA_ALGORITHM_HASH),
+        (Alg::Null, 0),
+        (
+            Alg::RSASSA,
+            TPMA_ALGORITHM_ASYMMETRIC | TPMA_ALGORITHM_SIGNING,
+        ),
+        (
+            Alg::RSAPSS,
+            TPMA_ALGORITHM_ASYMMETRIC | TPMA_ALGORITHM_SIGNING,
+        ),
+        (
+            Alg::ECDSA,
+            TPMA_ALGORITHM_ASYMMETRIC | TPMA_ALGORITHM_SIGNING,
+        ),
+        (Alg::ECC, TPMA_ALGORITHM_ASYMMETRIC | TPMA_ALGORITHM_OBJECT),
+        (
+            Alg::CFB,
+            TPMA_ALGORITHM_SYMMETRIC | TPMA_ALGORITHM_ENCRYPTING,
+        ),
+    ];
+
+    /// At most `count` of the `entries` (sorted and starting at the
+    /// requested property) and whether there are more.
+    fn page<T>(mut entries: Vec<T>, count: u32) -> (bool, Vec<T>) {
+        let count = (count as usize).min(MAX_CAP_ENTRIES);
+        let more_data = entries.len() > count;
+        entries.truncate(count);
+        (more_data, entries)
+    }
+
+    impl Simulator {
+        fn tpm_properties(&self) -> Vec<(PropertyTag, u32)> {
+            let loaded_sessions = self
+                .sessions
+                .values()
+                .filter(|slot| matches!(slot, SessionSlot::Loaded(_)))
+                .count() as u32;
+            let active_sessions = self.sessions.len() as u32;
+            let loaded_objects = self.objects.len() as u32;
+
+            vec![
+                (PropertyTag::FamilyIndicator, u32::from_be_bytes(*b"2.0\0")),
+                (PropertyTag::Level, 0),
+                (PropertyTag::Revision, 159),
+                (PropertyTag::Manufacturer, u32::from_be_bytes(MANUFACTURER)),
+                (PropertyTag::VendorString1, u32::from_be_bytes(*b"sim\0")),
+                (
+                    PropertyTag::FirmwareVersion1,
+                    (FIRMWARE_VERSION >> 32) as u32,
+                ),
+                (PropertyTag::FirmwareVersion2, FIRMWARE_VERSION as u32),
+                (PropertyTag::InputBuffer, MAX_DIGEST_BUFFER as u32),
+                (PropertyTag::HRTransientMin, MAX_LOADED_OBJECTS as u32),
+                (PropertyTag::HRLoadedMin, MAX_LOADED_SESSIONS as u32),
+                (PropertyTag::ActiveSessionsMax, MAX_ACTIVE_SESSIONS as u32),
+                (PropertyTag::PCRCount, PCR_COUNT as u32),
+                (PropertyTag::PCRSelectMin, PCR_SELECT_SIZE as u32),
+                (PropertyTag::NvIndexMax, MAX_NV_INDEX_SIZE as u32),
+                (PropertyTag::MaxCommandSize, MAX_COMMAND_SIZE),
+                (PropertyTag::MaxResponseSize, MAX_COMMAND_SIZE),
+                (PropertyTag::MaxDigest, 64),
+                (PropertyTag::TotalCommands, COMMANDS.len() as u32),
+                (PropertyTag::LibraryCommands, COMMANDS.len() as u32),
+                (PropertyTag::VendorCommands, 0),
+                (PropertyTag::NvBufferMax, MAX_NV_BUFFER_SIZE as u32),
+                (PropertyTag::HRNvIndex, self.nv.len() as u32),
+                (PropertyTag::HRLoaded, loaded_sessions),
+                (
+                    PropertyTag::HRLoadedAvail,
+                    MAX_LOADED_SESSIONS as u32 - loaded_sessions,
+                ),
+                (PropertyTag::HRActive, active_sessions),
+                (
+                    PropertyTag::HRActiveAvail,
+                    MAX_ACTIVE_SESSIONS as u32 - active_sessions,
+                ),
+                (
+                    PropertyTag::HRTransientAvail,
+                    MAX_LOADED_OBJECTS as u32 - loaded_objects,
+                ),
+                (PropertyTag::HRPersistent, 0),
+                (PropertyTag::HRPersistentAvail, 0),
+                (PropertyTag::NVCounters, 0),
+                (
+                    PropertyTag::NVCountersAvail,
+                    MAX_NV_INDICES as u32 - self.nv.len() as u32,
+                ),
+            ]
+        }
+
+        /// Handles of the type of `first`, starting there.
+        fn handles(&self, first: u32) -> Vec<u32> {
+            let handles: Vec<u32> = match handle_type(first) {
+                TPM_HT_PCR => (0..PCR_COUNT as u32).collect(),
+                TPM_HT_NV_INDEX => self.nv.keys().copied().collect(),
+                TPM_HT_HMAC_SESSION => self.sessions.keys().copied().collect(),
+                TPM_HT_TRANSIENT => self.objects.keys().copied().collect(),
+                TPM_HT_PERMANENT => {
+                    let mut handles = PERMANENT_HANDLES.to_vec();
+                    handles.sort();
+                    handles
+                }
+                _ => Vec::new(),
+            };
+            handles.into_iter().filter(|&h| h >= first).collect()
+        }
+
+        pub(crate) fn get_capability(&mut self, parameters: &[u8]) -> Result<Reply, ReturnCode> {
+            let get = parse::<GetCapability>(parameters)?;
+            let (property, count) = (get.property, get.property_count);
+
+            let (more_data, capability_data) = match get.capability {
+                Capability::Algs => {
+                    let algorithms = ALGORITHMS
+                        .iter()
+                        .filter(|(alg, _)| alg.clone() as u32 >= property)
+                        .map(|(alg, attributes)| AlgorithmProperty {
+                            alg: alg.clone(),
+                            attributes: *attributes,
+                        })
+                        .collect();
+                    let (more_data, algorithms) = page(algorithms, count);
+                    (more_data, Capabilities::Algorithms(algorithms))
+                }
+                Capability::Handles => {
+                    let handles = self
+                        .handles(property)
+                        .into_iter()
+                        .map(|h| Handle::try_from(h).map_err(|_| TPM_RC_FAILURE))
+                        .collect::<Result<_, _>>()?;
+                    let (more_data, handles) = page(handles, count);
+                    (more_data, Capabilities::Handles(handles))
+                }
+                Capability::Commands => {
+                    let commands = COMMANDS
+                        .iter()
+                        .filter(|&&cc| u32::from(cc) >= property)
+                        .map(|cc| {
+                            let mut attributes = u32::from(*cc)
+                                | (cc.handle_count() as u32) << TPMA_CC_C_HANDLES_SHIFT;
+                            if cc.response_handle_count() > 0 {
+                                attributes |= TPMA_CC_R_HANDLE;
+                            }
+                            if *cc == CommandCode::FlushContext {
+                                attributes |= TPMA_CC_FLUSHED;
+                            }
+                            attributes
+                        })
+                        .collect();
+                    let (more_data, commands) = page(commands, count);
+                    (more_data, Capabilities::Commands(commands))
+                }
+                Capability::Pcrs => (false, Capabilities::AssignedPCRs(self.pcrs.allocation())),
+                Capability::TpmProperties => {
+                    let properties = self
+                        .tpm_properties()
+                        .into_iter()
+                        .filter(|(tag, _)| tag.clone() as u32 >= property)
+                        .map(|(property, value)| TaggedProperty { property, value })
+                        .collect();
+                    let (more_data, properties) = page(properties, count);
+                    (more_data, Capabilities::TpmProperties(properties))
+                }
+                Capability::EccCurves => {
+                    let curves = [EccCurve::NistP256]
+                        .into_iter()
+                        .filter(|curve| curve.clone() as u32 >= property)
+                        .collect();
+                    let (more_data, curves) = page(curves, count);
+                    (more_data, Capabilities::EccCurves(curves))
+                }
+                _ => return Err(parameter(TPM_RC_VALUE, 1)),
+            };
+
+            Reply::new(&GetCapabilityResponse {
+                more_data,
+                capability_data,
+            })
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::simulator::simulator::tests::{command, rc, started};
+        use crate::simulator::simulator::{TPM_HEADER_SIZE, TPM_ST_NO_SESSIONS};
+
+        fn get_capability(
+            tpm: &mut Simulator,
+            capability: u32,
+            property: u32,
+            count: u32,
+        ) -> Vec<u8> {
+            let parameters: Vec<u8> = [capability, property, count]
+                .iter()
+                .flat_map(|v| v.to_be_bytes())
+                .collect();
+            let response = tpm.execute(&command(
+                TPM_ST_NO_SESSIONS,
+                CommandCode::GetCapability,
+                &parameters,
+            ));
+            assert_eq!(rc(&response), TPM_RC_SUCCESS);
+            response[TPM_HEADER_SIZE..].to_vec()
+        }
+
+        #[test]
+        fn test_properties() {
+            let mut tpm = started();
+            let manufacturer = get_capability(&mut tpm, 6, PropertyTag::Manufacturer as u32, 1);
+            assert_eq!(
+                manufacturer,
+                b"\x01\x00\x00\x00\x06\x00\x00\x00\x01\x00\x00\x01\x05RUST"
+            );
+            let last = get_capability(&mut tpm, 6, PropertyTag::NVCountersAvail as u32, 8);
+            assert_eq!(
+                last,
+                b"\x00\x00\x00\x00\x06\x00\x00\x00\x01\x00\x00\x02\x11\x00\x00\x00\x40"
+            );
+        }
+
+        #[test]
+        fn test_commands_and_handles() {
+            let mut tpm = started();
+            // TPM2_NV_UndefineSpace: 2 handles
+            let commands = get_capability(&mut tpm, 2, 0, 1);
+            assert_eq!(
+                commands,
+                b"\x01\x00\x00\x00\x02\x00\x00\x00\x01\x04\x00\x01\x22"
+            );
+            let startup = get_capability(&mut tpm, 2, CommandCode::StartAuthSession as u32, 1);
+            assert_eq!(&startup[9..], &(0x14000176u32).to_be_bytes());
+
+            let pcrs = get_capability(&mut tpm, 1, 0x00000010, 100);
+            assert_eq!(&pcrs[..9], b"\x00\x00\x00\x00\x01\x00\x00\x00\x08");
+            let transient = get_capability(&mut tpm, 1, 0x80000000, 100);
+            assert_eq!(transient, b"\x00\x00\x00\x00\x01\x00\x00\x00\x00");
+
+            let response = tpm.execute(&command(
+                TPM_ST_NO_SESSIONS,
+                CommandCode::GetCapability,
+                &[0, 0, 0, 0x09, 0, 0, 0, 0, 0, 0, 0, 1],
+            ));
+            assert_eq!(rc(&response), parameter(TPM_RC_VALUE, 1));
+        }
+    }
+}
@@ -0,0 +1,310 @@
+/// `TPM2_ContextSave` and `TPM2_ContextLoad` of objects and sessions.
+///
+/// The context blob is obfuscated and integrity protected with a key derived
+/// from the proof of the simulator. Contexts of sessions and of objects in
+/// the null hierarchy become invalid with the next TPM Reset.
+pub mod context {
+    use serde::{Deserialize, Serialize};
+    use tpm2_types::alg::AlgHash;
+    use tpm2_types::commands::ContextLoad;
+    use tpm2_types::constants::ReturnCode;
+    use tpm2_types::handles::{Hierarchy, Saved};
+    use tpm2_types::selectables::{Public, Sensitive};
+    use tpm2_types::serde_types::sized_vector::U16SizedVector;
+    use tpm2_types::structs::{Context, ContextData};
+
+    use crate::crypto::crypto;
+    use crate::object::object::Object;
+    use crate::rc::rc::*;
+    use crate::session::session::{Session, SessionSlot};
+    use crate::simulator::simulator::{
+        handle_type, marshal, parse, Reply, Simulator, MAX_LOADED_SESSIONS, TPM_HT_HMAC_SESSION,
+        TPM_HT_TRANSIENT, TPM_RH_NULL,
+    };
+
+    /// Saved handle of the contexts of ordinary objects.
+    pub const TRANSIENT_SAVED_HANDLE: u32 = 0x80000000;
+
+    /// Content of the context blob of an object.
+    #[derive(Serialize, Deserialize)]
+    struct ObjectContext {
+        /// TPM2B_PUBLIC
+        #[serde(with = "U16SizedVector")]
+        public: Vec<u8>,
+        /// TPM2B_SENSITIVE
+        #[serde(with = "U16SizedVector")]
+        sensitive: Vec<u8>,
+        #[serde(with = "U16SizedVector")]
+        parent_qualified_name: Vec<u8>,
+    }
+
+    impl Simulator {
+        /// Key of the context integrity HMAC and the obfuscation of the
+        /// context. Volatile contexts are bound to the reset count.
+        fn context_key(
+            &self,
+            hierarchy: Hierarchy,
+            saved_handle: u32,
+        ) -> Result<Vec<u8>, ReturnCode> {
+            let reset_count = match (handle_type(saved_handle), hierarchy) {
+                (
+                    TPM_HT_TRANSIENT,
+                    Hierarchy::Owner | Hierarchy::Platform | Hierarchy::Endorsement,
+                ) => 0,
+                _ => self.reset_count,
+            };
+            crypto::kdfa(
+                &AlgHash::SHA256,
+                &self.proof(TPM_RH_NULL)?,
+                "CONTEXT",
+                &reset_count.to_be_bytes(),
+                &u32::from(hierarchy).to_be_bytes(),
+                256,
+            )
+        }
+
+        fn context_integrity(
+            &self,
+            key: &[u8],
+            sequence: u64,
+            saved_handle: u32,
+            encrypted: &[u8],
+        ) -> Result<Vec<u8>, ReturnCode> {
+            crypto::hmac(
+                &AlgHash::SHA256,
+                key,
+                &[
+                    &sequence.to_be_bytes(),
+                    &saved_handle.to_be_bytes(),
+                    encrypted,
+                ],
+            )
+        }
+
+        pub(crate) fn context_save(
+            &mut self,
+            save_handle: u32,
+            parameters: &[u8],
+        ) -> Result<Reply, ReturnCode> {
+            if !parameters.is_empty() {
+                return Err(TPM_RC_SIZE);
+            }
+
+            let (saved_handle, hierarchy, payload) = match handle_type(save_handle) {
+                TPM_HT_TRANSIENT => {
+                    let object = self.object(save_handle).map_err(|rc| handle(rc, 1))?;
+                    let payload = marshal(&ObjectContext {
+                        public: marshal(&object.public)?,
+                        sensitive: marshal(&object.sensitive())?,
+                        parent_qualified_name: object.parent_qualified_name.clone(),
+                    })?;
+                    (TRANSIENT_SAVED_HANDLE, object.hierarchy, payload)
+                }
+                TPM_HT_HMAC_SESSION => match self.sessions.get(&save_handle) {
+                    Some(SessionSlot::Loaded(session)) => {
+                        (save_handle, Hierarchy::Null, marshal(session)?)
+                    }
+                    _ => return Err(handle(TPM_RC_HANDLE, 1)),
+                },
+                _ => return Err(handle(TPM_RC_VALUE, 1)),
+            };
+
+            let sequence = self.context_counter;
+            self.context_counter += 1;
+            let key = self.context_key(hierarchy, saved_handle)?;
+            let mut encrypted = payload;
+            crypto::xor(
+                &AlgHash::SHA256,
+                &key,
+                &sequence.to_be_bytes(),
+                &saved_handle.to_be_bytes(),
+                &mut encrypted,
+            )?;
+            let integrity = self.context_integrity(&key, sequence, saved_handle, &encrypted)?;
+            let context_blob = marshal(&ContextData {
+                integrity,
+                encrypted,
+            })?;
+
+            if handle_type(save_handle) == TPM_HT_HMAC_SESSION {
+                self.sessions
+                    .insert(save_handle, SessionSlot::Saved(sequence));
+            }
+            Reply::new(&Context {
+                sequence,
+                saved_handle: Saved::try_from(saved_handle).map_err(|_| TPM_RC_FAILURE)?,
+                hierarchy,
+                context_blob,
+            })
+        }
+
+        pub(crate) fn context_load(&mut self, parameters: &[u8]) -> Result<Reply, ReturnCode> {
+            let context = parse::<ContextLoad>(parameters)?.context;
+            let saved_handle = u32::from(context.saved_handle);
+            let data =
+                parse::<ContextData>(&context.context_blob).map_err(|rc| parameter(rc, 1))?;
+
+            let key = self.context_key(context.hierarchy, saved_handle)?;
+            let integrity =
+                self.context_integrity(&key, context.sequence, saved_handle, &data.encrypted)?;
+            if !crypto::equal(&integrity, &data.integrity) {
+                return Err(parameter(TPM_RC_INTEGRITY, 1));
+            }
+            let mut payload = data.encrypted;
+            crypto::xor(
+                &AlgHash::SHA256,
+                &key,
+                &context.sequence.to_be_bytes(),
+                &saved_handle.to_be_bytes(),
+                &mut payload,
+            )?;
+
+            match handle_type(saved_handle) {
+                TPM_HT_TRANSIENT => {
+                    let object = parse::<ObjectContext>(&payload)?;
+                    let public = parse::<Public>(&object.public)?;
+                    let sensitive = parse::<Sensitive>(&object.sensitive)?;
+                    let object = Object::from_sensitive(
+                        public,
+                        sensitive,
+                        context.hierarchy,
+                        object.parent_qualified_name,
+                    )?;
+                    let object_handle = self.object_slot()?;
+                    self.objects.insert(object_handle, object);
+                    Ok(Reply {
+                        handles: vec![object_handle],
+                        parameters: Vec::new(),
+                    })
+                }
+                TPM_HT_HMAC_SESSION => {
+                    // only the most recent context of a saved session can be
+                    // loaded, and only once
+                    match self.sessions.get(&saved_handle) {
+                        Some(SessionSlot::Saved(sequence)) if *sequence == context.sequence => (),
+                        _ => return Err(parameter(TPM_RC_HANDLE, 1)),
+                    }
+                    let loaded = self
+                        .sessions
+                        .values()
+                        .filter(|slot| matches!(slot, SessionSlot::Loaded(_)))
+                        .count();
+                    if loaded >= MAX_LOADED_SESSIONS {
+                        return Err(TPM_RC_SESSION_MEMORY);
+                    }
+                    let session = parse::<Session>(&payload)?;
+                    self.sessions
+                        .insert(saved_handle, SessionSlot::Loaded(session));
+                    Ok(Reply {
+                        handles: vec![saved_handle],
+                        parameters: Vec::new(),
+                    })
+                }
+                _ => Err(parameter(TPM_RC_HANDLE, 1)),
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::keys::keys::tests::srk_and_key;
+        use crate::simulator::simulator::tests::{command, rc, started};
+        use crate::simulator::simulator::{TPM_HEADER_SIZE, TPM_ST_NO_SESSIONS};
+        use tpm2_types::constants::CommandCode;
+        use tpm2_types::selectables::RSAScheme;
+
+        fn save(tpm: &mut Simulator, handle: u32) -> Vec<u8> {
+            let response = tpm.execute(&command(
+                TPM_ST_NO_SESSIONS,
+                CommandCode::ContextSave,
+                &handle.to_be_bytes(),
+            ));
+            assert_eq!(rc(&response), TPM_RC_SUCCESS);
+            response[TPM_HEADER_SIZE..].to_vec()
+        }
+
+        fn load(tpm: &mut Simulator, context: &[u8]) -> Vec<u8> {
+            tpm.execute(&command(
+                TPM_ST_NO_SESSIONS,
+                CommandCode::ContextLoad,
+                context,
+            ))
+        }
+
+        fn flush(tpm: &mut Simulator, handle: u32) {
+            let response = tpm.execute(&command(
+                TPM_ST_NO_SESSIONS,
+                CommandCode::FlushContext,
+                &handle.to_be_bytes(),
+            ));
+            assert_eq!(rc(&response), TPM_RC_SUCCESS);
+        }
+
+        #[test]
+        fn test_object_context() {
+            let mut tpm = started();
+            let (srk, key) = srk_and_key(&mut tpm, RSAScheme::RSASSA(AlgHash::SHA256));
+            let name = tpm.objects[&key].name.clone();
+            let context = save(&mut tpm, key);
+            flush(&mut tpm, key);
+            flush(&mut tpm, srk);
+
+            let mut tampered = context.clone();
+            *tampered.last_mut().unwrap() ^= 1;
+            assert_eq!(
+                rc(&load(&mut tpm, &tampered)),
+                parameter(TPM_RC_INTEGRITY, 1)
+            );
+
+            let response = load(&mut tpm, &context);
+            assert_eq!(rc(&response), TPM_RC_SUCCESS);
+            let handle = u32::from_be_bytes(response[10..14].try_into().unwrap());
+            assert_eq!(tpm.objects[&handle].name, name);
+
+            // objects of the owner hierarchy survive a TPM Reset
+            tpm.power_cycle();
+            let startup = command(TPM_ST_NO_SESSIONS, CommandCode::Startup, &[0, 0]);
+            assert_eq!(rc(&tpm.execute(&startup)), TPM_RC_SUCCESS);
+            assert_eq!(rc(&load(&mut tpm, &context)), TPM_RC_SUCCESS);
+        }
+
+        #[test]
+        fn test_session_context() {
+            let mut tpm = started();
+            let mut start = [TPM_RH_NULL, TPM_RH_NULL]
+                .iter()
+                .flat_map(|h| h.to_be_bytes())
+                .collect::<Vec<u8>>();
+            start.extend_from_slice(&[0, 16]);
+            start.extend_from_slice(&[0; 16]);
+            start.extend_from_slice(&[0, 0, 0, 0x00, 0x10, 0x00, 0x0b]);
+            let response = tpm.execute(&command(
+                TPM_ST_NO_SESSIONS,
+                CommandCode::StartAuthSession,
+                &start,
+            ));
+            let session = u32::from_be_bytes(response[10..14].try_into().unwrap());
+
+            let context = save(&mut tpm, session);
+            assert!(matches!(tpm.sessions[&session], SessionSlot::Saved(_)));
+            let response = load(&mut tpm, &context);
+            assert_eq!(rc(&response), TPM_RC_SUCCESS);
+            assert_eq!(&response[10..14], &session.to_be_bytes());
+            // no replay
+            let latest = save(&mut tpm, session);
+            assert_eq!(rc(&load(&mut tpm, &context)), parameter(TPM_RC_HANDLE, 1));
+            assert_eq!(rc(&load(&mut tpm, &latest)), TPM_RC_SUCCESS);
+
+            // sessions do not survive a TPM Reset
+            let context = save(&mut tpm, session);
+            tpm.power_cycle();
+            let startup = command(TPM_ST_NO_SESSIONS, CommandCode::Startup, &[0, 0]);
+            assert_eq!(rc(&tpm.execute(&startup)), TPM_RC_SUCCESS);
+            assert_eq!(
+                rc(&load(&mut tpm, &context)),
+                parameter(TPM_RC_INTEGRITY, 1)
+            );
+        }
+    }
+}
@@ -0,0 +1,203 @@
+/// Hash based primitives of the simulator (TPM 2.0 Part 1, 11.4).
+pub mod crypto {
+    use hmac::{Hmac, Mac};
+    use sha1::Sha1;
+    use sha2::{Sha256, Sha384, Sha512};
+    use tpm2_types::alg::AlgHash;
+    use tpm2_types::selectables::Digest;
+
+    use crate::rc::rc::{TPM_RC_HASH, TPM_RC_VALUE};
+    use tpm2_types::constants::ReturnCode;
+
+    /// Hash algorithms implemented by the simulator.
+    pub const HASH_ALGS: [AlgHash; 4] = [
+        AlgHash::SHA1,
+        AlgHash::SHA256,
+        AlgHash::SHA384,
+        AlgHash::SHA512,
+    ];
+
+    /// Digest size in bytes, `TPM_RC_HASH` if `alg` is not implemented.
+    pub fn digest_size(alg: &AlgHash) -> Result<usize, ReturnCode> {
+        match alg {
+            AlgHash::SHA1 => Ok(20),
+            AlgHash::SHA256 => Ok(32),
+            AlgHash::SHA384 => Ok(48),
+            AlgHash::SHA512 => Ok(64),
+            _ => Err(TPM_RC_HASH),
+        }
+    }
+
+    fn hash_with<D: sha2::Digest>(data: &[&[u8]]) -> Vec<u8> {
+        let mut hasher = D::new();
+        for chunk in data {
+            hasher.update(chunk);
+        }
+        hasher.finalize().to_vec()
+    }
+
+    fn hmac_with<M: Mac + hmac::digest::KeyInit>(key: &[u8], data: &[&[u8]]) -> Vec<u8> {
+        let mut mac = <M as hmac::digest::KeyInit>::new_from_slice(key).unwrap();
+        for chunk in data {
+            mac.update(chunk);
+        }
+        mac.finalize().into_bytes().to_vec()
+    }
+
+    /// Digest of the concatenation of `data`.
+    pub fn hash(alg: &AlgHash, data: &[&[u8]]) -> Result<Vec<u8>, ReturnCode> {
+        match alg {
+            AlgHash::SHA1 => Ok(hash_with::<Sha1>(data)),
+            AlgHash::SHA256 => Ok(hash_with::<Sha256>(data)),
+            AlgHash::SHA384 => Ok(hash_with::<Sha384>(data)),
+            AlgHash::SHA512 => Ok(hash_with::<Sha512>(data)),
+            _ => Err(TPM_RC_HASH),
+        }
+    }
+
+    /// HMAC of the concatenation of `data`.
+    pub fn hmac(alg: &AlgHash, key: &[u8], data: &[&[u8]]) -> Result<Vec<u8>, ReturnCode> {
+        match alg {
+            AlgHash::SHA1 => Ok(hmac_with::<Hmac<Sha1>>(key, data)),
+            AlgHash::SHA256 => Ok(hmac_with::<Hmac<Sha256>>(key, data)),
+            AlgHash::SHA384 => Ok(hmac_with::<Hmac<Sha384>>(key, data)),
+            AlgHash::SHA512 => Ok(hmac_with::<Hmac<Sha512>>(key, data)),
+            _ => Err(TPM_RC_HASH),
+        }
+    }
+
+    /// KDFa, i.e. SP800-108 in counter mode with HMAC. `label` is given
+    /// without the terminating zero.
+    pub fn kdfa(
+        alg: &AlgHash,
+        key: &[u8],
+        label: &str,
+        context_u: &[u8],
+        context_v: &[u8],
+        bits: u32,
+    ) -> Result<Vec<u8>, ReturnCode> {
+        let size = bits.div_ceil(8) as usize;
+        let mut out = Vec::with_capacity(size + digest_size(alg)?);
+        let mut counter = 1u32;
+        while out.len() < size {
+            out.extend(hmac(
+                alg,
+                key,
+                &[
+                    &counter.to_be_bytes(),
+                    label.as_bytes(),
+                    &[0],
+                    context_u,
+                    context_v,
+                    &bits.to_be_bytes(),
+                ],
+            )?);
+            counter += 1;
+        }
+        out.truncate(size);
+        Ok(out)
+    }
+
+    /// XOR obfuscation of `data` in place (TPM 2.0 Part 1, 11.4.6.3).
+    pub fn xor(
+        alg: &AlgHash,
+        key: &[u8],
+        context_u: &[u8],
+        context_v: &[u8],
+        data: &mut [u8],
+    ) -> Result<(), ReturnCode> {
+        let mask = kdfa(alg, key, "XOR", context_u, context_v, data.len() as u32 * 8)?;
+        data.iter_mut()
+            .zip(mask)
+            .for_each(|(byte, mask)| *byte ^= mask);
+        Ok(())
+    }
+
+    fn array<const N: usize>(digest: &[u8]) -> Result<[u8; N], ReturnCode> {
+        digest.try_into().map_err(|_| TPM_RC_VALUE)
+    }
+
+    /// TPMT_HA of `alg` from its digest, `TPM_RC_VALUE` if the size does
+    /// not match.
+    pub fn to_digest(alg: &AlgHash, digest: &[u8]) -> Result<Digest, ReturnCode> {
+        match alg {
+            AlgHash::SHA1 => Ok(Digest::Sha1(array(digest)?)),
+            AlgHash::SHA256 => Ok(Digest::Sha256(array(digest)?)),
+            AlgHash::SHA384 => Ok(Digest::Sha384(array(digest)?)),
+            AlgHash::SHA512 => Ok(Digest::Sha512(array(digest)?)),
+            _ => Err(TPM_RC_HASH),
+        }
+    }
+
+    /// Algorithm and digest of a TPMT_HA.
+    pub fn from_digest(digest: &Digest) -> Result<(AlgHash, &[u8]), ReturnCode> {
+        match digest {
+            Digest::Sha1(value) => Ok((AlgHash::SHA1, value)),
+            Digest::Sha256(value) => Ok((AlgHash::SHA256, value)),
+            Digest::Sha384(value) => Ok((AlgHash::SHA384, value)),
+            Digest::Sha512(value) => Ok((AlgHash::SHA512, value)),
+            _ => Err(TPM_RC_HASH),
+        }
+    }
+
+    /// Name of an entity from its marshalled public area: nameAlg followed
+    /// by the digest of the public area.
+    pub fn name(alg: &AlgHash, public: &[u8]) -> Result<Vec<u8>, ReturnCode> {
+        let mut name = (alg.clone() as u16).to_be_bytes().to_vec();
+        name.extend(hash(alg, &[public])?);
+        Ok(name)
+    }
+
+    /// Compare in constant time.
+    pub fn equal(a: &[u8], b: &[u8]) -> bool {
+        a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use tpm2_types::util::to_hex;
+
+        #[test]
+        fn test_hash() {
+            assert_eq!(
+                to_hex(&hash(&AlgHash::SHA256, &[b"a", b"bc"]).unwrap()),
+                "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+            );
+            assert_eq!(
+                to_hex(
+                    &hmac(
+                        &AlgHash::SHA1,
+                        b"key",
+                        &[b"The quick brown fox ", b"jumps over the lazy dog"]
+                    )
+                    .unwrap()
+                ),
+                "de7c9b85b8b78aa6bc8a7a36f70a90701c9db4d9"
+            );
+            assert_eq!(hash(&AlgHash::SM3_256, &[]), Err(TPM_RC_HASH));
+        }
+
+        #[test]
+        fn test_kdfa() {
+            let key = [0x55; 32];
+            let long = kdfa(&AlgHash::SHA256, &key, "STORAGE", b"u", b"v", 512).unwrap();
+            assert_eq!(long.len(), 64);
+            // the size is part of every block
+            let short = kdfa(&AlgHash::SHA256, &key, "STORAGE", b"u", b"v", 256).unwrap();
+            assert_ne!(short, long[..32]);
+            assert_eq!(
+                kdfa(&AlgHash::SHA256, &key, "STORAGE", b"u", b"v", 12)
+                    .unwrap()
+                    .len(),
+                2
+            );
+
+            let mut data = *b"secret";
+            xor(&AlgHash::SHA256, &key, b"", b"", &mut data).unwrap();
+            assert_ne!(&data, b"secret");
+            xor(&AlgHash::SHA256, &key, b"", b"", &mut data).unwrap();
+            assert_eq!(&data, b"secret");
+        }
+    }
+}
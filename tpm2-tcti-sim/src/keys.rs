@@ -0,0 +1,762 @@
+/// Object commands: primary keys, child keys, signing and attestation.
+pub mod keys {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha20Rng;
+    use serde::{Deserialize, Serialize};
+    use tpm2_types::alg::AlgHash;
+    use tpm2_types::commands::FlushContext;
+    use tpm2_types::constants::{ReturnCode, StructureTag};
+    use tpm2_types::handles::Hierarchy;
+    use tpm2_types::selectables::{AttestBody, Public, SigScheme, Signature, Ticket};
+    use tpm2_types::serde_types::sized_vector::{U16SizedVector, U32SizedVector};
+    use tpm2_types::structs::{Attest, CreationData, PCRSelection, QuoteInfo, SensitiveCreate};
+    use tpm2_types::util::ConstantU32;
+
+    use crate::crypto::crypto::{self, digest_size};
+    use crate::object::object::{
+        scheme, scheme_hash, unwrap, wrap, Object, TPMA_OBJECT_RESTRICTED, TPMA_OBJECT_SIGN_ENCRYPT,
+    };
+    use crate::rc::rc::*;
+    use crate::simulator::simulator::{
+        handle_type, marshal, parse, Reply, Simulator, FIRMWARE_VERSION, MAX_LOADED_OBJECTS,
+        TPM_HT_TRANSIENT,
+    };
+
+    /// Maximum size of the data hashed by `TPM2_Hash`.
+    pub const MAX_DIGEST_BUFFER: usize = 1024;
+    /// TPMA_LOCALITY of locality 0, the only one of the simulator.
+    pub const TPM_LOC_ZERO: u8 = 0x01;
+
+    #[derive(Deserialize)]
+    struct CreateIn {
+        /// TPM2B_SENSITIVE_CREATE
+        #[serde(with = "U16SizedVector")]
+        in_sensitive: Vec<u8>,
+        /// TPM2B_PUBLIC
+        #[serde(with = "U16SizedVector")]
+        in_public: Vec<u8>,
+        #[serde(with = "U16SizedVector")]
+        outside_info: Vec<u8>,
+        #[serde(with = "U32SizedVector")]
+        creation_pcr: Vec<PCRSelection>,
+    }
+
+    #[derive(Serialize)]
+    struct CreatePrimaryOut {
+        #[serde(with = "U16SizedVector")]
+        out_public: Vec<u8>,
+        #[serde(with = "U16SizedVector")]
+        creation_data: Vec<u8>,
+        #[serde(with = "U16SizedVector")]
+        creation_hash: Vec<u8>,
+        creation_ticket: Ticket,
+        #[serde(with = "U16SizedVector")]
+        name: Vec<u8>,
+    }
+
+    #[derive(Serialize)]
+    struct CreateOut {
+        #[serde(with = "U16SizedVector")]
+        out_private: Vec<u8>,
+        #[serde(with = "U16SizedVector")]
+        out_public: Vec<u8>,
+        #[serde(with = "U16SizedVector")]
+        creation_data: Vec<u8>,
+        #[serde(with = "U16SizedVector")]
+        creation_hash: Vec<u8>,
+        creation_ticket: Ticket,
+    }
+
+    #[derive(Deserialize)]
+    struct LoadIn {
+        #[serde(with = "U16SizedVector")]
+        in_private: Vec<u8>,
+        #[serde(with = "U16SizedVector")]
+        in_public: Vec<u8>,
+    }
+
+    #[derive(Serialize)]
+    struct LoadOut {
+        #[serde(with = "U16SizedVector")]
+        name: Vec<u8>,
+    }
+
+    #[derive(Serialize)]
+    struct ReadPublicOut {
+        #[serde(with = "U16SizedVector")]
+        out_public: Vec<u8>,
+        #[serde(with = "U16SizedVector")]
+        name: Vec<u8>,
+        #[serde(with = "U16SizedVector")]
+        qualified_name: Vec<u8>,
+    }
+
+    #[derive(Deserialize)]
+    struct HashIn {
+        #[serde(with = "U16SizedVector")]
+        data: Vec<u8>,
+        hash_alg: AlgHash,
+        hierarchy: Hierarchy,
+    }
+
+    #[derive(Serialize)]
+    struct HashOut {
+        #[serde(with = "U16SizedVector")]
+        out_hash: Vec<u8>,
+        validation: Ticket,
+    }
+
+    #[derive(Deserialize)]
+    struct SignIn {
+        #[serde(with = "U16SizedVector")]
+        digest: Vec<u8>,
+        in_scheme: SigScheme,
+        validation: Ticket,
+    }
+
+    #[derive(Deserialize)]
+    struct VerifySignatureIn {
+        #[serde(with = "U16SizedVector")]
+        digest: Vec<u8>,
+        signature: Signature,
+    }
+
+    #[derive(Deserialize)]
+    struct QuoteIn {
+        #[serde(with = "U16SizedVector")]
+        qualifying_data: Vec<u8>,
+        in_scheme: SigScheme,
+        #[serde(with = "U32SizedVector")]
+        pcr_select: Vec<PCRSelection>,
+    }
+
+    #[derive(Serialize)]
+    struct QuoteOut {
+        /// TPM2B_ATTEST
+        #[serde(with = "U16SizedVector")]
+        quoted: Vec<u8>,
+        signature: Signature,
+    }
+
+    /// Scheme for signing with `key`, the one of the key unless that one is
+    /// `TPM_ALG_NULL`. `n` is the number of the scheme parameter.
+    fn signing_scheme(key: &Object, in_scheme: SigScheme, n: u32) -> Result<SigScheme, ReturnCode> {
+        if key.attributes() & TPMA_OBJECT_SIGN_ENCRYPT == 0 {
+            return Err(handle(TPM_RC_KEY, 1));
+        }
+        let scheme = match (scheme(&key.public), in_scheme) {
+            (SigScheme::Null, SigScheme::Null) => return Err(parameter(TPM_RC_SCHEME, n)),
+            (SigScheme::Null, scheme) => scheme,
+            (scheme, SigScheme::Null) => scheme,
+            (scheme, in_scheme) if scheme == in_scheme => scheme,
+            _ => return Err(parameter(TPM_RC_SCHEME, n)),
+        };
+        match scheme_hash(&scheme) {
+            Some(hash) => digest_size(hash).map_err(|rc| parameter(rc, n))?,
+            None => return Err(parameter(TPM_RC_SCHEME, n)),
+        };
+        Ok(scheme)
+    }
+
+    impl Simulator {
+        /// Ticket digest proving that the TPM produced `data` in `hierarchy`,
+        /// empty for the null hierarchy.
+        pub(crate) fn ticket_digest(
+            &self,
+            hierarchy: Hierarchy,
+            hash: &AlgHash,
+            tag: StructureTag,
+            data: &[&[u8]],
+        ) -> Result<Vec<u8>, ReturnCode> {
+            if hierarchy == Hierarchy::Null {
+                return Ok(Vec::new());
+            }
+            let proof = self.proof(hierarchy.into())?;
+            let tag = (tag as u16).to_be_bytes();
+            let data: Vec<&[u8]> = [&tag[..]].into_iter().chain(data.iter().copied()).collect();
+            crypto::hmac(hash, &proof, &data)
+        }
+
+        /// Handle for a new object, `TPM_RC_OBJECT_MEMORY` if all slots are
+        /// occupied.
+        pub(crate) fn object_slot(&self) -> Result<u32, ReturnCode> {
+            (0..MAX_LOADED_OBJECTS as u32)
+                .map(|i| (TPM_HT_TRANSIENT << 24) | i)
+                .find(|h| !self.objects.contains_key(h))
+                .ok_or(TPM_RC_OBJECT_MEMORY)
+        }
+
+        /// Storage key at `parent_handle`.
+        fn parent(&self, parent_handle: u32) -> Result<&Object, ReturnCode> {
+            let parent = self.object(parent_handle).map_err(|rc| handle(rc, 1))?;
+            if parent.is_storage_key() {
+                Ok(parent)
+            } else {
+                Err(handle(TPM_RC_TYPE, 1))
+            }
+        }
+
+        /// Creation data, creation hash and creation ticket of `object`.
+        fn creation(
+            &self,
+            object: &Object,
+            parent: Option<&Object>,
+            create: CreateIn,
+        ) -> Result<(Vec<u8>, Vec<u8>, Ticket), ReturnCode> {
+            let name_alg = object.name_alg();
+            let (pcr_select, values) = self.pcrs.select(&create.creation_pcr, usize::MAX);
+            let pcr_digest =
+                crypto::hash(name_alg, &values.iter().map(|v| &v[..]).collect::<Vec<_>>())?;
+            let parent_handle = u32::from(object.hierarchy).to_be_bytes().to_vec();
+            let (parent_name_alg, parent_name, parent_qualified_name) = match parent {
+                Some(parent) => (
+                    parent.name_alg().clone(),
+                    parent.name.clone(),
+                    parent.qualified_name()?,
+                ),
+                None => (AlgHash::Null, parent_handle.clone(), parent_handle),
+            };
+
+            let creation_data = marshal(&CreationData {
+                pcr_select,
+                pcr_digest,
+                locality: TPM_LOC_ZERO,
+                parent_name_alg,
+                parent_name,
+                parent_qualified_name,
+                outside_info: create.outside_info,
+            })?;
+            let creation_hash = crypto::hash(name_alg, &[&creation_data])?;
+            let ticket = Ticket::Creation {
+                hierarchy: object.hierarchy,
+                digest: self.ticket_digest(
+                    object.hierarchy,
+                    name_alg,
+                    StructureTag::Creation,
+                    &[&object.name, &creation_hash],
+                )?,
+            };
+            Ok((creation_data, creation_hash, ticket))
+        }
+
+        fn parse_create(
+            parameters: &[u8],
+        ) -> Result<(CreateIn, SensitiveCreate, Public), ReturnCode> {
+            let create = parse::<CreateIn>(parameters)?;
+            let sensitive = parse(&create.in_sensitive).map_err(|rc| parameter(rc, 1))?;
+            let public = parse(&create.in_public).map_err(|rc| parameter(rc, 2))?;
+            Ok((create, sensitive, public))
+        }
+
+        /// `TPM2_CreatePrimary`. Primary keys are derived from the seed of the
+        /// hierarchy, the template and the sensitive data, i.e. they stay the
+        /// same until the seed changes.
+        pub(crate) fn create_primary(
+            &mut self,
+            primary_handle: u32,
+            parameters: &[u8],
+        ) -> Result<Reply, ReturnCode> {
+            let hierarchy = Self::hierarchy(primary_handle).map_err(|rc| handle(rc, 1))?;
+            let (create, sensitive, template) = Self::parse_create(parameters)?;
+            let object_handle = self.object_slot()?;
+
+            let seed = crypto::kdfa(
+                &AlgHash::SHA256,
+                &self.seeds[&primary_handle],
+                "PRIMARY",
+                &crypto::hash(&AlgHash::SHA256, &[&create.in_public])?,
+                &sensitive.data,
+                256,
+            )?;
+            let mut rng = ChaCha20Rng::from_seed(seed.try_into().unwrap());
+            let parent_qualified_name = primary_handle.to_be_bytes().to_vec();
+            let object = Object::generate(
+                &template,
+                &sensitive,
+                &mut rng,
+                hierarchy,
+                parent_qualified_name,
+            )?;
+
+            let (creation_data, creation_hash, creation_ticket) =
+                self.creation(&object, None, create)?;
+            let reply = Reply::with_handle(
+                object_handle,
+                &CreatePrimaryOut {
+                    out_public: marshal(&object.public)?,
+                    creation_data,
+                    creation_hash,
+                    creation_ticket,
+                    name: object.name.clone(),
+                },
+            )?;
+            self.objects.insert(object_handle, object);
+            Ok(reply)
+        }
+
+        pub(crate) fn create(
+            &mut self,
+            parent_handle: u32,
+            parameters: &[u8],
+        ) -> Result<Reply, ReturnCode> {
+            let (create, sensitive, template) = Self::parse_create(parameters)?;
+            let parent = self.parent(parent_handle)?.clone();
+            let object = Object::generate(
+                &template,
+                &sensitive,
+                &mut self.rng,
+                parent.hierarchy,
+                parent.qualified_name()?,
+            )?;
+
+            let out_private = wrap(&parent, &object)?;
+            let (creation_data, creation_hash, creation_ticket) =
+                self.creation(&object, Some(&parent), create)?;
+            Reply::new(&CreateOut {
+                out_private,
+                out_public: marshal(&object.public)?,
+                creation_data,
+                creation_hash,
+                creation_ticket,
+            })
+        }
+
+        pub(crate) fn load(
+            &mut self,
+            parent_handle: u32,
+            parameters: &[u8],
+        ) -> Result<Reply, ReturnCode> {
+            let load = parse::<LoadIn>(parameters)?;
+            let public: Public = parse(&load.in_public).map_err(|rc| parameter(rc, 2))?;
+            let parent = self.parent(parent_handle)?;
+            let sensitive =
+                unwrap(parent, &public, &load.in_private).map_err(|rc| parameter(rc, 1))?;
+            let object = Object::from_sensitive(
+                public,
+                sensitive,
+                parent.hierarchy,
+                parent.qualified_name()?,
+            )
+            .map_err(|rc| parameter(rc, 2))?;
+
+            let object_handle = self.object_slot()?;
+            let reply = Reply::with_handle(
+                object_handle,
+                &LoadOut {
+                    name: object.name.clone(),
+                },
+            )?;
+            self.objects.insert(object_handle, object);
+            Ok(reply)
+        }
+
+        pub(crate) fn read_public(
+            &mut self,
+            object_handle: u32,
+            parameters: &[u8],
+        ) -> Result<Reply, ReturnCode> {
+            if !parameters.is_empty() {
+                return Err(TPM_RC_SIZE);
+            }
+            let object = self.object(object_handle)?;
+            Reply::new(&ReadPublicOut {
+                out_public: marshal(&object.public)?,
+                name: object.name.clone(),
+                qualified_name: object.qualified_name()?,
+            })
+        }
+
+        /// `TPM2_Hash`, with a ticket for signing the digest with a
+        /// restricted key unless the data starts with `TPM_GENERATED_VALUE`.
+        pub(crate) fn hash(&mut self, parameters: &[u8]) -> Result<Reply, ReturnCode> {
+            let hash = parse::<HashIn>(parameters)?;
+            if hash.data.len() > MAX_DIGEST_BUFFER {
+                return Err(parameter(TPM_RC_SIZE, 1));
+            }
+            let out_hash =
+                crypto::hash(&hash.hash_alg, &[&hash.data]).map_err(|rc| parameter(rc, 2))?;
+
+            let generated = u32::from(ConstantU32::<{ u32::from_be_bytes(*b"\xffTCG") }>);
+            let hierarchy = if hash.data.starts_with(&generated.to_be_bytes()) {
+                Hierarchy::Null
+            } else {
+                hash.hierarchy
+            };
+            let validation = Ticket::Hashcheck {
+                hierarchy,
+                digest: self.ticket_digest(
+                    hierarchy,
+                    &hash.hash_alg,
+                    StructureTag::Hashcheck,
+                    &[&out_hash],
+                )?,
+            };
+            Reply::new(&HashOut {
+                out_hash,
+                validation,
+            })
+        }
+
+        pub(crate) fn sign(
+            &mut self,
+            key_handle: u32,
+            parameters: &[u8],
+        ) -> Result<Reply, ReturnCode> {
+            let sign = parse::<SignIn>(parameters)?;
+            let key = self.object(key_handle)?;
+            let scheme = signing_scheme(key, sign.in_scheme, 2)?;
+            let hash = scheme_hash(&scheme).unwrap();
+            if sign.digest.len() != digest_size(hash)? {
+                return Err(parameter(TPM_RC_SIZE, 1));
+            }
+
+            // restricted keys only sign digests of data not starting with
+            // TPM_GENERATED_VALUE
+            if key.attributes() & TPMA_OBJECT_RESTRICTED != 0 {
+                let valid = match &sign.validation {
+                    Ticket::Hashcheck { hierarchy, digest } if *hierarchy != Hierarchy::Null => {
+                        let expected = self.ticket_digest(
+                            *hierarchy,
+                            hash,
+                            StructureTag::Hashcheck,
+                            &[&sign.digest],
+                        )?;
+                        crypto::equal(digest, &expected)
+                    }
+                    _ => false,
+                };
+                if !valid {
+                    return Err(parameter(TPM_RC_TICKET, 3));
+                }
+            }
+
+            let key = key.clone();
+            let signature = key.sign(&scheme, &sign.digest, &mut self.rng)?;
+            Reply::new(&signature)
+        }
+
+        pub(crate) fn verify_signature(
+            &mut self,
+            key_handle: u32,
+            parameters: &[u8],
+        ) -> Result<Reply, ReturnCode> {
+            let verify = parse::<VerifySignatureIn>(parameters)?;
+            let key = self.object(key_handle)?;
+            if key.attributes() & TPMA_OBJECT_SIGN_ENCRYPT == 0 {
+                return Err(handle(TPM_RC_ATTRIBUTES, 1));
+            }
+            if !key.verify(&verify.digest, &verify.signature) {
+                return Err(parameter(TPM_RC_SIGNATURE, 2));
+            }
+
+            let validation = Ticket::Verified {
+                hierarchy: key.hierarchy,
+                digest: self.ticket_digest(
+                    key.hierarchy,
+                    key.name_alg(),
+                    StructureTag::Verified,
+                    &[&verify.digest, &key.name],
+                )?,
+            };
+            Reply::new(&validation)
+        }
+
+        pub(crate) fn quote(
+            &mut self,
+            sign_handle: u32,
+            parameters: &[u8],
+        ) -> Result<Reply, ReturnCode> {
+            let quote = parse::<QuoteIn>(parameters)?;
+            let key = self.object(sign_handle)?.clone();
+            let scheme = signing_scheme(&key, quote.in_scheme, 2)?;
+            let hash = scheme_hash(&scheme).unwrap();
+            if quote.qualifying_data.len() > digest_size(hash)? {
+                return Err(parameter(TPM_RC_SIZE, 1));
+            }
+
+            let (pcr_select, values) = self.pcrs.select(&quote.pcr_select, usize::MAX);
+            let pcr_digest =
+                crypto::hash(hash, &values.iter().map(|v| &v[..]).collect::<Vec<_>>())?;
+            let attest = marshal(&Attest {
+                magic: ConstantU32,
+                body: AttestBody::Quote {
+                    qualified_signer: key.qualified_name()?,
+                    extra_data: quote.qualifying_data,
+                    clock_info: self.clock_info(),
+                    firmware_version: FIRMWARE_VERSION,
+                    attested: QuoteInfo {
+                        pcr_select,
+                        pcr_digest,
+                    },
+                },
+            })?;
+
+            let digest = crypto::hash(hash, &[&attest])?;
+            let signature = key.sign(&scheme, &digest, &mut self.rng)?;
+            Reply::new(&QuoteOut {
+                quoted: attest,
+                signature,
+            })
+        }
+
+        pub(crate) fn flush_context(&mut self, parameters: &[u8]) -> Result<Reply, ReturnCode> {
+            let flush_handle = u32::from(parse::<FlushContext>(parameters)?.flush_handle);
+            let flushed = match handle_type(flush_handle) {
+                TPM_HT_TRANSIENT => self.objects.remove(&flush_handle).is_some(),
+                _ => self.sessions.remove(&flush_handle).is_some(),
+            };
+            if flushed {
+                Ok(Reply::default())
+            } else {
+                Err(parameter(TPM_RC_HANDLE, 1))
+            }
+        }
+    }
+
+    #[cfg(test)]
+    pub mod tests {
+        use super::*;
+        use crate::object::object::tests::{ecc_parent_template, rsa_signing_template};
+        use crate::simulator::simulator::tests::{command, rc, started};
+        use crate::simulator::simulator::{
+            TPM_HEADER_SIZE, TPM_RH_NULL, TPM_RH_OWNER, TPM_RS_PW, TPM_ST_NO_SESSIONS,
+            TPM_ST_SESSIONS,
+        };
+        use serde_tpm2::de::from_bytes;
+        use tpm2_types::constants::CommandCode;
+        use tpm2_types::selectables::RSAScheme;
+
+        /// Command with a password session for each handle.
+        pub fn authorized(
+            cc: CommandCode,
+            handles: &[u32],
+            password: &[u8],
+            parameters: &[u8],
+        ) -> Vec<u8> {
+            let mut body: Vec<u8> = handles.iter().flat_map(|h| h.to_be_bytes()).collect();
+            let mut session = TPM_RS_PW.to_be_bytes().to_vec();
+            session.extend_from_slice(&[0, 0, 1]);
+            session.extend_from_slice(&(password.len() as u16).to_be_bytes());
+            session.extend_from_slice(password);
+            let auth_area = session.repeat(cc.auth_handle_count());
+            body.extend_from_slice(&(auth_area.len() as u32).to_be_bytes());
+            body.extend(auth_area);
+            body.extend_from_slice(parameters);
+            command(TPM_ST_SESSIONS, cc, &body)
+        }
+
+        pub fn create_parameters(template: &Public, auth: &[u8]) -> Vec<u8> {
+            let sensitive = marshal(&SensitiveCreate {
+                user_auth: auth.to_vec(),
+                data: vec![],
+            })
+            .unwrap();
+            let public = marshal(template).unwrap();
+            let mut parameters = (sensitive.len() as u16).to_be_bytes().to_vec();
+            parameters.extend(sensitive);
+            parameters.extend_from_slice(&(public.len() as u16).to_be_bytes());
+            parameters.extend(public);
+            // outsideInfo, creationPCR: SHA256 PCR 0
+            parameters.extend_from_slice(&[0, 0, 0, 0, 0, 1, 0, 0x0b, 3, 1, 0, 0]);
+            parameters
+        }
+
+        /// Parameters of a successful response with sessions.
+        pub fn response_parameters(response: &[u8], handles: usize) -> &[u8] {
+            assert_eq!(rc(response), TPM_RC_SUCCESS);
+            let start = TPM_HEADER_SIZE + handles * 4;
+            let size = u32::from_be_bytes(response[start..start + 4].try_into().unwrap()) as usize;
+            &response[start + 4..start + 4 + size]
+        }
+
+        /// SRK under the owner hierarchy and a signing key (without
+        /// authorization value) under it.
+        pub fn srk_and_key(tpm: &mut Simulator, scheme: RSAScheme) -> (u32, u32) {
+            let create_primary = authorized(
+                CommandCode::CreatePrimary,
+                &[TPM_RH_OWNER],
+                b"",
+                &create_parameters(&ecc_parent_template(), b"srk"),
+            );
+            let response = tpm.execute(&create_primary);
+            response_parameters(&response, 1);
+            let srk = u32::from_be_bytes(response[10..14].try_into().unwrap());
+
+            let create = authorized(
+                CommandCode::Create,
+                &[srk],
+                b"srk",
+                &create_parameters(&rsa_signing_template(scheme), b""),
+            );
+            let response = tpm.execute(&create);
+            let parameters = response_parameters(&response, 0);
+            let private_size = u16::from_be_bytes(parameters[..2].try_into().unwrap()) as usize;
+            let public_size = u16::from_be_bytes(
+                parameters[2 + private_size..4 + private_size]
+                    .try_into()
+                    .unwrap(),
+            ) as usize;
+            let load = authorized(
+                CommandCode::Load,
+                &[srk],
+                b"srk",
+                &parameters[..4 + private_size + public_size],
+            );
+            let response = tpm.execute(&load);
+            response_parameters(&response, 1);
+            (
+                srk,
+                u32::from_be_bytes(response[10..14].try_into().unwrap()),
+            )
+        }
+
+        #[test]
+        fn test_primary_deterministic() {
+            let mut tpm = started();
+            let create_primary = |hierarchy: u32| {
+                authorized(
+                    CommandCode::CreatePrimary,
+                    &[hierarchy],
+                    b"",
+                    &create_parameters(&ecc_parent_template(), b""),
+                )
+            };
+            let first = tpm.execute(&create_primary(TPM_RH_OWNER));
+            let second = tpm.execute(&create_primary(TPM_RH_OWNER));
+            let null = tpm.execute(&create_primary(TPM_RH_NULL));
+            assert_eq!(
+                rc(&tpm.execute(&create_primary(TPM_RH_OWNER))),
+                TPM_RC_OBJECT_MEMORY
+            );
+
+            // same public area and name
+            let (first, second, null) = (
+                response_parameters(&first, 1),
+                response_parameters(&second, 1),
+                response_parameters(&null, 1),
+            );
+            let public_size = u16::from_be_bytes(first[..2].try_into().unwrap()) as usize;
+            assert_eq!(first[..2 + public_size], second[..2 + public_size]);
+            assert_eq!(first[first.len() - 36..], second[second.len() - 36..]);
+            assert_ne!(first[..2 + public_size], null[..2 + public_size]);
+
+            // the ticket of the null hierarchy is empty
+            assert_eq!(
+                &null[null.len() - 36 - 8..null.len() - 36],
+                &[0x80, 0x21, 0x40, 0, 0, 7, 0, 0]
+            );
+        }
+
+        #[test]
+        fn test_sign_and_quote() {
+            let mut tpm = started();
+            let (_, key) = srk_and_key(&mut tpm, RSAScheme::RSASSA(AlgHash::SHA256));
+            let mut digest_parameters = vec![0, 32];
+            digest_parameters.extend_from_slice(&[0x42; 32]);
+
+            // unrestricted: no ticket necessary, scheme of the key
+            let mut parameters = digest_parameters.clone();
+            parameters.extend_from_slice(&[0, 0x10, 0x80, 0x24, 0x40, 0, 0, 7, 0, 0]);
+            let response = tpm.execute(&authorized(CommandCode::Sign, &[key], b"", &parameters));
+            let signature: Signature = from_bytes(response_parameters(&response, 0)).unwrap();
+            assert!(matches!(&signature, Signature::RSASSA(s) if s.hash == AlgHash::SHA256));
+
+            let mut parameters = digest_parameters.clone();
+            parameters.extend(marshal(&signature).unwrap());
+            let verify = command(
+                TPM_ST_NO_SESSIONS,
+                CommandCode::VerifySignature,
+                &[key.to_be_bytes().as_slice(), &parameters].concat(),
+            );
+            let response = tpm.execute(&verify);
+            assert_eq!(rc(&response), TPM_RC_SUCCESS);
+            assert_eq!(
+                &response[TPM_HEADER_SIZE..TPM_HEADER_SIZE + 6],
+                &[0x80, 0x22, 0x40, 0, 0, 1]
+            );
+            parameters[5] ^= 1;
+            let verify = command(
+                TPM_ST_NO_SESSIONS,
+                CommandCode::VerifySignature,
+                &[key.to_be_bytes().as_slice(), &parameters].concat(),
+            );
+            assert_eq!(rc(&tpm.execute(&verify)), parameter(TPM_RC_SIGNATURE, 2));
+
+            // mismatching scheme
+            let mut parameters = digest_parameters.clone();
+            parameters.extend_from_slice(&[0, 0x16, 0, 0x0b, 0x80, 0x24, 0x40, 0, 0, 7, 0, 0]);
+            let response = tpm.execute(&authorized(CommandCode::Sign, &[key], b"", &parameters));
+            assert_eq!(rc(&response), parameter(TPM_RC_SCHEME, 2));
+
+            // quote SHA256 PCR 0
+            let parameters = [
+                0, 3, b'a', b'b', b'c', 0, 0x10, 0, 0, 0, 1, 0, 0x0b, 3, 1, 0, 0,
+            ];
+            let response = tpm.execute(&authorized(CommandCode::Quote, &[key], b"", &parameters));
+            let parameters = response_parameters(&response, 0);
+            let size = u16::from_be_bytes(parameters[..2].try_into().unwrap()) as usize;
+            let attest: Attest = from_bytes(&parameters[2..2 + size]).unwrap();
+            let AttestBody::Quote {
+                extra_data,
+                attested,
+                ..
+            } = &attest.body
+            else {
+                panic!("not a quote: {attest:?}");
+            };
+            assert_eq!(extra_data, b"abc");
+            assert_eq!(
+                attested.pcr_digest,
+                crypto::hash(&AlgHash::SHA256, &[&[0; 32]]).unwrap()
+            );
+            let signature: Signature = from_bytes(&parameters[2 + size..]).unwrap();
+            let digest = crypto::hash(&AlgHash::SHA256, &[&parameters[2..2 + size]]).unwrap();
+            assert!(tpm.objects[&key].verify(&digest, &signature));
+        }
+
+        #[test]
+        fn test_restricted_sign() {
+            let mut tpm = started();
+            let mut template = rsa_signing_template(RSAScheme::RSAPSS(AlgHash::SHA256));
+            if let Public::RSA {
+                object_attributes, ..
+            } = &mut template
+            {
+                *object_attributes |= TPMA_OBJECT_RESTRICTED;
+            }
+            let response = tpm.execute(&authorized(
+                CommandCode::CreatePrimary,
+                &[TPM_RH_OWNER],
+                b"",
+                &create_parameters(&template, b""),
+            ));
+            response_parameters(&response, 1);
+            let key = u32::from_be_bytes(response[10..14].try_into().unwrap());
+
+            let mut hash = |data: &[u8]| {
+                let mut parameters = (data.len() as u16).to_be_bytes().to_vec();
+                parameters.extend_from_slice(data);
+                parameters.extend_from_slice(&[0, 0x0b]);
+                parameters.extend_from_slice(&TPM_RH_OWNER.to_be_bytes());
+                let response =
+                    tpm.execute(&command(TPM_ST_NO_SESSIONS, CommandCode::Hash, &parameters));
+                assert_eq!(rc(&response), TPM_RC_SUCCESS);
+                response[TPM_HEADER_SIZE..].to_vec()
+            };
+            let ticket = hash(b"data");
+            let generated = hash(b"\xffTCGdata");
+            // TPM_RH_NULL, empty digest
+            assert_eq!(&generated[34..], &[0x80, 0x24, 0x40, 0, 0, 7, 0, 0]);
+
+            let mut parameters = ticket.clone();
+            parameters.splice(34..34, [0, 0x10]);
+            let response = tpm.execute(&authorized(CommandCode::Sign, &[key], b"", &parameters));
+            assert_eq!(rc(&response), TPM_RC_SUCCESS);
+
+            let mut parameters = generated.clone();
+            parameters.splice(34..34, [0, 0x10]);
+            let response = tpm.execute(&authorized(CommandCode::Sign, &[key], b"", &parameters));
+            assert_eq!(rc(&response), parameter(TPM_RC_TICKET, 3));
+        }
+    }
+}
@@ -0,0 +1,158 @@
+pub mod capability;
+pub mod context;
+pub mod crypto;
+pub mod keys;
+pub mod nv;
+pub mod object;
+pub mod pcr;
+pub mod rc;
+pub mod session;
+pub mod simulator;
+
+pub mod lib {
+    use std::time::Duration;
+
+    use tss2_tcti::config::config::{reject_conf, TctiConfig};
+    use tss2_tcti::define_api_symbols;
+    use tss2_tcti::tcti::error::TctiError;
+    use tss2_tcti::tcti::tcti::{Api, Info, PendingResponse, State, TctiLib};
+    use tss2_tcti::telemetry::telemetry::CommandSpan;
+    use tss2_tcti_sys::tpm2_tss;
+
+    use crate::simulator::simulator::Simulator;
+
+    #[derive(TctiConfig, Default, Debug, PartialEq)]
+    pub struct SimConfig {
+        /// Seed of the random number generator, i.e. of primary seeds, keys
+        /// and nonces. Random if not given.
+        pub seed: Option<u64>,
+    }
+
+    /// Tcti executing commands on an in-process [Simulator], e.g. for tests
+    /// without a TPM. Every context has its own simulator, which is powered
+    /// on (but not started) when the context is initialized.
+    #[repr(C)]
+    #[derive(Debug)]
+    pub struct TctiSim {
+        api: Api,
+        state: State,
+        pending_response: PendingResponse,
+        command_span: CommandSpan,
+        simulator: Option<Simulator>,
+        response: Option<Vec<u8>>,
+    }
+
+    impl TctiSim {
+        fn get_simulator(&mut self) -> Result<&mut Simulator, TctiError> {
+            self.simulator.as_mut().ok_or(TctiError::BadSequence)
+        }
+    }
+
+    impl TctiLib for TctiSim {
+        const INFO: Info<'static> = Info {
+            name: b"tpm2_tcti-sim\0",
+            description: b"In-process TPM simulator written in Rust.\0",
+            config_help: <SimConfig as TctiConfig>::CONFIG_HELP,
+        };
+        const MAGIC: u64 = 0x73696d756c61746f;
+
+        fn new(conf: &str) -> Result<Self, TctiError> {
+            let mut tcti = Self {
+                api: Self::get_api_static(),
+                state: State::NotInitialized,
+                pending_response: None,
+                command_span: CommandSpan::default(),
+                simulator: None,
+                response: None,
+            };
+
+            tcti.init(conf)?;
+
+            Ok(tcti)
+        }
+
+        fn init_inner(&mut self, conf: &str) -> Result<(), TctiError> {
+            let config = SimConfig::from_conf(conf).map_err(reject_conf)?;
+
+            self.api = Self::get_api_static();
+            self.simulator = Some(Simulator::new(config.seed));
+            self.response = None;
+            self.state = State::Transmit;
+            Ok(())
+        }
+
+        fn transmit_inner(&mut self, command: &[u8]) -> Result<(), TctiError> {
+            let response = self.get_simulator()?.execute(command);
+            self.response = Some(response);
+            Ok(())
+        }
+
+        fn receive_inner(&mut self, _timeout: Duration) -> Result<Vec<u8>, TctiError> {
+            self.response.take().ok_or(TctiError::BadSequence)
+        }
+
+        fn finalize_inner(&mut self) {
+            self.simulator = None;
+            self.response = None;
+        }
+
+        fn get_state(&self) -> Option<State> {
+            Some(self.state)
+        }
+        fn set_state(&mut self, state: State) {
+            self.state = state;
+        }
+        fn get_pending_response(&mut self) -> Option<&mut PendingResponse> {
+            Some(&mut self.pending_response)
+        }
+
+        fn get_command_span(&mut self) -> Option<&mut CommandSpan> {
+            Some(&mut self.command_span)
+        }
+    }
+
+    define_api_symbols!(TctiSim);
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use tss2_tcti::conformance::conformance::check_tcti;
+        use tss2_tcti::tcti::tcti::Tcti;
+
+        const STARTUP: &[u8] = b"\x80\x01\x00\x00\x00\x0c\x00\x00\x01\x44\x00\x00";
+        const GET_RANDOM: &[u8] = b"\x80\x01\x00\x00\x00\x0c\x00\x00\x01\x7b\x00\x08";
+
+        fn execute(tcti: &mut TctiSim, command: &[u8]) -> Vec<u8> {
+            Tcti::transmit(tcti, command).unwrap();
+            Tcti::receive(tcti).unwrap()
+        }
+
+        #[test]
+        fn test_seed() {
+            let mut a = <TctiSim as Tcti>::new("seed=42").unwrap();
+            let mut b = <TctiSim as Tcti>::new("seed=42").unwrap();
+            let mut c = <TctiSim as Tcti>::new("").unwrap();
+            for tcti in [&mut a, &mut b, &mut c] {
+                assert_eq!(
+                    execute(tcti, STARTUP),
+                    b"\x80\x01\x00\x00\x00\x0a\x00\x00\x00\x00"
+                );
+            }
+
+            let random = execute(&mut a, GET_RANDOM);
+            assert_eq!(
+                &random[..12],
+                b"\x80\x01\x00\x00\x00\x14\x00\x00\x00\x00\x00\x08"
+            );
+            assert_eq!(random, execute(&mut b, GET_RANDOM));
+            assert_ne!(random, execute(&mut c, GET_RANDOM));
+
+            assert!(<TctiSim as Tcti>::new("seed=x").is_err());
+        }
+
+        #[test]
+        fn test_conformance() {
+            check_tcti::<TctiSim>("seed=0", STARTUP);
+        }
+    }
+}
@@ -0,0 +1,415 @@
+/// Ordinary NV indices. Counters, bit fields, extend indices as well as
+/// policy authorizations are not implemented.
+pub mod nv {
+    use serde::{Deserialize, Serialize};
+    use tpm2_types::bitfields::NVAttributes;
+    use tpm2_types::constants::ReturnCode;
+    use tpm2_types::serde_types::sized_vector::U16SizedVector;
+    use tpm2_types::structs::NVPublic;
+
+    use crate::crypto::crypto::{self, digest_size};
+    use crate::rc::rc::*;
+    use crate::simulator::simulator::{
+        handle_type, marshal, parse, Reply, Simulator, TPM_HT_NV_INDEX, TPM_RH_OWNER,
+        TPM_RH_PLATFORM,
+    };
+
+    pub const TPMA_NV_PPWRITE: NVAttributes = 1 << 0;
+    pub const TPMA_NV_OWNERWRITE: NVAttributes = 1 << 1;
+    pub const TPMA_NV_AUTHWRITE: NVAttributes = 1 << 2;
+    pub const TPMA_NV_POLICYWRITE: NVAttributes = 1 << 3;
+    pub const TPMA_NV_TPM_NT: NVAttributes = 0xf << 4;
+    pub const TPMA_NV_POLICY_DELETE: NVAttributes = 1 << 10;
+    pub const TPMA_NV_WRITELOCKED: NVAttributes = 1 << 11;
+    pub const TPMA_NV_WRITEALL: NVAttributes = 1 << 12;
+    pub const TPMA_NV_PPREAD: NVAttributes = 1 << 16;
+    pub const TPMA_NV_OWNERREAD: NVAttributes = 1 << 17;
+    pub const TPMA_NV_AUTHREAD: NVAttributes = 1 << 18;
+    pub const TPMA_NV_POLICYREAD: NVAttributes = 1 << 19;
+    pub const TPMA_NV_READLOCKED: NVAttributes = 1 << 28;
+    pub const TPMA_NV_WRITTEN: NVAttributes = 1 << 29;
+    pub const TPMA_NV_PLATFORMCREATE: NVAttributes = 1 << 30;
+
+    pub const MAX_NV_INDICES: usize = 64;
+    pub const MAX_NV_INDEX_SIZE: usize = 2048;
+    pub const MAX_NV_BUFFER_SIZE: usize = 1024;
+
+    /// Defined NV index.
+    #[derive(Debug, Clone)]
+    pub struct NvIndex {
+        pub public: NVPublic,
+        pub auth: Vec<u8>,
+        pub data: Vec<u8>,
+    }
+
+    impl NvIndex {
+        /// Name of the index, which changes with `TPMA_NV_WRITTEN`.
+        pub fn name(&self) -> Result<Vec<u8>, ReturnCode> {
+            crypto::name(&self.public.name_alg, &marshal(&self.public)?)
+        }
+
+        fn is_set(&self, attribute: NVAttributes) -> bool {
+            self.public.attributes & attribute != 0
+        }
+
+        /// Whether `auth_handle` may access the index, given the attributes
+        /// for platform, owner and index authorization.
+        fn check_access(
+            &self,
+            auth_handle: u32,
+            [platform, owner, index]: [NVAttributes; 3],
+        ) -> Result<(), ReturnCode> {
+            let attribute = match auth_handle {
+                TPM_RH_PLATFORM => platform,
+                TPM_RH_OWNER => owner,
+                handle if handle == u32::from(self.public.nv_index) => index,
+                _ => return Err(handle(TPM_RC_VALUE, 1)),
+            };
+            if self.is_set(attribute) {
+                Ok(())
+            } else {
+                Err(TPM_RC_NV_AUTHORIZATION)
+            }
+        }
+    }
+
+    #[derive(Deserialize)]
+    struct NVDefineSpaceIn {
+        #[serde(with = "U16SizedVector")]
+        auth: Vec<u8>,
+        /// TPM2B_NV_PUBLIC
+        #[serde(with = "U16SizedVector")]
+        public_info: Vec<u8>,
+    }
+
+    #[derive(Serialize)]
+    struct NVReadPublicOut {
+        /// TPM2B_NV_PUBLIC
+        #[serde(with = "U16SizedVector")]
+        nv_public: Vec<u8>,
+        #[serde(with = "U16SizedVector")]
+        nv_name: Vec<u8>,
+    }
+
+    #[derive(Deserialize)]
+    struct NVWriteIn {
+        #[serde(with = "U16SizedVector")]
+        data: Vec<u8>,
+        offset: u16,
+    }
+
+    #[derive(Deserialize)]
+    struct NVReadIn {
+        size: u16,
+        offset: u16,
+    }
+
+    #[derive(Serialize)]
+    struct NVReadOut {
+        #[serde(with = "U16SizedVector")]
+        data: Vec<u8>,
+    }
+
+    /// `TPMI_RH_PROVISION`
+    fn check_provision(auth_handle: u32) -> Result<(), ReturnCode> {
+        match auth_handle {
+            TPM_RH_OWNER | TPM_RH_PLATFORM => Ok(()),
+            _ => Err(handle(TPM_RC_VALUE, 1)),
+        }
+    }
+
+    /// Range of `size` bytes at `offset` within `index`.
+    fn range(
+        index: &NvIndex,
+        offset: u16,
+        size: usize,
+    ) -> Result<std::ops::Range<usize>, ReturnCode> {
+        let (start, end) = (offset as usize, offset as usize + size);
+        if end <= index.data.len() {
+            Ok(start..end)
+        } else {
+            Err(TPM_RC_NV_RANGE)
+        }
+    }
+
+    impl Simulator {
+        pub(crate) fn nv_define_space(
+            &mut self,
+            auth_handle: u32,
+            parameters: &[u8],
+        ) -> Result<Reply, ReturnCode> {
+            check_provision(auth_handle)?;
+            let define = parse::<NVDefineSpaceIn>(parameters)?;
+            let public = parse::<NVPublic>(&define.public_info).map_err(|rc| parameter(rc, 2))?;
+            let nv_index = u32::from(public.nv_index);
+
+            let size = digest_size(&public.name_alg).map_err(|rc| parameter(rc, 2))?;
+            if define.auth.len() > size {
+                return Err(parameter(TPM_RC_SIZE, 1));
+            }
+            if public.data_size as usize > MAX_NV_INDEX_SIZE {
+                return Err(parameter(TPM_RC_SIZE, 2));
+            }
+            if !public.auth_policy.is_empty() && public.auth_policy.len() != size {
+                return Err(parameter(TPM_RC_SIZE, 2));
+            }
+
+            let attributes = public.attributes;
+            let write =
+                TPMA_NV_PPWRITE | TPMA_NV_OWNERWRITE | TPMA_NV_AUTHWRITE | TPMA_NV_POLICYWRITE;
+            let read = TPMA_NV_PPREAD | TPMA_NV_OWNERREAD | TPMA_NV_AUTHREAD | TPMA_NV_POLICYREAD;
+            let platform_create = auth_handle == TPM_RH_PLATFORM;
+            if attributes & TPMA_NV_TPM_NT != 0
+                || attributes & (TPMA_NV_WRITELOCKED | TPMA_NV_READLOCKED | TPMA_NV_WRITTEN) != 0
+                || attributes & write == 0
+                || attributes & read == 0
+                || (attributes & TPMA_NV_PLATFORMCREATE != 0) != platform_create
+                || (attributes & TPMA_NV_POLICY_DELETE != 0 && !platform_create)
+            {
+                return Err(parameter(TPM_RC_ATTRIBUTES, 2));
+            }
+
+            if handle_type(nv_index) != TPM_HT_NV_INDEX {
+                return Err(parameter(TPM_RC_VALUE, 2));
+            }
+            if self.nv.contains_key(&nv_index) {
+                return Err(TPM_RC_NV_DEFINED);
+            }
+            if self.nv.len() >= MAX_NV_INDICES {
+                return Err(TPM_RC_NV_SPACE);
+            }
+
+            let index = NvIndex {
+                data: vec![0xff; public.data_size as usize],
+                public,
+                auth: define.auth,
+            };
+            self.nv.insert(nv_index, index);
+            Ok(Reply::default())
+        }
+
+        pub(crate) fn nv_undefine_space(
+            &mut self,
+            auth_handle: u32,
+            nv_index: u32,
+            parameters: &[u8],
+        ) -> Result<Reply, ReturnCode> {
+            check_provision(auth_handle)?;
+            if !parameters.is_empty() {
+                return Err(TPM_RC_SIZE);
+            }
+            let index = self.nv_index(nv_index)?;
+            if index.is_set(TPMA_NV_POLICY_DELETE) {
+                // requires TPM2_NV_UndefineSpaceSpecial
+                return Err(handle(TPM_RC_ATTRIBUTES, 2));
+            }
+            if index.is_set(TPMA_NV_PLATFORMCREATE) != (auth_handle == TPM_RH_PLATFORM) {
+                return Err(TPM_RC_NV_AUTHORIZATION);
+            }
+
+            self.nv.remove(&nv_index);
+            Ok(Reply::default())
+        }
+
+        pub(crate) fn nv_read_public(
+            &mut self,
+            nv_index: u32,
+            parameters: &[u8],
+        ) -> Result<Reply, ReturnCode> {
+            if !parameters.is_empty() {
+                return Err(TPM_RC_SIZE);
+            }
+            let index = self.nv_index(nv_index)?;
+            Reply::new(&NVReadPublicOut {
+                nv_public: marshal(&index.public)?,
+                nv_name: index.name()?,
+            })
+        }
+
+        pub(crate) fn nv_write(
+            &mut self,
+            auth_handle: u32,
+            nv_index: u32,
+            parameters: &[u8],
+        ) -> Result<Reply, ReturnCode> {
+            let write = parse::<NVWriteIn>(parameters)?;
+            let index = self.nv.get_mut(&nv_index).ok_or(handle(TPM_RC_HANDLE, 2))?;
+            index.check_access(
+                auth_handle,
+                [TPMA_NV_PPWRITE, TPMA_NV_OWNERWRITE, TPMA_NV_AUTHWRITE],
+            )?;
+            if index.is_set(TPMA_NV_WRITELOCKED) {
+                return Err(TPM_RC_NV_LOCKED);
+            }
+            if write.data.len() > MAX_NV_BUFFER_SIZE {
+                return Err(parameter(TPM_RC_SIZE, 1));
+            }
+            let range = range(index, write.offset, write.data.len())?;
+            if index.is_set(TPMA_NV_WRITEALL) && range.len() != index.data.len() {
+                return Err(TPM_RC_NV_RANGE);
+            }
+
+            index.data[range].copy_from_slice(&write.data);
+            index.public.attributes |= TPMA_NV_WRITTEN;
+            Ok(Reply::default())
+        }
+
+        pub(crate) fn nv_read(
+            &mut self,
+            auth_handle: u32,
+            nv_index: u32,
+            parameters: &[u8],
+        ) -> Result<Reply, ReturnCode> {
+            let read = parse::<NVReadIn>(parameters)?;
+            let index = self.nv_index(nv_index).map_err(|rc| handle(rc, 2))?;
+            index.check_access(
+                auth_handle,
+                [TPMA_NV_PPREAD, TPMA_NV_OWNERREAD, TPMA_NV_AUTHREAD],
+            )?;
+            if index.is_set(TPMA_NV_READLOCKED) {
+                return Err(TPM_RC_NV_LOCKED);
+            }
+            if !index.is_set(TPMA_NV_WRITTEN) {
+                return Err(TPM_RC_NV_UNINITIALIZED);
+            }
+            if read.size as usize > MAX_NV_BUFFER_SIZE {
+                return Err(parameter(TPM_RC_VALUE, 1));
+            }
+
+            let range = range(index, read.offset, read.size as usize)?;
+            Reply::new(&NVReadOut {
+                data: index.data[range].to_vec(),
+            })
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::simulator::simulator::tests::{command, rc, started};
+        use crate::simulator::simulator::{
+            TPM_HEADER_SIZE, TPM_RS_PW, TPM_ST_NO_SESSIONS, TPM_ST_SESSIONS,
+        };
+        use tpm2_types::alg::AlgHash;
+        use tpm2_types::constants::CommandCode;
+
+        const INDEX: u32 = 0x01000042;
+
+        fn owner_authorized(handles: &[u32], parameters: &[u8]) -> Vec<u8> {
+            let mut body: Vec<u8> = handles.iter().flat_map(|h| h.to_be_bytes()).collect();
+            body.extend_from_slice(&9u32.to_be_bytes());
+            body.extend_from_slice(&TPM_RS_PW.to_be_bytes());
+            body.extend_from_slice(&[0, 0, 1, 0, 0]);
+            body.extend_from_slice(parameters);
+            body
+        }
+
+        fn define(attributes: NVAttributes, size: u16) -> Vec<u8> {
+            let public = NVPublic {
+                nv_index: INDEX.try_into().unwrap(),
+                name_alg: AlgHash::SHA256,
+                attributes,
+                auth_policy: vec![],
+                data_size: size,
+            };
+            let mut parameters = vec![0, 0];
+            let public = marshal(&public).unwrap();
+            parameters.extend_from_slice(&(public.len() as u16).to_be_bytes());
+            parameters.extend(public);
+            command(
+                TPM_ST_SESSIONS,
+                CommandCode::NVDefineSpace,
+                &owner_authorized(&[TPM_RH_OWNER], &parameters),
+            )
+        }
+
+        fn write(data: &[u8], offset: u16) -> Vec<u8> {
+            let mut parameters = (data.len() as u16).to_be_bytes().to_vec();
+            parameters.extend_from_slice(data);
+            parameters.extend_from_slice(&offset.to_be_bytes());
+            command(
+                TPM_ST_SESSIONS,
+                CommandCode::NVWrite,
+                &owner_authorized(&[TPM_RH_OWNER, INDEX], &parameters),
+            )
+        }
+
+        fn read(size: u16, offset: u16) -> Vec<u8> {
+            let parameters = [size.to_be_bytes(), offset.to_be_bytes()].concat();
+            command(
+                TPM_ST_SESSIONS,
+                CommandCode::NVRead,
+                &owner_authorized(&[TPM_RH_OWNER, INDEX], &parameters),
+            )
+        }
+
+        #[test]
+        fn test_define_write_read() {
+            let mut tpm = started();
+            let attributes = TPMA_NV_OWNERWRITE | TPMA_NV_OWNERREAD;
+            assert_eq!(rc(&tpm.execute(&define(attributes, 8))), TPM_RC_SUCCESS);
+            assert_eq!(rc(&tpm.execute(&define(attributes, 8))), TPM_RC_NV_DEFINED);
+            assert_eq!(rc(&tpm.execute(&read(4, 0))), TPM_RC_NV_UNINITIALIZED);
+
+            let read_public = command(
+                TPM_ST_NO_SESSIONS,
+                CommandCode::NVReadPublic,
+                &INDEX.to_be_bytes(),
+            );
+            let unwritten = tpm.execute(&read_public);
+            assert_eq!(rc(&unwritten), TPM_RC_SUCCESS);
+
+            assert_eq!(rc(&tpm.execute(&write(b"abc", 2))), TPM_RC_SUCCESS);
+            assert_eq!(rc(&tpm.execute(&write(b"abc", 6))), TPM_RC_NV_RANGE);
+            let response = tpm.execute(&read(6, 0));
+            assert_eq!(rc(&response), TPM_RC_SUCCESS);
+            // parameterSize, data
+            assert_eq!(
+                &response[TPM_HEADER_SIZE + 4..TPM_HEADER_SIZE + 12],
+                b"\x00\x06\xff\xffabc\xff"
+            );
+
+            // the name changes with TPMA_NV_WRITTEN
+            let written = tpm.execute(&read_public);
+            assert_eq!(written.len(), unwritten.len());
+            assert_ne!(written, unwritten);
+            let name = tpm.nv[&INDEX].name().unwrap();
+            assert_eq!(&written[written.len() - name.len()..], &name[..]);
+
+            let undefine = command(
+                TPM_ST_SESSIONS,
+                CommandCode::NVUndefineSpace,
+                &owner_authorized(&[TPM_RH_OWNER, INDEX], &[]),
+            );
+            assert_eq!(rc(&tpm.execute(&undefine)), TPM_RC_SUCCESS);
+            assert_eq!(rc(&tpm.execute(&read(4, 0))), handle(TPM_RC_HANDLE, 2));
+        }
+
+        #[test]
+        fn test_attributes() {
+            let mut tpm = started();
+            // no read access
+            let define_write_only = define(TPMA_NV_OWNERWRITE, 8);
+            assert_eq!(
+                rc(&tpm.execute(&define_write_only)),
+                parameter(TPM_RC_ATTRIBUTES, 2)
+            );
+            // counter
+            let counter = define(TPMA_NV_OWNERWRITE | TPMA_NV_OWNERREAD | 0x10, 8);
+            assert_eq!(rc(&tpm.execute(&counter)), parameter(TPM_RC_ATTRIBUTES, 2));
+            let too_large = define(TPMA_NV_OWNERWRITE | TPMA_NV_OWNERREAD, 4096);
+            assert_eq!(rc(&tpm.execute(&too_large)), parameter(TPM_RC_SIZE, 2));
+
+            // owner may read, but not write
+            let attributes = TPMA_NV_AUTHWRITE | TPMA_NV_OWNERREAD | TPMA_NV_WRITEALL;
+            assert_eq!(rc(&tpm.execute(&define(attributes, 4))), TPM_RC_SUCCESS);
+            assert_eq!(
+                rc(&tpm.execute(&write(b"abcd", 0))),
+                TPM_RC_NV_AUTHORIZATION
+            );
+            tpm.nv.get_mut(&INDEX).unwrap().public.attributes |= TPMA_NV_OWNERWRITE;
+            assert_eq!(rc(&tpm.execute(&write(b"ab", 0))), TPM_RC_NV_RANGE);
+            assert_eq!(rc(&tpm.execute(&write(b"abcd", 0))), TPM_RC_SUCCESS);
+        }
+    }
+}
@@ -0,0 +1,718 @@
+/// Loaded objects: key generation from templates, signing and the
+/// protection of their sensitive area.
+pub mod object {
+    use p256::ecdsa::signature::hazmat::{PrehashSigner, PrehashVerifier};
+    use p256::ecdsa::{Signature as EcdsaSignature, SigningKey};
+    use rand::{CryptoRng, RngCore};
+    use rsa::traits::{PrivateKeyParts, PublicKeyParts};
+    use rsa::{BigUint, Pkcs1v15Sign, Pss, RsaPrivateKey};
+    use serde_tpm2::{de::from_bytes, se::to_bytes};
+    use sha1::Sha1;
+    use sha2::{Sha256, Sha384, Sha512};
+    use tpm2_types::alg::{AlgHash, EccCurve};
+    use tpm2_types::bitfields::ObjectAttributes;
+    use tpm2_types::constants::ReturnCode;
+    use tpm2_types::enums::RSAKeyBits;
+    use tpm2_types::handles::Hierarchy;
+    use tpm2_types::selectables::{
+        EccScheme, KdfScheme, KeyedHashScheme, Public, RSAScheme, Sensitive, SigScheme, Signature,
+        SymDefObject,
+    };
+    use tpm2_types::structs::{EccPoint, SensitiveCreate, SignatureECC, SignatureRSA};
+
+    use crate::crypto::crypto::{self, digest_size};
+    use crate::rc::rc::*;
+
+    pub const TPMA_OBJECT_FIXED_TPM: ObjectAttributes = 0x00000002;
+    pub const TPMA_OBJECT_FIXED_PARENT: ObjectAttributes = 0x00000010;
+    pub const TPMA_OBJECT_SENSITIVE_DATA_ORIGIN: ObjectAttributes = 0x00000020;
+    pub const TPMA_OBJECT_USER_WITH_AUTH: ObjectAttributes = 0x00000040;
+    pub const TPMA_OBJECT_RESTRICTED: ObjectAttributes = 0x00010000;
+    pub const TPMA_OBJECT_DECRYPT: ObjectAttributes = 0x00020000;
+    pub const TPMA_OBJECT_SIGN_ENCRYPT: ObjectAttributes = 0x00040000;
+
+    /// Default RSA public exponent, i.e. an exponent of 0 in the template.
+    pub const RSA_DEFAULT_EXPONENT: u32 = 65537;
+
+    pub fn name_alg(public: &Public) -> &AlgHash {
+        match public {
+            Public::KeyedHash { name_alg, .. }
+            | Public::SymCipher { name_alg, .. }
+            | Public::RSA { name_alg, .. }
+            | Public::ECC { name_alg, .. } => name_alg,
+        }
+    }
+
+    pub fn attributes(public: &Public) -> ObjectAttributes {
+        match public {
+            Public::KeyedHash {
+                object_attributes, ..
+            }
+            | Public::SymCipher {
+                object_attributes, ..
+            }
+            | Public::RSA {
+                object_attributes, ..
+            }
+            | Public::ECC {
+                object_attributes, ..
+            } => *object_attributes,
+        }
+    }
+
+    /// Signing scheme of a key, `SigScheme::Null` if the key does not
+    /// restrict it.
+    pub fn scheme(public: &Public) -> SigScheme {
+        match public {
+            Public::KeyedHash {
+                parameters: KeyedHashScheme::HMAC(hash),
+                ..
+            } => SigScheme::HMAC(hash.clone()),
+            Public::RSA { parameters, .. } => match &parameters.scheme {
+                RSAScheme::RSASSA(hash) => SigScheme::RSASSA(hash.clone()),
+                RSAScheme::RSAPSS(hash) => SigScheme::RSAPSS(hash.clone()),
+                _ => SigScheme::Null,
+            },
+            Public::ECC { parameters, .. } => match &parameters.scheme {
+                EccScheme::ECDSA(hash) => SigScheme::ECDSA(hash.clone()),
+                _ => SigScheme::Null,
+            },
+            _ => SigScheme::Null,
+        }
+    }
+
+    /// Hash algorithm of a signing scheme, `None` for `SigScheme::Null`.
+    pub fn scheme_hash(scheme: &SigScheme) -> Option<&AlgHash> {
+        match scheme {
+            SigScheme::HMAC(hash)
+            | SigScheme::RSASSA(hash)
+            | SigScheme::RSAPSS(hash)
+            | SigScheme::ECDSA(hash)
+            | SigScheme::SM2(hash)
+            | SigScheme::ECSCHNORR(hash) => Some(hash),
+            SigScheme::ECDAA(scheme) => Some(&scheme.hash_alg),
+            SigScheme::Null => None,
+        }
+    }
+
+    /// Private part of an object.
+    #[derive(Debug, Clone)]
+    pub enum Key {
+        Rsa(Box<RsaPrivateKey>),
+        Ecc(SigningKey),
+        /// HMAC key or sealed data
+        KeyedHash(Vec<u8>),
+    }
+
+    /// Object loaded into the simulator.
+    #[derive(Debug, Clone)]
+    pub struct Object {
+        pub public: Public,
+        pub key: Key,
+        pub auth: Vec<u8>,
+        /// Protection seed of storage keys, obfuscation value otherwise.
+        pub seed: Vec<u8>,
+        pub hierarchy: Hierarchy,
+        pub name: Vec<u8>,
+        /// Qualified name of the parent, the handle of the hierarchy for
+        /// primary objects.
+        pub parent_qualified_name: Vec<u8>,
+    }
+
+    /// `TPM_RC_SIZE` et al. of the template, i.e. of the second parameter
+    /// of `Create` and `CreatePrimary`.
+    fn template_rc(rc: ReturnCode) -> ReturnCode {
+        parameter(rc, 2)
+    }
+
+    impl Object {
+        fn new(
+            public: Public,
+            key: Key,
+            auth: Vec<u8>,
+            seed: Vec<u8>,
+            hierarchy: Hierarchy,
+            parent_qualified_name: Vec<u8>,
+        ) -> Result<Self, ReturnCode> {
+            let marshalled = to_bytes(&public).map_err(|_| TPM_RC_FAILURE)?;
+            let name = crypto::name(name_alg(&public), &marshalled)?;
+            Ok(Self {
+                public,
+                key,
+                auth,
+                seed,
+                hierarchy,
+                name,
+                parent_qualified_name,
+            })
+        }
+
+        pub fn attributes(&self) -> ObjectAttributes {
+            attributes(&self.public)
+        }
+
+        pub fn name_alg(&self) -> &AlgHash {
+            name_alg(&self.public)
+        }
+
+        /// Whether this is a parent, i.e. a restricted decryption key.
+        pub fn is_storage_key(&self) -> bool {
+            let attributes = self.attributes();
+            attributes & TPMA_OBJECT_RESTRICTED != 0
+                && attributes & TPMA_OBJECT_DECRYPT != 0
+                && attributes & TPMA_OBJECT_SIGN_ENCRYPT == 0
+                && !matches!(self.key, Key::KeyedHash(_))
+        }
+
+        pub fn qualified_name(&self) -> Result<Vec<u8>, ReturnCode> {
+            let mut qualified_name = (self.name_alg().clone() as u16).to_be_bytes().to_vec();
+            qualified_name.extend(crypto::hash(
+                self.name_alg(),
+                &[&self.parent_qualified_name, &self.name],
+            )?);
+            Ok(qualified_name)
+        }
+
+        /// Create an object from `template`. All randomness is taken from
+        /// `rng`, i.e. primary objects are derived from a deterministic
+        /// `rng`. Errors refer to the parameters of `Create`.
+        pub fn generate<R: CryptoRng + RngCore>(
+            template: &Public,
+            sensitive: &SensitiveCreate,
+            rng: &mut R,
+            hierarchy: Hierarchy,
+            parent_qualified_name: Vec<u8>,
+        ) -> Result<Self, ReturnCode> {
+            let mut public = template.clone();
+            let name_alg = name_alg(template);
+            let size = digest_size(name_alg).map_err(template_rc)?;
+            if sensitive.user_auth.len() > size {
+                return Err(parameter(TPM_RC_SIZE, 1));
+            }
+
+            let attributes = attributes(template);
+            let restricted = attributes & TPMA_OBJECT_RESTRICTED != 0;
+            let decrypt = attributes & TPMA_OBJECT_DECRYPT != 0;
+            let sign = attributes & TPMA_OBJECT_SIGN_ENCRYPT != 0;
+            let sensitive_data_origin = attributes & TPMA_OBJECT_SENSITIVE_DATA_ORIGIN != 0;
+            if restricted && decrypt == sign {
+                return Err(template_rc(TPM_RC_ATTRIBUTES));
+            }
+
+            let mut seed = vec![0; size];
+            rng.fill_bytes(&mut seed);
+
+            let key = match &mut public {
+                Public::KeyedHash {
+                    parameters, unique, ..
+                } => {
+                    if decrypt || restricted || (sign && sensitive_data_origin) {
+                        match parameters {
+                            KeyedHashScheme::HMAC(hash) if sign && !decrypt => {
+                                digest_size(hash).map_err(template_rc)?;
+                            }
+                            _ => return Err(template_rc(TPM_RC_SCHEME)),
+                        }
+                    }
+                    if sensitive_data_origin && !sensitive.data.is_empty() {
+                        return Err(template_rc(TPM_RC_ATTRIBUTES));
+                    }
+
+                    let data = if sensitive_data_origin {
+                        let mut data = vec![0; size];
+                        rng.fill_bytes(&mut data);
+                        data
+                    } else {
+                        sensitive.data.clone()
+                    };
+                    *unique = crypto::hash(name_alg, &[&seed, &data])?;
+                    Key::KeyedHash(data)
+                }
+                Public::RSA {
+                    parameters, unique, ..
+                } => {
+                    if !sensitive.data.is_empty() {
+                        return Err(parameter(TPM_RC_VALUE, 1));
+                    }
+                    check_asym(restricted, decrypt, sign, &parameters.symmetric)?;
+                    match (&parameters.scheme, sign, decrypt) {
+                        (RSAScheme::Null, _, _) => (),
+                        (RSAScheme::RSASSA(hash) | RSAScheme::RSAPSS(hash), true, false) => {
+                            digest_size(hash).map_err(template_rc)?;
+                        }
+                        _ => return Err(template_rc(TPM_RC_SCHEME)),
+                    }
+
+                    let bits = match parameters.key_bits {
+                        RSAKeyBits::_1024 => 1024,
+                        RSAKeyBits::_2048 => 2048,
+                        RSAKeyBits::_3072 => 3072,
+                        RSAKeyBits::_4096 => 4096,
+                    };
+                    let exponent = match parameters.exponent {
+                        0 => RSA_DEFAULT_EXPONENT,
+                        exponent => exponent,
+                    };
+                    let key = RsaPrivateKey::new_with_exp(rng, bits, &BigUint::from(exponent))
+                        .map_err(|_| template_rc(TPM_RC_KEY_SIZE))?;
+                    *unique = key.n().to_bytes_be();
+                    Key::Rsa(Box::new(key))
+                }
+                Public::ECC {
+                    parameters, unique, ..
+                } => {
+                    if !sensitive.data.is_empty() {
+                        return Err(parameter(TPM_RC_VALUE, 1));
+                    }
+                    check_asym(restricted, decrypt, sign, &parameters.symmetric)?;
+                    if parameters.curve_id != EccCurve::NistP256 {
+                        return Err(template_rc(TPM_RC_CURVE));
+                    }
+                    if parameters.kdf != KdfScheme::Null {
+                        return Err(template_rc(TPM_RC_KDF));
+                    }
+                    match (&parameters.scheme, sign, decrypt) {
+                        (EccScheme::Null, _, _) => (),
+                        (EccScheme::ECDSA(hash), true, false) => {
+                            digest_size(hash).map_err(template_rc)?;
+                        }
+                        _ => return Err(template_rc(TPM_RC_SCHEME)),
+                    }
+
+                    let key = SigningKey::random(rng);
+                    let point = key.verifying_key().to_encoded_point(false);
+                    *unique = EccPoint {
+                        x: point.x().unwrap().to_vec(),
+                        y: point.y().unwrap().to_vec(),
+                    };
+                    Key::Ecc(key)
+                }
+                Public::SymCipher { .. } => return Err(template_rc(TPM_RC_TYPE)),
+            };
+
+            Self::new(
+                public,
+                key,
+                sensitive.user_auth.clone(),
+                seed,
+                hierarchy,
+                parent_qualified_name,
+            )
+        }
+
+        /// TPMT_SENSITIVE of the object.
+        pub fn sensitive(&self) -> Sensitive {
+            let auth_value = self.auth.clone();
+            let seed_value = self.seed.clone();
+            match &self.key {
+                Key::Rsa(key) => Sensitive::RSA {
+                    auth_value,
+                    seed_value,
+                    sensitive: key.primes()[0].to_bytes_be(),
+                },
+                Key::Ecc(key) => Sensitive::ECC {
+                    auth_value,
+                    seed_value,
+                    sensitive: key.to_bytes().to_vec(),
+                },
+                Key::KeyedHash(data) => Sensitive::KeyedHash {
+                    auth_value,
+                    seed_value,
+                    sensitive: data.clone(),
+                },
+            }
+        }
+
+        /// Object from its public and sensitive area, e.g. after unwrapping
+        /// a private blob.
+        pub fn from_sensitive(
+            public: Public,
+            sensitive: Sensitive,
+            hierarchy: Hierarchy,
+            parent_qualified_name: Vec<u8>,
+        ) -> Result<Self, ReturnCode> {
+            let (key, auth, seed) = match (&public, sensitive) {
+                (
+                    Public::RSA {
+                        parameters, unique, ..
+                    },
+                    Sensitive::RSA {
+                        auth_value,
+                        seed_value,
+                        sensitive,
+                    },
+                ) => {
+                    let n = BigUint::from_bytes_be(unique);
+                    let p = BigUint::from_bytes_be(&sensitive);
+                    let exponent = match parameters.exponent {
+                        0 => RSA_DEFAULT_EXPONENT,
+                        exponent => exponent,
+                    };
+                    if p == BigUint::from(0u32) || &n % &p != BigUint::from(0u32) {
+                        return Err(TPM_RC_KEY);
+                    }
+                    let key = RsaPrivateKey::from_p_q(p.clone(), &n / &p, exponent.into())
+                        .map_err(|_| TPM_RC_KEY)?;
+                    (Key::Rsa(Box::new(key)), auth_value, seed_value)
+                }
+                (
+                    Public::ECC { unique, .. },
+                    Sensitive::ECC {
+                        auth_value,
+                        seed_value,
+                        sensitive,
+                    },
+                ) => {
+                    let key = SigningKey::from_slice(&sensitive).map_err(|_| TPM_RC_KEY)?;
+                    let point = key.verifying_key().to_encoded_point(false);
+                    if point.x().unwrap()[..] != unique.x || point.y().unwrap()[..] != unique.y {
+                        return Err(TPM_RC_KEY);
+                    }
+                    (Key::Ecc(key), auth_value, seed_value)
+                }
+                (
+                    Public::KeyedHash { .. },
+                    Sensitive::KeyedHash {
+                        auth_value,
+                        seed_value,
+                        sensitive,
+                    },
+                ) => (Key::KeyedHash(sensitive), auth_value, seed_value),
+                _ => return Err(TPM_RC_TYPE),
+            };
+
+            Self::new(public, key, auth, seed, hierarchy, parent_qualified_name)
+        }
+
+        /// Sign `digest`. The scheme must have been checked against the
+        /// key's scheme, see [scheme()], and `digest` must match its hash.
+        pub fn sign<R: CryptoRng + RngCore>(
+            &self,
+            scheme: &SigScheme,
+            digest: &[u8],
+            rng: &mut R,
+        ) -> Result<Signature, ReturnCode> {
+            let mut rsa = |key: &RsaPrivateKey,
+                           hash: &AlgHash,
+                           pss: bool|
+             -> Result<SignatureRSA, ReturnCode> {
+                let sig = if pss {
+                    key.sign_with_rng(rng, pss_padding(hash)?, digest)
+                } else {
+                    key.sign(pkcs1v15(hash)?, digest)
+                };
+                Ok(SignatureRSA {
+                    hash: hash.clone(),
+                    sig: sig.map_err(|_| TPM_RC_FAILURE)?,
+                })
+            };
+
+            let signature = match (&self.key, scheme) {
+                (Key::Rsa(key), SigScheme::RSASSA(hash)) => {
+                    Signature::RSASSA(rsa(key, hash, false)?)
+                }
+                (Key::Rsa(key), SigScheme::RSAPSS(hash)) => {
+                    Signature::RSAPSS(rsa(key, hash, true)?)
+                }
+                (Key::Ecc(key), SigScheme::ECDSA(hash)) => {
+                    let signature: EcdsaSignature =
+                        key.sign_prehash(digest).map_err(|_| TPM_RC_FAILURE)?;
+                    let (r, s) = signature.split_bytes();
+                    Signature::ECDSA(SignatureECC {
+                        hash: hash.clone(),
+                        signature_r: r.to_vec(),
+                        signature_s: s.to_vec(),
+                    })
+                }
+                (Key::KeyedHash(key), SigScheme::HMAC(hash)) => Signature::HMAC(crypto::to_digest(
+                    hash,
+                    &crypto::hmac(hash, key, &[digest])?,
+                )?),
+                _ => return Err(TPM_RC_SCHEME),
+            };
+            Ok(signature)
+        }
+
+        /// Whether `signature` is a valid signature of `digest` by this key.
+        pub fn verify(&self, digest: &[u8], signature: &Signature) -> bool {
+            match (&self.key, signature) {
+                (Key::Rsa(key), Signature::RSASSA(signature)) => match pkcs1v15(&signature.hash) {
+                    Ok(padding) => key
+                        .to_public_key()
+                        .verify(padding, digest, &signature.sig)
+                        .is_ok(),
+                    Err(_) => false,
+                },
+                (Key::Rsa(key), Signature::RSAPSS(signature)) => {
+                    match pss_padding(&signature.hash) {
+                        Ok(padding) => key
+                            .to_public_key()
+                            .verify(padding, digest, &signature.sig)
+                            .is_ok(),
+                        Err(_) => false,
+                    }
+                }
+                (Key::Ecc(key), Signature::ECDSA(signature)) => {
+                    let scalar = |value: &[u8]| {
+                        let mut bytes = [0u8; 32];
+                        let start = 32usize.checked_sub(value.len())?;
+                        bytes[start..].copy_from_slice(value);
+                        Some(bytes)
+                    };
+                    let (Some(r), Some(s)) = (
+                        scalar(&signature.signature_r),
+                        scalar(&signature.signature_s),
+                    ) else {
+                        return false;
+                    };
+                    match EcdsaSignature::from_scalars(r, s) {
+                        Ok(signature) => key
+                            .verifying_key()
+                            .verify_prehash(digest, &signature)
+                            .is_ok(),
+                        Err(_) => false,
+                    }
+                }
+                (Key::KeyedHash(key), Signature::HMAC(expected)) => {
+                    match crypto::from_digest(expected) {
+                        Ok((hash, expected)) => match crypto::hmac(&hash, key, &[digest]) {
+                            Ok(hmac) => crypto::equal(&hmac, expected),
+                            Err(_) => false,
+                        },
+                        Err(_) => false,
+                    }
+                }
+                _ => false,
+            }
+        }
+    }
+
+    /// Attributes and symmetric algorithm of an asymmetric key. Parents
+    /// need a symmetric algorithm, which is used by real TPMs to protect
+    /// their children (see [wrap()]); all other keys must not have one.
+    fn check_asym(
+        restricted: bool,
+        decrypt: bool,
+        sign: bool,
+        symmetric: &SymDefObject,
+    ) -> Result<(), ReturnCode> {
+        if !decrypt && !sign {
+            return Err(template_rc(TPM_RC_ATTRIBUTES));
+        }
+        match (restricted && decrypt, symmetric) {
+            (true, SymDefObject::AES { .. }) | (false, SymDefObject::Null) => Ok(()),
+            _ => Err(template_rc(TPM_RC_SYMMETRIC)),
+        }
+    }
+
+    fn pkcs1v15(hash: &AlgHash) -> Result<Pkcs1v15Sign, ReturnCode> {
+        match hash {
+            AlgHash::SHA1 => Ok(Pkcs1v15Sign::new::<Sha1>()),
+            AlgHash::SHA256 => Ok(Pkcs1v15Sign::new::<Sha256>()),
+            AlgHash::SHA384 => Ok(Pkcs1v15Sign::new::<Sha384>()),
+            AlgHash::SHA512 => Ok(Pkcs1v15Sign::new::<Sha512>()),
+            _ => Err(TPM_RC_HASH),
+        }
+    }
+
+    fn pss_padding(hash: &AlgHash) -> Result<Pss, ReturnCode> {
+        match hash {
+            AlgHash::SHA1 => Ok(Pss::new::<Sha1>()),
+            AlgHash::SHA256 => Ok(Pss::new::<Sha256>()),
+            AlgHash::SHA384 => Ok(Pss::new::<Sha384>()),
+            AlgHash::SHA512 => Ok(Pss::new::<Sha512>()),
+            _ => Err(TPM_RC_HASH),
+        }
+    }
+
+    /// Protect the sensitive area of `object` by `parent`, i.e. the content
+    /// of its TPM2B_PRIVATE: an HMAC over the obfuscated TPM2B_SENSITIVE and
+    /// the name of the object, followed by the obfuscated TPM2B_SENSITIVE.
+    ///
+    /// Unlike a real TPM, the simulator obfuscates with KDFa instead of
+    /// encrypting with the symmetric algorithm of the parent, i.e. private
+    /// blobs cannot be exchanged with other TPMs.
+    pub fn wrap(parent: &Object, object: &Object) -> Result<Vec<u8>, ReturnCode> {
+        let sensitive = to_bytes(&object.sensitive()).map_err(|_| TPM_RC_FAILURE)?;
+        let mut encrypted = (sensitive.len() as u16).to_be_bytes().to_vec();
+        encrypted.extend(sensitive);
+
+        let hash = parent.name_alg();
+        crypto::xor(hash, &parent.seed, &object.name, b"", &mut encrypted)?;
+        let integrity = integrity(parent, &encrypted, &object.name)?;
+
+        let mut private = (integrity.len() as u16).to_be_bytes().to_vec();
+        private.extend(integrity);
+        private.extend(encrypted);
+        Ok(private)
+    }
+
+    fn integrity(parent: &Object, encrypted: &[u8], name: &[u8]) -> Result<Vec<u8>, ReturnCode> {
+        let hash = parent.name_alg();
+        let bits = digest_size(hash)? as u32 * 8;
+        let key = crypto::kdfa(hash, &parent.seed, "INTEGRITY", b"", b"", bits)?;
+        crypto::hmac(hash, &key, &[encrypted, name])
+    }
+
+    /// Inverse of [wrap()], `TPM_RC_INTEGRITY` if `private` was not created
+    /// by `parent` for `public`.
+    pub fn unwrap(
+        parent: &Object,
+        public: &Public,
+        private: &[u8],
+    ) -> Result<Sensitive, ReturnCode> {
+        let marshalled = to_bytes(public).map_err(|_| TPM_RC_FAILURE)?;
+        let name = crypto::name(name_alg(public), &marshalled)?;
+
+        let size = match private.get(..2) {
+            Some(size) => u16::from_be_bytes(size.try_into().unwrap()) as usize,
+            None => return Err(TPM_RC_INSUFFICIENT),
+        };
+        let (integrity_hmac, encrypted) = match private.get(2..2 + size) {
+            Some(integrity_hmac) => (integrity_hmac, &private[2 + size..]),
+            None => return Err(TPM_RC_INSUFFICIENT),
+        };
+        if !crypto::equal(integrity_hmac, &integrity(parent, encrypted, &name)?) {
+            return Err(TPM_RC_INTEGRITY);
+        }
+
+        let mut sensitive = encrypted.to_vec();
+        crypto::xor(parent.name_alg(), &parent.seed, &name, b"", &mut sensitive)?;
+        match sensitive.get(2..) {
+            Some(sensitive) => from_bytes::<Sensitive>(sensitive).map_err(|_| TPM_RC_INTEGRITY),
+            None => Err(TPM_RC_INTEGRITY),
+        }
+    }
+
+    #[cfg(test)]
+    pub mod tests {
+        use super::*;
+        use rand::SeedableRng;
+        use rand_chacha::ChaCha20Rng;
+        use tpm2_types::alg::AlgSymMode;
+        use tpm2_types::enums::AESKeyBits;
+        use tpm2_types::structs::{ECCParams, RSAParams};
+
+        /// Template of a restricted decryption key, e.g. an SRK.
+        pub fn ecc_parent_template() -> Public {
+            Public::ECC {
+                name_alg: AlgHash::SHA256,
+                object_attributes: TPMA_OBJECT_FIXED_TPM
+                    | TPMA_OBJECT_FIXED_PARENT
+                    | TPMA_OBJECT_SENSITIVE_DATA_ORIGIN
+                    | TPMA_OBJECT_USER_WITH_AUTH
+                    | TPMA_OBJECT_RESTRICTED
+                    | TPMA_OBJECT_DECRYPT,
+                auth_policy: vec![],
+                parameters: ECCParams {
+                    symmetric: SymDefObject::AES {
+                        key_bits: AESKeyBits::_128,
+                        mode: AlgSymMode::CFB,
+                    },
+                    scheme: EccScheme::Null,
+                    curve_id: EccCurve::NistP256,
+                    kdf: KdfScheme::Null,
+                },
+                unique: EccPoint {
+                    x: vec![],
+                    y: vec![],
+                },
+            }
+        }
+
+        pub fn rsa_signing_template(scheme: RSAScheme) -> Public {
+            Public::RSA {
+                name_alg: AlgHash::SHA256,
+                object_attributes: TPMA_OBJECT_FIXED_TPM
+                    | TPMA_OBJECT_FIXED_PARENT
+                    | TPMA_OBJECT_SENSITIVE_DATA_ORIGIN
+                    | TPMA_OBJECT_USER_WITH_AUTH
+                    | TPMA_OBJECT_SIGN_ENCRYPT,
+                auth_policy: vec![],
+                parameters: RSAParams {
+                    symmetric: SymDefObject::Null,
+                    scheme,
+                    key_bits: RSAKeyBits::_1024,
+                    exponent: 0,
+                },
+                unique: vec![],
+            }
+        }
+
+        fn generate(template: &Public, seed: u64) -> Result<Object, ReturnCode> {
+            let sensitive = SensitiveCreate {
+                user_auth: b"auth".to_vec(),
+                data: vec![],
+            };
+            let mut rng = ChaCha20Rng::seed_from_u64(seed);
+            Object::generate(template, &sensitive, &mut rng, Hierarchy::Owner, vec![])
+        }
+
+        #[test]
+        fn test_generate_deterministic() {
+            let a = generate(&ecc_parent_template(), 1).unwrap();
+            assert_eq!(a.name, generate(&ecc_parent_template(), 1).unwrap().name);
+            assert_ne!(a.name, generate(&ecc_parent_template(), 2).unwrap().name);
+            assert!(a.is_storage_key());
+
+            let mut template = ecc_parent_template();
+            if let Public::ECC { parameters, .. } = &mut template {
+                parameters.curve_id = EccCurve::NistP384;
+            }
+            assert_eq!(
+                generate(&template, 1).unwrap_err(),
+                parameter(TPM_RC_CURVE, 2)
+            );
+            if let Public::ECC { parameters, .. } = &mut template {
+                parameters.curve_id = EccCurve::NistP256;
+                parameters.symmetric = SymDefObject::Null;
+            }
+            assert_eq!(
+                generate(&template, 1).unwrap_err(),
+                parameter(TPM_RC_SYMMETRIC, 2)
+            );
+        }
+
+        #[test]
+        fn test_sign_and_wrap() {
+            let mut rng = ChaCha20Rng::seed_from_u64(0);
+            let parent = generate(&ecc_parent_template(), 1).unwrap();
+            let digest = [0x5a; 32];
+
+            for scheme in [
+                SigScheme::RSASSA(AlgHash::SHA256),
+                SigScheme::RSAPSS(AlgHash::SHA256),
+            ] {
+                let key = generate(&rsa_signing_template(RSAScheme::Null), 3).unwrap();
+                let signature = key.sign(&scheme, &digest, &mut rng).unwrap();
+                assert!(key.verify(&digest, &signature));
+                assert!(!key.verify(&[0; 32], &signature));
+
+                // the sensitive area survives wrapping
+                let private = wrap(&parent, &key).unwrap();
+                let sensitive = unwrap(&parent, &key.public, &private).unwrap();
+                let loaded =
+                    Object::from_sensitive(key.public.clone(), sensitive, Hierarchy::Owner, vec![])
+                        .unwrap();
+                assert!(loaded.verify(&digest, &signature));
+                assert_eq!(loaded.auth, b"auth");
+
+                let mut tampered = private.clone();
+                *tampered.last_mut().unwrap() ^= 1;
+                assert_eq!(
+                    unwrap(&parent, &key.public, &tampered).unwrap_err(),
+                    TPM_RC_INTEGRITY
+                );
+            }
+
+            let signature = parent
+                .sign(&SigScheme::ECDSA(AlgHash::SHA256), &digest, &mut rng)
+                .unwrap();
+            assert!(parent.verify(&digest, &signature));
+            assert_eq!(
+                parent.sign(&SigScheme::RSASSA(AlgHash::SHA256), &digest, &mut rng),
+                Err(TPM_RC_SCHEME)
+            );
+        }
+    }
+}
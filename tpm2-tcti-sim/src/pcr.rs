@@ -0,0 +1,272 @@
+/// Platform Configuration Registers: a SHA1 and a SHA256 bank with the
+/// PCRs of a PC client platform.
+pub mod pcr {
+    use serde::{Deserialize, Serialize};
+    use tpm2_types::alg::AlgHash;
+    use tpm2_types::constants::ReturnCode;
+    use tpm2_types::selectables::Digest;
+    use tpm2_types::serde_types::sized_vector::U32SizedVector;
+    use tpm2_types::structs::PCRSelection;
+
+    use crate::crypto::crypto::{self, digest_size};
+    use crate::rc::rc::*;
+    use crate::simulator::simulator::{parse, Buffer, Reply, Simulator, TPM_RH_NULL};
+
+    pub const PCR_COUNT: usize = 24;
+    /// Size of a PCR selection covering all PCRs.
+    pub const PCR_SELECT_SIZE: usize = PCR_COUNT / 8;
+    /// PCRs resettable in locality 0.
+    pub const RESETTABLE_PCRS: [usize; 2] = [16, 23];
+    /// At most that many values are returned by `TPM2_PCR_Read`.
+    pub const MAX_PCR_VALUES: usize = 8;
+
+    /// PCR banks and the update counter.
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct Pcrs {
+        pub banks: Vec<(AlgHash, Vec<Vec<u8>>)>,
+        pub update_counter: u32,
+    }
+
+    impl Default for Pcrs {
+        fn default() -> Self {
+            let bank = |alg: AlgHash| {
+                let size = digest_size(&alg).unwrap();
+                (alg, vec![vec![0; size]; PCR_COUNT])
+            };
+            Self {
+                banks: vec![bank(AlgHash::SHA1), bank(AlgHash::SHA256)],
+                update_counter: 0,
+            }
+        }
+    }
+
+    impl Pcrs {
+        fn bank(&self, alg: &AlgHash) -> Option<&Vec<Vec<u8>>> {
+            self.banks
+                .iter()
+                .find(|(a, _)| a == alg)
+                .map(|(_, pcrs)| pcrs)
+        }
+
+        /// Selection of all PCRs of all banks, i.e. `TPM_CAP_PCRS`.
+        pub fn allocation(&self) -> Vec<PCRSelection> {
+            self.banks
+                .iter()
+                .map(|(alg, _)| PCRSelection {
+                    hash: alg.clone(),
+                    pcr_select: vec![0xff; PCR_SELECT_SIZE],
+                })
+                .collect()
+        }
+
+        /// Values of the selected PCRs, at most `max` of them. The returned
+        /// selection has the bits of unimplemented banks and PCRs as well
+        /// as the ones exceeding `max` cleared.
+        pub fn select(
+            &self,
+            selection: &[PCRSelection],
+            max: usize,
+        ) -> (Vec<PCRSelection>, Vec<Vec<u8>>) {
+            let mut values = Vec::new();
+            let selection = selection
+                .iter()
+                .map(|selection| {
+                    let mut pcr_select = vec![0; selection.pcr_select.len()];
+                    if let Some(bank) = self.bank(&selection.hash) {
+                        for (pcr, value) in bank.iter().enumerate() {
+                            let (byte, bit) = (pcr / 8, 1 << (pcr % 8));
+                            let selected = selection.pcr_select.get(byte).copied().unwrap_or(0);
+                            if selected & bit != 0 && values.len() < max {
+                                pcr_select[byte] |= bit;
+                                values.push(value.clone());
+                            }
+                        }
+                    }
+                    PCRSelection {
+                        hash: selection.hash.clone(),
+                        pcr_select,
+                    }
+                })
+                .collect();
+            (selection, values)
+        }
+    }
+
+    #[derive(Deserialize)]
+    struct PCRReadIn {
+        #[serde(with = "U32SizedVector")]
+        pcr_selection_in: Vec<PCRSelection>,
+    }
+
+    #[derive(Serialize)]
+    struct PCRReadOut {
+        pcr_update_counter: u32,
+        #[serde(with = "U32SizedVector")]
+        pcr_selection_out: Vec<PCRSelection>,
+        #[serde(with = "U32SizedVector")]
+        pcr_values: Vec<Buffer>,
+    }
+
+    #[derive(Deserialize)]
+    struct PCRExtendIn {
+        #[serde(with = "U32SizedVector")]
+        digests: Vec<Digest>,
+    }
+
+    /// Index of a PCR handle, `TPM_RC_VALUE` otherwise.
+    fn pcr_index(pcr_handle: u32) -> Result<usize, ReturnCode> {
+        match pcr_handle as usize {
+            index if index < PCR_COUNT => Ok(index),
+            _ => Err(handle(TPM_RC_VALUE, 1)),
+        }
+    }
+
+    impl Simulator {
+        pub(crate) fn pcr_read(&mut self, parameters: &[u8]) -> Result<Reply, ReturnCode> {
+            let read = parse::<PCRReadIn>(parameters)?;
+            let (pcr_selection_out, values) =
+                self.pcrs.select(&read.pcr_selection_in, MAX_PCR_VALUES);
+            Reply::new(&PCRReadOut {
+                pcr_update_counter: self.pcrs.update_counter,
+                pcr_selection_out,
+                pcr_values: values.into_iter().map(|buffer| Buffer { buffer }).collect(),
+            })
+        }
+
+        pub(crate) fn pcr_extend(
+            &mut self,
+            pcr_handle: u32,
+            parameters: &[u8],
+        ) -> Result<Reply, ReturnCode> {
+            let extend = parse::<PCRExtendIn>(parameters)?;
+            if pcr_handle == TPM_RH_NULL {
+                return Ok(Reply::default());
+            }
+            let index = pcr_index(pcr_handle)?;
+
+            for digest in extend.digests.iter() {
+                let (alg, digest) = crypto::from_digest(digest).map_err(|rc| parameter(rc, 1))?;
+                if let Some((_, bank)) = self.pcrs.banks.iter_mut().find(|(a, _)| *a == alg) {
+                    bank[index] = crypto::hash(&alg, &[&bank[index], digest])?;
+                }
+            }
+            self.pcrs.update_counter = self.pcrs.update_counter.wrapping_add(1);
+            Ok(Reply::default())
+        }
+
+        pub(crate) fn pcr_reset(
+            &mut self,
+            pcr_handle: u32,
+            parameters: &[u8],
+        ) -> Result<Reply, ReturnCode> {
+            if !parameters.is_empty() {
+                return Err(TPM_RC_SIZE);
+            }
+            let index = pcr_index(pcr_handle)?;
+            if !RESETTABLE_PCRS.contains(&index) {
+                return Err(TPM_RC_LOCALITY);
+            }
+
+            for (_, bank) in self.pcrs.banks.iter_mut() {
+                bank[index].fill(0);
+            }
+            self.pcrs.update_counter = self.pcrs.update_counter.wrapping_add(1);
+            Ok(Reply::default())
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::simulator::simulator::tests::{command, rc, started};
+        use crate::simulator::simulator::{
+            TPM_HEADER_SIZE, TPM_RS_PW, TPM_ST_NO_SESSIONS, TPM_ST_SESSIONS,
+        };
+        use tpm2_types::constants::CommandCode;
+
+        fn password_session(handle: u32, parameters: &[u8]) -> Vec<u8> {
+            let mut body = handle.to_be_bytes().to_vec();
+            body.extend_from_slice(&9u32.to_be_bytes());
+            body.extend_from_slice(&TPM_RS_PW.to_be_bytes());
+            body.extend_from_slice(&[0, 0, 0, 0, 0]);
+            body.extend_from_slice(parameters);
+            body
+        }
+
+        #[test]
+        fn test_extend_and_read() {
+            let mut tpm = started();
+            // SHA256 digest of PCR 16 and SHA1 digest of PCRs 0, 1 and 16
+            let read = command(
+                TPM_ST_NO_SESSIONS,
+                CommandCode::PCRRead,
+                &[0, 0, 0, 2, 0, 0x0b, 3, 0, 0, 1, 0, 0x04, 3, 3, 0, 1],
+            );
+
+            let mut digests = vec![0, 0, 0, 1, 0, 0x0b];
+            digests.extend_from_slice(&[0xab; 32]);
+            let extend = command(
+                TPM_ST_SESSIONS,
+                CommandCode::PCRExtend,
+                &password_session(16, &digests),
+            );
+            assert_eq!(rc(&tpm.execute(&extend)), TPM_RC_SUCCESS);
+
+            let response = tpm.execute(&read);
+            assert_eq!(rc(&response), TPM_RC_SUCCESS);
+            let expected = crypto::hash(&AlgHash::SHA256, &[&[0; 32], &[0xab; 32]]).unwrap();
+            let parameters = &response[TPM_HEADER_SIZE..];
+            assert_eq!(&parameters[..4], &1u32.to_be_bytes());
+            assert_eq!(
+                &parameters[4..20],
+                &[0, 0, 0, 2, 0, 0x0b, 3, 0, 0, 1, 0, 0x04, 3, 3, 0, 1]
+            );
+            assert_eq!(&parameters[20..24], &4u32.to_be_bytes());
+            assert_eq!(&parameters[24..26], &[0, 32]);
+            assert_eq!(&parameters[26..58], &expected[..]);
+            // the SHA1 bank is unchanged
+            assert_eq!(
+                &parameters[58..],
+                [[0, 20].as_slice(), &[0; 20]].concat().repeat(3)
+            );
+
+            let reset = command(
+                TPM_ST_SESSIONS,
+                CommandCode::PCRReset,
+                &password_session(16, &[]),
+            );
+            assert_eq!(rc(&tpm.execute(&reset)), TPM_RC_SUCCESS);
+            assert_eq!(
+                tpm.pcrs,
+                Pcrs {
+                    update_counter: 2,
+                    ..Pcrs::default()
+                }
+            );
+            let reset = command(
+                TPM_ST_SESSIONS,
+                CommandCode::PCRReset,
+                &password_session(0, &[]),
+            );
+            assert_eq!(rc(&tpm.execute(&reset)), TPM_RC_LOCALITY);
+        }
+
+        #[test]
+        fn test_select_max() {
+            let pcrs = Pcrs::default();
+            let all = pcrs.allocation();
+            let (selection, values) = pcrs.select(&all, MAX_PCR_VALUES);
+            assert_eq!(values.len(), MAX_PCR_VALUES);
+            assert_eq!(selection[0].pcr_select, vec![0xff, 0, 0]);
+            assert_eq!(selection[1].pcr_select, vec![0, 0, 0]);
+
+            let sha384 = [PCRSelection {
+                hash: AlgHash::SHA384,
+                pcr_select: vec![0xff; 3],
+            }];
+            let (selection, values) = pcrs.select(&sha384, MAX_PCR_VALUES);
+            assert!(values.is_empty());
+            assert_eq!(selection[0].pcr_select, vec![0, 0, 0]);
+        }
+    }
+}
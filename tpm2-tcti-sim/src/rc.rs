@@ -0,0 +1,73 @@
+/// Response codes (TPM 2.0 Part 2, TPM_RC) returned by the simulator.
+pub mod rc {
+    use tpm2_types::constants::ReturnCode;
+
+    pub const TPM_RC_SUCCESS: ReturnCode = 0x000;
+    pub const TPM_RC_BAD_TAG: ReturnCode = 0x01e;
+
+    // format zero
+    pub const TPM_RC_INITIALIZE: ReturnCode = 0x100;
+    pub const TPM_RC_FAILURE: ReturnCode = 0x101;
+    pub const TPM_RC_AUTH_TYPE: ReturnCode = 0x124;
+    pub const TPM_RC_AUTH_MISSING: ReturnCode = 0x125;
+    pub const TPM_RC_AUTH_UNAVAILABLE: ReturnCode = 0x12f;
+    pub const TPM_RC_COMMAND_SIZE: ReturnCode = 0x142;
+    pub const TPM_RC_COMMAND_CODE: ReturnCode = 0x143;
+    pub const TPM_RC_AUTHSIZE: ReturnCode = 0x144;
+    pub const TPM_RC_AUTH_CONTEXT: ReturnCode = 0x145;
+    pub const TPM_RC_NV_RANGE: ReturnCode = 0x146;
+    pub const TPM_RC_NV_LOCKED: ReturnCode = 0x148;
+    pub const TPM_RC_NV_AUTHORIZATION: ReturnCode = 0x149;
+    pub const TPM_RC_NV_UNINITIALIZED: ReturnCode = 0x14a;
+    pub const TPM_RC_NV_SPACE: ReturnCode = 0x14b;
+    pub const TPM_RC_NV_DEFINED: ReturnCode = 0x14c;
+
+    // format one, combined with the number of the handle, parameter or
+    // session, see handle(), parameter() and session()
+    pub const TPM_RC_ATTRIBUTES: ReturnCode = 0x082;
+    pub const TPM_RC_HASH: ReturnCode = 0x083;
+    pub const TPM_RC_VALUE: ReturnCode = 0x084;
+    pub const TPM_RC_HIERARCHY: ReturnCode = 0x085;
+    pub const TPM_RC_KEY_SIZE: ReturnCode = 0x087;
+    pub const TPM_RC_TYPE: ReturnCode = 0x08a;
+    pub const TPM_RC_HANDLE: ReturnCode = 0x08b;
+    pub const TPM_RC_KDF: ReturnCode = 0x08c;
+    pub const TPM_RC_AUTH_FAIL: ReturnCode = 0x08e;
+    pub const TPM_RC_SCHEME: ReturnCode = 0x092;
+    pub const TPM_RC_SIZE: ReturnCode = 0x095;
+    pub const TPM_RC_SYMMETRIC: ReturnCode = 0x096;
+    pub const TPM_RC_INSUFFICIENT: ReturnCode = 0x09a;
+    pub const TPM_RC_SIGNATURE: ReturnCode = 0x09b;
+    pub const TPM_RC_KEY: ReturnCode = 0x09c;
+    pub const TPM_RC_INTEGRITY: ReturnCode = 0x09f;
+    pub const TPM_RC_TICKET: ReturnCode = 0x0a0;
+    pub const TPM_RC_CURVE: ReturnCode = 0x0a6;
+
+    // warnings
+    pub const TPM_RC_OBJECT_MEMORY: ReturnCode = 0x902;
+    pub const TPM_RC_SESSION_MEMORY: ReturnCode = 0x903;
+    pub const TPM_RC_SESSION_HANDLES: ReturnCode = 0x905;
+    pub const TPM_RC_LOCALITY: ReturnCode = 0x907;
+    pub const TPM_RC_REFERENCE_H0: ReturnCode = 0x910;
+    pub const TPM_RC_REFERENCE_S0: ReturnCode = 0x918;
+
+    /// Error refers to a parameter.
+    pub const TPM_RC_P: ReturnCode = 0x040;
+    /// Error refers to a session.
+    pub const TPM_RC_S: ReturnCode = 0x800;
+
+    /// Format one `rc` caused by the `n`th (1-based) handle.
+    pub const fn handle(rc: ReturnCode, n: u32) -> ReturnCode {
+        rc | (n << 8)
+    }
+
+    /// Format one `rc` caused by the `n`th (1-based) parameter.
+    pub const fn parameter(rc: ReturnCode, n: u32) -> ReturnCode {
+        rc | TPM_RC_P | (n << 8)
+    }
+
+    /// Format one `rc` caused by the `n`th (1-based) session.
+    pub const fn session(rc: ReturnCode, n: u32) -> ReturnCode {
+        rc | TPM_RC_S | (n << 8)
+    }
+}
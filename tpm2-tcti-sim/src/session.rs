@@ -0,0 +1,277 @@
+/// HMAC authorization sessions (TPM 2.0 Part 1, 19.6).
+pub mod session {
+    use serde::{Deserialize, Serialize};
+    use tpm2_types::alg::AlgHash;
+    use tpm2_types::bitfields::SessionAttributes;
+    use tpm2_types::constants::{ReturnCode, SessionType};
+    use tpm2_types::selectables::SymDef;
+    use tpm2_types::serde_types::sized_vector::U16SizedVector;
+
+    use crate::crypto::crypto::{self, digest_size};
+    use crate::rc::rc::*;
+    use crate::simulator::simulator::{
+        parse, Reply, Simulator, MAX_ACTIVE_SESSIONS, MAX_LOADED_SESSIONS, TPM_HT_HMAC_SESSION,
+        TPM_RH_NULL,
+    };
+
+    pub const TPMA_SESSION_CONTINUE_SESSION: SessionAttributes = 0x01;
+    pub const TPMA_SESSION_AUDIT_EXCLUSIVE: SessionAttributes = 0x02;
+    pub const TPMA_SESSION_AUDIT_RESET: SessionAttributes = 0x04;
+    pub const TPMA_SESSION_DECRYPT: SessionAttributes = 0x20;
+    pub const TPMA_SESSION_ENCRYPT: SessionAttributes = 0x40;
+    pub const TPMA_SESSION_AUDIT: SessionAttributes = 0x80;
+
+    /// State of a loaded session, marshalled into its saved context.
+    #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+    pub struct Session {
+        pub hash: AlgHash,
+        #[serde(with = "U16SizedVector")]
+        pub nonce_tpm: Vec<u8>,
+        #[serde(with = "U16SizedVector")]
+        pub session_key: Vec<u8>,
+        /// Name of the bind entity, empty for unbound sessions.
+        #[serde(with = "U16SizedVector")]
+        pub bind: Vec<u8>,
+    }
+
+    impl Session {
+        /// HMAC of a command or response (`data`) authorizing the entity
+        /// with `name` and `auth_value`. The authorization value of the bind
+        /// entity is already part of the session key.
+        pub fn hmac(
+            &self,
+            auth_value: &[u8],
+            name: &[u8],
+            data: &[&[u8]],
+            session_attributes: SessionAttributes,
+        ) -> Result<Vec<u8>, ReturnCode> {
+            let mut key = self.session_key.clone();
+            if self.bind.is_empty() || self.bind != name {
+                key.extend_from_slice(auth_value);
+            }
+            let attributes = [session_attributes];
+            let data: Vec<&[u8]> = data.iter().copied().chain([&attributes[..]]).collect();
+            crypto::hmac(&self.hash, &key, &data)
+        }
+    }
+
+    /// Slot of an active session. A saved session keeps its slot (and
+    /// handle), remembering the sequence of the context to prevent replay.
+    #[derive(Debug, Clone)]
+    pub enum SessionSlot {
+        Loaded(Session),
+        Saved(u64),
+    }
+
+    #[derive(Deserialize)]
+    struct StartAuthSessionIn {
+        #[serde(with = "U16SizedVector")]
+        nonce_caller: Vec<u8>,
+        #[serde(with = "U16SizedVector")]
+        encrypted_salt: Vec<u8>,
+        session_type: SessionType,
+        symmetric: SymDef,
+        auth_hash: AlgHash,
+    }
+
+    #[derive(Serialize)]
+    struct StartAuthSessionOut {
+        #[serde(with = "U16SizedVector")]
+        nonce_tpm: Vec<u8>,
+    }
+
+    impl Simulator {
+        /// `TPM2_StartAuthSession`, without salt, parameter encryption and
+        /// policy sessions.
+        pub(crate) fn start_auth_session(
+            &mut self,
+            tpm_key: u32,
+            bind: u32,
+            parameters: &[u8],
+        ) -> Result<Reply, ReturnCode> {
+            let start = parse::<StartAuthSessionIn>(parameters)?;
+            if tpm_key != TPM_RH_NULL {
+                return Err(handle(TPM_RC_KEY, 1));
+            }
+            if !start.encrypted_salt.is_empty() {
+                return Err(parameter(TPM_RC_VALUE, 2));
+            }
+            if start.session_type != SessionType::HMAC {
+                return Err(parameter(TPM_RC_VALUE, 3));
+            }
+            if start.symmetric != SymDef::Null {
+                return Err(parameter(TPM_RC_SYMMETRIC, 4));
+            }
+            let size = digest_size(&start.auth_hash).map_err(|rc| parameter(rc, 5))?;
+            if start.nonce_caller.len() < 16 || start.nonce_caller.len() > size {
+                return Err(parameter(TPM_RC_SIZE, 1));
+            }
+
+            let loaded = self
+                .sessions
+                .values()
+                .filter(|slot| matches!(slot, SessionSlot::Loaded(_)))
+                .count();
+            if loaded >= MAX_LOADED_SESSIONS {
+                return Err(TPM_RC_SESSION_MEMORY);
+            }
+            let session_handle = (0..MAX_ACTIVE_SESSIONS as u32)
+                .map(|i| (TPM_HT_HMAC_SESSION << 24) | i)
+                .find(|h| !self.sessions.contains_key(h))
+                .ok_or(TPM_RC_SESSION_HANDLES)?;
+
+            let nonce_tpm = self.random(size);
+            let (session_key, bind) = match bind {
+                TPM_RH_NULL => (Vec::new(), Vec::new()),
+                bind => {
+                    let auth_value = self.entity_auth(bind, 2)?;
+                    let session_key = crypto::kdfa(
+                        &start.auth_hash,
+                        &auth_value,
+                        "ATH",
+                        &nonce_tpm,
+                        &start.nonce_caller,
+                        size as u32 * 8,
+                    )?;
+                    (session_key, self.entity_name(bind)?)
+                }
+            };
+
+            let session = Session {
+                hash: start.auth_hash,
+                nonce_tpm: nonce_tpm.clone(),
+                session_key,
+                bind,
+            };
+            self.sessions
+                .insert(session_handle, SessionSlot::Loaded(session));
+            Reply::with_handle(session_handle, &StartAuthSessionOut { nonce_tpm })
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::simulator::simulator::tests::{command, rc, started};
+        use crate::simulator::simulator::{TPM_HEADER_SIZE, TPM_ST_NO_SESSIONS, TPM_ST_SESSIONS};
+        use tpm2_types::constants::CommandCode;
+
+        fn start_session(tpm: &mut Simulator, nonce_caller: &[u8]) -> (u32, Vec<u8>) {
+            let mut body = [TPM_RH_NULL, TPM_RH_NULL]
+                .iter()
+                .flat_map(|h| h.to_be_bytes())
+                .collect::<Vec<u8>>();
+            body.extend_from_slice(&(nonce_caller.len() as u16).to_be_bytes());
+            body.extend_from_slice(nonce_caller);
+            // no salt, HMAC session, no symmetric, SHA256
+            body.extend_from_slice(&[0, 0, 0, 0x00, 0x10, 0x00, 0x0b]);
+            let response = tpm.execute(&command(
+                TPM_ST_NO_SESSIONS,
+                CommandCode::StartAuthSession,
+                &body,
+            ));
+            assert_eq!(rc(&response), TPM_RC_SUCCESS);
+            let session_handle = u32::from_be_bytes(response[10..14].try_into().unwrap());
+            assert_eq!(&response[14..16], &[0, 32]);
+            (session_handle, response[16..].to_vec())
+        }
+
+        #[test]
+        fn test_hmac_session() {
+            let mut tpm = started();
+            let nonce_caller = [0x11; 16];
+            let (session_handle, mut nonce_tpm) = start_session(&mut tpm, &nonce_caller);
+            assert_eq!(session_handle, 0x02000000);
+
+            // GetRandom with an (unbound, unsalted) audit-less HMAC session,
+            // i.e. the HMAC key is empty
+            let get_random = |nonce_tpm: &[u8], continue_session: bool| {
+                let attributes = continue_session as u8;
+                let cp_hash = crypto::hash(
+                    &AlgHash::SHA256,
+                    &[&(CommandCode::GetRandom as u32).to_be_bytes(), &[0, 4]],
+                )
+                .unwrap();
+                let hmac = crypto::hmac(
+                    &AlgHash::SHA256,
+                    &[],
+                    &[&cp_hash, &nonce_caller, nonce_tpm, &[attributes]],
+                )
+                .unwrap();
+                let mut body = 57u32.to_be_bytes().to_vec();
+                body.extend_from_slice(&session_handle.to_be_bytes());
+                body.extend_from_slice(&[0, 16]);
+                body.extend_from_slice(&nonce_caller);
+                body.push(attributes);
+                body.extend_from_slice(&[0, 32]);
+                body.extend_from_slice(&hmac);
+                body.extend_from_slice(&[0, 4]);
+                command(TPM_ST_SESSIONS, CommandCode::GetRandom, &body)
+            };
+
+            let response = tpm.execute(&get_random(&[0; 32], true));
+            assert_eq!(rc(&response), session(TPM_RC_AUTH_FAIL, 1));
+
+            let response = tpm.execute(&get_random(&nonce_tpm, true));
+            assert_eq!(rc(&response), TPM_RC_SUCCESS);
+            // parameterSize, randomBytes, nonceTPM, attributes, hmac
+            let parameters = &response[TPM_HEADER_SIZE + 4..TPM_HEADER_SIZE + 10];
+            let auth = &response[TPM_HEADER_SIZE + 10..];
+            assert_eq!(&auth[..2], &[0, 32]);
+            let new_nonce_tpm = auth[2..34].to_vec();
+            assert_ne!(new_nonce_tpm, nonce_tpm);
+            assert_eq!(auth[34], TPMA_SESSION_CONTINUE_SESSION);
+            let rp_hash = crypto::hash(
+                &AlgHash::SHA256,
+                &[
+                    &[0; 4],
+                    &(CommandCode::GetRandom as u32).to_be_bytes(),
+                    parameters,
+                ],
+            )
+            .unwrap();
+            let hmac = crypto::hmac(
+                &AlgHash::SHA256,
+                &[],
+                &[&rp_hash, &new_nonce_tpm, &nonce_caller, &[1]],
+            )
+            .unwrap();
+            assert_eq!(&auth[35..], [&[0, 32], &hmac[..]].concat());
+            nonce_tpm = new_nonce_tpm;
+
+            // the session is flushed without continueSession
+            let response = tpm.execute(&get_random(&nonce_tpm, false));
+            assert_eq!(rc(&response), TPM_RC_SUCCESS);
+            assert!(tpm.sessions.is_empty());
+        }
+
+        #[test]
+        fn test_session_limits() {
+            let mut tpm = started();
+            for _ in 0..MAX_LOADED_SESSIONS {
+                start_session(&mut tpm, &[0; 16]);
+            }
+            let mut body = [TPM_RH_NULL, TPM_RH_NULL]
+                .iter()
+                .flat_map(|h| h.to_be_bytes())
+                .collect::<Vec<u8>>();
+            body.extend_from_slice(&[0, 16]);
+            body.extend_from_slice(&[0; 16]);
+            body.extend_from_slice(&[0, 0, 0, 0x00, 0x10, 0x00, 0x0b]);
+            let start = command(TPM_ST_NO_SESSIONS, CommandCode::StartAuthSession, &body);
+            assert_eq!(rc(&tpm.execute(&start)), TPM_RC_SESSION_MEMORY);
+
+            tpm.sessions.clear();
+            // policy sessions are not implemented
+            let mut policy = start.clone();
+            policy[TPM_HEADER_SIZE + 8 + 18 + 2] = 0x01;
+            assert_eq!(rc(&tpm.execute(&policy)), parameter(TPM_RC_VALUE, 3));
+            // nonceCaller too short
+            let mut body = body.clone();
+            body.drain(8..10 + 8);
+            body.splice(8..8, [0, 8]);
+            let short = command(TPM_ST_NO_SESSIONS, CommandCode::StartAuthSession, &body);
+            assert_eq!(rc(&tpm.execute(&short)), parameter(TPM_RC_SIZE, 1));
+        }
+    }
+}
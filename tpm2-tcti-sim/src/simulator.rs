@@ -0,0 +1,793 @@
+/// Software TPM executing marshalled commands.
+///
+/// The simulator keeps its state in memory only, i.e. every [Simulator] is
+/// a freshly manufactured TPM. Commands are parsed into the structures of
+/// `tpm2-types`; the handlers of the command groups live next to their
+/// state, e.g. [pcr](crate::pcr::pcr) and [nv](crate::nv::nv).
+pub mod simulator {
+    use std::collections::BTreeMap;
+    use std::time::Instant;
+
+    use rand::{RngCore, SeedableRng};
+    use rand_chacha::ChaCha20Rng;
+    use serde::{Deserialize, Serialize};
+    use serde_tpm2::{de::from_bytes, error::Error, se::to_bytes};
+    use tpm2_types::alg::AlgHash;
+    use tpm2_types::commands::{
+        GetRandom, GetRandomResponse, GetTestResultResponse, SelfTest, Shutdown, Startup,
+    };
+    use tpm2_types::constants::{CommandCode, ReturnCode, StartupType};
+    use tpm2_types::handles::Hierarchy;
+    use tpm2_types::serde_types::sized_vector::U16SizedVector;
+    use tpm2_types::structs::{AuthCommand, AuthResponse, ClockInfo};
+    use tracing::debug;
+
+    use crate::crypto::crypto;
+    use crate::nv::nv::NvIndex;
+    use crate::object::object::{Object, TPMA_OBJECT_USER_WITH_AUTH};
+    use crate::pcr::pcr::{Pcrs, PCR_COUNT};
+    use crate::rc::rc::*;
+    use crate::session::session::{SessionSlot, TPMA_SESSION_CONTINUE_SESSION};
+
+    pub const TPM_HEADER_SIZE: usize = 10;
+    pub const TPM_ST_NO_SESSIONS: u16 = 0x8001;
+    pub const TPM_ST_SESSIONS: u16 = 0x8002;
+
+    pub const TPM_RH_OWNER: u32 = 0x40000001;
+    pub const TPM_RH_NULL: u32 = 0x40000007;
+    pub const TPM_RS_PW: u32 = 0x40000009;
+    pub const TPM_RH_LOCKOUT: u32 = 0x4000000A;
+    pub const TPM_RH_ENDORSEMENT: u32 = 0x4000000B;
+    pub const TPM_RH_PLATFORM: u32 = 0x4000000C;
+
+    pub const TPM_HT_PCR: u32 = 0x00;
+    pub const TPM_HT_NV_INDEX: u32 = 0x01;
+    pub const TPM_HT_HMAC_SESSION: u32 = 0x02;
+    pub const TPM_HT_POLICY_SESSION: u32 = 0x03;
+    pub const TPM_HT_PERMANENT: u32 = 0x40;
+    pub const TPM_HT_TRANSIENT: u32 = 0x80;
+
+    pub fn handle_type(handle: u32) -> u32 {
+        handle >> 24
+    }
+
+    /// Permanent handles known to the simulator.
+    pub const PERMANENT_HANDLES: [u32; 6] = [
+        TPM_RH_OWNER,
+        TPM_RH_NULL,
+        TPM_RS_PW,
+        TPM_RH_LOCKOUT,
+        TPM_RH_ENDORSEMENT,
+        TPM_RH_PLATFORM,
+    ];
+
+    /// Hierarchies with a primary seed.
+    pub const HIERARCHIES: [u32; 4] = [
+        TPM_RH_OWNER,
+        TPM_RH_ENDORSEMENT,
+        TPM_RH_PLATFORM,
+        TPM_RH_NULL,
+    ];
+
+    pub const MAX_LOADED_OBJECTS: usize = 3;
+    pub const MAX_LOADED_SESSIONS: usize = 3;
+    pub const MAX_ACTIVE_SESSIONS: usize = 64;
+    pub const MAX_SESSIONS_PER_COMMAND: usize = 3;
+
+    /// TPM_PT_MANUFACTURER of the simulator.
+    pub const MANUFACTURER: [u8; 4] = *b"RUST";
+    /// TPM_PT_FIRMWARE_VERSION_1 and _2 of the simulator.
+    pub const FIRMWARE_VERSION: u64 = 0x0000_0001_0000_0000;
+
+    /// Commands implemented by the simulator, in the order of their
+    /// command codes.
+    pub const COMMANDS: [CommandCode; 26] = [
+        CommandCode::NVUndefineSpace,
+        CommandCode::NVDefineSpace,
+        CommandCode::CreatePrimary,
+        CommandCode::NVWrite,
+        CommandCode::PCRReset,
+        CommandCode::SelfTest,
+        CommandCode::Startup,
+        CommandCode::Shutdown,
+        CommandCode::NVRead,
+        CommandCode::Create,
+        CommandCode::Load,
+        CommandCode::Quote,
+        CommandCode::Sign,
+        CommandCode::ContextLoad,
+        CommandCode::ContextSave,
+        CommandCode::FlushContext,
+        CommandCode::NVReadPublic,
+        CommandCode::ReadPublic,
+        CommandCode::StartAuthSession,
+        CommandCode::VerifySignature,
+        CommandCode::GetCapability,
+        CommandCode::GetRandom,
+        CommandCode::GetTestResult,
+        CommandCode::Hash,
+        CommandCode::PCRRead,
+        CommandCode::PCRExtend,
+    ];
+
+    /// TPM2B of a list, e.g. the TPM2B_DIGESTs of a TPML_DIGEST.
+    #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+    pub struct Buffer {
+        #[serde(with = "U16SizedVector")]
+        pub buffer: Vec<u8>,
+    }
+
+    /// Handles and parameters of a successful response.
+    #[derive(Debug, Default, PartialEq)]
+    pub struct Reply {
+        pub handles: Vec<u32>,
+        pub parameters: Vec<u8>,
+    }
+
+    impl Reply {
+        pub fn new(parameters: &impl Serialize) -> Result<Self, ReturnCode> {
+            Ok(Self {
+                handles: Vec::new(),
+                parameters: marshal(parameters)?,
+            })
+        }
+
+        pub fn with_handle(handle: u32, parameters: &impl Serialize) -> Result<Self, ReturnCode> {
+            Ok(Self {
+                handles: vec![handle],
+                parameters: marshal(parameters)?,
+            })
+        }
+    }
+
+    /// Unmarshal command parameters (or a TPM2B thereof). Errors do not
+    /// tell which parameter is malformed.
+    pub fn parse<'de, T: Deserialize<'de>>(bytes: &'de [u8]) -> Result<T, ReturnCode> {
+        from_bytes(bytes).map_err(|error| match error {
+            Error::Eof => TPM_RC_INSUFFICIENT,
+            Error::TrailingCharacters => TPM_RC_SIZE,
+            _ => TPM_RC_VALUE,
+        })
+    }
+
+    pub fn marshal<T: Serialize>(value: &T) -> Result<Vec<u8>, ReturnCode> {
+        to_bytes(value).map_err(|_| TPM_RC_FAILURE)
+    }
+
+    /// Response without handles and parameters.
+    pub fn rc_response(rc: ReturnCode) -> Vec<u8> {
+        let mut response = TPM_ST_NO_SESSIONS.to_be_bytes().to_vec();
+        response.extend_from_slice(&(TPM_HEADER_SIZE as u32).to_be_bytes());
+        response.extend_from_slice(&rc.to_be_bytes());
+        response
+    }
+
+    fn u16_at(buf: &[u8], offset: usize) -> Option<u16> {
+        Some(u16::from_be_bytes(
+            buf.get(offset..offset + 2)?.try_into().unwrap(),
+        ))
+    }
+
+    fn u32_at(buf: &[u8], offset: usize) -> Option<u32> {
+        Some(u32::from_be_bytes(
+            buf.get(offset..offset + 4)?.try_into().unwrap(),
+        ))
+    }
+
+    /// Session of the authorization area with the authorization value of
+    /// the handle it authorizes.
+    struct Authorization {
+        session_handle: u32,
+        command: AuthCommand,
+        auth_value: Vec<u8>,
+        name: Vec<u8>,
+    }
+
+    /// Authorization value without trailing zeros, which a TPM removes when
+    /// the value is set.
+    fn trim(auth_value: &[u8]) -> &[u8] {
+        let len = auth_value
+            .iter()
+            .rposition(|&byte| byte != 0)
+            .map_or(0, |i| i + 1);
+        &auth_value[..len]
+    }
+
+    /// TPM simulator. Use [execute()](Simulator::execute) to run commands
+    /// or [TctiSim](crate::lib::TctiSim) to access it as a tcti.
+    #[derive(Debug)]
+    pub struct Simulator {
+        pub(crate) rng: ChaCha20Rng,
+        started: bool,
+        /// PCRs stored by `Shutdown(TPM_SU_STATE)`
+        saved_pcrs: Option<Pcrs>,
+        /// Primary seeds by hierarchy, the one of the null hierarchy changes
+        /// on every TPM Reset.
+        pub(crate) seeds: BTreeMap<u32, Vec<u8>>,
+        /// Secret value for tickets and saved contexts.
+        pub(crate) proof: Vec<u8>,
+        pub(crate) reset_count: u32,
+        pub(crate) restart_count: u32,
+        clock: Instant,
+        pub(crate) pcrs: Pcrs,
+        pub(crate) nv: BTreeMap<u32, NvIndex>,
+        pub(crate) objects: BTreeMap<u32, Object>,
+        pub(crate) sessions: BTreeMap<u32, SessionSlot>,
+        pub(crate) context_counter: u64,
+    }
+
+    impl Default for Simulator {
+        fn default() -> Self {
+            Self::new(None)
+        }
+    }
+
+    impl Simulator {
+        /// Manufacture a TPM. With a `seed`, its primary seeds, keys and
+        /// nonces are reproducible.
+        pub fn new(seed: Option<u64>) -> Self {
+            let mut rng = match seed {
+                Some(seed) => ChaCha20Rng::seed_from_u64(seed),
+                None => ChaCha20Rng::from_entropy(),
+            };
+            let mut random = || {
+                let mut value = vec![0; 32];
+                rng.fill_bytes(&mut value);
+                value
+            };
+            let seeds = HIERARCHIES.iter().map(|&h| (h, random())).collect();
+            let proof = random();
+
+            Self {
+                rng,
+                started: false,
+                saved_pcrs: None,
+                seeds,
+                proof,
+                reset_count: 0,
+                restart_count: 0,
+                clock: Instant::now(),
+                pcrs: Pcrs::default(),
+                nv: BTreeMap::new(),
+                objects: BTreeMap::new(),
+                sessions: BTreeMap::new(),
+                context_counter: 0,
+            }
+        }
+
+        /// Remove and restore power, i.e. lose all volatile state. The TPM
+        /// has to be started via `TPM2_Startup` again.
+        pub fn power_cycle(&mut self) {
+            self.started = false;
+            self.objects.clear();
+            self.sessions
+                .retain(|_, session| matches!(session, SessionSlot::Saved(_)));
+        }
+
+        pub(crate) fn random(&mut self, size: usize) -> Vec<u8> {
+            let mut bytes = vec![0; size];
+            self.rng.fill_bytes(&mut bytes);
+            bytes
+        }
+
+        pub(crate) fn clock_info(&self) -> ClockInfo {
+            ClockInfo {
+                clock: self.clock.elapsed().as_millis() as u64,
+                reset_count: self.reset_count,
+                restart_count: self.restart_count,
+                safe: true,
+            }
+        }
+
+        /// Key for tickets of `hierarchy` and saved contexts (`TPM_RH_NULL`).
+        pub(crate) fn proof(&self, hierarchy: u32) -> Result<Vec<u8>, ReturnCode> {
+            crypto::kdfa(
+                &AlgHash::SHA256,
+                &self.proof,
+                "PROOF",
+                &hierarchy.to_be_bytes(),
+                b"",
+                256,
+            )
+        }
+
+        /// Execute a marshalled command, returning the marshalled response.
+        ///
+        /// # Examples
+        /// ```
+        /// use tpm2_tcti_sim::simulator::simulator::Simulator;
+        ///
+        /// let mut tpm = Simulator::new(Some(1));
+        /// let response = tpm.execute(b"\x80\x01\x00\x00\x00\x0c\x00\x00\x01\x44\x00\x00");
+        /// assert_eq!(response, b"\x80\x01\x00\x00\x00\x0a\x00\x00\x00\x00");
+        ///
+        /// // TPM_RC_INITIALIZE
+        /// let response = tpm.execute(b"\x80\x01\x00\x00\x00\x0c\x00\x00\x01\x44\x00\x00");
+        /// assert_eq!(response, b"\x80\x01\x00\x00\x00\x0a\x00\x00\x01\x00");
+        /// ```
+        pub fn execute(&mut self, command: &[u8]) -> Vec<u8> {
+            match self.execute_inner(command) {
+                Ok(response) => response,
+                Err(rc) => {
+                    debug!("Command failed: {rc:#05x}");
+                    rc_response(rc)
+                }
+            }
+        }
+
+        fn execute_inner(&mut self, command: &[u8]) -> Result<Vec<u8>, ReturnCode> {
+            let (tag, size, cc) = match (u16_at(command, 0), u32_at(command, 2), u32_at(command, 6))
+            {
+                (Some(tag), Some(size), Some(cc)) => (tag, size, cc),
+                _ => return Err(TPM_RC_COMMAND_SIZE),
+            };
+            if size as usize != command.len() {
+                return Err(TPM_RC_COMMAND_SIZE);
+            }
+            if tag != TPM_ST_NO_SESSIONS && tag != TPM_ST_SESSIONS {
+                return Err(TPM_RC_BAD_TAG);
+            }
+            let command_code = CommandCode::try_from(cc)
+                .ok()
+                .filter(|command_code| COMMANDS.contains(command_code))
+                .ok_or(TPM_RC_COMMAND_CODE)?;
+            if self.started == (command_code == CommandCode::Startup) {
+                return Err(TPM_RC_INITIALIZE);
+            }
+
+            let handles_end = TPM_HEADER_SIZE + command_code.handle_count() * 4;
+            let handles = (TPM_HEADER_SIZE..handles_end)
+                .step_by(4)
+                .map(|offset| u32_at(command, offset))
+                .collect::<Option<Vec<u32>>>()
+                .ok_or(TPM_RC_COMMAND_SIZE)?;
+            let names = handles
+                .iter()
+                .enumerate()
+                .map(|(i, &h)| self.entity_name(h).map_err(|rc| handle(rc, i as u32 + 1)))
+                .collect::<Result<Vec<_>, _>>()?;
+
+            let (auth_area, parameters) = match tag {
+                TPM_ST_SESSIONS => {
+                    let auth_size = u32_at(command, handles_end).ok_or(TPM_RC_AUTHSIZE)? as usize;
+                    let start = handles_end + 4;
+                    match command.get(start..start + auth_size) {
+                        Some(auth_area) if auth_size > 0 => {
+                            (auth_area, &command[start + auth_size..])
+                        }
+                        _ => return Err(TPM_RC_AUTHSIZE),
+                    }
+                }
+                _ => (&command[handles_end..handles_end], &command[handles_end..]),
+            };
+
+            let mut authorizations = Vec::new();
+            if tag == TPM_ST_SESSIONS {
+                for (i, (session_handle, command)) in
+                    Self::parse_auth_area(auth_area)?.into_iter().enumerate()
+                {
+                    let n = i as u32 + 1;
+                    let (auth_value, name) = if i < command_code.auth_handle_count() {
+                        (self.entity_auth(handles[i], n)?, names[i].clone())
+                    } else {
+                        (Vec::new(), Vec::new())
+                    };
+                    authorizations.push(Authorization {
+                        session_handle,
+                        command,
+                        auth_value,
+                        name,
+                    });
+                }
+            }
+            if authorizations.len() < command_code.auth_handle_count() {
+                return Err(TPM_RC_AUTH_MISSING);
+            }
+
+            let cp_hash_data: Vec<u8> = cc
+                .to_be_bytes()
+                .into_iter()
+                .chain(names.concat())
+                .chain(parameters.iter().copied())
+                .collect();
+            for (i, authorization) in authorizations.iter().enumerate() {
+                self.check_authorization(i as u32 + 1, authorization, &cp_hash_data)?;
+            }
+
+            let reply = self.dispatch(command_code, &handles, parameters)?;
+
+            let mut response = tag.to_be_bytes().to_vec();
+            response.extend_from_slice(&[0; 8]);
+            for handle in reply.handles.iter() {
+                response.extend_from_slice(&handle.to_be_bytes());
+            }
+            if tag == TPM_ST_SESSIONS {
+                response.extend_from_slice(&(reply.parameters.len() as u32).to_be_bytes());
+            }
+            response.extend_from_slice(&reply.parameters);
+
+            let rp_hash_data: Vec<u8> = [0u32, cc]
+                .iter()
+                .flat_map(|value| value.to_be_bytes())
+                .chain(reply.parameters.iter().copied())
+                .collect();
+            for authorization in authorizations.iter() {
+                let auth_response = self.respond(authorization, &rp_hash_data)?;
+                response.extend(marshal(&auth_response)?);
+            }
+
+            let size = response.len() as u32;
+            response[2..6].copy_from_slice(&size.to_be_bytes());
+            Ok(response)
+        }
+
+        /// Session handles and their TPMS_AUTH_COMMAND.
+        fn parse_auth_area(mut auth_area: &[u8]) -> Result<Vec<(u32, AuthCommand)>, ReturnCode> {
+            let mut sessions = Vec::new();
+            while !auth_area.is_empty() {
+                // sessionHandle, nonce (TPM2B), sessionAttributes, hmac (TPM2B)
+                let nonce_size = u16_at(auth_area, 4).ok_or(TPM_RC_AUTHSIZE)? as usize;
+                let hmac_offset = 4 + 2 + nonce_size + 1;
+                let hmac_size = u16_at(auth_area, hmac_offset).ok_or(TPM_RC_AUTHSIZE)? as usize;
+                let end = hmac_offset + 2 + hmac_size;
+                if end > auth_area.len() {
+                    return Err(TPM_RC_AUTHSIZE);
+                }
+
+                let n = sessions.len() as u32 + 1;
+                let session_handle = u32_at(auth_area, 0).unwrap();
+                let command = parse(&auth_area[..end]).map_err(|_| session(TPM_RC_HANDLE, n))?;
+                sessions.push((session_handle, command));
+                auth_area = &auth_area[end..];
+            }
+
+            if sessions.len() > MAX_SESSIONS_PER_COMMAND {
+                return Err(TPM_RC_AUTHSIZE);
+            }
+            Ok(sessions)
+        }
+
+        /// Name of an entity in the handle area.
+        pub(crate) fn entity_name(&self, handle: u32) -> Result<Vec<u8>, ReturnCode> {
+            match handle_type(handle) {
+                TPM_HT_NV_INDEX => self.nv_index(handle)?.name(),
+                TPM_HT_TRANSIENT => Ok(self.object(handle)?.name.clone()),
+                TPM_HT_PERMANENT if !PERMANENT_HANDLES.contains(&handle) => Err(TPM_RC_HANDLE),
+                TPM_HT_PCR if handle as usize >= PCR_COUNT => Err(TPM_RC_HANDLE),
+                TPM_HT_PCR | TPM_HT_HMAC_SESSION | TPM_HT_POLICY_SESSION | TPM_HT_PERMANENT => {
+                    Ok(handle.to_be_bytes().to_vec())
+                }
+                _ => Err(TPM_RC_HANDLE),
+            }
+        }
+
+        /// Authorization value of the `n`th handle.
+        pub(crate) fn entity_auth(&self, handle: u32, n: u32) -> Result<Vec<u8>, ReturnCode> {
+            match handle_type(handle) {
+                TPM_HT_NV_INDEX => Ok(self.nv_index(handle)?.auth.clone()),
+                TPM_HT_TRANSIENT => {
+                    let object = self.object(handle)?;
+                    // without policy sessions, there is only the user role
+                    match object.attributes() & TPMA_OBJECT_USER_WITH_AUTH {
+                        0 => Err(TPM_RC_AUTH_UNAVAILABLE),
+                        _ => Ok(object.auth.clone()),
+                    }
+                }
+                // hierarchies have no authorization values (yet)
+                _ => Ok(Vec::new()),
+            }
+            .map_err(|rc| match rc {
+                TPM_RC_HANDLE => crate::rc::rc::handle(rc, n),
+                rc => rc,
+            })
+        }
+
+        fn check_authorization(
+            &self,
+            n: u32,
+            authorization: &Authorization,
+            cp_hash_data: &[u8],
+        ) -> Result<(), ReturnCode> {
+            let command = &authorization.command;
+            if command.session_attributes & !TPMA_SESSION_CONTINUE_SESSION != 0 {
+                // neither auditing nor parameter encryption are implemented
+                return Err(session(TPM_RC_ATTRIBUTES, n));
+            }
+
+            let valid = match authorization.session_handle {
+                TPM_RS_PW => {
+                    if !command.nonce.is_empty() {
+                        return Err(session(TPM_RC_SIZE, n));
+                    }
+                    crypto::equal(trim(&command.hmac), trim(&authorization.auth_value))
+                }
+                handle => {
+                    let session = match self.sessions.get(&handle) {
+                        Some(SessionSlot::Loaded(session)) => session,
+                        Some(SessionSlot::Saved(_)) => return Err(TPM_RC_REFERENCE_S0 + n - 1),
+                        None => return Err(session(TPM_RC_HANDLE, n)),
+                    };
+                    let cp_hash = crypto::hash(&session.hash, &[cp_hash_data])?;
+                    let hmac = session.hmac(
+                        trim(&authorization.auth_value),
+                        &authorization.name,
+                        &[&cp_hash, &command.nonce, &session.nonce_tpm],
+                        command.session_attributes,
+                    )?;
+                    crypto::equal(&hmac, &command.hmac)
+                }
+            };
+
+            if valid {
+                Ok(())
+            } else {
+                Err(session(TPM_RC_AUTH_FAIL, n))
+            }
+        }
+
+        /// TPMS_AUTH_RESPONSE of a session, closing it unless
+        /// continueSession is set.
+        fn respond(
+            &mut self,
+            authorization: &Authorization,
+            rp_hash_data: &[u8],
+        ) -> Result<AuthResponse, ReturnCode> {
+            let command = &authorization.command;
+            let session_handle = authorization.session_handle;
+            let hash = match self.sessions.get(&session_handle) {
+                Some(SessionSlot::Loaded(session)) => session.hash.clone(),
+                _ => {
+                    return Ok(AuthResponse {
+                        nonce: Vec::new(),
+                        session_attributes: TPMA_SESSION_CONTINUE_SESSION,
+                        hmac: Vec::new(),
+                    })
+                }
+            };
+
+            let nonce_tpm = self.random(crypto::digest_size(&hash)?);
+            let session = match self.sessions.get_mut(&session_handle) {
+                Some(SessionSlot::Loaded(session)) => session,
+                _ => unreachable!(),
+            };
+            session.nonce_tpm = nonce_tpm;
+            let rp_hash = crypto::hash(&hash, &[rp_hash_data])?;
+            let hmac = session.hmac(
+                trim(&authorization.auth_value),
+                &authorization.name,
+                &[&rp_hash, &session.nonce_tpm, &command.nonce],
+                command.session_attributes,
+            )?;
+            let response = AuthResponse {
+                nonce: session.nonce_tpm.clone(),
+                session_attributes: command.session_attributes,
+                hmac,
+            };
+
+            if command.session_attributes & TPMA_SESSION_CONTINUE_SESSION == 0 {
+                self.sessions.remove(&session_handle);
+            }
+            Ok(response)
+        }
+
+        pub(crate) fn object(&self, handle: u32) -> Result<&Object, ReturnCode> {
+            self.objects.get(&handle).ok_or(TPM_RC_HANDLE)
+        }
+
+        pub(crate) fn nv_index(&self, handle: u32) -> Result<&NvIndex, ReturnCode> {
+            self.nv.get(&handle).ok_or(TPM_RC_HANDLE)
+        }
+
+        fn dispatch(
+            &mut self,
+            command_code: CommandCode,
+            handles: &[u32],
+            parameters: &[u8],
+        ) -> Result<Reply, ReturnCode> {
+            match command_code {
+                CommandCode::Startup => self.startup(parameters),
+                CommandCode::Shutdown => self.shutdown(parameters),
+                CommandCode::SelfTest => {
+                    parse::<SelfTest>(parameters)?;
+                    Ok(Reply::default())
+                }
+                CommandCode::GetTestResult => Reply::new(&GetTestResultResponse {
+                    out_data: Vec::new(),
+                    test_result: TPM_RC_SUCCESS,
+                }),
+                CommandCode::GetRandom => self.get_random(parameters),
+                CommandCode::GetCapability => self.get_capability(parameters),
+                CommandCode::Hash => self.hash(parameters),
+                CommandCode::PCRRead => self.pcr_read(parameters),
+                CommandCode::PCRExtend => self.pcr_extend(handles[0], parameters),
+                CommandCode::PCRReset => self.pcr_reset(handles[0], parameters),
+                CommandCode::CreatePrimary => self.create_primary(handles[0], parameters),
+                CommandCode::Create => self.create(handles[0], parameters),
+                CommandCode::Load => self.load(handles[0], parameters),
+                CommandCode::ReadPublic => self.read_public(handles[0], parameters),
+                CommandCode::Sign => self.sign(handles[0], parameters),
+                CommandCode::VerifySignature => self.verify_signature(handles[0], parameters),
+                CommandCode::Quote => self.quote(handles[0], parameters),
+                CommandCode::StartAuthSession => {
+                    self.start_auth_session(handles[0], handles[1], parameters)
+                }
+                CommandCode::FlushContext => self.flush_context(parameters),
+                CommandCode::ContextSave => self.context_save(handles[0], parameters),
+                CommandCode::ContextLoad => self.context_load(parameters),
+                CommandCode::NVDefineSpace => self.nv_define_space(handles[0], parameters),
+                CommandCode::NVUndefineSpace => {
+                    self.nv_undefine_space(handles[0], handles[1], parameters)
+                }
+                CommandCode::NVReadPublic => self.nv_read_public(handles[0], parameters),
+                CommandCode::NVWrite => self.nv_write(handles[0], handles[1], parameters),
+                CommandCode::NVRead => self.nv_read(handles[0], handles[1], parameters),
+                _ => Err(TPM_RC_COMMAND_CODE),
+            }
+        }
+
+        /// `TPM2_Startup`: `TPM_SU_CLEAR` is a TPM Reset, `TPM_SU_STATE` a TPM
+        /// Resume which requires a preceding `TPM2_Shutdown(TPM_SU_STATE)`.
+        fn startup(&mut self, parameters: &[u8]) -> Result<Reply, ReturnCode> {
+            let startup = parse::<Startup>(parameters)?;
+            let saved_pcrs = self.saved_pcrs.take();
+            match (startup.startup_type, saved_pcrs) {
+                (StartupType::State, Some(pcrs)) => {
+                    self.pcrs = pcrs;
+                    self.restart_count += 1;
+                }
+                (StartupType::State, None) => return Err(parameter(TPM_RC_VALUE, 1)),
+                (StartupType::Clear, _) => {
+                    self.pcrs = Pcrs::default();
+                    self.reset_count += 1;
+                    self.restart_count = 0;
+                    self.sessions.clear();
+                    let null_seed = self.random(32);
+                    self.seeds.insert(TPM_RH_NULL, null_seed);
+                }
+            }
+
+            self.started = true;
+            Ok(Reply::default())
+        }
+
+        fn shutdown(&mut self, parameters: &[u8]) -> Result<Reply, ReturnCode> {
+            let shutdown = parse::<Shutdown>(parameters)?;
+            self.saved_pcrs = match shutdown.shutdown_type {
+                StartupType::State => Some(self.pcrs.clone()),
+                StartupType::Clear => None,
+            };
+            Ok(Reply::default())
+        }
+
+        fn get_random(&mut self, parameters: &[u8]) -> Result<Reply, ReturnCode> {
+            let get_random = parse::<GetRandom>(parameters)?;
+            // at most the size of the largest digest
+            let size = (get_random.bytes_requested as usize).min(64);
+            Reply::new(&GetRandomResponse {
+                random_bytes: self.random(size),
+            })
+        }
+
+        /// Hierarchy of a hierarchy handle, `TPM_RC_VALUE` otherwise.
+        pub(crate) fn hierarchy(handle: u32) -> Result<Hierarchy, ReturnCode> {
+            Hierarchy::try_from(handle).map_err(|_| TPM_RC_VALUE)
+        }
+    }
+
+    #[cfg(test)]
+    pub mod tests {
+        use super::*;
+
+        pub fn command(tag: u16, cc: CommandCode, body: &[u8]) -> Vec<u8> {
+            let mut command = tag.to_be_bytes().to_vec();
+            command.extend_from_slice(&((TPM_HEADER_SIZE + body.len()) as u32).to_be_bytes());
+            command.extend_from_slice(&(cc as u32).to_be_bytes());
+            command.extend_from_slice(body);
+            command
+        }
+
+        pub fn rc(response: &[u8]) -> ReturnCode {
+            u32_at(response, 6).unwrap()
+        }
+
+        /// Started simulator.
+        pub fn started() -> Simulator {
+            let mut tpm = Simulator::new(Some(0));
+            let response = tpm.execute(&command(TPM_ST_NO_SESSIONS, CommandCode::Startup, &[0, 0]));
+            assert_eq!(rc(&response), TPM_RC_SUCCESS);
+            tpm
+        }
+
+        #[test]
+        fn test_framing() {
+            let mut tpm = Simulator::new(Some(0));
+            let get_random = command(TPM_ST_NO_SESSIONS, CommandCode::GetRandom, &[0, 8]);
+            assert_eq!(rc(&tpm.execute(&get_random)), TPM_RC_INITIALIZE);
+            assert_eq!(rc(&tpm.execute(&[0x80, 0x01])), TPM_RC_COMMAND_SIZE);
+
+            let mut tpm = started();
+            let response = tpm.execute(&get_random);
+            assert_eq!(rc(&response), TPM_RC_SUCCESS);
+            assert_eq!(&response[TPM_HEADER_SIZE..TPM_HEADER_SIZE + 2], &[0, 8]);
+            assert_eq!(response.len(), TPM_HEADER_SIZE + 2 + 8);
+
+            let mut bad_size = get_random.clone();
+            bad_size[5] += 1;
+            assert_eq!(rc(&tpm.execute(&bad_size)), TPM_RC_COMMAND_SIZE);
+            let mut bad_tag = get_random.clone();
+            bad_tag[1] = 0x03;
+            assert_eq!(rc(&tpm.execute(&bad_tag)), TPM_RC_BAD_TAG);
+            let unknown = command(TPM_ST_NO_SESSIONS, CommandCode::ClockSet, &[]);
+            assert_eq!(rc(&tpm.execute(&unknown)), TPM_RC_COMMAND_CODE);
+            // truncated parameters
+            let truncated = command(TPM_ST_NO_SESSIONS, CommandCode::GetRandom, &[0]);
+            assert_eq!(rc(&tpm.execute(&truncated)), TPM_RC_INSUFFICIENT);
+        }
+
+        #[test]
+        fn test_password_authorization() {
+            let mut tpm = started();
+            // nvIndex 0x01000001, owner read/write, auth "pw", 4 bytes
+            let mut body = TPM_RH_OWNER.to_be_bytes().to_vec();
+            body.extend_from_slice(&[0, 0, 0, 9, 0x40, 0, 0, 9, 0, 0, 1, 0, 0]);
+            body.extend_from_slice(&[0, 2, b'p', b'w']);
+            body.extend_from_slice(&[
+                0, 14, 0x01, 0, 0, 1, 0, 0x0b, 0x00, 0x06, 0x00, 0x06, 0, 0, 0, 4,
+            ]);
+            let define = command(TPM_ST_SESSIONS, CommandCode::NVDefineSpace, &body);
+            let response = tpm.execute(&define);
+            assert_eq!(rc(&response), TPM_RC_SUCCESS);
+            // parameterSize, empty password session response
+            assert_eq!(&response[TPM_HEADER_SIZE..], &[0, 0, 0, 0, 0, 0, 1, 0, 0]);
+
+            // the index authorizes itself: wrong, correct (with trailing zeros)
+            let write = |password: &[u8]| {
+                let mut body = [0x01000001u32, 0x01000001]
+                    .iter()
+                    .flat_map(|h| h.to_be_bytes())
+                    .collect::<Vec<u8>>();
+                body.extend_from_slice(&(9 + password.len() as u32).to_be_bytes());
+                body.extend_from_slice(&[0x40, 0, 0, 9, 0, 0, 0]);
+                body.extend_from_slice(&(password.len() as u16).to_be_bytes());
+                body.extend_from_slice(password);
+                body.extend_from_slice(&[0, 1, 0x42, 0, 0]);
+                command(TPM_ST_SESSIONS, CommandCode::NVWrite, &body)
+            };
+            assert_eq!(
+                rc(&tpm.execute(&write(b"wrong"))),
+                session(TPM_RC_AUTH_FAIL, 1)
+            );
+            assert_eq!(rc(&tpm.execute(&write(b"pw\0"))), TPM_RC_SUCCESS);
+
+            // missing authorization
+            let mut body = [TPM_RH_OWNER, 0x01000001]
+                .iter()
+                .flat_map(|h| h.to_be_bytes())
+                .collect::<Vec<u8>>();
+            body.extend_from_slice(&[0, 1, 0, 0]);
+            let read = command(TPM_ST_NO_SESSIONS, CommandCode::NVRead, &body);
+            assert_eq!(rc(&tpm.execute(&read)), TPM_RC_AUTH_MISSING);
+        }
+
+        #[test]
+        fn test_startup() {
+            let mut tpm = started();
+            let startup_state = command(TPM_ST_NO_SESSIONS, CommandCode::Startup, &[0, 1]);
+            let shutdown_state = command(TPM_ST_NO_SESSIONS, CommandCode::Shutdown, &[0, 1]);
+
+            tpm.power_cycle();
+            assert_eq!(rc(&tpm.execute(&startup_state)), parameter(TPM_RC_VALUE, 1));
+
+            let null_seed = tpm.seeds[&TPM_RH_NULL].clone();
+            let started = tpm.execute(&command(TPM_ST_NO_SESSIONS, CommandCode::Startup, &[0, 0]));
+            assert_eq!(rc(&started), TPM_RC_SUCCESS);
+            assert_ne!(tpm.seeds[&TPM_RH_NULL], null_seed);
+            assert_eq!(tpm.reset_count, 2);
+
+            assert_eq!(rc(&tpm.execute(&shutdown_state)), TPM_RC_SUCCESS);
+            tpm.power_cycle();
+            assert_eq!(rc(&tpm.execute(&startup_state)), TPM_RC_SUCCESS);
+            assert_eq!((tpm.reset_count, tpm.restart_count), (2, 1));
+        }
+    }
+}
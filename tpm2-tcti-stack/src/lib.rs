@@ -0,0 +1,157 @@
+pub mod lib {
+    use std::fmt;
+    use std::time::Duration;
+
+    use tracing::warn;
+    use tss2_tcti::define_api_symbols;
+    use tss2_tcti::layer::layer::open_stack;
+    use tss2_tcti::tcti::error::TctiError;
+    use tss2_tcti::tcti::tcti::{Api, Info, PendingResponse, State, Tcti, TctiLib};
+    use tss2_tcti::telemetry::telemetry::CommandSpan;
+    use tss2_tcti_sys::tpm2_tss;
+
+    /// A stack of layers and a tcti as a single tcti for C, see
+    /// [open_stack()].
+    #[repr(C)]
+    pub struct TctiStack {
+        api: Api,
+        state: State,
+        pending_response: PendingResponse,
+        command_span: CommandSpan,
+        stack: Option<Box<dyn Tcti>>,
+    }
+
+    impl fmt::Debug for TctiStack {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_struct("TctiStack")
+                .field("state", &self.state)
+                .field("pending_response", &self.pending_response)
+                .finish_non_exhaustive()
+        }
+    }
+
+    impl TctiStack {
+        fn get_stack(&mut self) -> Result<&mut Box<dyn Tcti>, TctiError> {
+            self.stack.as_mut().ok_or_else(|| {
+                warn!("Tcti context is not initialized");
+                TctiError::BadSequence
+            })
+        }
+    }
+
+    impl TctiLib for TctiStack {
+        const INFO: Info<'static> = Info {
+            name: b"tpm2_tcti-stack\0",
+            description: b"Tcti stacking layers on top of a child tcti.\0",
+            config_help: b"Layers and child tcti, separated by |, the first layer is the outermost one. Builtin layers: trace, retry, fault, metrics, filter, record. Example: trace|retry:max_attempts=3|mssim:port=2321\0",
+        };
+        const MAGIC: u64 = 0x737461636b746369;
+
+        fn new(conf: &str) -> Result<Self, TctiError> {
+            let mut tcti = Self {
+                api: Self::get_api_static(),
+                state: State::NotInitialized,
+                pending_response: None,
+                command_span: CommandSpan::default(),
+                stack: None,
+            };
+
+            tcti.init(conf)?;
+
+            Ok(tcti)
+        }
+
+        fn init_inner(&mut self, conf: &str) -> Result<(), TctiError> {
+            self.api = Self::get_api_static();
+            self.stack = Some(open_stack(conf)?);
+            self.state = State::Transmit;
+            Ok(())
+        }
+
+        fn transmit_inner(&mut self, command: &[u8]) -> Result<(), TctiError> {
+            self.get_stack()?.transmit(command)
+        }
+
+        fn receive_inner(&mut self, timeout: Duration) -> Result<Vec<u8>, TctiError> {
+            self.get_stack()?.receive_with_timeout(timeout)
+        }
+
+        fn finalize_inner(&mut self) {
+            self.stack = None;
+        }
+
+        fn cancel_inner(&mut self) -> Result<(), TctiError> {
+            self.get_stack()?.cancel()
+        }
+
+        fn get_poll_handles_inner(
+            &mut self,
+        ) -> Result<Vec<tpm2_tss::TSS2_TCTI_POLL_HANDLE>, TctiError> {
+            self.get_stack()?.get_poll_handles()
+        }
+
+        fn set_locality_inner(&mut self, locality: u8) -> Result<(), TctiError> {
+            self.get_stack()?.set_locality(locality)
+        }
+
+        fn make_sticky_inner(&mut self, handle: &mut u32, sticky: bool) -> Result<(), TctiError> {
+            self.get_stack()?.make_sticky(handle, sticky)
+        }
+
+        fn get_state(&self) -> Option<State> {
+            Some(self.state)
+        }
+        fn set_state(&mut self, state: State) {
+            self.state = state;
+        }
+        fn get_pending_response(&mut self) -> Option<&mut PendingResponse> {
+            Some(&mut self.pending_response)
+        }
+
+        fn get_command_span(&mut self) -> Option<&mut CommandSpan> {
+            Some(&mut self.command_span)
+        }
+    }
+
+    define_api_symbols!(TctiStack);
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use tss2_tcti::conformance::conformance::check_tcti;
+        use tss2_tcti::registry::registry::register_tcti;
+        use tss2_tcti::testing::testing::Echo;
+
+        const COMMAND: &[u8] = b"\x80\x01\x00\x00\x00\x0c\x00\x00\x01\x7b\x00\x08";
+
+        #[test]
+        fn test_stack() {
+            register_tcti::<Echo>("echo");
+
+            let mut tcti = <TctiStack as Tcti>::new("trace|retry:max_attempts=3|echo").unwrap();
+            Tcti::transmit(&mut tcti, COMMAND).unwrap();
+            let size = Tcti::response_size(&mut tcti, Duration::ZERO).unwrap();
+            assert_eq!(size, COMMAND.len());
+            assert_eq!(Tcti::receive(&mut tcti).unwrap(), COMMAND);
+
+            let mut tcti = <TctiStack as Tcti>::new("fault:seed=1,tpm_warning=1|echo").unwrap();
+            Tcti::transmit(&mut tcti, COMMAND).unwrap();
+            assert_ne!(Tcti::receive(&mut tcti).unwrap()[6..10], [0; 4]);
+
+            assert_eq!(
+                <TctiStack as Tcti>::new("retry:max_attempts=0|echo").unwrap_err(),
+                TctiError::BadValue
+            );
+            assert_eq!(
+                <TctiStack as Tcti>::new("unknown|echo").unwrap_err(),
+                TctiError::NotSupported
+            );
+        }
+
+        #[test]
+        fn test_conformance() {
+            register_tcti::<Echo>("echo");
+            check_tcti::<TctiStack>("echo", COMMAND);
+        }
+    }
+}
@@ -0,0 +1,146 @@
+use crate::{
+    constants::{Capability, CommandCode, ReturnCode, StartupType},
+    handles::Handle,
+    selectables::Capabilities,
+    serde_types::sized_vector::U16SizedVector,
+    structs::Context,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+/// Command without authorization area. The command is serialized as its
+/// handles followed by its parameters, [Response](TpmCommand::Response) as
+/// the response handles followed by the response parameters.
+pub trait TpmCommand: Serialize {
+    const COMMAND_CODE: CommandCode;
+    type Response: DeserializeOwned;
+}
+
+macro_rules! tpm_command {
+    ($command:ty, $command_code:ident, $response:ty) => {
+        impl TpmCommand for $command {
+            const COMMAND_CODE: CommandCode = CommandCode::$command_code;
+            type Response = $response;
+        }
+    };
+}
+
+/// TPM2_Startup
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct Startup {
+    pub startup_type: StartupType,
+}
+tpm_command!(Startup, Startup, ());
+
+/// TPM2_Shutdown
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct Shutdown {
+    pub shutdown_type: StartupType,
+}
+tpm_command!(Shutdown, Shutdown, ());
+
+/// TPM2_SelfTest
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct SelfTest {
+    pub full_test: bool,
+}
+tpm_command!(SelfTest, SelfTest, ());
+
+/// TPM2_GetTestResult
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct GetTestResult;
+tpm_command!(GetTestResult, GetTestResult, GetTestResultResponse);
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct GetTestResultResponse {
+    #[serde(with = "U16SizedVector")]
+    pub out_data: Vec<u8>,
+    pub test_result: ReturnCode,
+}
+
+/// TPM2_GetRandom
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct GetRandom {
+    pub bytes_requested: u16,
+}
+tpm_command!(GetRandom, GetRandom, GetRandomResponse);
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct GetRandomResponse {
+    #[serde(with = "U16SizedVector")]
+    pub random_bytes: Vec<u8>,
+}
+
+/// TPM2_GetCapability
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct GetCapability {
+    pub capability: Capability,
+    pub property: u32,
+    pub property_count: u32,
+}
+tpm_command!(GetCapability, GetCapability, GetCapabilityResponse);
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct GetCapabilityResponse {
+    pub more_data: bool,
+    pub capability_data: Capabilities,
+}
+
+/// TPM2_ContextSave
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct ContextSave {
+    pub save_handle: Handle,
+}
+tpm_command!(ContextSave, ContextSave, Context);
+
+/// TPM2_ContextLoad
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct ContextLoad {
+    pub context: Context,
+}
+tpm_command!(ContextLoad, ContextLoad, ContextLoadResponse);
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct ContextLoadResponse {
+    pub loaded_handle: Handle,
+}
+
+/// TPM2_FlushContext
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct FlushContext {
+    pub flush_handle: Handle,
+}
+tpm_command!(FlushContext, FlushContext, ());
+
+#[test]
+fn test_serialize() {
+    use serde_tpm2::{de::from_bytes, se::to_bytes};
+
+    assert_eq!(
+        to_bytes(&Startup {
+            startup_type: StartupType::Clear
+        })
+        .unwrap(),
+        [0, 0]
+    );
+    assert_eq!(to_bytes(&GetTestResult).unwrap(), []);
+    assert_eq!(
+        to_bytes(&GetCapability {
+            capability: Capability::TpmProperties,
+            property: 0x100,
+            property_count: 1,
+        })
+        .unwrap(),
+        [0, 0, 0, 6, 0, 0, 1, 0, 0, 0, 0, 1]
+    );
+    assert_eq!(
+        to_bytes(&FlushContext {
+            flush_handle: Handle::try_from(0x80000001).unwrap(),
+        })
+        .unwrap(),
+        [0x80, 0, 0, 1]
+    );
+
+    let response: GetRandomResponse = from_bytes(&[0, 2, 0xaa, 0xbb]).unwrap();
+    assert_eq!(response.random_bytes, [0xaa, 0xbb]);
+    from_bytes::<<Startup as TpmCommand>::Response>(&[]).unwrap();
+}
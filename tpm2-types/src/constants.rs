@@ -14,162 +14,255 @@ impl Spec {
 /// TPM_GENERATED: 0xff544347
 pub type GENERATED = ConstantU32<{ u32::from_be_bytes(*b"\xffTCG") }>;
 
-/// TPM_CC
-#[derive(Deserialize_repr, Serialize_repr, Debug, Clone, Default, PartialEq)]
-#[repr(u32)]
-pub enum CommandCode {
-    /// NV_UndefineSpaceSpecial
-    NVUndefineSpaceSpecial = 0x0000011F,
-    EvictControl = 0x00000120,
-    HierarchyControl = 0x00000121,
-    /// NV_UndefineSpace
-    NVUndefineSpace = 0x00000122,
-    ChangeEPS = 0x00000124,
-    ChangePPS = 0x00000125,
-    Clear = 0x00000126,
-    ClearControl = 0x00000127,
-    ClockSet = 0x00000128,
-    HierarchyChangeAuth = 0x00000129,
-    /// NV_DefineSpace
-    NVDefineSpace = 0x0000012A,
-    /// PCR_Allocate
-    PCRAllocate = 0x0000012B,
-    /// PCR_SetAuthPolicy
-    PCRSetAuthPolicy = 0x0000012C,
-    /// PP_Commands
-    PPCommands = 0x0000012D,
-    SetPrimaryPolicy = 0x0000012E,
-    FieldUpgradeStart = 0x0000012F,
-    ClockRateAdjust = 0x00000130,
-    CreatePrimary = 0x00000131,
-    /// NV_GlobalWriteLock
-    NVGlobalWriteLock = 0x00000132,
-    GetCommandAuditDigest = 0x00000133,
-    /// NV_Increment
-    NVIncrement = 0x00000134,
-    /// NV_SetBits
-    NVSetBits = 0x00000135,
-    /// NV_Extend
-    NVExtend = 0x00000136,
-    /// NV_Write
-    NVWrite = 0x00000137,
-    /// NV_WriteLock
-    NVWriteLock = 0x00000138,
-    DictionaryAttackLockReset = 0x00000139,
-    DictionaryAttackParameters = 0x0000013A,
-    /// NV_ChangeAuth
-    NVChangeAuth = 0x0000013B,
-    /// PCR_Event
-    PCREvent = 0x0000013C,
-    /// PCR_Reset
-    PCRReset = 0x0000013D,
-    SequenceComplete = 0x0000013E,
-    SetAlgorithmSet = 0x0000013F,
-    SetCommandCodeAuditStatus = 0x00000140,
-    FieldUpgradeData = 0x00000141,
-    IncrementalSelfTest = 0x00000142,
-    SelfTest = 0x00000143,
-    Startup = 0x00000144,
-    Shutdown = 0x00000145,
-    StirRandom = 0x00000146,
-    ActivateCredential = 0x00000147,
-    Certify = 0x00000148,
-    PolicyNV = 0x00000149,
-    CertifyCreation = 0x0000014A,
-    Duplicate = 0x0000014B,
-    GetTime = 0x0000014C,
-    GetSessionAuditDigest = 0x0000014D,
-    /// NV_Read
-    NVRead = 0x0000014E,
-    /// NV_ReadLock
-    NVReadLock = 0x0000014F,
-    ObjectChangeAuth = 0x00000150,
-    PolicySecret = 0x00000151,
-    Rewrap = 0x00000152,
-    Create = 0x00000153,
-    /// ECDH_ZGen
-    ECDHZGen = 0x00000154,
-    HMAC = 0x00000155,
-    Import = 0x00000156,
-    Load = 0x00000157,
-    Quote = 0x00000158,
-    /// RSA_Decrypt
-    RSADecrypt = 0x00000159,
-    /// HMAC_Start
-    HMACStart = 0x0000015B,
-    SequenceUpdate = 0x0000015C,
-    Sign = 0x0000015D,
-    Unseal = 0x0000015E,
-    PolicySigned = 0x00000160,
-    ContextLoad = 0x00000161,
-    ContextSave = 0x00000162,
-    /// ECDH_KeyGen
-    ECDHKeyGen = 0x00000163,
-    EncryptDecrypt = 0x00000164,
-    FlushContext = 0x00000165,
-    LoadExternal = 0x00000167,
-    MakeCredential = 0x00000168,
-    /// NV_ReadPublic
-    NVReadPublic = 0x00000169,
-    PolicyAuthorize = 0x0000016A,
-    PolicyAuthValue = 0x0000016B,
-    PolicyCommandCode = 0x0000016C,
-    PolicyCounterTimer = 0x0000016D,
-    PolicyCpHash = 0x0000016E,
-    PolicyLocality = 0x0000016F,
-    PolicyNameHash = 0x00000170,
-    PolicyOR = 0x00000171,
-    PolicyTicket = 0x00000172,
-    ReadPublic = 0x00000173,
-    /// RSA_Encrypt
-    RSAEncrypt = 0x00000174,
-    StartAuthSession = 0x00000176,
-    VerifySignature = 0x00000177,
-    /// ECC_Parameters
-    ECCParameters = 0x00000178,
-    FirmwareRead = 0x00000179,
-    GetCapability = 0x0000017A,
-    #[default]
-    GetRandom = 0x0000017B,
-    GetTestResult = 0x0000017C,
-    Hash = 0x0000017D,
-    /// PCR_Read
-    PCRRead = 0x0000017E,
-    PolicyPCR = 0x0000017F,
-    PolicyRestart = 0x00000180,
-    ReadClock = 0x00000181,
-    /// PCR_Extend
-    PCRExtend = 0x00000182,
-    /// PCR_SetAuthValue
-    PCRSetAuthValue = 0x00000183,
-    /// NV_Certify
-    NVCertify = 0x00000184,
-    EventSequenceComplete = 0x00000185,
-    HashSequenceStart = 0x00000186,
-    PolicyPhysicalPresence = 0x00000187,
-    PolicyDuplicationSelect = 0x00000188,
-    PolicyGetDigest = 0x00000189,
-    TestParms = 0x0000018A,
-    Commit = 0x0000018B,
-    PolicyPassword = 0x0000018C,
-    /// ZGen_2Phase
-    ZGen2Phase = 0x0000018D,
-    /// EC_Ephemeral
-    ECEphemeral = 0x0000018E,
-    PolicyNvWritten = 0x0000018F,
-    PolicyTemplate = 0x00000190,
-    CreateLoaded = 0x00000191,
-    PolicyAuthorizeNV = 0x00000192,
-    EncryptDecrypt2 = 0x00000193,
-    /// AC_GetCapability
-    ACGetCapability = 0x00000194,
-    /// AC_Send
-    ACSend = 0x00000195,
-    /// Policy_AC_SendSelect
-    PolicyACSendSelect = 0x00000196,
-    CertifyX509 = 0x00000197,
-    /// ACT_SetTimeout
-    ACTSetTimeout = 0x00000198,
+/// Defines [CommandCode] along with the metadata of each command (TPM 2.0
+/// Part 3), such that there is a single table to maintain. Every command is
+/// given as `Variant = code (handles, response handles, handles requiring
+/// authorization, first parameter is a TPM2B)`.
+macro_rules! command_codes {
+    (
+        $(#[$enum_meta:meta])*
+        pub enum $name:ident {
+            $(
+                $(#[$meta:meta])*
+                $variant:ident = $value:literal (
+                    $handles:literal,
+                    $response_handles:literal,
+                    $auth_handles:literal,
+                    $first_param_2b:literal
+                ),
+            )*
+        }
+    ) => {
+        $(#[$enum_meta])*
+        pub enum $name {
+            $(
+                $(#[$meta])*
+                $variant = $value,
+            )*
+        }
+
+        impl $name {
+            /// Number of handles in the handle area of the command.
+            pub const fn handle_count(&self) -> usize {
+                match self {
+                    $(Self::$variant => $handles,)*
+                }
+            }
+
+            /// Number of handles in the handle area of a successful response.
+            pub const fn response_handle_count(&self) -> usize {
+                match self {
+                    $(Self::$variant => $response_handles,)*
+                }
+            }
+
+            /// Number of handles requiring authorization, i.e. the number of
+            /// sessions a command must at least contain. These are always the
+            /// first handles of the handle area.
+            pub const fn auth_handle_count(&self) -> usize {
+                match self {
+                    $(Self::$variant => $auth_handles,)*
+                }
+            }
+
+            /// Whether the first command parameter is a TPM2B, i.e. whether it
+            /// can be encrypted by a session.
+            pub const fn is_first_param_2b(&self) -> bool {
+                match self {
+                    $(Self::$variant => $first_param_2b,)*
+                }
+            }
+        }
+
+        impl TryFrom<u32> for $name {
+            type Error = ();
+
+            fn try_from(value: u32) -> Result<Self, Self::Error> {
+                match value {
+                    $($value => Ok(Self::$variant),)*
+                    _ => Err(()),
+                }
+            }
+        }
+
+        impl From<$name> for u32 {
+            fn from(value: $name) -> u32 {
+                value as u32
+            }
+        }
+
+        /// Parses the name of a variant, e.g. `GetRandom`.
+        impl std::str::FromStr for $name {
+            type Err = ();
+
+            fn from_str(name: &str) -> Result<Self, Self::Err> {
+                match name {
+                    $(stringify!($variant) => Ok(Self::$variant),)*
+                    _ => Err(()),
+                }
+            }
+        }
+    };
+}
+
+command_codes! {
+    /// TPM_CC
+    #[derive(Deserialize_repr, Serialize_repr, Debug, Clone, Copy, Default, PartialEq, Eq)]
+    #[repr(u32)]
+    pub enum CommandCode {
+        /// NV_UndefineSpaceSpecial
+        NVUndefineSpaceSpecial = 0x0000011F (2, 0, 2, false),
+        EvictControl = 0x00000120 (2, 0, 1, false),
+        HierarchyControl = 0x00000121 (1, 0, 1, false),
+        /// NV_UndefineSpace
+        NVUndefineSpace = 0x00000122 (2, 0, 1, false),
+        ChangeEPS = 0x00000124 (1, 0, 1, false),
+        ChangePPS = 0x00000125 (1, 0, 1, false),
+        Clear = 0x00000126 (1, 0, 1, false),
+        ClearControl = 0x00000127 (1, 0, 1, false),
+        ClockSet = 0x00000128 (1, 0, 1, false),
+        HierarchyChangeAuth = 0x00000129 (1, 0, 1, true),
+        /// NV_DefineSpace
+        NVDefineSpace = 0x0000012A (1, 0, 1, true),
+        /// PCR_Allocate
+        PCRAllocate = 0x0000012B (1, 0, 1, false),
+        /// PCR_SetAuthPolicy
+        PCRSetAuthPolicy = 0x0000012C (1, 0, 1, true),
+        /// PP_Commands
+        PPCommands = 0x0000012D (1, 0, 1, false),
+        SetPrimaryPolicy = 0x0000012E (1, 0, 1, true),
+        FieldUpgradeStart = 0x0000012F (2, 0, 1, true),
+        ClockRateAdjust = 0x00000130 (1, 0, 1, false),
+        CreatePrimary = 0x00000131 (1, 1, 1, true),
+        /// NV_GlobalWriteLock
+        NVGlobalWriteLock = 0x00000132 (1, 0, 1, false),
+        GetCommandAuditDigest = 0x00000133 (2, 0, 2, true),
+        /// NV_Increment
+        NVIncrement = 0x00000134 (2, 0, 1, false),
+        /// NV_SetBits
+        NVSetBits = 0x00000135 (2, 0, 1, false),
+        /// NV_Extend
+        NVExtend = 0x00000136 (2, 0, 1, true),
+        /// NV_Write
+        NVWrite = 0x00000137 (2, 0, 1, true),
+        /// NV_WriteLock
+        NVWriteLock = 0x00000138 (2, 0, 1, false),
+        DictionaryAttackLockReset = 0x00000139 (1, 0, 1, false),
+        DictionaryAttackParameters = 0x0000013A (1, 0, 1, false),
+        /// NV_ChangeAuth
+        NVChangeAuth = 0x0000013B (1, 0, 1, true),
+        /// PCR_Event
+        PCREvent = 0x0000013C (1, 0, 1, true),
+        /// PCR_Reset
+        PCRReset = 0x0000013D (1, 0, 1, false),
+        SequenceComplete = 0x0000013E (1, 0, 1, true),
+        SetAlgorithmSet = 0x0000013F (1, 0, 1, false),
+        SetCommandCodeAuditStatus = 0x00000140 (1, 0, 1, false),
+        FieldUpgradeData = 0x00000141 (0, 0, 0, true),
+        IncrementalSelfTest = 0x00000142 (0, 0, 0, false),
+        SelfTest = 0x00000143 (0, 0, 0, false),
+        Startup = 0x00000144 (0, 0, 0, false),
+        Shutdown = 0x00000145 (0, 0, 0, false),
+        StirRandom = 0x00000146 (0, 0, 0, true),
+        ActivateCredential = 0x00000147 (2, 0, 2, true),
+        Certify = 0x00000148 (2, 0, 2, true),
+        PolicyNV = 0x00000149 (3, 0, 1, true),
+        CertifyCreation = 0x0000014A (2, 0, 1, true),
+        Duplicate = 0x0000014B (2, 0, 1, true),
+        GetTime = 0x0000014C (2, 0, 2, true),
+        GetSessionAuditDigest = 0x0000014D (3, 0, 2, true),
+        /// NV_Read
+        NVRead = 0x0000014E (2, 0, 1, false),
+        /// NV_ReadLock
+        NVReadLock = 0x0000014F (2, 0, 1, false),
+        ObjectChangeAuth = 0x00000150 (2, 0, 1, true),
+        PolicySecret = 0x00000151 (2, 0, 1, true),
+        Rewrap = 0x00000152 (2, 0, 1, true),
+        Create = 0x00000153 (1, 0, 1, true),
+        /// ECDH_ZGen
+        ECDHZGen = 0x00000154 (1, 0, 1, true),
+        HMAC = 0x00000155 (1, 0, 1, true),
+        Import = 0x00000156 (1, 0, 1, true),
+        Load = 0x00000157 (1, 1, 1, true),
+        Quote = 0x00000158 (1, 0, 1, true),
+        /// RSA_Decrypt
+        RSADecrypt = 0x00000159 (1, 0, 1, true),
+        /// HMAC_Start
+        HMACStart = 0x0000015B (1, 1, 1, true),
+        SequenceUpdate = 0x0000015C (1, 0, 1, true),
+        Sign = 0x0000015D (1, 0, 1, true),
+        Unseal = 0x0000015E (1, 0, 1, false),
+        PolicySigned = 0x00000160 (2, 0, 0, true),
+        ContextLoad = 0x00000161 (0, 1, 0, false),
+        ContextSave = 0x00000162 (1, 0, 0, false),
+        /// ECDH_KeyGen
+        ECDHKeyGen = 0x00000163 (1, 0, 0, false),
+        EncryptDecrypt = 0x00000164 (1, 0, 1, false),
+        FlushContext = 0x00000165 (0, 0, 0, false),
+        LoadExternal = 0x00000167 (0, 1, 0, true),
+        MakeCredential = 0x00000168 (1, 0, 0, true),
+        /// NV_ReadPublic
+        NVReadPublic = 0x00000169 (1, 0, 0, false),
+        PolicyAuthorize = 0x0000016A (1, 0, 0, true),
+        PolicyAuthValue = 0x0000016B (1, 0, 0, false),
+        PolicyCommandCode = 0x0000016C (1, 0, 0, false),
+        PolicyCounterTimer = 0x0000016D (1, 0, 0, true),
+        PolicyCpHash = 0x0000016E (1, 0, 0, true),
+        PolicyLocality = 0x0000016F (1, 0, 0, false),
+        PolicyNameHash = 0x00000170 (1, 0, 0, true),
+        PolicyOR = 0x00000171 (1, 0, 0, false),
+        PolicyTicket = 0x00000172 (1, 0, 0, true),
+        ReadPublic = 0x00000173 (1, 0, 0, false),
+        /// RSA_Encrypt
+        RSAEncrypt = 0x00000174 (1, 0, 0, true),
+        StartAuthSession = 0x00000176 (2, 1, 0, true),
+        VerifySignature = 0x00000177 (1, 0, 0, true),
+        /// ECC_Parameters
+        ECCParameters = 0x00000178 (0, 0, 0, false),
+        FirmwareRead = 0x00000179 (0, 0, 0, false),
+        GetCapability = 0x0000017A (0, 0, 0, false),
+        #[default]
+        GetRandom = 0x0000017B (0, 0, 0, false),
+        GetTestResult = 0x0000017C (0, 0, 0, false),
+        Hash = 0x0000017D (0, 0, 0, true),
+        /// PCR_Read
+        PCRRead = 0x0000017E (0, 0, 0, false),
+        PolicyPCR = 0x0000017F (1, 0, 0, true),
+        PolicyRestart = 0x00000180 (1, 0, 0, false),
+        ReadClock = 0x00000181 (0, 0, 0, false),
+        /// PCR_Extend
+        PCRExtend = 0x00000182 (1, 0, 1, false),
+        /// PCR_SetAuthValue
+        PCRSetAuthValue = 0x00000183 (1, 0, 1, true),
+        /// NV_Certify
+        NVCertify = 0x00000184 (3, 0, 2, true),
+        EventSequenceComplete = 0x00000185 (2, 0, 2, true),
+        HashSequenceStart = 0x00000186 (0, 1, 0, true),
+        PolicyPhysicalPresence = 0x00000187 (1, 0, 0, false),
+        PolicyDuplicationSelect = 0x00000188 (1, 0, 0, true),
+        PolicyGetDigest = 0x00000189 (1, 0, 0, false),
+        TestParms = 0x0000018A (0, 0, 0, false),
+        Commit = 0x0000018B (1, 0, 1, true),
+        PolicyPassword = 0x0000018C (1, 0, 0, false),
+        /// ZGen_2Phase
+        ZGen2Phase = 0x0000018D (1, 0, 1, true),
+        /// EC_Ephemeral
+        ECEphemeral = 0x0000018E (0, 0, 0, false),
+        PolicyNvWritten = 0x0000018F (1, 0, 0, false),
+        PolicyTemplate = 0x00000190 (1, 0, 0, true),
+        CreateLoaded = 0x00000191 (1, 1, 1, true),
+        PolicyAuthorizeNV = 0x00000192 (3, 0, 1, false),
+        EncryptDecrypt2 = 0x00000193 (1, 0, 1, true),
+        /// AC_GetCapability
+        ACGetCapability = 0x00000194 (1, 0, 0, false),
+        /// AC_Send
+        ACSend = 0x00000195 (3, 0, 2, true),
+        /// Policy_AC_SendSelect
+        PolicyACSendSelect = 0x00000196 (1, 0, 0, true),
+        CertifyX509 = 0x00000197 (2, 0, 2, true),
+        /// ACT_SetTimeout
+        ACTSetTimeout = 0x00000198 (1, 0, 1, false),
+    }
 }
 
 // TODO
@@ -406,3 +499,21 @@ pub enum AttachedComponentTag {
 
 /// TPM_AE_NONE
 pub type AttachedComponentErrorNone = ConstantU32<0>;
+
+#[test]
+fn test_command_code_metadata() {
+    let cc = CommandCode::try_from(0x00000131).unwrap();
+    assert_eq!(cc, CommandCode::CreatePrimary);
+    assert_eq!(cc.handle_count(), 1);
+    assert_eq!(cc.response_handle_count(), 1);
+    assert_eq!(cc.auth_handle_count(), 1);
+    assert!(cc.is_first_param_2b());
+
+    let cc = CommandCode::PolicySigned;
+    assert_eq!((cc.handle_count(), cc.auth_handle_count()), (2, 0));
+    assert!(!CommandCode::FlushContext.is_first_param_2b());
+    assert_eq!(CommandCode::try_from(0x2000ffff), Err(()));
+    assert_eq!(u32::from(CommandCode::Startup), 0x00000144);
+    assert_eq!("GetRandom".parse(), Ok(CommandCode::GetRandom));
+    assert_eq!("getrandom".parse::<CommandCode>(), Err(()));
+}
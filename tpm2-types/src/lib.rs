@@ -1,5 +1,6 @@
 pub mod alg;
 pub mod bitfields;
+pub mod commands;
 pub mod constants;
 pub mod enums;
 pub mod handles;
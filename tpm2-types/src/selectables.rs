@@ -79,24 +79,24 @@ pub enum Ticket {
 
 /// TPMS_CAPABILITY_DATA: TPM_CAP, TPMU_CAPABILITIES
 #[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
-#[repr(u16)]
+#[repr(u32)]
 pub enum Capabilities {
-    Algorithms(#[serde(with = "U32SizedVector")] Vec<AlgorithmProperty>) = Capability::Algs as u16,
-    Handles(#[serde(with = "U32SizedVector")] Vec<Handle>) = Capability::Handles as u16,
+    Algorithms(#[serde(with = "U32SizedVector")] Vec<AlgorithmProperty>) = Capability::Algs as u32,
+    Handles(#[serde(with = "U32SizedVector")] Vec<Handle>) = Capability::Handles as u32,
     Commands(#[serde(with = "U32SizedVector")] Vec<CommandCodeAttributes>) =
-        Capability::Commands as u16,
-    PPCommands(#[serde(with = "U32SizedVector")] Vec<CommandCode>) = Capability::PPCommands as u16,
+        Capability::Commands as u32,
+    PPCommands(#[serde(with = "U32SizedVector")] Vec<CommandCode>) = Capability::PPCommands as u32,
     AuditCommands(#[serde(with = "U32SizedVector")] Vec<CommandCode>) =
-        Capability::AuditCommands as u16,
-    AssignedPCRs(#[serde(with = "U32SizedVector")] Vec<PCRSelection>) = Capability::Pcrs as u16,
+        Capability::AuditCommands as u32,
+    AssignedPCRs(#[serde(with = "U32SizedVector")] Vec<PCRSelection>) = Capability::Pcrs as u32,
     TpmProperties(#[serde(with = "U32SizedVector")] Vec<TaggedProperty>) =
-        Capability::TpmProperties as u16,
+        Capability::TpmProperties as u32,
     PcrProperties(#[serde(with = "U32SizedVector")] Vec<TaggedPCRSelect>) =
-        Capability::PcrProperties as u16,
-    EccCurves(#[serde(with = "U32SizedVector")] Vec<EccCurve>) = Capability::EccCurves as u16,
+        Capability::PcrProperties as u32,
+    EccCurves(#[serde(with = "U32SizedVector")] Vec<EccCurve>) = Capability::EccCurves as u32,
     AuthPolicies(#[serde(with = "U32SizedVector")] Vec<TaggedPolicy>) =
-        Capability::AuthPolicies as u16,
-    ACT(#[serde(with = "U32SizedVector")] Vec<ACTData>) = Capability::ACT as u16,
+        Capability::AuthPolicies as u32,
+    ACT(#[serde(with = "U32SizedVector")] Vec<ACTData>) = Capability::ACT as u32,
 }
 
 /// TPMS_ATTEST: TPMI_ST_ATTEST, TPMU_ATTEST
@@ -11,7 +11,7 @@ use crate::{
         AsymScheme, AttestBody, Digest, EccScheme, KdfScheme, KeyedHashScheme, RSAScheme,
         SymDefObject,
     },
-    serde_types::sized_vector::{U16SizedVector, U8SizedVector},
+    serde_types::sized_vector::{U16SizedVector, U32SizedVector, U8SizedVector},
 };
 use serde::{Deserialize, Serialize};
 
@@ -104,7 +104,7 @@ pub struct CertifyInfo {
 /// TPMS_QUOTE_INFO
 #[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
 pub struct QuoteInfo {
-    // TODO deserialize_with_u32_size
+    #[serde(with = "U32SizedVector")]
     pub pcr_select: Vec<PCRSelection>,
     #[serde(with = "U16SizedVector")]
     pub pcr_digest: Vec<u8>,
@@ -411,9 +411,10 @@ pub struct Context {
 /// TPMS_CREATION_DATA
 #[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
 pub struct CreationData {
-    pub pcr_select: PCRPropertyTag,
+    #[serde(with = "U32SizedVector")]
+    pub pcr_select: Vec<PCRSelection>,
     #[serde(with = "U16SizedVector")]
-    pub digest: Vec<u8>,
+    pub pcr_digest: Vec<u8>,
     pub locality: LocalityAttributes,
     pub parent_name_alg: AlgHash,
     #[serde(with = "U16SizedVector")]
@@ -430,3 +431,52 @@ pub struct AttachedComponentOutput {
     pub tag: AttachedComponentTag,
     pub data: u32,
 }
+
+#[test]
+fn test_pcr_selection_lists() {
+    use serde_tpm2::{de::from_bytes, se::to_bytes};
+
+    let pcr_select = vec![
+        PCRSelection {
+            hash: AlgHash::SHA1,
+            pcr_select: vec![0x01, 0x00, 0x00],
+        },
+        PCRSelection {
+            hash: AlgHash::SHA256,
+            pcr_select: vec![0x00, 0x00, 0x80],
+        },
+    ];
+    // TPML_PCR_SELECTION
+    let pcr_select_bytes = [
+        0, 0, 0, 2, 0x00, 0x04, 3, 0x01, 0x00, 0x00, 0x00, 0x0b, 3, 0x00, 0x00, 0x80,
+    ];
+
+    let quote_info = QuoteInfo {
+        pcr_select: pcr_select.clone(),
+        pcr_digest: vec![0xaa, 0xbb],
+    };
+    let bytes = to_bytes(&quote_info).unwrap();
+    assert_eq!(bytes, [&pcr_select_bytes[..], &[0, 2, 0xaa, 0xbb]].concat());
+    assert_eq!(from_bytes::<QuoteInfo>(&bytes).unwrap(), quote_info);
+
+    let creation_data = CreationData {
+        pcr_select,
+        pcr_digest: vec![0xaa, 0xbb],
+        locality: 0x01,
+        parent_name_alg: AlgHash::SHA256,
+        parent_name: vec![0x40, 0x00, 0x00, 0x01],
+        parent_qualified_name: vec![],
+        outside_info: vec![0xcc],
+    };
+    let bytes = to_bytes(&creation_data).unwrap();
+    assert_eq!(
+        bytes,
+        [
+            &pcr_select_bytes[..],
+            &[0, 2, 0xaa, 0xbb, 0x01, 0x00, 0x0b],
+            &[0, 4, 0x40, 0x00, 0x00, 0x01, 0, 0, 0, 1, 0xcc],
+        ]
+        .concat()
+    );
+    assert_eq!(from_bytes::<CreationData>(&bytes).unwrap(), creation_data);
+}
@@ -1,8 +1,11 @@
 pub mod lib {
+    use std::time::Duration;
+    use tracing::{trace, warn};
     use tss2_tcti::define_api_symbols;
     use tss2_tcti::tcti::error::TctiError;
-    use tss2_tcti::tcti::tcti::{Api, Info, State, Tcti, TctiLib};
+    use tss2_tcti::tcti::tcti::{Api, Info, PendingResponse, State, Tcti, TctiLib};
     use tss2_tcti::tctildr::tcti_loader::TctiLoader;
+    use tss2_tcti::telemetry::telemetry::CommandSpan;
     use tss2_tcti_sys::tpm2_tss;
 
     #[repr(C)]
@@ -10,6 +13,8 @@ pub mod lib {
     pub struct TctiFoobar {
         api: Api,
         state: State,
+        pending_response: PendingResponse,
+        command_span: CommandSpan,
         child_tcti: Option<TctiLoader>,
     }
 
@@ -25,6 +30,8 @@ pub mod lib {
             let mut tcti = Self {
                 api: Self::get_api_static(),
                 state: State::NotInitialized,
+                pending_response: None,
+                command_span: CommandSpan::default(),
                 child_tcti: None,
             };
 
@@ -41,21 +48,41 @@ pub mod lib {
         }
 
         fn transmit_inner(&mut self, command: &[u8]) -> Result<(), TctiError> {
-            println!("TX: {:?}", command);
+            trace!("TX: {:02x?}", command);
 
             if let Some(child_tcti) = self.child_tcti.as_mut() {
                 return child_tcti.transmit(command);
             }
 
-            todo!()
+            warn!("No child tcti");
+            Err(TctiError::BadSequence)
         }
 
-        fn receive_inner(&mut self) -> Result<Vec<u8>, TctiError> {
+        fn receive_inner(&mut self, timeout: Duration) -> Result<Vec<u8>, TctiError> {
             if let Some(child_tcti) = self.child_tcti.as_mut() {
-                return child_tcti.receive();
+                return child_tcti.receive_with_timeout(timeout);
             }
 
-            todo!()
+            warn!("No child tcti");
+            Err(TctiError::BadSequence)
+        }
+
+        fn get_poll_handles_inner(
+            &mut self,
+        ) -> Result<Vec<tpm2_tss::TSS2_TCTI_POLL_HANDLE>, TctiError> {
+            if let Some(child_tcti) = self.child_tcti.as_mut() {
+                return child_tcti.get_poll_handles();
+            }
+
+            Err(TctiError::BadSequence)
+        }
+
+        fn make_sticky_inner(&mut self, handle: &mut u32, sticky: bool) -> Result<(), TctiError> {
+            if let Some(child_tcti) = self.child_tcti.as_mut() {
+                return child_tcti.make_sticky(handle, sticky);
+            }
+
+            Err(TctiError::BadSequence)
         }
 
         fn get_state(&self) -> Option<State> {
@@ -64,6 +91,13 @@ pub mod lib {
         fn set_state(&mut self, state: State) {
             self.state = state;
         }
+        fn get_pending_response(&mut self) -> Option<&mut PendingResponse> {
+            Some(&mut self.pending_response)
+        }
+
+        fn get_command_span(&mut self) -> Option<&mut CommandSpan> {
+            Some(&mut self.command_span)
+        }
     }
 
     define_api_symbols!(TctiFoobar);
@@ -0,0 +1,7 @@
+mod tcti_config;
+use proc_macro::TokenStream;
+
+#[proc_macro_derive(TctiConfig, attributes(tcti_config))]
+pub fn tcti_config(input: TokenStream) -> TokenStream {
+    tcti_config::tcti_config(input)
+}
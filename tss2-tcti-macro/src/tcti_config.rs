@@ -0,0 +1,174 @@
+use proc_macro::TokenStream;
+use proc_macro2;
+use quote::quote;
+use syn::{
+    self, parse_macro_input, Attribute, Data, DataStruct, DeriveInput, Expr, ExprLit, Field,
+    Fields, GenericArgument, Lit, LitByteStr, Meta, PathArguments, Type,
+};
+
+/// Returns the inner type if `ty` is `Option<T>`.
+fn option_inner(ty: &Type) -> Option<&Type> {
+    let segment = match ty {
+        Type::Path(type_path) => type_path.path.segments.last()?,
+        _ => return None,
+    };
+    if segment.ident != "Option" {
+        return None;
+    }
+
+    match &segment.arguments {
+        PathArguments::AngleBracketed(arguments) => match arguments.args.first()? {
+            GenericArgument::Type(inner) => Some(inner),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Short type name for the help text, e.g. `u16` for `std::primitive::u16`.
+fn type_name(ty: &Type) -> String {
+    match ty {
+        Type::Path(type_path) => match type_path.path.segments.last() {
+            Some(segment) => segment.ident.to_string(),
+            None => quote!(#ty).to_string(),
+        },
+        _ => quote!(#ty).to_string(),
+    }
+}
+
+/// Joins all `///` doc comments of a field into a single line.
+fn doc_string(attrs: &[Attribute]) -> String {
+    attrs
+        .iter()
+        .filter_map(|attr| match &attr.meta {
+            Meta::NameValue(name_value) if name_value.path.is_ident("doc") => {
+                match &name_value.value {
+                    Expr::Lit(ExprLit {
+                        lit: Lit::Str(doc), ..
+                    }) => Some(doc.value().trim().to_string()),
+                    _ => None,
+                }
+            }
+            _ => None,
+        })
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+fn is_positional(field: &Field) -> bool {
+    let mut positional = false;
+
+    for attr in field
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("tcti_config"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("positional") {
+                positional = true;
+                Ok(())
+            } else {
+                Err(meta.error("Expected `positional`."))
+            }
+        })
+        .unwrap_or_else(|error| panic!("{error}"));
+    }
+
+    positional
+}
+
+pub fn tcti_config(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    let fields = match input.data {
+        Data::Struct(DataStruct {
+            fields: Fields::Named(fields),
+            ..
+        }) => fields.named,
+        _ => panic!("Expected struct with named fields."),
+    };
+
+    let struct_ident = input.ident;
+
+    let mut match_arms: Vec<proc_macro2::TokenStream> = Vec::new();
+    let mut help_lines: Vec<String> = Vec::new();
+    let mut positional_field = None;
+    let mut positional_check = None;
+
+    for field in fields.iter() {
+        let field_ident = field.ident.as_ref().unwrap();
+        let key = field_ident.to_string();
+        let positional = is_positional(field);
+
+        let (assignment, help_type) = match option_inner(&field.ty) {
+            Some(inner) => (
+                quote! { config.#field_ident = Some(parse_value(&key, &value)?) },
+                format!("{} (optional)", type_name(inner)),
+            ),
+            None => (
+                quote! { config.#field_ident = parse_value(&key, &value)? },
+                type_name(&field.ty),
+            ),
+        };
+
+        match_arms.push(quote! { #key => #assignment, });
+
+        if positional {
+            if positional_field.is_some() {
+                panic!("Only one field can be positional.");
+            }
+            positional_field = Some(quote! { "" => { let key = String::from(#key); #assignment } });
+            // the positional value and the explicit key must not both be given
+            positional_check = Some(quote! {
+                if pairs.iter().any(|(key, _)| key.is_empty())
+                    && pairs.iter().any(|(key, _)| key == #key)
+                {
+                    return Err(ConfigError::DuplicateKey(String::from(#key)));
+                }
+            });
+            help_lines.push(format!(
+                "[{key}=]<{help_type}>: {}",
+                doc_string(&field.attrs)
+            ));
+        } else {
+            help_lines.push(format!("{key}=<{help_type}>: {}", doc_string(&field.attrs)));
+        }
+    }
+
+    let positional_arm = positional_field.unwrap_or(quote! {
+        "" => return Err(ConfigError::Positional(value)),
+    });
+
+    let config_help = LitByteStr::new(
+        format!("{}\0", help_lines.join("\n")).as_bytes(),
+        struct_ident.span(),
+    );
+
+    let expanded = quote! {
+        impl ::tss2_tcti::config::config::TctiConfig for #struct_ident {
+            const CONFIG_HELP: &'static [u8] = #config_help;
+
+            fn from_conf(conf: &str) -> Result<Self, ::tss2_tcti::config::config::ConfigError> {
+                use ::tss2_tcti::config::config::{parse, parse_value, ConfigError};
+
+                let mut config = <Self as Default>::default();
+
+                let pairs = parse(conf)?;
+                #positional_check
+
+                for (key, value) in pairs {
+                    match key.as_str() {
+                        #(#match_arms)*
+                        #positional_arm
+                        _ => return Err(ConfigError::UnknownKey(key)),
+                    }
+                }
+
+                Ok(config)
+            }
+        }
+    };
+
+    TokenStream::from(expanded)
+}
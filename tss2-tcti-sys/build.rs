@@ -1,45 +1,41 @@
-extern crate bindgen;
-extern crate pkg_config;
-
-use std::env;
-use std::path::PathBuf;
-
+#[cfg(feature = "bindgen")]
 fn main() {
-    let tctildr_pkg = match pkg_config::Config::new().probe("tss2-tctildr") {
+    use std::env;
+    use std::path::PathBuf;
+
+    // only the headers are needed, tctis are loaded at runtime
+    let tcti_pkg = match pkg_config::Config::new()
+        .cargo_metadata(false)
+        .probe("tss2-tcti-device")
+    {
         Ok(pkg) => pkg,
-        _ => panic!("pkg-config could not find tss2-tctildr"),
+        _ => panic!("pkg-config could not find the tpm2-tss headers (tss2-tcti-device)"),
     };
 
-    for path in tctildr_pkg.link_paths {
-        println!("cargo:rustc-link-search={}", path.display());
-    }
-    for lib in &tctildr_pkg.libs {
-        println!("cargo:rustc-link-lib={}", lib);
-    }
-
-    println!("cargo:rerun-if-pkgconfig-changed=tss2-tctildr");
-
-    let include_path = tctildr_pkg
+    let include_path = tcti_pkg
         .include_paths
         .iter()
         .filter(|path| path.ends_with("include"))
         .next()
         .expect(&format!(
             "No include path ends in 'include': {:#?}",
-            tctildr_pkg.include_paths,
+            tcti_pkg.include_paths,
         ));
 
     let bindings = bindgen::builder()
-        .header("tss2/tss2_tcti.h")
         .header("tss2/tss2_common.h")
         // TODO clang_args does not work unless the last header path is absolute
-        .header([include_path.to_str().unwrap(), "tss2/tss2_tctildr.h"].join("/"))
+        .header([include_path.to_str().unwrap(), "tss2/tss2_tcti.h"].join("/"))
         .clang_args(
-            tctildr_pkg
+            tcti_pkg
                 .include_paths
                 .iter()
                 .map(|path| format!("-I{}", path.to_string_lossy())),
         )
+        .allowlist_type("TSS2_.*")
+        .allowlist_type("TPM2_(RC|HANDLE|CC)")
+        .allowlist_var("TSS2_.*")
+        .layout_tests(false)
         .parse_callbacks(Box::new(bindgen::CargoCallbacks))
         .generate()
         .expect("Unable to generate rust bindings for TCTI C code");
@@ -50,3 +46,8 @@ fn main() {
         .write_to_file(out_path.join("bindings.rs"))
         .expect("Couldn't write bindings!");
 }
+
+#[cfg(not(feature = "bindgen"))]
+fn main() {
+    println!("cargo:rerun-if-changed=src/bindings.rs");
+}
@@ -0,0 +1,155 @@
+/* Pre-generated bindings for tss2/tss2_common.h and tss2/tss2_tcti.h (tpm2-tss 4.0).
+ *
+ * Regenerate with `cargo build --features bindgen` (requires pkg-config and
+ * the tpm2-tss headers) and copy $OUT_DIR/bindings.rs here. */
+
+pub type TSS2_RC = u32;
+pub type TPM2_RC = u32;
+pub type TPM2_HANDLE = u32;
+pub type TPM2_CC = u32;
+pub const TSS2_RC_LAYER_SHIFT: u32 = 16;
+pub const TSS2_BASE_RC_GENERAL_FAILURE: u32 = 1;
+pub const TSS2_BASE_RC_NOT_IMPLEMENTED: u32 = 2;
+pub const TSS2_BASE_RC_BAD_CONTEXT: u32 = 3;
+pub const TSS2_BASE_RC_ABI_MISMATCH: u32 = 4;
+pub const TSS2_BASE_RC_BAD_REFERENCE: u32 = 5;
+pub const TSS2_BASE_RC_INSUFFICIENT_BUFFER: u32 = 6;
+pub const TSS2_BASE_RC_BAD_SEQUENCE: u32 = 7;
+pub const TSS2_BASE_RC_NO_CONNECTION: u32 = 8;
+pub const TSS2_BASE_RC_TRY_AGAIN: u32 = 9;
+pub const TSS2_BASE_RC_IO_ERROR: u32 = 10;
+pub const TSS2_BASE_RC_BAD_VALUE: u32 = 11;
+pub const TSS2_BASE_RC_NOT_PERMITTED: u32 = 12;
+pub const TSS2_BASE_RC_INVALID_SESSIONS: u32 = 13;
+pub const TSS2_BASE_RC_NO_DECRYPT_PARAM: u32 = 14;
+pub const TSS2_BASE_RC_NO_ENCRYPT_PARAM: u32 = 15;
+pub const TSS2_BASE_RC_BAD_SIZE: u32 = 16;
+pub const TSS2_BASE_RC_MALFORMED_RESPONSE: u32 = 17;
+pub const TSS2_BASE_RC_INSUFFICIENT_CONTEXT: u32 = 18;
+pub const TSS2_BASE_RC_INSUFFICIENT_RESPONSE: u32 = 19;
+pub const TSS2_BASE_RC_INCOMPATIBLE_TCTI: u32 = 20;
+pub const TSS2_BASE_RC_NOT_SUPPORTED: u32 = 21;
+pub const TSS2_BASE_RC_BAD_TCTI_STRUCTURE: u32 = 22;
+pub const TSS2_BASE_RC_MEMORY: u32 = 23;
+pub const TSS2_BASE_RC_BAD_TR: u32 = 24;
+pub const TSS2_BASE_RC_MULTIPLE_DECRYPT_SESSIONS: u32 = 25;
+pub const TSS2_BASE_RC_MULTIPLE_ENCRYPT_SESSIONS: u32 = 26;
+pub const TSS2_BASE_RC_RSP_AUTH_FAILED: u32 = 27;
+pub const TSS2_BASE_RC_NO_CONFIG: u32 = 28;
+pub const TSS2_BASE_RC_BAD_PATH: u32 = 29;
+pub const TSS2_BASE_RC_NOT_DELETABLE: u32 = 30;
+pub const TSS2_BASE_RC_PATH_ALREADY_EXISTS: u32 = 31;
+pub const TSS2_BASE_RC_KEY_NOT_FOUND: u32 = 32;
+pub const TSS2_BASE_RC_SIGNATURE_VERIFICATION_FAILED: u32 = 33;
+pub const TSS2_BASE_RC_HASH_MISMATCH: u32 = 34;
+pub const TSS2_BASE_RC_KEY_NOT_DUPLICABLE: u32 = 35;
+pub const TSS2_BASE_RC_PATH_NOT_FOUND: u32 = 36;
+pub const TSS2_BASE_RC_NO_CERT: u32 = 37;
+pub const TSS2_BASE_RC_NO_PCR: u32 = 38;
+pub const TSS2_BASE_RC_PCR_NOT_RESETTABLE: u32 = 39;
+pub const TSS2_BASE_RC_BAD_TEMPLATE: u32 = 40;
+pub const TSS2_BASE_RC_AUTHORIZATION_FAILED: u32 = 41;
+pub const TSS2_BASE_RC_AUTHORIZATION_UNKNOWN: u32 = 42;
+pub const TSS2_BASE_RC_NV_NOT_READABLE: u32 = 43;
+pub const TSS2_BASE_RC_NV_TOO_SMALL: u32 = 44;
+pub const TSS2_BASE_RC_NV_NOT_WRITEABLE: u32 = 45;
+pub const TSS2_BASE_RC_POLICY_UNKNOWN: u32 = 46;
+pub const TSS2_BASE_RC_NV_WRONG_TYPE: u32 = 47;
+pub const TSS2_BASE_RC_NAME_ALREADY_EXISTS: u32 = 48;
+pub const TSS2_BASE_RC_NO_TPM: u32 = 49;
+pub const TSS2_BASE_RC_BAD_KEY: u32 = 50;
+pub const TSS2_BASE_RC_NO_HANDLE: u32 = 51;
+pub const TSS2_BASE_RC_NOT_PROVISIONED: u32 = 52;
+pub const TSS2_BASE_RC_ALREADY_PROVISIONED: u32 = 53;
+pub const TSS2_BASE_RC_CALLBACK_NULL: u32 = 54;
+pub const TSS2_LAYER_IMPLEMENTATION_SPECIFIC_OFFSET: u32 = 63488;
+pub const TSS2_LEVEL_IMPLEMENTATION_SPECIFIC_SHIFT: u32 = 11;
+pub const TSS2_TCTI_TIMEOUT_BLOCK: i32 = -1;
+pub const TSS2_TCTI_TIMEOUT_NONE: u32 = 0;
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct TSS2_TCTI_OPAQUE_CONTEXT_BLOB {
+    _unused: [u8; 0],
+}
+pub type TSS2_TCTI_CONTEXT = TSS2_TCTI_OPAQUE_CONTEXT_BLOB;
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone)]
+pub struct pollfd {
+    pub fd: ::std::os::raw::c_int,
+    pub events: ::std::os::raw::c_short,
+    pub revents: ::std::os::raw::c_short,
+}
+pub type TSS2_TCTI_POLL_HANDLE = pollfd;
+pub type TSS2_TCTI_TRANSMIT_FCN = ::std::option::Option<
+    unsafe extern "C" fn(
+        tctiContext: *mut TSS2_TCTI_CONTEXT,
+        size: usize,
+        command: *const u8,
+    ) -> TSS2_RC,
+>;
+pub type TSS2_TCTI_RECEIVE_FCN = ::std::option::Option<
+    unsafe extern "C" fn(
+        tctiContext: *mut TSS2_TCTI_CONTEXT,
+        size: *mut usize,
+        response: *mut u8,
+        timeout: i32,
+    ) -> TSS2_RC,
+>;
+pub type TSS2_TCTI_FINALIZE_FCN =
+    ::std::option::Option<unsafe extern "C" fn(tctiContext: *mut TSS2_TCTI_CONTEXT)>;
+pub type TSS2_TCTI_CANCEL_FCN =
+    ::std::option::Option<unsafe extern "C" fn(tctiContext: *mut TSS2_TCTI_CONTEXT) -> TSS2_RC>;
+pub type TSS2_TCTI_GET_POLL_HANDLES_FCN = ::std::option::Option<
+    unsafe extern "C" fn(
+        tctiContext: *mut TSS2_TCTI_CONTEXT,
+        handles: *mut TSS2_TCTI_POLL_HANDLE,
+        num_handles: *mut usize,
+    ) -> TSS2_RC,
+>;
+pub type TSS2_TCTI_SET_LOCALITY_FCN = ::std::option::Option<
+    unsafe extern "C" fn(tctiContext: *mut TSS2_TCTI_CONTEXT, locality: u8) -> TSS2_RC,
+>;
+pub type TSS2_TCTI_MAKE_STICKY_FCN = ::std::option::Option<
+    unsafe extern "C" fn(
+        tctiContext: *mut TSS2_TCTI_CONTEXT,
+        handle: *mut TPM2_HANDLE,
+        sticky: u8,
+    ) -> TSS2_RC,
+>;
+pub type TSS2_TCTI_INIT_FUNC = ::std::option::Option<
+    unsafe extern "C" fn(
+        tctiContext: *mut TSS2_TCTI_CONTEXT,
+        size: *mut usize,
+        config: *const ::std::os::raw::c_char,
+    ) -> TSS2_RC,
+>;
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct TSS2_TCTI_INFO {
+    pub version: u32,
+    pub name: *const ::std::os::raw::c_char,
+    pub description: *const ::std::os::raw::c_char,
+    pub config_help: *const ::std::os::raw::c_char,
+    pub init: TSS2_TCTI_INIT_FUNC,
+}
+pub type TSS2_TCTI_INFO_FUNC =
+    ::std::option::Option<unsafe extern "C" fn() -> *const TSS2_TCTI_INFO>;
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct TSS2_TCTI_CONTEXT_COMMON_V1 {
+    pub magic: u64,
+    pub version: u32,
+    pub transmit: TSS2_TCTI_TRANSMIT_FCN,
+    pub receive: TSS2_TCTI_RECEIVE_FCN,
+    pub finalize: TSS2_TCTI_FINALIZE_FCN,
+    pub cancel: TSS2_TCTI_CANCEL_FCN,
+    pub getPollHandles: TSS2_TCTI_GET_POLL_HANDLES_FCN,
+    pub setLocality: TSS2_TCTI_SET_LOCALITY_FCN,
+}
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct TSS2_TCTI_CONTEXT_COMMON_V2 {
+    pub v1: TSS2_TCTI_CONTEXT_COMMON_V1,
+    pub makeSticky: TSS2_TCTI_MAKE_STICKY_FCN,
+}
+pub type TSS2_TCTI_CONTEXT_COMMON_CURRENT = TSS2_TCTI_CONTEXT_COMMON_V2;
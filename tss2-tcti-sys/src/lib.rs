@@ -1,10 +1,16 @@
 #[allow(dead_code)]
 #[allow(nonstandard_style)]
 pub mod tpm2_tss {
+    #[cfg(feature = "bindgen")]
     include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
+    #[cfg(not(feature = "bindgen"))]
+    include!("bindings.rs");
 
     // specified TCTI API definition (currently: non-public tpm2-tss header)
     pub const TCTI_VERSION: u32 = 2;
+
+    // symbol of TSS2_TCTI_INFO_FUNC which every tcti library exports
+    pub const TSS2_TCTI_INFO_SYMBOL: &[u8; 15] = b"Tss2_Tcti_Info\0";
 }
 
 impl Default for tpm2_tss::TSS2_TCTI_CONTEXT_COMMON_V1 {
@@ -0,0 +1,345 @@
+pub mod config {
+    use std::cell::RefCell;
+    use std::str::FromStr;
+
+    use crate::tcti::error::TctiError;
+    use thiserror::Error;
+    use tracing::warn;
+
+    pub use tss2_tcti_macro::TctiConfig;
+
+    #[derive(Debug, Error, Clone, PartialEq, Eq)]
+    pub enum ConfigError {
+        #[error("Syntax error in conf string: {0}")]
+        Syntax(String),
+
+        #[error("Unknown key: {0}")]
+        UnknownKey(String),
+
+        #[error("Key is given more than once: {0}")]
+        DuplicateKey(String),
+
+        #[error("Value without key: {0:?}")]
+        Positional(String),
+
+        #[error("Bad value for key {key}: {value:?}")]
+        BadValue { key: String, value: String },
+    }
+
+    impl ConfigError {
+        /// The offending key, if there is one.
+        pub fn key(&self) -> Option<&str> {
+            match self {
+                ConfigError::UnknownKey(key)
+                | ConfigError::DuplicateKey(key)
+                | ConfigError::BadValue { key, .. } => Some(key),
+                _ => None,
+            }
+        }
+    }
+
+    thread_local! {
+        static LAST_ERROR: RefCell<Option<ConfigError>> = const { RefCell::new(None) };
+    }
+
+    /// Rejects the conf string of a tcti: logs `error`, keeps it for
+    /// [take_last_error()] and returns [TctiError::BadValue]. Tctis call this
+    /// where `Tcti::new()` fails because of its conf string.
+    pub fn reject_conf(error: ConfigError) -> TctiError {
+        warn!("Bad tcti config: {error}");
+        LAST_ERROR.with(|last_error| *last_error.borrow_mut() = Some(error));
+        TctiError::BadValue
+    }
+
+    /// Returns (and clears) the last [ConfigError] of this thread which was
+    /// passed to [reject_conf()].
+    ///
+    /// The tcti interface only carries [TctiError::BadValue], so this is how
+    /// to find the offending key after e.g. `Tcti::new()` failed.
+    ///
+    /// # Examples
+    /// ```
+    /// use tss2_tcti::config::config::{reject_conf, take_last_error, ConfigError};
+    /// use tss2_tcti::tcti::error::TctiError;
+    ///
+    /// let error = ConfigError::UnknownKey(String::from("speed"));
+    /// assert_eq!(reject_conf(error.clone()), TctiError::BadValue);
+    /// assert_eq!(take_last_error(), Some(error));
+    /// assert_eq!(take_last_error(), None);
+    /// ```
+    pub fn take_last_error() -> Option<ConfigError> {
+        LAST_ERROR.with(|last_error| last_error.borrow_mut().take())
+    }
+
+    impl From<ConfigError> for TctiError {
+        fn from(_error: ConfigError) -> Self {
+            TctiError::BadValue
+        }
+    }
+
+    /// Tcti configuration which can be parsed from a conf string. Usually
+    /// derived via `#[derive(TctiConfig)]`:
+    ///
+    ///  * every field is a key, fields which are not given keep their
+    ///    [Default] value
+    ///  * field types must implement [FromStr], `Option<T>` fields are set to
+    ///    `Some` if given
+    ///  * one field can be marked as `#[tcti_config(positional)]`, i.e. its
+    ///    key can be omitted (e.g. `/dev/tpmrm0` instead of
+    ///    `path=/dev/tpmrm0`)
+    ///  * [CONFIG_HELP](TctiConfig::CONFIG_HELP) is generated from the doc
+    ///    comments of the fields
+    ///
+    /// # Examples
+    /// ```
+    /// use tss2_tcti::config::config::{ConfigError, TctiConfig};
+    ///
+    /// #[derive(TctiConfig, Debug, PartialEq)]
+    /// struct MssimConfig {
+    ///     /// Hostname or IP address of the simulator.
+    ///     host: String,
+    ///     /// Command port of the simulator.
+    ///     port: u16,
+    ///     /// Locality of the commands.
+    ///     locality: Option<u8>,
+    /// }
+    ///
+    /// impl Default for MssimConfig {
+    ///     fn default() -> Self {
+    ///         Self {
+    ///             host: String::from("localhost"),
+    ///             port: 2321,
+    ///             locality: None,
+    ///         }
+    ///     }
+    /// }
+    ///
+    /// assert_eq!(
+    ///     MssimConfig::from_conf("host=\"127.0.0.1\",locality=3").unwrap(),
+    ///     MssimConfig {
+    ///         host: String::from("127.0.0.1"),
+    ///         port: 2321,
+    ///         locality: Some(3),
+    ///     }
+    /// );
+    /// assert_eq!(
+    ///     MssimConfig::from_conf("port=http").unwrap_err(),
+    ///     ConfigError::BadValue { key: String::from("port"), value: String::from("http") }
+    /// );
+    /// assert_eq!(
+    ///     MssimConfig::CONFIG_HELP,
+    ///     b"host=<String>: Hostname or IP address of the simulator.\n\
+    ///       port=<u16>: Command port of the simulator.\n\
+    ///       locality=<u8 (optional)>: Locality of the commands.\0"
+    /// );
+    /// ```
+    pub trait TctiConfig: Sized {
+        /// Description of all keys, NUL-terminated (see
+        /// [Info::config_help](crate::tcti::tcti::Info::config_help)).
+        const CONFIG_HELP: &'static [u8];
+
+        /// Parse the conf string.
+        fn from_conf(conf: &str) -> Result<Self, ConfigError>;
+    }
+
+    /// Parse a single value. Used by `#[derive(TctiConfig)]`.
+    pub fn parse_value<T: FromStr>(key: &str, value: &str) -> Result<T, ConfigError> {
+        value.parse().map_err(|_| ConfigError::BadValue {
+            key: key.to_string(),
+            value: value.to_string(),
+        })
+    }
+
+    /// Removes the quotes from a value, if it is quoted. Within quotes, `\"`
+    /// and `\\` are unescaped.
+    fn unquote(value: &str) -> Result<String, ConfigError> {
+        let inner = match value.strip_prefix('"') {
+            Some(rest) => match rest.strip_suffix('"') {
+                Some(inner) => inner,
+                None => return Err(ConfigError::Syntax(format!("Unterminated quote: {value}"))),
+            },
+            None => return Ok(value.to_string()),
+        };
+
+        let mut unquoted = String::with_capacity(inner.len());
+        let mut chars = inner.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => match chars.next() {
+                    Some(escaped @ ('"' | '\\')) => unquoted.push(escaped),
+                    _ => return Err(ConfigError::Syntax(format!("Bad escape sequence: {value}"))),
+                },
+                '"' => return Err(ConfigError::Syntax(format!("Unescaped quote: {value}"))),
+                c => unquoted.push(c),
+            }
+        }
+
+        Ok(unquoted)
+    }
+
+    /// Splits the conf string at all occurrences of `separator` which are not
+    /// quoted.
+    pub fn split_unquoted(conf: &str, separator: char) -> Result<Vec<&str>, ConfigError> {
+        let mut items = Vec::new();
+        let mut start = 0;
+        let mut quoted = false;
+        let mut escaped = false;
+
+        for (i, c) in conf.char_indices() {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' if quoted => escaped = true,
+                '"' => quoted = !quoted,
+                c if c == separator && !quoted => {
+                    items.push(&conf[start..i]);
+                    start = i + c.len_utf8();
+                }
+                _ => {}
+            }
+        }
+        if quoted {
+            return Err(ConfigError::Syntax(format!("Unterminated quote: {conf}")));
+        }
+        items.push(&conf[start..]);
+
+        Ok(items)
+    }
+
+    /// Parse a conf string following the tpm2-tss conventions, i.e.
+    /// `key=value,key=value`, into `(key, value)` pairs.
+    ///
+    /// Whitespace around keys and values is ignored, empty items are
+    /// skipped. Values can be quoted (`key="a,b"`). Values without key are
+    /// returned with an empty key.
+    ///
+    /// # Examples
+    /// ```
+    /// use tss2_tcti::config::config::{parse, ConfigError};
+    ///
+    /// assert_eq!(
+    ///     parse("host=localhost, port=2321").unwrap(),
+    ///     [("host".to_string(), "localhost".to_string()), ("port".to_string(), "2321".to_string())],
+    /// );
+    /// assert_eq!(
+    ///     parse("/dev/tpmrm0").unwrap(),
+    ///     [("".to_string(), "/dev/tpmrm0".to_string())],
+    /// );
+    /// assert_eq!(
+    ///     parse(r#"child="mssim:host=localhost,port=2321""#).unwrap(),
+    ///     [("child".to_string(), "mssim:host=localhost,port=2321".to_string())],
+    /// );
+    /// assert_eq!(
+    ///     parse("port=2321,port=2322").unwrap_err(),
+    ///     ConfigError::DuplicateKey("port".to_string()),
+    /// );
+    /// ```
+    pub fn parse(conf: &str) -> Result<Vec<(String, String)>, ConfigError> {
+        let mut pairs: Vec<(String, String)> = Vec::new();
+
+        for item in split_unquoted(conf, ',')? {
+            let item = item.trim();
+            if item.is_empty() {
+                continue;
+            }
+
+            // keys cannot be quoted, i.e. a '=' after the first quote is part of the value
+            let key_end = item.find('"').unwrap_or(item.len());
+            let (key, value) = match item[..key_end].find('=') {
+                Some(i) => (item[..i].trim(), item[i + 1..].trim()),
+                None => ("", item),
+            };
+
+            if !key
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+            {
+                return Err(ConfigError::Syntax(format!("Bad key: {key}")));
+            }
+            if pairs.iter().any(|(other_key, _)| other_key == key) {
+                return Err(ConfigError::DuplicateKey(key.to_string()));
+            }
+
+            pairs.push((key.to_string(), unquote(value)?));
+        }
+
+        Ok(pairs)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[derive(TctiConfig, Default, Debug, PartialEq)]
+        struct DeviceConfig {
+            /// Path of the TPM device.
+            #[tcti_config(positional)]
+            path: String,
+            /// Use non-blocking IO.
+            nonblocking: bool,
+        }
+
+        #[test]
+        fn test_parse_quoting() {
+            assert_eq!(
+                parse(r#"a="x\"y\\z", b = " spaced " ,"#).unwrap(),
+                [
+                    ("a".to_string(), "x\"y\\z".to_string()),
+                    ("b".to_string(), " spaced ".to_string())
+                ]
+            );
+            assert_eq!(parse("").unwrap(), []);
+        }
+
+        #[test]
+        fn test_parse_errors() {
+            assert!(matches!(parse(r#"a="x"#), Err(ConfigError::Syntax(_))));
+            assert!(matches!(parse(r#"a="x\n""#), Err(ConfigError::Syntax(_))));
+            assert!(matches!(parse(r#"a="x"y""#), Err(ConfigError::Syntax(_))));
+            assert!(matches!(parse("a b=c"), Err(ConfigError::Syntax(_))));
+        }
+
+        #[test]
+        fn test_derive_positional() {
+            assert_eq!(
+                DeviceConfig::from_conf("/dev/tpm0").unwrap(),
+                DeviceConfig {
+                    path: "/dev/tpm0".to_string(),
+                    nonblocking: false
+                }
+            );
+            assert_eq!(
+                DeviceConfig::from_conf("path=/dev/tpm0,nonblocking=true").unwrap(),
+                DeviceConfig {
+                    path: "/dev/tpm0".to_string(),
+                    nonblocking: true
+                }
+            );
+            assert_eq!(
+                DeviceConfig::CONFIG_HELP,
+                b"[path=]<String>: Path of the TPM device.\nnonblocking=<bool>: Use non-blocking IO.\0"
+            );
+        }
+
+        #[test]
+        fn test_derive_errors() {
+            let error = DeviceConfig::from_conf("nonblocking=maybe").unwrap_err();
+            assert_eq!(error.key(), Some("nonblocking"));
+            assert_eq!(TctiError::from(error.clone()), TctiError::BadValue);
+            // converting has no side effects
+            assert_eq!(take_last_error(), None);
+            assert_eq!(reject_conf(error.clone()), TctiError::BadValue);
+            assert_eq!(take_last_error(), Some(error));
+
+            assert_eq!(
+                DeviceConfig::from_conf("speed=9600").unwrap_err(),
+                ConfigError::UnknownKey("speed".to_string())
+            );
+
+            // the positional value is the same key
+            assert_eq!(
+                DeviceConfig::from_conf("/dev/tpm0,path=/dev/tpm1").unwrap_err(),
+                ConfigError::DuplicateKey("path".to_string())
+            );
+        }
+    }
+}
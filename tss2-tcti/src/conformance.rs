@@ -0,0 +1,445 @@
+/// ABI conformance checks for [TctiLib](crate::tcti::tcti::TctiLib) implementations.
+///
+/// The checks use a tcti only through its `Tss2_Tcti_Info` and the function
+/// pointers of its context, exactly as libtss2-tctildr does, and panic on the
+/// first violation. Every tcti should run
+/// [check_tcti()](conformance::check_tcti) in its tests.
+pub mod conformance {
+    use std::ffi::{CStr, CString};
+    use std::os::raw::c_char;
+    use std::ptr::{null, null_mut};
+
+    use tss2_tcti_sys::tpm2_tss;
+
+    use crate::tcti::error::TctiError;
+    use crate::tcti::rc::Tss2Rc;
+    use crate::tcti::tcti::{TctiLib, MAX_RESPONSE_SIZE};
+    use crate::tpm::tpm::TPM_HEADER_SIZE;
+
+    /// Tcti context allocated like libtss2-tctildr does (zeroed and aligned)
+    /// together with the function pointers used to call into it. Finalized
+    /// on drop.
+    struct Context {
+        memory: Vec<u64>,
+        api: tpm2_tss::TSS2_TCTI_CONTEXT_COMMON_V2,
+    }
+
+    impl Context {
+        fn zeroed(size: usize, api: tpm2_tss::TSS2_TCTI_CONTEXT_COMMON_V2) -> Self {
+            Self {
+                memory: vec![0; size.div_ceil(std::mem::size_of::<u64>())],
+                api,
+            }
+        }
+
+        /// Query the size, allocate and initialize a context of `T`.
+        fn init<T: TctiLib>(conf: &str) -> Self {
+            let init = init_fn::<T>();
+            let conf = CString::new(conf).expect("conf contains a NUL byte");
+
+            let mut size = 0;
+            let return_code = unsafe { init(null_mut(), &mut size, conf.as_ptr()) };
+            assert_eq!(
+                Tss2Rc::from(return_code),
+                Tss2Rc::SUCCESS,
+                "init size query"
+            );
+
+            let mut context = Self::zeroed(size, Default::default());
+            let return_code = unsafe { init(context.as_ptr(), &mut size, conf.as_ptr()) };
+            assert_eq!(Tss2Rc::from(return_code), Tss2Rc::SUCCESS, "init");
+
+            context.api = unsafe { std::ptr::read(context.as_ptr() as *const _) };
+            context
+        }
+
+        fn as_ptr(&mut self) -> *mut tpm2_tss::TSS2_TCTI_CONTEXT {
+            self.memory.as_mut_ptr() as *mut _
+        }
+
+        fn transmit(&mut self, command: &[u8]) -> Tss2Rc {
+            let transmit = self.api.v1.transmit.expect("transmit is NULL");
+            Tss2Rc::from(unsafe { transmit(self.as_ptr(), command.len(), command.as_ptr()) })
+        }
+
+        /// Receive into `response` (size query if `None`), blocking.
+        fn receive(&mut self, size: &mut usize, response: Option<&mut [u8]>) -> Tss2Rc {
+            let receive = self.api.v1.receive.expect("receive is NULL");
+            let response = response.map_or(null_mut(), |response| response.as_mut_ptr());
+            Tss2Rc::from(unsafe {
+                receive(
+                    self.as_ptr(),
+                    size,
+                    response,
+                    tpm2_tss::TSS2_TCTI_TIMEOUT_BLOCK,
+                )
+            })
+        }
+
+        fn cancel(&mut self) -> Tss2Rc {
+            let cancel = self.api.v1.cancel.expect("cancel is NULL");
+            Tss2Rc::from(unsafe { cancel(self.as_ptr()) })
+        }
+
+        fn get_poll_handles(&mut self) -> Tss2Rc {
+            let get_poll_handles = self.api.v1.getPollHandles.expect("getPollHandles is NULL");
+            let mut num_handles = 0;
+            Tss2Rc::from(unsafe { get_poll_handles(self.as_ptr(), null_mut(), &mut num_handles) })
+        }
+
+        fn set_locality(&mut self, locality: u8) -> Tss2Rc {
+            let set_locality = self.api.v1.setLocality.expect("setLocality is NULL");
+            Tss2Rc::from(unsafe { set_locality(self.as_ptr(), locality) })
+        }
+
+        fn make_sticky(&mut self) -> Tss2Rc {
+            let make_sticky = self.api.makeSticky.expect("makeSticky is NULL");
+            let mut handle = 0x80000000;
+            Tss2Rc::from(unsafe { make_sticky(self.as_ptr(), &mut handle, 1) })
+        }
+
+        fn finalize(&mut self) {
+            if let Some(finalize) = self.api.v1.finalize {
+                unsafe { finalize(self.as_ptr()) };
+            }
+        }
+    }
+
+    impl Drop for Context {
+        fn drop(&mut self) {
+            self.finalize();
+        }
+    }
+
+    fn init_fn<T: TctiLib>() -> unsafe extern "C" fn(
+        *mut tpm2_tss::TSS2_TCTI_CONTEXT,
+        *mut usize,
+        *const c_char,
+    ) -> tpm2_tss::TSS2_RC {
+        unsafe { &*T::info() }.init.expect("Info.init is NULL")
+    }
+
+    fn rc(error: TctiError) -> Tss2Rc {
+        Tss2Rc::from(error)
+    }
+
+    fn info_str(ptr: *const c_char, field: &str) -> &'static str {
+        assert!(!ptr.is_null(), "Info.{field} is NULL");
+        unsafe { CStr::from_ptr(ptr) }
+            .to_str()
+            .unwrap_or_else(|_| panic!("Info.{field} is not valid UTF-8"))
+    }
+
+    /// Run all checks. `conf` must initialize the tcti and `command` must be
+    /// a command the tcti responds to.
+    ///
+    /// # Examples
+    /// ```
+    /// use tss2_tcti::conformance::conformance::check_tcti;
+    /// use tpm2_tcti_sim::lib::TctiSim;
+    ///
+    /// // TPM2_Startup(TPM2_SU_CLEAR)
+    /// check_tcti::<TctiSim>("seed=0", b"\x80\x01\x00\x00\x00\x0c\x00\x00\x01\x44\x00\x00");
+    /// ```
+    pub fn check_tcti<T: TctiLib>(conf: &str, command: &[u8]) {
+        check_info::<T>();
+        check_init::<T>(conf);
+        check_bad_context::<T>(conf);
+        check_null_pointers::<T>(conf);
+        check_state_machine::<T>(conf, command);
+        check_receive::<T>(conf, command);
+        check_finalize::<T>(conf, command);
+    }
+
+    /// `Tss2_Tcti_Info`: version, NUL-terminated strings and `init`.
+    pub fn check_info<T: TctiLib>() {
+        let info = unsafe { &*T::info() };
+
+        assert!(
+            matches!(T::VERSION, 1 | 2),
+            "unknown version {}",
+            T::VERSION
+        );
+        assert_eq!(info.version, T::VERSION, "Info.version");
+        assert!(
+            !info_str(info.name, "name").is_empty(),
+            "Info.name is empty"
+        );
+        info_str(info.description, "description");
+        info_str(info.config_help, "config_help");
+        assert!(info.init.is_some(), "Info.init is NULL");
+    }
+
+    /// Size query with NULL context, too small contexts and the common
+    /// header of an initialized context.
+    pub fn check_init<T: TctiLib>(conf: &str) {
+        let init = init_fn::<T>();
+        let conf_c = CString::new(conf).expect("conf contains a NUL byte");
+
+        let return_code = unsafe { init(null_mut(), null_mut(), conf_c.as_ptr()) };
+        assert_eq!(
+            Tss2Rc::from(return_code),
+            rc(TctiError::BadValue),
+            "init with NULL context and size"
+        );
+
+        let mut size = 0;
+        let return_code = unsafe { init(null_mut(), &mut size, conf_c.as_ptr()) };
+        assert_eq!(
+            Tss2Rc::from(return_code),
+            Tss2Rc::SUCCESS,
+            "init size query"
+        );
+        let common_size = match T::VERSION {
+            1 => std::mem::size_of::<tpm2_tss::TSS2_TCTI_CONTEXT_COMMON_V1>(),
+            _ => std::mem::size_of::<tpm2_tss::TSS2_TCTI_CONTEXT_COMMON_V2>(),
+        };
+        assert!(
+            size >= common_size,
+            "init size query returned {size} bytes, less than the common context"
+        );
+
+        let mut small_size = size - 1;
+        let mut context = Context::zeroed(small_size, Default::default());
+        let return_code = unsafe { init(context.as_ptr(), &mut small_size, conf_c.as_ptr()) };
+        assert_eq!(
+            Tss2Rc::from(return_code),
+            rc(TctiError::InsufficientBuffer),
+            "init with a too small context"
+        );
+
+        let context = Context::init::<T>(conf);
+        assert_eq!(context.api.v1.magic, T::MAGIC, "context magic");
+        assert_eq!(context.api.v1.version, T::VERSION, "context version");
+        assert!(context.api.v1.transmit.is_some(), "transmit is NULL");
+        assert!(context.api.v1.receive.is_some(), "receive is NULL");
+        assert!(context.api.v1.finalize.is_some(), "finalize is NULL");
+        assert!(context.api.v1.cancel.is_some(), "cancel is NULL");
+        assert!(
+            context.api.v1.getPollHandles.is_some(),
+            "getPollHandles is NULL"
+        );
+        assert!(context.api.v1.setLocality.is_some(), "setLocality is NULL");
+        // version 1 contexts may end before makeSticky
+        if T::VERSION >= 2 {
+            assert!(context.api.makeSticky.is_some(), "makeSticky is NULL");
+        }
+    }
+
+    /// Contexts which are not initialized or belong to another tcti are
+    /// rejected with [TctiError::BadContext], finalize ignores them.
+    pub fn check_bad_context<T: TctiLib>(conf: &str) {
+        let api = Context::init::<T>(conf).api;
+
+        for magic in [0, !T::MAGIC] {
+            let mut context = Context::zeroed(std::mem::size_of::<T>(), api);
+            context.memory[0] = magic;
+
+            assert_eq!(
+                unsafe { T::from_ptr(context.as_ptr()) }.err(),
+                Some(TctiError::BadContext {
+                    magic: Some(magic),
+                    expected_magic: Some(T::MAGIC),
+                })
+            );
+            let bad_context = rc(TctiError::BadContext {
+                magic: None,
+                expected_magic: None,
+            });
+            assert_eq!(
+                context.transmit(&[0; TPM_HEADER_SIZE]),
+                bad_context,
+                "transmit"
+            );
+            assert_eq!(context.receive(&mut 0, None), bad_context, "receive");
+            assert_eq!(context.cancel(), bad_context, "cancel");
+            assert_eq!(context.get_poll_handles(), bad_context, "getPollHandles");
+            assert_eq!(context.set_locality(0), bad_context, "setLocality");
+            if T::VERSION >= 2 {
+                assert_eq!(context.make_sticky(), bad_context, "makeSticky");
+            }
+            // dropping the context finalizes it
+        }
+    }
+
+    /// NULL contexts and NULL arguments are rejected with
+    /// [TctiError::BadReference].
+    pub fn check_null_pointers<T: TctiLib>(conf: &str) {
+        let mut context = Context::init::<T>(conf);
+        let api = context.api.v1;
+        let bad_reference = rc(TctiError::BadReference);
+        let timeout = tpm2_tss::TSS2_TCTI_TIMEOUT_BLOCK;
+        let mut size = 0;
+
+        unsafe {
+            let return_code = api.transmit.unwrap()(null_mut(), 0, [0u8].as_ptr());
+            assert_eq!(
+                Tss2Rc::from(return_code),
+                bad_reference,
+                "transmit with NULL context"
+            );
+            let return_code = api.receive.unwrap()(null_mut(), &mut size, null_mut(), timeout);
+            assert_eq!(
+                Tss2Rc::from(return_code),
+                bad_reference,
+                "receive with NULL context"
+            );
+            let return_code = api.cancel.unwrap()(null_mut());
+            assert_eq!(
+                Tss2Rc::from(return_code),
+                bad_reference,
+                "cancel with NULL context"
+            );
+            let return_code = api.getPollHandles.unwrap()(null_mut(), null_mut(), &mut size);
+            assert_eq!(
+                Tss2Rc::from(return_code),
+                bad_reference,
+                "getPollHandles with NULL context"
+            );
+            let return_code = api.setLocality.unwrap()(null_mut(), 0);
+            assert_eq!(
+                Tss2Rc::from(return_code),
+                bad_reference,
+                "setLocality with NULL context"
+            );
+            api.finalize.unwrap()(null_mut());
+
+            let return_code = api.transmit.unwrap()(context.as_ptr(), 0, null());
+            assert_eq!(
+                Tss2Rc::from(return_code),
+                bad_reference,
+                "transmit with NULL command"
+            );
+            let return_code =
+                api.receive.unwrap()(context.as_ptr(), null_mut(), null_mut(), timeout);
+            assert_eq!(
+                Tss2Rc::from(return_code),
+                bad_reference,
+                "receive with NULL size"
+            );
+            let return_code = api.getPollHandles.unwrap()(context.as_ptr(), null_mut(), null_mut());
+            assert_eq!(
+                Tss2Rc::from(return_code),
+                bad_reference,
+                "getPollHandles with NULL num_handles"
+            );
+
+            if T::VERSION >= 2 {
+                let make_sticky = context.api.makeSticky.unwrap();
+                let return_code = make_sticky(null_mut(), &mut 0, 1);
+                assert_eq!(
+                    Tss2Rc::from(return_code),
+                    bad_reference,
+                    "makeSticky with NULL context"
+                );
+                let return_code = make_sticky(context.as_ptr(), null_mut(), 1);
+                assert_eq!(
+                    Tss2Rc::from(return_code),
+                    bad_reference,
+                    "makeSticky with NULL handle"
+                );
+            }
+        }
+    }
+
+    /// Calls out of order are rejected with [TctiError::BadSequence].
+    pub fn check_state_machine<T: TctiLib>(conf: &str, command: &[u8]) {
+        let mut context = Context::init::<T>(conf);
+        let bad_sequence = rc(TctiError::BadSequence);
+        let mut size = 0;
+
+        assert_eq!(
+            context.receive(&mut size, None),
+            bad_sequence,
+            "receive before transmit"
+        );
+        assert_eq!(context.cancel(), bad_sequence, "cancel before transmit");
+
+        assert_eq!(context.transmit(command), Tss2Rc::SUCCESS, "transmit");
+        assert_eq!(context.transmit(command), bad_sequence, "transmit twice");
+        assert_eq!(
+            context.set_locality(0),
+            bad_sequence,
+            "setLocality after transmit"
+        );
+
+        let mut response = vec![0; MAX_RESPONSE_SIZE];
+        size = response.len();
+        assert_eq!(
+            context.receive(&mut size, Some(&mut response)),
+            Tss2Rc::SUCCESS,
+            "receive"
+        );
+        assert_eq!(
+            context.receive(&mut size, Some(&mut response)),
+            bad_sequence,
+            "receive twice"
+        );
+    }
+
+    /// Size query convention: `receive` with a NULL response returns the
+    /// response size, a too small buffer fails with
+    /// [TctiError::InsufficientBuffer], keeps the response and returns its
+    /// size.
+    pub fn check_receive<T: TctiLib>(conf: &str, command: &[u8]) {
+        let mut context = Context::init::<T>(conf);
+
+        // twice, the state must go back to transmit
+        for _ in 0..2 {
+            assert_eq!(context.transmit(command), Tss2Rc::SUCCESS, "transmit");
+
+            let mut size = 0;
+            assert_eq!(
+                context.receive(&mut size, None),
+                Tss2Rc::SUCCESS,
+                "receive size query"
+            );
+            assert!(
+                size >= TPM_HEADER_SIZE,
+                "receive size query returned {size} bytes, less than a header"
+            );
+            // tctis without pending response return an upper bound
+            if size != MAX_RESPONSE_SIZE {
+                let mut small_size = size - 1;
+                let mut response = vec![0; small_size];
+                assert_eq!(
+                    context.receive(&mut small_size, Some(&mut response)),
+                    rc(TctiError::InsufficientBuffer),
+                    "receive into a too small buffer"
+                );
+                assert_eq!(
+                    small_size, size,
+                    "receive into a too small buffer must return the response size"
+                );
+            }
+
+            let expected_size = size;
+            let mut response = vec![0; size];
+            assert_eq!(
+                context.receive(&mut size, Some(&mut response)),
+                Tss2Rc::SUCCESS,
+                "receive"
+            );
+            assert!(size >= TPM_HEADER_SIZE, "response of {size} bytes");
+            if expected_size != MAX_RESPONSE_SIZE {
+                assert_eq!(size, expected_size, "response size");
+            }
+            let header_size = u32::from_be_bytes(response[2..6].try_into().unwrap());
+            assert_eq!(header_size as usize, size, "size in the response header");
+        }
+    }
+
+    /// Finalize can be called repeatedly, afterwards the context is unusable.
+    pub fn check_finalize<T: TctiLib>(conf: &str, command: &[u8]) {
+        let mut context = Context::init::<T>(conf);
+
+        context.finalize();
+        context.finalize();
+        assert_eq!(
+            context.transmit(command),
+            rc(TctiError::BadSequence),
+            "transmit after finalize"
+        );
+        // dropping the context finalizes it again
+    }
+}
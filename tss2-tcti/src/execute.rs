@@ -0,0 +1,184 @@
+/// Executing typed commands of `tpm2-types`.
+pub mod execute {
+    use serde_tpm2::{de::from_bytes, se::to_bytes};
+    use thiserror::Error;
+    use tpm2_types::commands::TpmCommand;
+    use tpm2_types::constants::ReturnCode;
+    use tracing::warn;
+
+    use crate::tcti::error::TctiError;
+    use crate::tcti::tcti::Tcti;
+    use crate::tpm::tpm::{self, TPM_HEADER_SIZE, TPM_RC_SUCCESS, TPM_ST_NO_SESSIONS};
+
+    #[derive(Debug, Error, Clone, PartialEq, Eq)]
+    pub enum TpmError {
+        #[error("Tcti error: {0}")]
+        Tcti(#[from] TctiError),
+
+        #[error("TPM returned response code {0:#x}")]
+        Tpm(ReturnCode),
+
+        #[error("Could not marshal command: {0}")]
+        Marshal(String),
+
+        #[error("Malformed response: {0}")]
+        BadResponse(String),
+    }
+
+    /// Marshal `command` including the command header.
+    pub fn marshal_command<C: TpmCommand>(command: &C) -> Result<Vec<u8>, TpmError> {
+        let body = to_bytes(command).map_err(|error| TpmError::Marshal(error.to_string()))?;
+        Ok(tpm::command(C::COMMAND_CODE, &body))
+    }
+
+    /// Check the response header and unmarshal the response of `C`.
+    pub fn unmarshal_response<C: TpmCommand>(response: &[u8]) -> Result<C::Response, TpmError> {
+        if response.len() < TPM_HEADER_SIZE {
+            return Err(TpmError::BadResponse(format!(
+                "Response too short: {} bytes",
+                response.len()
+            )));
+        }
+
+        let tag = u16::from_be_bytes(response[0..2].try_into().unwrap());
+        let size = u32::from_be_bytes(response[2..6].try_into().unwrap());
+        let rc = u32::from_be_bytes(response[6..10].try_into().unwrap());
+        if size as usize != response.len() {
+            return Err(TpmError::BadResponse(format!(
+                "Size {size} does not match the response length {}",
+                response.len()
+            )));
+        }
+        if rc != TPM_RC_SUCCESS {
+            return Err(TpmError::Tpm(rc));
+        }
+        if tag != TPM_ST_NO_SESSIONS {
+            return Err(TpmError::BadResponse(format!("Unexpected tag {tag:#x}")));
+        }
+
+        from_bytes(&response[TPM_HEADER_SIZE..]).map_err(|error| {
+            warn!("Malformed response to {:?}: {error:?}", C::COMMAND_CODE);
+            TpmError::BadResponse(format!("{error:?}"))
+        })
+    }
+
+    /// Execute typed commands, see [tpm2_types::commands].
+    ///
+    /// # Examples
+    /// ```
+    /// use tss2_tcti::execute::execute::TctiExt;
+    /// use tss2_tcti::tcti::tcti::Tcti;
+    /// use tpm2_tcti_sim::lib::TctiSim;
+    /// use tpm2_types::commands::{GetCapability, GetRandom, Startup};
+    /// use tpm2_types::constants::{Capability, PropertyTag, StartupType};
+    /// use tpm2_types::selectables::Capabilities;
+    /// use tpm2_types::structs::TaggedProperty;
+    ///
+    /// let mut tcti = <TctiSim as Tcti>::new("seed=0").unwrap();
+    /// tcti.execute(&Startup { startup_type: StartupType::Clear }).unwrap();
+    ///
+    /// let response = tcti.execute(&GetRandom { bytes_requested: 8 }).unwrap();
+    /// assert_eq!(response.random_bytes.len(), 8);
+    ///
+    /// let response = tcti
+    ///     .execute(&GetCapability {
+    ///         capability: Capability::TpmProperties,
+    ///         property: PropertyTag::Manufacturer as u32,
+    ///         property_count: 1,
+    ///     })
+    ///     .unwrap();
+    /// assert_eq!(
+    ///     response.capability_data,
+    ///     Capabilities::TpmProperties(vec![TaggedProperty {
+    ///         property: PropertyTag::Manufacturer,
+    ///         value: u32::from_be_bytes(*b"RUST"),
+    ///     }])
+    /// );
+    /// ```
+    pub trait TctiExt: Tcti {
+        /// Transmit `command`, wait for its response and unmarshal it.
+        /// Response codes other than `TPM_RC_SUCCESS` are returned as
+        /// [TpmError::Tpm].
+        fn execute<C: TpmCommand>(&mut self, command: &C) -> Result<C::Response, TpmError> {
+            self.transmit(&marshal_command(command)?)?;
+            let response = self.receive()?;
+            unmarshal_response::<C>(&response)
+        }
+    }
+
+    impl<T: Tcti + ?Sized> TctiExt for T {}
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use std::time::Duration;
+        use tpm2_types::commands::{GetCapability, GetRandom, Startup};
+        use tpm2_types::constants::{Capability, StartupType};
+
+        /// Answers every command with the same response.
+        struct Fixed(Vec<u8>);
+
+        impl Tcti for Fixed {
+            fn new(_conf: &str) -> Result<Self, TctiError> {
+                Err(TctiError::NotSupported)
+            }
+
+            fn transmit(&mut self, _command: &[u8]) -> Result<(), TctiError> {
+                Ok(())
+            }
+
+            fn receive_with_timeout(&mut self, _timeout: Duration) -> Result<Vec<u8>, TctiError> {
+                Ok(self.0.clone())
+            }
+        }
+
+        fn fixed(response: &[u8]) -> Box<dyn Tcti> {
+            Box::new(Fixed(response.to_vec()))
+        }
+
+        #[test]
+        fn test_marshal_command() {
+            let command = marshal_command(&GetCapability {
+                capability: Capability::TpmProperties,
+                property: 0x105,
+                property_count: 1,
+            })
+            .unwrap();
+            assert_eq!(
+                command,
+                b"\x80\x01\x00\x00\x00\x16\x00\x00\x01\x7a\x00\x00\x00\x06\x00\x00\x01\x05\x00\x00\x00\x01"
+            );
+        }
+
+        #[test]
+        fn test_execute() {
+            let mut tcti = fixed(b"\x80\x01\x00\x00\x00\x0e\x00\x00\x00\x00\x00\x02\xaa\xbb");
+            let response = tcti.execute(&GetRandom { bytes_requested: 2 }).unwrap();
+            assert_eq!(response.random_bytes, [0xaa, 0xbb]);
+
+            let startup = Startup {
+                startup_type: StartupType::Clear,
+            };
+            let mut tcti = fixed(b"\x80\x01\x00\x00\x00\x0a\x00\x00\x01\x00");
+            assert_eq!(tcti.execute(&startup), Err(TpmError::Tpm(0x100)));
+        }
+
+        #[test]
+        fn test_bad_responses() {
+            let get_random = GetRandom { bytes_requested: 2 };
+            for response in [
+                &b"\x80\x01\x00\x00\x00\x0a\x00\x00"[..],
+                b"\x80\x01\x00\x00\x00\x0b\x00\x00\x00\x00",
+                b"\x80\x02\x00\x00\x00\x0a\x00\x00\x00\x00",
+                b"\x80\x01\x00\x00\x00\x0e\x00\x00\x00\x00\x00\x03\xaa\xbb",
+                b"\x80\x01\x00\x00\x00\x0f\x00\x00\x00\x00\x00\x02\xaa\xbb\xcc",
+            ] {
+                let mut tcti = fixed(response);
+                assert!(matches!(
+                    tcti.execute(&get_random),
+                    Err(TpmError::BadResponse(_))
+                ));
+            }
+        }
+    }
+}
@@ -0,0 +1,391 @@
+/// Fault injection between a tcti user and its child tcti.
+pub mod fault {
+    use std::thread;
+    use std::time::Duration;
+
+    use crate::config::config::{ConfigError, TctiConfig};
+    use crate::layer::layer::{forward_to_child, impl_layer};
+    use crate::tcti::error::TctiError;
+    use crate::tcti::tcti::{PendingResponse, Tcti, TIMEOUT_BLOCK};
+    use crate::tpm::tpm::{self, TPM_WARNINGS};
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha20Rng;
+    use tracing::info;
+
+    /// Faults are injected with the given probabilities (between 0 and 1),
+    /// every one of them is decided independently.
+    #[derive(TctiConfig, Debug, Clone, PartialEq)]
+    pub struct FaultConfig {
+        /// Conf string of the child tcti `T` whose responses are faulted, must be empty in a stack.
+        #[tcti_config(positional)]
+        pub child: String,
+        /// Seed of the random number generator deciding on the faults. Random if not given.
+        pub seed: Option<u64>,
+        /// Probability of TctiError::TryAgain on transmit or non-blocking receive. The command is not transmitted or the response is kept, respectively.
+        pub try_again: f64,
+        /// Probability of TctiError::IoError on transmit or receive. The response is lost.
+        pub io_error: f64,
+        /// Probability of a truncated response.
+        pub truncate: f64,
+        /// Probability of trailing garbage after the response.
+        pub oversize: f64,
+        /// Probability of a wrong size in the response header.
+        pub bad_size: f64,
+        /// Probability of TPM_RC_RETRY, TPM_RC_YIELDED or TPM_RC_TESTING instead of executing the command.
+        pub tpm_warning: f64,
+        /// Probability of a delayed response. If the timeout is shorter than the delay, TctiError::TryAgain is returned.
+        pub delay: f64,
+        /// Duration of delays in milliseconds.
+        pub delay_ms: u64,
+        /// Probability of TctiError::NotPermitted when setting the locality.
+        pub locality: f64,
+    }
+
+    impl Default for FaultConfig {
+        fn default() -> Self {
+            Self {
+                child: String::new(),
+                seed: None,
+                try_again: 0.0,
+                io_error: 0.0,
+                truncate: 0.0,
+                oversize: 0.0,
+                bad_size: 0.0,
+                tpm_warning: 0.0,
+                delay: 0.0,
+                delay_ms: 100,
+                locality: 0.0,
+            }
+        }
+    }
+
+    impl FaultConfig {
+        /// Parse the conf string, checking that all probabilities are
+        /// between 0 and 1.
+        pub fn parse(conf: &str) -> Result<Self, ConfigError> {
+            let config = Self::from_conf(conf)?;
+
+            let probabilities = [
+                ("try_again", config.try_again),
+                ("io_error", config.io_error),
+                ("truncate", config.truncate),
+                ("oversize", config.oversize),
+                ("bad_size", config.bad_size),
+                ("tpm_warning", config.tpm_warning),
+                ("delay", config.delay),
+                ("locality", config.locality),
+            ];
+            for (key, probability) in probabilities {
+                if !(0.0..=1.0).contains(&probability) {
+                    return Err(ConfigError::BadValue {
+                        key: key.to_string(),
+                        value: probability.to_string(),
+                    });
+                }
+            }
+
+            Ok(config)
+        }
+    }
+
+    /// A fault which was injected.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Fault {
+        TryAgain,
+        IoError,
+        Truncate,
+        Oversize,
+        BadSize,
+        TpmWarning(u32),
+        Delay,
+        Locality,
+    }
+
+    /// Tcti which forwards to its child, injecting faults as configured by
+    /// [FaultConfig]. With the same seed, the same faults are injected.
+    ///
+    /// # Examples
+    /// ```
+    /// use tss2_tcti::fault::fault::{Fault, FaultTcti};
+    /// use tpm2_tcti_sim::lib::TctiSim;
+    /// use tss2_tcti::tcti::tcti::Tcti;
+    ///
+    /// let mut tcti = FaultTcti::<TctiSim>::new("child=\"seed=0\",seed=1,tpm_warning=1").unwrap();
+    ///
+    /// // TPM2_Startup is not executed, but answered with a TPM warning
+    /// tcti.transmit(b"\x80\x01\x00\x00\x00\x0c\x00\x00\x01\x44\x00\x00").unwrap();
+    /// let response = tcti.receive().unwrap();
+    /// let rc = u32::from_be_bytes(response[6..10].try_into().unwrap());
+    /// assert_eq!(tcti.injected(), [Fault::TpmWarning(rc)]);
+    /// ```
+    pub struct FaultTcti<T: Tcti> {
+        child: T,
+        config: FaultConfig,
+        rng: ChaCha20Rng,
+        /// Response which was received from the child (or synthesized), but
+        /// not faulted yet.
+        held: Option<Vec<u8>>,
+        /// Response which was faulted, but not handed to the caller yet.
+        ready: PendingResponse,
+        injected: Vec<Fault>,
+    }
+
+    impl<T: Tcti> FaultTcti<T> {
+        /// Wrap `child`. [FaultConfig::child] is ignored.
+        pub fn with_child(child: T, config: FaultConfig) -> Self {
+            let rng = match config.seed {
+                Some(seed) => ChaCha20Rng::seed_from_u64(seed),
+                None => ChaCha20Rng::from_entropy(),
+            };
+
+            Self {
+                child,
+                config,
+                rng,
+                held: None,
+                ready: None,
+                injected: Vec::new(),
+            }
+        }
+
+        /// Faults injected so far, oldest first.
+        pub fn injected(&self) -> &[Fault] {
+            &self.injected
+        }
+
+        /// Decide whether to inject `fault`.
+        fn inject(&mut self, probability: f64, fault: Fault) -> bool {
+            if !self.rng.gen_bool(probability) {
+                return false;
+            }
+
+            info!("Injecting fault: {fault:?}");
+            self.injected.push(fault);
+            true
+        }
+
+        fn tpm_warning(&mut self) -> Option<Vec<u8>> {
+            let rc = TPM_WARNINGS[self.rng.gen_range(0..TPM_WARNINGS.len())];
+            if !self.inject(self.config.tpm_warning, Fault::TpmWarning(rc)) {
+                return None;
+            }
+
+            Some(tpm::response(rc, &[]))
+        }
+
+        /// Truncate, extend or mangle the size header of a response.
+        fn corrupt(&mut self, mut response: Vec<u8>) -> Vec<u8> {
+            if !response.is_empty() && self.inject(self.config.truncate, Fault::Truncate) {
+                let size = self.rng.gen_range(0..response.len());
+                response.truncate(size);
+            }
+            if self.inject(self.config.oversize, Fault::Oversize) {
+                let garbage = self.rng.gen_range(1..=64);
+                response.extend((0..garbage).map(|_| self.rng.gen::<u8>()));
+            }
+            if response.len() >= 6 && self.inject(self.config.bad_size, Fault::BadSize) {
+                let mut size = self.rng.gen::<u32>();
+                if size as usize == response.len() {
+                    size = size.wrapping_add(1);
+                }
+                response[2..6].copy_from_slice(&size.to_be_bytes());
+            }
+            response
+        }
+    }
+
+    impl_layer!(FaultTcti, FaultConfig, [config, injected]);
+
+    impl<T: Tcti> Tcti for FaultTcti<T> {
+        forward_to_child!(
+            new(FaultConfig::parse),
+            keep_response(ready),
+            cancel,
+            get_poll_handles,
+            make_sticky
+        );
+
+        fn transmit(&mut self, command: &[u8]) -> Result<(), TctiError> {
+            if self.held.is_some() || self.ready.is_some() {
+                return Err(TctiError::BadSequence);
+            }
+            if self.inject(self.config.try_again, Fault::TryAgain) {
+                return Err(TctiError::TryAgain);
+            }
+            if self.inject(self.config.io_error, Fault::IoError) {
+                return Err(TctiError::IoError);
+            }
+            if let Some(response) = self.tpm_warning() {
+                self.held = Some(response);
+                return Ok(());
+            }
+
+            self.child.transmit(command)
+        }
+
+        fn receive_with_timeout(&mut self, timeout: Duration) -> Result<Vec<u8>, TctiError> {
+            if let Some(response) = self.ready.take() {
+                return Ok(response);
+            }
+
+            let response = match self.held.take() {
+                Some(response) => response,
+                None => self.child.receive_with_timeout(timeout)?,
+            };
+
+            if self.inject(self.config.delay, Fault::Delay) {
+                let delay = Duration::from_millis(self.config.delay_ms);
+                if timeout < delay {
+                    thread::sleep(timeout);
+                    self.held = Some(response);
+                    return Err(TctiError::TryAgain);
+                }
+                thread::sleep(delay);
+            }
+            // blocking receives do not time out
+            if timeout != TIMEOUT_BLOCK && self.inject(self.config.try_again, Fault::TryAgain) {
+                self.held = Some(response);
+                return Err(TctiError::TryAgain);
+            }
+            if self.inject(self.config.io_error, Fault::IoError) {
+                return Err(TctiError::IoError);
+            }
+
+            Ok(self.corrupt(response))
+        }
+
+        fn set_locality(&mut self, locality: u8) -> Result<(), TctiError> {
+            if self.inject(self.config.locality, Fault::Locality) {
+                return Err(TctiError::NotPermitted);
+            }
+
+            self.child.set_locality(locality)
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::testing::testing::Echo;
+        use crate::tpm::tpm::TPM_HEADER_SIZE;
+
+        const COMMAND: &[u8] = b"\x80\x01\x00\x00\x00\x0c\x00\x00\x01\x7b\x00\x08";
+
+        fn fault_tcti(conf: &str) -> FaultTcti<Echo> {
+            FaultTcti::new(&format!("seed=7,{conf}")).unwrap()
+        }
+
+        fn execute(tcti: &mut FaultTcti<Echo>) -> Result<Vec<u8>, TctiError> {
+            tcti.transmit(COMMAND)?;
+            tcti.receive()
+        }
+
+        #[test]
+        fn test_config() {
+            assert_eq!(
+                FaultConfig::parse("mssim,try_again=0.5").unwrap(),
+                FaultConfig {
+                    child: "mssim".to_string(),
+                    try_again: 0.5,
+                    ..Default::default()
+                }
+            );
+            assert_eq!(
+                FaultConfig::parse("io_error=1.5").unwrap_err(),
+                ConfigError::BadValue {
+                    key: "io_error".to_string(),
+                    value: "1.5".to_string()
+                }
+            );
+            assert!(FaultConfig::parse("delay=NaN").is_err());
+        }
+
+        #[test]
+        fn test_no_faults() {
+            let mut tcti = fault_tcti("");
+            for _ in 0..100 {
+                assert_eq!(execute(&mut tcti).unwrap(), COMMAND);
+            }
+            assert_eq!(tcti.set_locality(3), Ok(()));
+            assert!(tcti.injected().is_empty());
+        }
+
+        #[test]
+        fn test_try_again_keeps_response() {
+            let mut tcti = fault_tcti("try_again=1");
+            assert_eq!(tcti.transmit(COMMAND), Err(TctiError::TryAgain));
+
+            tcti.config.try_again = 0.0;
+            tcti.transmit(COMMAND).unwrap();
+            tcti.config.try_again = 1.0;
+            assert_eq!(
+                tcti.receive_with_timeout(Duration::ZERO),
+                Err(TctiError::TryAgain)
+            );
+            assert_eq!(tcti.transmit(COMMAND), Err(TctiError::BadSequence));
+            assert_eq!(tcti.receive().unwrap(), COMMAND);
+            assert_eq!(tcti.injected(), [Fault::TryAgain; 2]);
+        }
+
+        #[test]
+        fn test_delay() {
+            let mut tcti = fault_tcti("delay=1,delay_ms=20");
+            tcti.transmit(COMMAND).unwrap();
+            assert_eq!(
+                tcti.receive_with_timeout(Duration::from_millis(1)),
+                Err(TctiError::TryAgain)
+            );
+            assert_eq!(tcti.receive().unwrap(), COMMAND);
+        }
+
+        #[test]
+        fn test_corrupted_responses() {
+            let mut tcti = fault_tcti("truncate=1");
+            assert!(execute(&mut tcti).unwrap().len() < COMMAND.len());
+
+            let mut tcti = fault_tcti("oversize=1");
+            let response = execute(&mut tcti).unwrap();
+            assert!(response.len() > COMMAND.len());
+            assert_eq!(&response[..COMMAND.len()], COMMAND);
+
+            let mut tcti = fault_tcti("bad_size=1");
+            let response = execute(&mut tcti).unwrap();
+            assert_ne!(&response[2..6], &COMMAND[2..6]);
+            assert_eq!(&response[6..], &COMMAND[6..]);
+
+            // the response size is the size of the faulted response
+            let mut tcti = fault_tcti("truncate=1");
+            tcti.transmit(COMMAND).unwrap();
+            let size = tcti.response_size(Duration::ZERO).unwrap();
+            let mut response = vec![0; size];
+            assert_eq!(tcti.receive_into(&mut response, Duration::ZERO), Ok(size));
+        }
+
+        #[test]
+        fn test_tpm_warnings_and_errors() {
+            let mut tcti = fault_tcti("tpm_warning=1");
+            for _ in 0..10 {
+                let response = execute(&mut tcti).unwrap();
+                assert_eq!(response.len(), TPM_HEADER_SIZE);
+                let rc = u32::from_be_bytes(response[6..10].try_into().unwrap());
+                assert!(TPM_WARNINGS.contains(&rc));
+            }
+            assert!(tcti.child().command.is_none());
+
+            let mut tcti = fault_tcti("io_error=1,locality=1");
+            assert_eq!(execute(&mut tcti), Err(TctiError::IoError));
+            assert_eq!(tcti.set_locality(3), Err(TctiError::NotPermitted));
+        }
+
+        #[test]
+        fn test_seed() {
+            let conf = "try_again=0.2,io_error=0.2,truncate=0.2,tpm_warning=0.2";
+            let (mut a, mut b) = (fault_tcti(conf), fault_tcti(conf));
+            for _ in 0..100 {
+                assert_eq!(execute(&mut a), execute(&mut b));
+            }
+            assert!(!a.injected().is_empty());
+            assert_eq!(a.injected(), b.injected());
+        }
+    }
+}
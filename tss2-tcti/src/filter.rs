@@ -0,0 +1,223 @@
+/// Rejecting commands before they reach the TPM.
+pub mod filter {
+    use std::str::FromStr;
+    use std::time::Duration;
+
+    use tpm2_types::constants::CommandCode;
+    use tracing::info;
+
+    use crate::config::config::TctiConfig;
+    use crate::layer::layer::{copy_response, forward_to_child, impl_layer};
+    use crate::tcti::error::TctiError;
+    use crate::tcti::tcti::{PendingResponse, Tcti};
+    use crate::tpm::tpm::{self, command_code, command_name, TPM_RC_COMMAND_CODE};
+
+    /// Command codes separated by whitespace, given by name (e.g.
+    /// `GetRandom`) or as hex value (e.g. `0x2000ffff` for vendor
+    /// commands).
+    #[derive(Debug, Clone, Default, PartialEq, Eq)]
+    pub struct CommandCodes(pub Vec<u32>);
+
+    impl CommandCodes {
+        pub fn contains(&self, cc: u32) -> bool {
+            self.0.contains(&cc)
+        }
+    }
+
+    impl FromStr for CommandCodes {
+        type Err = ();
+
+        fn from_str(codes: &str) -> Result<Self, Self::Err> {
+            codes
+                .split_whitespace()
+                .map(|code| match code.strip_prefix("0x") {
+                    Some(hex) => u32::from_str_radix(hex, 16).map_err(|_| ()),
+                    None => CommandCode::from_str(code).map(u32::from),
+                })
+                .collect::<Result<_, _>>()
+                .map(Self)
+        }
+    }
+
+    #[derive(TctiConfig, Debug, Clone, Default, PartialEq)]
+    pub struct FilterConfig {
+        /// Conf string of the child tcti `T` which gets the allowed commands, must be empty in a stack.
+        #[tcti_config(positional)]
+        pub child: String,
+        /// Commands which are passed to the child (e.g. "GetRandom GetCapability"), all if not given.
+        pub allow: Option<CommandCodes>,
+        /// Commands which are rejected, even if allowed.
+        pub deny: CommandCodes,
+    }
+
+    impl FilterConfig {
+        /// Whether commands with command code `cc` are passed to the child.
+        pub fn is_allowed(&self, cc: u32) -> bool {
+            let allowed = match &self.allow {
+                Some(allow) => allow.contains(cc),
+                None => true,
+            };
+            allowed && !self.deny.contains(cc)
+        }
+    }
+
+    /// Tcti which answers commands that its [FilterConfig] does not allow
+    /// with `TPM_RC_COMMAND_CODE`, instead of passing them to its child.
+    /// Commands without a complete header are passed to the child.
+    ///
+    /// # Examples
+    /// ```
+    /// use tss2_tcti::filter::filter::FilterTcti;
+    /// use tss2_tcti::tcti::tcti::Tcti;
+    /// use tpm2_tcti_sim::lib::TctiSim;
+    ///
+    /// let mut tcti = FilterTcti::<TctiSim>::new("child=\"seed=0\",deny=GetRandom").unwrap();
+    ///
+    /// tcti.transmit(b"\x80\x01\x00\x00\x00\x0c\x00\x00\x01\x7b\x00\x08").unwrap();
+    /// assert_eq!(tcti.receive().unwrap(), b"\x80\x01\x00\x00\x00\x0a\x00\x00\x01\x43");
+    /// ```
+    pub struct FilterTcti<T: Tcti> {
+        child: T,
+        config: FilterConfig,
+        /// Response to a rejected command, not handed to the caller yet.
+        rejected: PendingResponse,
+    }
+
+    impl<T: Tcti> FilterTcti<T> {
+        /// Wrap `child`. [FilterConfig::child] is ignored.
+        pub fn with_child(child: T, config: FilterConfig) -> Self {
+            Self {
+                child,
+                config,
+                rejected: None,
+            }
+        }
+    }
+
+    impl_layer!(FilterTcti, FilterConfig, [config]);
+
+    impl<T: Tcti> Tcti for FilterTcti<T> {
+        forward_to_child!(
+            new(FilterConfig::from_conf),
+            get_poll_handles,
+            set_locality,
+            make_sticky
+        );
+
+        fn transmit(&mut self, command: &[u8]) -> Result<(), TctiError> {
+            if self.rejected.is_some() {
+                return Err(TctiError::BadSequence);
+            }
+
+            match command_code(command) {
+                Some(cc) if !self.config.is_allowed(cc) => {
+                    info!("Rejecting command {}", command_name(cc));
+                    self.rejected = Some(tpm::response(TPM_RC_COMMAND_CODE, &[]));
+                    Ok(())
+                }
+                _ => self.child.transmit(command),
+            }
+        }
+
+        fn receive_with_timeout(&mut self, timeout: Duration) -> Result<Vec<u8>, TctiError> {
+            match self.rejected.take() {
+                Some(response) => Ok(response),
+                None => self.child.receive_with_timeout(timeout),
+            }
+        }
+
+        fn response_size(&mut self, timeout: Duration) -> Result<usize, TctiError> {
+            match &self.rejected {
+                Some(response) => Ok(response.len()),
+                None => self.child.response_size(timeout),
+            }
+        }
+
+        fn receive_into(
+            &mut self,
+            response: &mut [u8],
+            timeout: Duration,
+        ) -> Result<usize, TctiError> {
+            match self.rejected.take() {
+                Some(rejected) => copy_response(&mut self.rejected, rejected, response),
+                None => self.child.receive_into(response, timeout),
+            }
+        }
+
+        fn cancel(&mut self) -> Result<(), TctiError> {
+            // the rejected command is answered already
+            match self.rejected {
+                Some(_) => Ok(()),
+                None => self.child.cancel(),
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::config::config::ConfigError;
+        use crate::testing::testing::Echo;
+        use crate::tpm::tpm::{response_code, TPM_HEADER_SIZE};
+
+        const GET_RANDOM: &[u8] = b"\x80\x01\x00\x00\x00\x0c\x00\x00\x01\x7b\x00\x08";
+        const STARTUP: &[u8] = b"\x80\x01\x00\x00\x00\x0c\x00\x00\x01\x44\x00\x00";
+
+        fn execute(tcti: &mut FilterTcti<Echo>, command: &[u8]) -> Vec<u8> {
+            tcti.transmit(command).unwrap();
+            tcti.receive().unwrap()
+        }
+
+        #[test]
+        fn test_config() {
+            let config = FilterConfig::from_conf("allow=\"Startup GetRandom 0x20000001\"").unwrap();
+            assert_eq!(
+                config.allow,
+                Some(CommandCodes(vec![0x144, 0x17b, 0x20000001]))
+            );
+            assert!(config.is_allowed(0x17b));
+            assert!(!config.is_allowed(0x17a));
+
+            let config =
+                FilterConfig::from_conf("allow=\"Startup GetRandom\",deny=GetRandom").unwrap();
+            assert!(config.is_allowed(0x144));
+            assert!(!config.is_allowed(0x17b));
+            assert!(FilterConfig::default().is_allowed(0x17b));
+
+            assert_eq!(
+                FilterConfig::from_conf("deny=Unknown").unwrap_err(),
+                ConfigError::BadValue {
+                    key: "deny".to_string(),
+                    value: "Unknown".to_string()
+                }
+            );
+        }
+
+        #[test]
+        fn test_filter() {
+            let config = FilterConfig::from_conf("deny=GetRandom").unwrap();
+            let mut tcti = FilterTcti::with_child(Echo::default(), config);
+
+            assert_eq!(execute(&mut tcti, STARTUP), STARTUP);
+            let response = execute(&mut tcti, GET_RANDOM);
+            assert_eq!(response_code(&response), Some(TPM_RC_COMMAND_CODE));
+            assert!(tcti.child().command.is_none());
+            // incomplete commands are passed on
+            assert_eq!(execute(&mut tcti, &GET_RANDOM[..8]), &GET_RANDOM[..8]);
+
+            tcti.transmit(GET_RANDOM).unwrap();
+            assert_eq!(tcti.transmit(GET_RANDOM), Err(TctiError::BadSequence));
+            assert_eq!(tcti.response_size(Duration::ZERO), Ok(TPM_HEADER_SIZE));
+            let mut response = [0; TPM_HEADER_SIZE];
+            assert_eq!(
+                tcti.receive_into(&mut response[..4], Duration::ZERO),
+                Err(TctiError::InsufficientBuffer)
+            );
+            assert_eq!(
+                tcti.receive_into(&mut response, Duration::ZERO),
+                Ok(TPM_HEADER_SIZE)
+            );
+            assert_eq!(response_code(&response), Some(TPM_RC_COMMAND_CODE));
+        }
+    }
+}
@@ -0,0 +1,403 @@
+/// Stacks of middleware tctis.
+pub mod layer {
+    use std::sync::Mutex;
+
+    use tracing::warn;
+
+    use crate::config::config::{reject_conf, split_unquoted, TctiConfig};
+    use crate::fault::fault::FaultConfig;
+    use crate::filter::filter::FilterConfig;
+    use crate::metrics::metrics::MetricsConfig;
+    use crate::record::record::RecordConfig;
+    use crate::registry::registry::open_tcti;
+    use crate::retry::retry::RetryConfig;
+    use crate::tcti::error::TctiError;
+    use crate::tcti::tcti::{PendingResponse, Tcti};
+    use crate::tctildr::tcti_loader::parse_name_conf;
+    use crate::trace::trace::TraceConfig;
+
+    /// Middleware which wraps a child tcti, usually implemented by its
+    /// config.
+    ///
+    /// # Examples
+    /// ```
+    /// use tss2_tcti::layer::layer::TctiLayer;
+    /// use tss2_tcti::retry::retry::RetryConfig;
+    /// use tss2_tcti::tcti::tcti::Tcti;
+    /// use tss2_tcti::trace::trace::TraceConfig;
+    /// use tpm2_tcti_sim::lib::TctiSim;
+    ///
+    /// let retry = RetryConfig::parse("startup=true").unwrap();
+    /// let sim = <TctiSim as Tcti>::new("seed=0").unwrap();
+    /// let mut tcti = TraceConfig::default().layer(retry.layer(sim));
+    ///
+    /// tcti.transmit(b"\x80\x01\x00\x00\x00\x0c\x00\x00\x01\x7b\x00\x08").unwrap();
+    /// assert_eq!(tcti.receive().unwrap()[6..10], [0, 0, 0, 0]);
+    /// ```
+    pub trait TctiLayer<T: Tcti> {
+        type Tcti: Tcti;
+
+        /// Wrap `child`.
+        fn layer(&self, child: T) -> Self::Tcti;
+    }
+
+    /// [Tcti::response_size()] of a layer which keeps the `response` it
+    /// received in `pending` until it is received.
+    pub fn keep_response(pending: &mut PendingResponse, response: Vec<u8>) -> usize {
+        let size = response.len();
+        *pending = Some(response);
+        size
+    }
+
+    /// [Tcti::receive_into()] of a layer which keeps the `received` response
+    /// in `pending` if it does not fit into `response`.
+    pub fn copy_response(
+        pending: &mut PendingResponse,
+        received: Vec<u8>,
+        response: &mut [u8],
+    ) -> Result<usize, TctiError> {
+        if received.len() > response.len() {
+            *pending = Some(received);
+            return Err(TctiError::InsufficientBuffer);
+        }
+
+        response[..received.len()].copy_from_slice(&received);
+        Ok(received.len())
+    }
+
+    /// Implements what all layers `$tcti<T>` share: [TctiLayer] for their
+    /// `$config`, `child()` and [Debug](std::fmt::Debug) showing `$field`s.
+    /// The layer has a `child: T` field and a `with_child(child, config)`
+    /// constructor.
+    macro_rules! impl_layer {
+        ($tcti:ident, $config:ty, [$($field:ident),*]) => {
+            impl<T: Tcti> std::fmt::Debug for $tcti<T> {
+                fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                    f.debug_struct(stringify!($tcti))
+                        $(.field(stringify!($field), &self.$field))*
+                        .finish_non_exhaustive()
+                }
+            }
+
+            impl<T: Tcti> $tcti<T> {
+                pub fn child(&mut self) -> &mut T {
+                    &mut self.child
+                }
+            }
+
+            impl<T: Tcti> $crate::layer::layer::TctiLayer<T> for $config {
+                type Tcti = $tcti<T>;
+
+                fn layer(&self, child: T) -> $tcti<T> {
+                    $tcti::with_child(child, self.clone())
+                }
+            }
+        };
+    }
+
+    /// Implements [Tcti] methods of a layer by passing them to its child,
+    /// used within `impl Tcti`:
+    ///  * `new(parse)` parses the conf string with `parse` and creates the
+    ///    child tcti from the `child` of the config,
+    ///  * `keep_response(pending)` implements [Tcti::response_size()] and
+    ///    [Tcti::receive_into()] on top of [Tcti::receive_with_timeout()]
+    ///    of the layer, see [keep_response()] and [copy_response()],
+    ///  * `response_size`, `cancel`, `get_poll_handles`, `set_locality` and
+    ///    `make_sticky` are passed to the child unchanged.
+    macro_rules! forward_to_child {
+        (new($parse:path)) => {
+            fn new(conf: &str) -> Result<Self, $crate::tcti::error::TctiError> {
+                let config = $parse(conf).map_err($crate::config::config::reject_conf)?;
+                let child = T::new(&config.child).inspect_err(|error| {
+                    tracing::warn!("Could not initialize child tcti: {error:?}")
+                })?;
+
+                Ok(Self::with_child(child, config))
+            }
+        };
+        (keep_response($pending:ident)) => {
+            fn response_size(
+                &mut self,
+                timeout: std::time::Duration,
+            ) -> Result<usize, $crate::tcti::error::TctiError> {
+                let response = self.receive_with_timeout(timeout)?;
+                Ok($crate::layer::layer::keep_response(&mut self.$pending, response))
+            }
+
+            fn receive_into(
+                &mut self,
+                response: &mut [u8],
+                timeout: std::time::Duration,
+            ) -> Result<usize, $crate::tcti::error::TctiError> {
+                let received = self.receive_with_timeout(timeout)?;
+                $crate::layer::layer::copy_response(&mut self.$pending, received, response)
+            }
+        };
+        (response_size) => {
+            fn response_size(
+                &mut self,
+                timeout: std::time::Duration,
+            ) -> Result<usize, $crate::tcti::error::TctiError> {
+                self.child.response_size(timeout)
+            }
+        };
+        (cancel) => {
+            fn cancel(&mut self) -> Result<(), $crate::tcti::error::TctiError> {
+                self.child.cancel()
+            }
+        };
+        (get_poll_handles) => {
+            fn get_poll_handles(
+                &mut self,
+            ) -> Result<
+                Vec<tss2_tcti_sys::tpm2_tss::TSS2_TCTI_POLL_HANDLE>,
+                $crate::tcti::error::TctiError,
+            > {
+                self.child.get_poll_handles()
+            }
+        };
+        (set_locality) => {
+            fn set_locality(&mut self, locality: u8) -> Result<(), $crate::tcti::error::TctiError> {
+                self.child.set_locality(locality)
+            }
+        };
+        (make_sticky) => {
+            fn make_sticky(
+                &mut self,
+                handle: &mut u32,
+                sticky: bool,
+            ) -> Result<(), $crate::tcti::error::TctiError> {
+                self.child.make_sticky(handle, sticky)
+            }
+        };
+        ($($method:ident $(($($arg:tt)*))?),+ $(,)?) => {
+            $($crate::layer::layer::forward_to_child!($method $(($($arg)*))?);)+
+        };
+    }
+
+    pub(crate) use {forward_to_child, impl_layer};
+
+    /// Creates a layer from its conf string, wrapping the child tcti.
+    pub type LayerConstructor = fn(Box<dyn Tcti>, &str) -> Result<Box<dyn Tcti>, TctiError>;
+
+    /// Layers in addition to the builtin ones, see [register_layer()].
+    static LAYERS: Mutex<Vec<(String, LayerConstructor)>> = Mutex::new(Vec::new());
+
+    /// Makes a layer available to [open_stack()] under `name`. Later
+    /// registrations take precedence over earlier ones and the builtin
+    /// layers.
+    pub fn register_layer(name: &str, constructor: LayerConstructor) {
+        LAYERS
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .push((name.to_string(), constructor));
+    }
+
+    /// The child of a layer in a stack is the next element, a child given
+    /// in the conf of the layer is rejected.
+    fn no_child(child: &str) -> Result<(), TctiError> {
+        if !child.is_empty() {
+            warn!("Child tcti {child:?} given to a layer of a stack");
+            return Err(TctiError::BadValue);
+        }
+        Ok(())
+    }
+
+    fn trace(child: Box<dyn Tcti>, conf: &str) -> Result<Box<dyn Tcti>, TctiError> {
+        let config = TraceConfig::from_conf(conf).map_err(reject_conf)?;
+        no_child(&config.child)?;
+        Ok(Box::new(config.layer(child)))
+    }
+
+    fn retry(child: Box<dyn Tcti>, conf: &str) -> Result<Box<dyn Tcti>, TctiError> {
+        let config = RetryConfig::parse(conf).map_err(reject_conf)?;
+        no_child(&config.child)?;
+        Ok(Box::new(config.layer(child)))
+    }
+
+    fn fault(child: Box<dyn Tcti>, conf: &str) -> Result<Box<dyn Tcti>, TctiError> {
+        let config = FaultConfig::parse(conf).map_err(reject_conf)?;
+        no_child(&config.child)?;
+        Ok(Box::new(config.layer(child)))
+    }
+
+    fn metrics(child: Box<dyn Tcti>, conf: &str) -> Result<Box<dyn Tcti>, TctiError> {
+        let config = MetricsConfig::from_conf(conf).map_err(reject_conf)?;
+        no_child(&config.child)?;
+        Ok(Box::new(config.layer(child)))
+    }
+
+    fn filter(child: Box<dyn Tcti>, conf: &str) -> Result<Box<dyn Tcti>, TctiError> {
+        let config = FilterConfig::from_conf(conf).map_err(reject_conf)?;
+        no_child(&config.child)?;
+        Ok(Box::new(config.layer(child)))
+    }
+
+    fn record(child: Box<dyn Tcti>, conf: &str) -> Result<Box<dyn Tcti>, TctiError> {
+        let config = RecordConfig::parse(conf).map_err(reject_conf)?;
+        no_child(&config.child)?;
+        Ok(Box::new(config.layer(child)))
+    }
+
+    fn find(name: &str) -> Option<LayerConstructor> {
+        let registered = LAYERS
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .iter()
+            .rev()
+            .find(|(registered_name, _)| registered_name == name)
+            .map(|(_, constructor)| *constructor);
+
+        registered.or(match name {
+            "trace" => Some(trace),
+            "retry" => Some(retry),
+            "fault" => Some(fault),
+            "metrics" => Some(metrics),
+            "filter" => Some(filter),
+            "record" => Some(record),
+            _ => None,
+        })
+    }
+
+    /// Open a stack `<layer>|<layer>|...|<tcti>`, where each layer is
+    /// `<name>:<conf>` and the last element is opened via
+    /// [open()](crate::registry::registry::open). The first layer is the
+    /// outermost one. `|` within quotes does not separate layers. The child
+    /// of a layer is the next element of the stack, a positional child in
+    /// the conf of a layer is rejected.
+    ///
+    /// The builtin layers are `trace` ([TraceConfig]), `retry`
+    /// ([RetryConfig]), `fault` ([FaultConfig]), `metrics`
+    /// ([MetricsConfig]), `filter` ([FilterConfig]) and `record`
+    /// ([RecordConfig]).
+    ///
+    /// # Examples
+    /// ```
+    /// use tss2_tcti::layer::layer::open_stack;
+    /// use tss2_tcti::registry::registry::register_tcti;
+    /// use tpm2_tcti_sim::lib::TctiSim;
+    ///
+    /// register_tcti::<TctiSim>("sim");
+    /// let mut tcti = open_stack("trace:name=app|retry:startup=true,max_attempts=3|sim:seed=0").unwrap();
+    ///
+    /// tcti.transmit(b"\x80\x01\x00\x00\x00\x0c\x00\x00\x01\x7b\x00\x08").unwrap();
+    /// assert_eq!(tcti.receive().unwrap()[6..10], [0, 0, 0, 0]);
+    /// ```
+    pub fn open_stack(stack: &str) -> Result<Box<dyn Tcti>, TctiError> {
+        let mut layers = split_unquoted(stack, '|')?;
+        let tcti = layers.pop().unwrap_or_default().trim();
+
+        let constructors = layers
+            .iter()
+            .map(|name_conf| {
+                let (name, conf) = parse_name_conf(name_conf.trim());
+                match find(name) {
+                    Some(constructor) => Ok((name, conf, constructor)),
+                    None => {
+                        warn!("Unknown layer {name:?}");
+                        Err(TctiError::NotSupported)
+                    }
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut tcti = open_tcti(tcti)?;
+        for (name, conf, constructor) in constructors.into_iter().rev() {
+            tcti = constructor(tcti, conf)
+                .inspect_err(|error| warn!("Could not initialize layer {name:?}: {error:?}"))?;
+        }
+
+        Ok(tcti)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::registry::registry::{open, register_tcti};
+        use crate::testing::testing::Echo;
+        use std::time::Duration;
+
+        const GET_RANDOM: &[u8] = b"\x80\x01\x00\x00\x00\x0c\x00\x00\x01\x7b\x00\x08";
+
+        /// Increments the last byte of the command.
+        struct Increment<T: Tcti>(T);
+
+        impl<T: Tcti> Tcti for Increment<T> {
+            fn new(conf: &str) -> Result<Self, TctiError> {
+                Ok(Self(T::new(conf)?))
+            }
+
+            fn transmit(&mut self, command: &[u8]) -> Result<(), TctiError> {
+                let mut command = command.to_vec();
+                *command.last_mut().unwrap() += 1;
+                self.0.transmit(&command)
+            }
+
+            fn receive_with_timeout(&mut self, timeout: Duration) -> Result<Vec<u8>, TctiError> {
+                self.0.receive_with_timeout(timeout)
+            }
+        }
+
+        fn increment(child: Box<dyn Tcti>, conf: &str) -> Result<Box<dyn Tcti>, TctiError> {
+            match conf {
+                "fail" => Err(TctiError::BadValue),
+                _ => Ok(Box::new(Increment(child))),
+            }
+        }
+
+        fn execute(tcti: &mut Box<dyn Tcti>, command: &[u8]) -> Vec<u8> {
+            tcti.transmit(command).unwrap();
+            tcti.receive().unwrap()
+        }
+
+        #[test]
+        fn test_open_stack() {
+            register_tcti::<Echo>("layer-echo");
+            register_layer("increment", increment);
+
+            let mut tcti =
+                open_stack("trace | metrics | increment|increment:x | layer-echo").unwrap();
+            let response = execute(&mut tcti, GET_RANDOM);
+            assert_eq!(response[..11], GET_RANDOM[..11]);
+            assert_eq!(response[11], 0x0a);
+
+            // registry::open() opens stacks, too
+            let mut tcti = open(r#"trace:name="x|y"|layer-echo:"a|b""#).unwrap();
+            assert_eq!(execute(&mut tcti, GET_RANDOM), GET_RANDOM);
+
+            let mut tcti = open_stack("layer-echo").unwrap();
+            assert_eq!(execute(&mut tcti, GET_RANDOM), GET_RANDOM);
+        }
+
+        #[test]
+        fn test_open_stack_errors() {
+            register_tcti::<Echo>("layer-echo");
+            register_layer("increment", increment);
+
+            assert!(matches!(
+                open_stack("unknown|layer-echo"),
+                Err(TctiError::NotSupported)
+            ));
+            assert!(matches!(
+                open_stack("increment:fail|layer-echo"),
+                Err(TctiError::BadValue)
+            ));
+            assert!(matches!(
+                open_stack("retry:max_attempts=0|layer-echo"),
+                Err(TctiError::BadValue)
+            ));
+            assert!(matches!(
+                open_stack(r#"trace:name="|layer-echo"#),
+                Err(TctiError::BadValue)
+            ));
+            assert!(matches!(
+                open_stack("trace|does-not-exist"),
+                Err(TctiError::NotSupported)
+            ));
+            assert!(matches!(
+                open_stack(r#"fault:"child|x",seed=1|layer-echo"#),
+                Err(TctiError::BadValue)
+            ));
+        }
+    }
+}
@@ -1,2 +1,21 @@
+// make `::tss2_tcti` paths generated by derive macros work within this crate
+extern crate self as tss2_tcti;
+
+pub mod config;
+pub mod conformance;
+pub mod execute;
+pub mod fault;
+pub mod filter;
+pub mod layer;
+pub mod metrics;
+pub mod record;
+pub mod registry;
+pub mod retry;
+pub mod shared;
 pub mod tcti;
 pub mod tctildr;
+pub mod telemetry;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+pub mod tpm;
+pub mod trace;
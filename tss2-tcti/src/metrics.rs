@@ -0,0 +1,439 @@
+/// Per-command statistics of the commands passing through a tcti.
+pub mod metrics {
+    use std::collections::BTreeMap;
+    use std::fmt::{self, Write};
+    use std::fs;
+    use std::time::{Duration, Instant};
+
+    #[cfg(feature = "metrics")]
+    use metrics::{counter, histogram};
+    use tracing::{info, warn};
+
+    use crate::config::config::TctiConfig;
+    use crate::layer::layer::{forward_to_child, impl_layer};
+    use crate::tcti::error::TctiError;
+    use crate::tcti::tcti::Tcti;
+    use crate::tpm::tpm::{command_code, command_name, response_code};
+
+    pub const METRIC_COMMANDS: &str = "tpm_commands_total";
+    pub const METRIC_RESPONSE_CODES: &str = "tpm_response_codes_total";
+    pub const METRIC_ERRORS: &str = "tpm_tcti_errors_total";
+    pub const METRIC_BYTES_OUT: &str = "tpm_command_bytes_total";
+    pub const METRIC_BYTES_IN: &str = "tpm_response_bytes_total";
+    pub const METRIC_LATENCY: &str = "tpm_command_duration_seconds";
+
+    /// Upper bounds of the latency histogram buckets. The last bucket holds
+    /// all larger latencies.
+    pub const LATENCY_BUCKETS: [Duration; 12] = [
+        Duration::from_micros(100),
+        Duration::from_micros(200),
+        Duration::from_micros(500),
+        Duration::from_millis(1),
+        Duration::from_millis(2),
+        Duration::from_millis(5),
+        Duration::from_millis(10),
+        Duration::from_millis(20),
+        Duration::from_millis(50),
+        Duration::from_millis(100),
+        Duration::from_millis(500),
+        Duration::from_secs(1),
+    ];
+
+    #[derive(TctiConfig, Debug, Clone, Default, PartialEq)]
+    pub struct MetricsConfig {
+        /// Conf string of the child tcti `T` which is measured, must be empty in a stack.
+        #[tcti_config(positional)]
+        pub child: String,
+        /// File the statistics are written to on finalization, instead of the log.
+        pub dump: Option<String>,
+    }
+
+    /// Statistics of a single command code.
+    #[derive(Debug, Clone, Default, PartialEq, Eq)]
+    pub struct CommandStats {
+        /// Number of responses received.
+        pub count: u64,
+        /// Number of errors of the child tcti, including responses too short for a response code.
+        pub errors: u64,
+        /// Number of responses by response code.
+        pub response_codes: BTreeMap<u32, u64>,
+        pub bytes_out: u64,
+        pub bytes_in: u64,
+        /// Number of responses by latency, see [LATENCY_BUCKETS].
+        pub latency_buckets: [u64; LATENCY_BUCKETS.len() + 1],
+        pub latency_sum: Duration,
+        pub latency_max: Duration,
+    }
+
+    impl CommandStats {
+        fn record_latency(&mut self, latency: Duration) {
+            let bucket = LATENCY_BUCKETS
+                .iter()
+                .position(|bound| latency <= *bound)
+                .unwrap_or(LATENCY_BUCKETS.len());
+            self.latency_buckets[bucket] += 1;
+            self.latency_sum += latency;
+            self.latency_max = self.latency_max.max(latency);
+        }
+
+        /// Mean latency of all responses.
+        pub fn latency_mean(&self) -> Duration {
+            match self.count {
+                0 => Duration::ZERO,
+                count => Duration::from_nanos((self.latency_sum.as_nanos() / count as u128) as u64),
+            }
+        }
+    }
+
+    /// Statistics of all commands by command code.
+    #[derive(Debug, Clone, Default, PartialEq, Eq)]
+    pub struct Stats(pub BTreeMap<u32, CommandStats>);
+
+    impl fmt::Display for Stats {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            for (cc, stats) in self.0.iter() {
+                writeln!(
+                    f,
+                    "{}: count={} errors={} bytes_out={} bytes_in={} latency_mean={:?} latency_max={:?}",
+                    command_name(*cc),
+                    stats.count,
+                    stats.errors,
+                    stats.bytes_out,
+                    stats.bytes_in,
+                    stats.latency_mean(),
+                    stats.latency_max,
+                )?;
+
+                let mut line = String::from("  response_codes:");
+                for (rc, count) in stats.response_codes.iter() {
+                    write!(line, " {rc:#x}={count}")?;
+                }
+                writeln!(f, "{line}")?;
+
+                let mut line = String::from("  latency:");
+                for (i, count) in stats.latency_buckets.iter().enumerate() {
+                    match LATENCY_BUCKETS.get(i) {
+                        Some(bound) => write!(line, " <={bound:?}={count}")?,
+                        None => write!(line, " >{:?}={count}", LATENCY_BUCKETS[i - 1])?,
+                    }
+                }
+                writeln!(f, "{line}")?;
+            }
+
+            Ok(())
+        }
+    }
+
+    /// Tcti which records statistics of the commands passing through to its
+    /// child: counts, response codes, bytes, errors of the child and
+    /// latencies, by command code.
+    ///
+    /// With the `metrics` feature (enabled by default), the statistics are
+    /// reported to the `metrics` recorder as they are recorded (see
+    /// `METRIC_*`, labeled by `command` and `rc`). They are dumped as text
+    /// when the tcti is dropped, unless no command was executed.
+    ///
+    /// # Examples
+    /// ```
+    /// use tss2_tcti::metrics::metrics::{MetricsConfig, MetricsTcti};
+    /// use tss2_tcti::tcti::tcti::Tcti;
+    /// use tpm2_tcti_sim::lib::TctiSim;
+    ///
+    /// let sim = <TctiSim as Tcti>::new("seed=0").unwrap();
+    /// let mut tcti = MetricsTcti::with_child(sim, MetricsConfig::default());
+    ///
+    /// tcti.transmit(b"\x80\x01\x00\x00\x00\x0c\x00\x00\x01\x44\x00\x00").unwrap();
+    /// tcti.receive().unwrap();
+    /// assert_eq!(tcti.stats().0[&0x144].count, 1);
+    /// assert!(tcti.stats().to_string().starts_with("Startup: count=1 errors=0"));
+    /// ```
+    pub struct MetricsTcti<T: Tcti> {
+        child: T,
+        config: MetricsConfig,
+        stats: Stats,
+        /// Command code and transmission time of the command in flight.
+        command: Option<(u32, Instant)>,
+    }
+
+    impl<T: Tcti> MetricsTcti<T> {
+        /// Wrap `child`. [MetricsConfig::child] is ignored.
+        pub fn with_child(child: T, config: MetricsConfig) -> Self {
+            Self {
+                child,
+                config,
+                stats: Stats::default(),
+                command: None,
+            }
+        }
+
+        pub fn stats(&self) -> &Stats {
+            &self.stats
+        }
+
+        fn record_error(&mut self, cc: u32, error: TctiError) -> TctiError {
+            // not receiving the response yet is part of the protocol
+            if error != TctiError::TryAgain {
+                self.command = None;
+                self.stats.0.entry(cc).or_default().errors += 1;
+                #[cfg(feature = "metrics")]
+                counter!(METRIC_ERRORS, "command" => command_name(cc)).increment(1);
+            }
+            error
+        }
+
+        fn record_response(&mut self, response: &[u8]) {
+            let (cc, transmitted) = match self.command.take() {
+                Some(command) => command,
+                None => return,
+            };
+            let latency = transmitted.elapsed();
+            // a response without response code is malformed, i.e. an error
+            let rc = response_code(response);
+            if rc.is_none() {
+                warn!("Response too short: {} bytes", response.len());
+            }
+
+            let stats = self.stats.0.entry(cc).or_default();
+            stats.count += 1;
+            match rc {
+                Some(rc) => *stats.response_codes.entry(rc).or_default() += 1,
+                None => stats.errors += 1,
+            }
+            stats.bytes_in += response.len() as u64;
+            stats.record_latency(latency);
+
+            #[cfg(feature = "metrics")]
+            {
+                let name = command_name(cc);
+                counter!(METRIC_COMMANDS, "command" => name.clone()).increment(1);
+                match rc {
+                    Some(rc) => {
+                        counter!(METRIC_RESPONSE_CODES, "command" => name.clone(), "rc" => format!("{rc:#x}"))
+                            .increment(1)
+                    }
+                    None => counter!(METRIC_ERRORS, "command" => name.clone()).increment(1),
+                }
+                counter!(METRIC_BYTES_IN, "command" => name.clone())
+                    .increment(response.len() as u64);
+                histogram!(METRIC_LATENCY, "command" => name).record(latency);
+            }
+        }
+
+        fn in_flight(&self) -> u32 {
+            self.command.map(|(cc, _)| cc).unwrap_or(0)
+        }
+    }
+
+    impl<T: Tcti> Drop for MetricsTcti<T> {
+        fn drop(&mut self) {
+            if self.stats.0.is_empty() {
+                return;
+            }
+
+            let dump = self.stats.to_string();
+            match &self.config.dump {
+                Some(path) => {
+                    if let Err(error) = fs::write(path, dump) {
+                        warn!("Could not write metrics to {path}: {error}");
+                    }
+                }
+                None => info!("Tcti metrics:\n{dump}"),
+            }
+        }
+    }
+
+    impl_layer!(MetricsTcti, MetricsConfig, [config, command]);
+
+    impl<T: Tcti> Tcti for MetricsTcti<T> {
+        forward_to_child!(
+            new(MetricsConfig::from_conf),
+            cancel,
+            get_poll_handles,
+            set_locality,
+            make_sticky
+        );
+
+        fn transmit(&mut self, command: &[u8]) -> Result<(), TctiError> {
+            let cc = command_code(command).unwrap_or(0);
+
+            if let Err(error) = self.child.transmit(command) {
+                return Err(self.record_error(cc, error));
+            }
+
+            let stats = self.stats.0.entry(cc).or_default();
+            stats.bytes_out += command.len() as u64;
+            #[cfg(feature = "metrics")]
+            counter!(METRIC_BYTES_OUT, "command" => command_name(cc))
+                .increment(command.len() as u64);
+            self.command = Some((cc, Instant::now()));
+            Ok(())
+        }
+
+        fn receive_with_timeout(&mut self, timeout: Duration) -> Result<Vec<u8>, TctiError> {
+            match self.child.receive_with_timeout(timeout) {
+                Ok(response) => {
+                    self.record_response(&response);
+                    Ok(response)
+                }
+                Err(error) => Err(self.record_error(self.in_flight(), error)),
+            }
+        }
+
+        fn response_size(&mut self, timeout: Duration) -> Result<usize, TctiError> {
+            self.child
+                .response_size(timeout)
+                .map_err(|error| self.record_error(self.in_flight(), error))
+        }
+
+        fn receive_into(
+            &mut self,
+            response: &mut [u8],
+            timeout: Duration,
+        ) -> Result<usize, TctiError> {
+            match self.child.receive_into(response, timeout) {
+                Ok(size) => {
+                    self.record_response(&response[..size]);
+                    Ok(size)
+                }
+                // the response can be received into a larger buffer
+                Err(TctiError::InsufficientBuffer) => Err(TctiError::InsufficientBuffer),
+                Err(error) => Err(self.record_error(self.in_flight(), error)),
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::testing::testing::Scripted;
+        use crate::tpm::tpm::{response, TPM_RC_RETRY, TPM_RC_SUCCESS};
+        #[cfg(feature = "metrics")]
+        use metrics_util::debugging::{DebugValue, DebuggingRecorder};
+
+        const GET_RANDOM: &[u8] = b"\x80\x01\x00\x00\x00\x0c\x00\x00\x01\x7b\x00\x08";
+
+        #[test]
+        fn test_stats() {
+            let child = Scripted::with_responses([
+                Ok(response(TPM_RC_SUCCESS, &[])),
+                Err(TctiError::TryAgain),
+                Err(TctiError::IoError),
+                Ok(response(TPM_RC_RETRY, &[])),
+                Ok(response(TPM_RC_RETRY, &[])),
+            ]);
+            let mut tcti = MetricsTcti::with_child(child, MetricsConfig::default());
+
+            for _ in 0..4 {
+                tcti.transmit(GET_RANDOM).unwrap();
+                let _ = tcti.receive_with_timeout(Duration::ZERO);
+            }
+            tcti.transmit(&[0x80]).unwrap();
+            tcti.receive().unwrap();
+
+            let stats = &tcti.stats().0[&0x17b];
+            assert_eq!(stats.count, 2);
+            assert_eq!(stats.errors, 1);
+            assert_eq!(stats.response_codes, BTreeMap::from([(0, 1), (0x922, 1)]));
+            assert_eq!(stats.bytes_out, 4 * GET_RANDOM.len() as u64);
+            assert_eq!(stats.bytes_in, 20);
+            assert_eq!(stats.latency_buckets.iter().sum::<u64>(), 2);
+            assert_eq!(tcti.stats().0[&0].count, 1);
+
+            let dump = tcti.stats().to_string();
+            assert!(dump.starts_with("0x00000000: count=1 errors=0 bytes_out=1 bytes_in=10"));
+            assert!(dump.contains("GetRandom: count=2 errors=1 bytes_out=48 bytes_in=20"));
+            assert!(dump.contains("  response_codes: 0x0=1 0x922=1\n"));
+            assert!(dump.contains(" >1s=0\n"));
+        }
+
+        #[test]
+        fn test_short_response() {
+            let child = Scripted::with_responses([Ok(vec![0x80, 0x01])]);
+            let mut tcti = MetricsTcti::with_child(child, MetricsConfig::default());
+            tcti.transmit(GET_RANDOM).unwrap();
+            tcti.receive().unwrap();
+
+            let stats = &tcti.stats().0[&0x17b];
+            assert_eq!(stats.count, 1);
+            assert_eq!(stats.errors, 1);
+            assert!(stats.response_codes.is_empty());
+            assert_eq!(stats.bytes_in, 2);
+        }
+
+        #[cfg(feature = "metrics")]
+        #[test]
+        fn test_recorder() {
+            let recorder = DebuggingRecorder::new();
+            let snapshotter = recorder.snapshotter();
+
+            metrics::with_local_recorder(&recorder, || {
+                let child = Scripted::with_responses([
+                    Ok(response(TPM_RC_SUCCESS, &[])),
+                    Ok(response(TPM_RC_RETRY, &[])),
+                ]);
+                let mut tcti = MetricsTcti::with_child(child, MetricsConfig::default());
+                for _ in 0..2 {
+                    tcti.transmit(GET_RANDOM).unwrap();
+                    tcti.receive().unwrap();
+                }
+            });
+
+            let metrics: Vec<_> = snapshotter
+                .snapshot()
+                .into_vec()
+                .into_iter()
+                .map(|(key, _, _, value)| {
+                    let key = key.key();
+                    let labels: Vec<_> = key
+                        .labels()
+                        .map(|label| format!("{}={}", label.key(), label.value()))
+                        .collect();
+                    (key.name().to_string(), labels.join(","), value)
+                })
+                .collect();
+
+            let find = |name: &str, labels: &str| {
+                metrics
+                    .iter()
+                    .find(|metric| metric.0 == name && metric.1 == labels)
+                    .map(|metric| &metric.2)
+            };
+            assert_eq!(
+                find(METRIC_COMMANDS, "command=GetRandom"),
+                Some(&DebugValue::Counter(2))
+            );
+            assert_eq!(
+                find(METRIC_RESPONSE_CODES, "command=GetRandom,rc=0x922"),
+                Some(&DebugValue::Counter(1))
+            );
+            assert_eq!(
+                find(METRIC_BYTES_OUT, "command=GetRandom"),
+                Some(&DebugValue::Counter(24))
+            );
+            assert!(matches!(
+                find(METRIC_LATENCY, "command=GetRandom"),
+                Some(DebugValue::Histogram(latencies)) if latencies.len() == 2
+            ));
+        }
+
+        #[test]
+        fn test_dump() {
+            let path = std::env::temp_dir().join(format!("tcti-metrics-{}", std::process::id()));
+            let config = MetricsConfig::from_conf(&format!("dump={}", path.display())).unwrap();
+            let child = Scripted::with_responses([Ok(response(TPM_RC_SUCCESS, &[]))]);
+            let mut tcti = MetricsTcti::with_child(child, config);
+            tcti.transmit(GET_RANDOM).unwrap();
+            tcti.receive().unwrap();
+            drop(tcti);
+
+            let dump = fs::read_to_string(&path).unwrap();
+            fs::remove_file(&path).unwrap();
+            assert!(dump.starts_with("GetRandom: count=1 errors=0"));
+
+            // nothing is dumped without commands
+            let config = MetricsConfig::from_conf(&format!("dump={}", path.display())).unwrap();
+            drop(MetricsTcti::with_child(Scripted::default(), config));
+            assert!(!path.exists());
+        }
+    }
+}
@@ -0,0 +1,161 @@
+/// Recording of the commands and responses passing through a tcti.
+pub mod record {
+    use std::fmt::Write as _;
+    use std::fs::{File, OpenOptions};
+    use std::io::Write;
+    use std::time::Duration;
+
+    use tracing::warn;
+
+    use crate::config::config::{ConfigError, TctiConfig};
+    use crate::layer::layer::{forward_to_child, impl_layer};
+    use crate::tcti::error::TctiError;
+    use crate::tcti::tcti::Tcti;
+
+    #[derive(TctiConfig, Debug, Clone, Default, PartialEq)]
+    pub struct RecordConfig {
+        /// Conf string of the child tcti `T` whose commands are recorded, must be empty in a stack.
+        #[tcti_config(positional)]
+        pub child: String,
+        /// File the commands and responses are appended to.
+        pub file: String,
+    }
+
+    impl RecordConfig {
+        /// Parse the conf string, checking that a file is given.
+        pub fn parse(conf: &str) -> Result<Self, ConfigError> {
+            let config = Self::from_conf(conf)?;
+            if config.file.is_empty() {
+                return Err(ConfigError::BadValue {
+                    key: "file".to_string(),
+                    value: config.file,
+                });
+            }
+
+            Ok(config)
+        }
+    }
+
+    /// Tcti which appends the commands and responses passing through to its
+    /// child to [RecordConfig::file]. Every command and response is a line
+    /// of hex digits, prefixed by `> ` or `< `, respectively.
+    ///
+    /// The file is opened when the first command is recorded. Errors
+    /// writing the file are logged, but do not fail the command.
+    pub struct RecordTcti<T: Tcti> {
+        child: T,
+        config: RecordConfig,
+        file: Option<File>,
+    }
+
+    impl<T: Tcti> RecordTcti<T> {
+        /// Wrap `child`. [RecordConfig::child] is ignored.
+        pub fn with_child(child: T, config: RecordConfig) -> Self {
+            Self {
+                child,
+                config,
+                file: None,
+            }
+        }
+
+        fn record(&mut self, prefix: &str, bytes: &[u8]) {
+            let mut line = String::with_capacity(prefix.len() + 2 * bytes.len() + 1);
+            line.push_str(prefix);
+            for byte in bytes {
+                let _ = write!(line, "{byte:02x}");
+            }
+            line.push('\n');
+
+            let path = &self.config.file;
+            if self.file.is_none() {
+                match OpenOptions::new().create(true).append(true).open(path) {
+                    Ok(file) => self.file = Some(file),
+                    Err(error) => {
+                        warn!("Could not open {path}: {error}");
+                        return;
+                    }
+                }
+            }
+            if let Err(error) = self.file.as_mut().unwrap().write_all(line.as_bytes()) {
+                warn!("Could not write to {path}: {error}");
+            }
+        }
+    }
+
+    impl_layer!(RecordTcti, RecordConfig, [config]);
+
+    impl<T: Tcti> Tcti for RecordTcti<T> {
+        forward_to_child!(
+            new(RecordConfig::parse),
+            response_size,
+            cancel,
+            get_poll_handles,
+            set_locality,
+            make_sticky
+        );
+
+        fn transmit(&mut self, command: &[u8]) -> Result<(), TctiError> {
+            self.child.transmit(command)?;
+            self.record("> ", command);
+            Ok(())
+        }
+
+        fn receive_with_timeout(&mut self, timeout: Duration) -> Result<Vec<u8>, TctiError> {
+            let response = self.child.receive_with_timeout(timeout)?;
+            self.record("< ", &response);
+            Ok(response)
+        }
+
+        fn receive_into(
+            &mut self,
+            response: &mut [u8],
+            timeout: Duration,
+        ) -> Result<usize, TctiError> {
+            let size = self.child.receive_into(response, timeout)?;
+            self.record("< ", &response[..size]);
+            Ok(size)
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::testing::testing::Echo;
+        use std::fs;
+
+        const GET_RANDOM: &[u8] = b"\x80\x01\x00\x00\x00\x0c\x00\x00\x01\x7b\x00\x08";
+
+        #[test]
+        fn test_record() {
+            let path = std::env::temp_dir().join(format!("tcti-record-{}", std::process::id()));
+            let config = RecordConfig::parse(&format!("file={}", path.display())).unwrap();
+            let mut tcti = RecordTcti::with_child(Echo::default(), config);
+
+            tcti.transmit(GET_RANDOM).unwrap();
+            tcti.receive().unwrap();
+            tcti.transmit(&GET_RANDOM[..2]).unwrap();
+            let mut response = [0; 2];
+            tcti.receive_into(&mut response, Duration::ZERO).unwrap();
+            assert_eq!(tcti.receive(), Err(TctiError::BadSequence));
+            drop(tcti);
+
+            let recorded = fs::read_to_string(&path).unwrap();
+            fs::remove_file(&path).unwrap();
+            assert_eq!(
+                recorded,
+                "> 80010000000c0000017b0008\n< 80010000000c0000017b0008\n> 8001\n< 8001\n"
+            );
+        }
+
+        #[test]
+        fn test_config() {
+            assert_eq!(
+                RecordConfig::parse("mssim").unwrap_err(),
+                ConfigError::BadValue {
+                    key: "file".to_string(),
+                    value: String::new()
+                }
+            );
+        }
+    }
+}
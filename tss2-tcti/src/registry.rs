@@ -0,0 +1,159 @@
+pub mod registry {
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    use crate::config::config::split_unquoted;
+    use crate::layer::layer::open_stack;
+    use crate::tcti::{error::TctiError, tcti::Tcti};
+    use crate::tctildr::tcti_loader::{parse_name_conf, TctiLoader};
+    use tracing::warn;
+    use tss2_tcti_sys::tpm2_tss;
+
+    /// Creates and initializes a tcti from its conf string.
+    pub type Constructor = fn(&str) -> Result<Box<dyn Tcti>, TctiError>;
+
+    /// Rust-native tctis, see [register()].
+    static REGISTRY: Mutex<Vec<(String, Constructor)>> = Mutex::new(Vec::new());
+
+    /// Makes a Rust-native tcti available to [open()] under `name`. Later
+    /// registrations take precedence over earlier ones.
+    pub fn register(name: &str, constructor: Constructor) {
+        REGISTRY
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .push((name.to_string(), constructor));
+    }
+
+    /// Registers `T` under `name`, see [register()].
+    pub fn register_tcti<T: Tcti + 'static>(name: &str) {
+        register(name, construct::<T>);
+    }
+
+    fn construct<T: Tcti + 'static>(conf: &str) -> Result<Box<dyn Tcti>, TctiError> {
+        Ok(Box::new(T::new(conf)?))
+    }
+
+    fn find(name: &str) -> Option<Constructor> {
+        REGISTRY
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .iter()
+            .rev()
+            .find(|(registered_name, _)| registered_name == name)
+            .map(|(_, constructor)| *constructor)
+    }
+
+    /// Open a tcti from a tctildr config string `<name>:<conf>`, or a stack
+    /// of layers and a tcti `<layer>|...|<name>:<conf>` (see [open_stack()]).
+    ///
+    /// Tctis registered via [register()] are looked up first. Otherwise, the
+    /// tcti is loaded via [TctiLoader].
+    pub fn open(name_conf: &str) -> Result<Box<dyn Tcti>, TctiError> {
+        if split_unquoted(name_conf, '|')?.len() > 1 {
+            return open_stack(name_conf);
+        }
+
+        open_tcti(name_conf)
+    }
+
+    /// Same as [open()], but without layers.
+    pub(crate) fn open_tcti(name_conf: &str) -> Result<Box<dyn Tcti>, TctiError> {
+        let (name, conf) = parse_name_conf(name_conf);
+
+        if let Some(constructor) = find(name) {
+            return constructor(conf)
+                .inspect_err(|error| warn!("Could not initialize tcti {name:?}: {error:?}"));
+        }
+
+        Ok(Box::new(<TctiLoader as Tcti>::new(name_conf)?))
+    }
+
+    impl Tcti for Box<dyn Tcti> {
+        /// Same as [open()].
+        fn new(name_conf: &str) -> Result<Self, TctiError> {
+            open(name_conf)
+        }
+
+        fn transmit(&mut self, command: &[u8]) -> Result<(), TctiError> {
+            (**self).transmit(command)
+        }
+
+        fn receive(&mut self) -> Result<Vec<u8>, TctiError> {
+            (**self).receive()
+        }
+
+        fn receive_with_timeout(&mut self, timeout: Duration) -> Result<Vec<u8>, TctiError> {
+            (**self).receive_with_timeout(timeout)
+        }
+
+        fn response_size(&mut self, timeout: Duration) -> Result<usize, TctiError> {
+            (**self).response_size(timeout)
+        }
+
+        fn receive_into(
+            &mut self,
+            response: &mut [u8],
+            timeout: Duration,
+        ) -> Result<usize, TctiError> {
+            (**self).receive_into(response, timeout)
+        }
+
+        fn finalize(&mut self) {
+            (**self).finalize()
+        }
+
+        fn cancel(&mut self) -> Result<(), TctiError> {
+            (**self).cancel()
+        }
+
+        fn get_poll_handles(&mut self) -> Result<Vec<tpm2_tss::TSS2_TCTI_POLL_HANDLE>, TctiError> {
+            (**self).get_poll_handles()
+        }
+
+        fn set_locality(&mut self, locality: u8) -> Result<(), TctiError> {
+            (**self).set_locality(locality)
+        }
+
+        fn make_sticky(&mut self, handle: &mut u32, sticky: bool) -> Result<(), TctiError> {
+            (**self).make_sticky(handle, sticky)
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::testing::testing::Echo;
+
+        #[test]
+        fn test_open_registered() {
+            register_tcti::<Echo>("rust-echo");
+
+            let mut tcti = open("rust-echo:conf").unwrap();
+            tcti.transmit(b"\x80\x01\x00\x00\x00\x0c\x00\x00\x01\x44\x00\x00")
+                .unwrap();
+            assert_eq!(
+                tcti.receive().unwrap(),
+                b"\x80\x01\x00\x00\x00\x0c\x00\x00\x01\x44\x00\x00"
+            );
+            assert_eq!(tcti.receive().unwrap_err(), TctiError::BadSequence);
+        }
+
+        #[test]
+        fn test_open_registered_init_error() {
+            register_tcti::<Echo>("rust-echo");
+
+            assert!(matches!(
+                <Box<dyn Tcti> as Tcti>::new("rust-echo:fail"),
+                Err(TctiError::BadValue)
+            ));
+        }
+
+        #[test]
+        fn test_open_unknown() {
+            assert!(matches!(
+                open("does-not-exist"),
+                Err(TctiError::NotSupported)
+            ));
+        }
+    }
+}
@@ -0,0 +1,429 @@
+/// Transparent resending of commands which the TPM asks to retry.
+pub mod retry {
+    use std::collections::BTreeMap;
+    use std::thread;
+    use std::time::Duration;
+
+    use tpm2_types::constants::{CommandCode, ReturnCode, StartupType};
+    use tracing::{info, warn};
+
+    use crate::config::config::{ConfigError, TctiConfig};
+    use crate::layer::layer::{forward_to_child, impl_layer};
+    use crate::tcti::error::TctiError;
+    use crate::tcti::tcti::{PendingResponse, Tcti};
+    use crate::tpm::tpm::{
+        self, command_code, response_code, u32_at, TPM_HEADER_SIZE, TPM_RC_CONTEXT_GAP,
+        TPM_RC_INITIALIZE, TPM_RC_SUCCESS, TPM_WARNINGS,
+    };
+
+    #[derive(TctiConfig, Debug, Clone, PartialEq)]
+    pub struct RetryConfig {
+        /// Conf string of the child tcti `T` whose commands are retried, must be empty in a stack.
+        #[tcti_config(positional)]
+        pub child: String,
+        /// Maximum number of times a command is sent, including the first time.
+        pub max_attempts: u32,
+        /// Delay before resending after a TPM warning in milliseconds, doubled for every further attempt.
+        pub backoff_ms: u64,
+        /// Upper bound of the delay in milliseconds.
+        pub max_backoff_ms: u64,
+        /// Issue TPM2_Startup(TPM_SU_CLEAR) and resend after TPM_RC_INITIALIZE.
+        pub startup: bool,
+    }
+
+    impl Default for RetryConfig {
+        fn default() -> Self {
+            Self {
+                child: String::new(),
+                max_attempts: 5,
+                backoff_ms: 10,
+                max_backoff_ms: 1000,
+                startup: false,
+            }
+        }
+    }
+
+    impl RetryConfig {
+        /// Parse the conf string, checking that commands are sent at least
+        /// once.
+        pub fn parse(conf: &str) -> Result<Self, ConfigError> {
+            let config = Self::from_conf(conf)?;
+            if config.max_attempts == 0 {
+                return Err(ConfigError::BadValue {
+                    key: "max_attempts".to_string(),
+                    value: config.max_attempts.to_string(),
+                });
+            }
+
+            Ok(config)
+        }
+
+        /// Delay before the `attempt`th resend (counting from 0).
+        pub fn backoff(&self, attempt: u32) -> Duration {
+            let backoff = self
+                .backoff_ms
+                .saturating_mul(1u64.checked_shl(attempt).unwrap_or(u64::MAX));
+            Duration::from_millis(backoff.min(self.max_backoff_ms))
+        }
+    }
+
+    fn is_session(handle: u32) -> bool {
+        matches!(handle >> 24, 0x02 | 0x03)
+    }
+
+    /// Sequence of a TPMS_CONTEXT.
+    fn context_sequence(context: &[u8]) -> u64 {
+        match context.get(0..8) {
+            Some(sequence) => u64::from_be_bytes(sequence.try_into().unwrap()),
+            None => 0,
+        }
+    }
+
+    fn startup() -> Vec<u8> {
+        tpm::command(
+            CommandCode::Startup,
+            &(StartupType::Clear as u16).to_be_bytes(),
+        )
+    }
+
+    /// Tcti which resends commands on `TPM_RC_RETRY`, `TPM_RC_YIELDED` and
+    /// `TPM_RC_TESTING` (with exponential backoff), on `TPM_RC_CONTEXT_GAP`
+    /// after re-saving the oldest saved session, and, if configured, on
+    /// `TPM_RC_INITIALIZE` after starting the TPM. The last response is
+    /// returned if [RetryConfig::max_attempts] is reached.
+    ///
+    /// Session contexts which pass through are tracked to close context
+    /// gaps. The caller may load the context it received, it is replaced by
+    /// the current one.
+    ///
+    /// Backoff delays and recovery commands block, regardless of the
+    /// timeout of the receive call.
+    ///
+    /// # Examples
+    /// ```
+    /// use tss2_tcti::retry::retry::{RetryConfig, RetryTcti};
+    /// use tss2_tcti::tcti::tcti::Tcti;
+    /// use tpm2_tcti_sim::lib::TctiSim;
+    ///
+    /// let config = RetryConfig::parse("startup=true").unwrap();
+    /// let mut tcti = RetryTcti::with_child(<TctiSim as Tcti>::new("seed=0").unwrap(), config);
+    ///
+    /// // TPM2_GetRandom on a TPM which has not been started yet
+    /// tcti.transmit(b"\x80\x01\x00\x00\x00\x0c\x00\x00\x01\x7b\x00\x08").unwrap();
+    /// let response = tcti.receive().unwrap();
+    /// assert_eq!(response[6..10], [0, 0, 0, 0]);
+    /// ```
+    pub struct RetryTcti<T: Tcti> {
+        child: T,
+        config: RetryConfig,
+        /// Command in flight and the number of times it was resent.
+        command: Option<(Vec<u8>, u32)>,
+        /// Most recent contexts of saved sessions by handle.
+        sessions: BTreeMap<u32, Vec<u8>>,
+        /// Response which was not handed to the caller yet, see
+        /// [response_size()](Tcti::response_size).
+        ready: PendingResponse,
+    }
+
+    impl<T: Tcti> RetryTcti<T> {
+        /// Wrap `child`. [RetryConfig::child] is ignored.
+        pub fn with_child(child: T, config: RetryConfig) -> Self {
+            Self {
+                child,
+                config,
+                command: None,
+                sessions: BTreeMap::new(),
+                ready: None,
+            }
+        }
+
+        /// Execute a command of the middleware itself.
+        fn execute_child(&mut self, command: &[u8]) -> Result<Vec<u8>, TctiError> {
+            self.child.transmit(command)?;
+            self.child.receive()
+        }
+
+        /// Load and save the oldest saved session, which makes its context
+        /// the most recent one.
+        fn regap(&mut self) -> Result<bool, TctiError> {
+            let oldest = self
+                .sessions
+                .iter()
+                .min_by_key(|(_, context)| context_sequence(context))
+                .map(|(handle, _)| *handle);
+            let handle = match oldest {
+                Some(handle) => handle,
+                None => return Ok(false),
+            };
+
+            let context = self.sessions.remove(&handle).unwrap();
+            let response = self.execute_child(&tpm::command(CommandCode::ContextLoad, &context))?;
+            if response_code(&response) != Some(TPM_RC_SUCCESS) {
+                warn!("Could not load session {handle:08x} to close the context gap");
+                return Ok(false);
+            }
+            let response = self.execute_child(&tpm::command(
+                CommandCode::ContextSave,
+                &handle.to_be_bytes(),
+            ))?;
+            if response_code(&response) != Some(TPM_RC_SUCCESS) {
+                warn!("Could not save session {handle:08x} to close the context gap");
+                return Ok(false);
+            }
+
+            self.sessions
+                .insert(handle, response[TPM_HEADER_SIZE..].to_vec());
+            Ok(true)
+        }
+
+        /// Prepare resending the command in flight after `rc`. Returns
+        /// whether the command is to be resent.
+        fn recover(&mut self, rc: ReturnCode, attempt: u32) -> Result<bool, TctiError> {
+            if attempt + 1 >= self.config.max_attempts {
+                warn!("Giving up after {} attempts: {rc:#x}", attempt + 1);
+                return Ok(false);
+            }
+
+            match rc {
+                rc if TPM_WARNINGS.contains(&rc) => {
+                    thread::sleep(self.config.backoff(attempt));
+                    Ok(true)
+                }
+                TPM_RC_INITIALIZE if self.config.startup => {
+                    let response = self.execute_child(&startup())?;
+                    Ok(response_code(&response) == Some(TPM_RC_SUCCESS))
+                }
+                TPM_RC_CONTEXT_GAP => self.regap(),
+                _ => Ok(false),
+            }
+        }
+
+        /// Track session contexts of a successful command.
+        fn track(&mut self, command: &[u8], response: &[u8]) {
+            if response_code(response) != Some(TPM_RC_SUCCESS) {
+                return;
+            }
+
+            let handle = u32_at(command, TPM_HEADER_SIZE).unwrap_or(0);
+            match command_code(command).and_then(|cc| CommandCode::try_from(cc).ok()) {
+                Some(CommandCode::ContextSave) if is_session(handle) => {
+                    self.sessions
+                        .insert(handle, response[TPM_HEADER_SIZE..].to_vec());
+                }
+                Some(CommandCode::ContextLoad) => {
+                    // TPMS_CONTEXT: sequence, savedHandle, ...
+                    if let Some(handle) = u32_at(command, TPM_HEADER_SIZE + 8) {
+                        self.sessions.remove(&handle);
+                    }
+                }
+                Some(CommandCode::FlushContext) => {
+                    self.sessions.remove(&handle);
+                }
+                _ => (),
+            }
+        }
+
+        /// Replace the context of a session in `TPM2_ContextLoad` by the
+        /// most recent one.
+        fn update_context(&self, command: &[u8]) -> Option<Vec<u8>> {
+            if command_code(command) != Some(u32::from(CommandCode::ContextLoad)) {
+                return None;
+            }
+            let handle = u32_at(command, TPM_HEADER_SIZE + 8)?;
+            let context = self.sessions.get(&handle)?;
+            if context[..] == command[TPM_HEADER_SIZE..] {
+                return None;
+            }
+
+            info!("Replacing context of session {handle:08x}");
+            Some(tpm::command(CommandCode::ContextLoad, context))
+        }
+    }
+
+    impl_layer!(RetryTcti, RetryConfig, [config, command, sessions]);
+
+    impl<T: Tcti> Tcti for RetryTcti<T> {
+        forward_to_child!(
+            new(RetryConfig::parse),
+            keep_response(ready),
+            cancel,
+            get_poll_handles,
+            set_locality,
+            make_sticky
+        );
+
+        fn transmit(&mut self, command: &[u8]) -> Result<(), TctiError> {
+            if self.command.is_some() || self.ready.is_some() {
+                return Err(TctiError::BadSequence);
+            }
+
+            let command = self
+                .update_context(command)
+                .unwrap_or_else(|| command.to_vec());
+            self.child.transmit(&command)?;
+            self.command = Some((command, 0));
+            Ok(())
+        }
+
+        fn receive_with_timeout(&mut self, timeout: Duration) -> Result<Vec<u8>, TctiError> {
+            if let Some(response) = self.ready.take() {
+                return Ok(response);
+            }
+            let (command, mut attempt) = self.command.take().ok_or(TctiError::BadSequence)?;
+
+            loop {
+                let response = match self.child.receive_with_timeout(timeout) {
+                    Ok(response) => response,
+                    Err(TctiError::TryAgain) => {
+                        self.command = Some((command, attempt));
+                        return Err(TctiError::TryAgain);
+                    }
+                    Err(error) => return Err(error),
+                };
+
+                let rc = match response_code(&response) {
+                    Some(rc) => rc,
+                    _ => return Ok(response),
+                };
+                if rc == TPM_RC_SUCCESS || !self.recover(rc, attempt)? {
+                    self.track(&command, &response);
+                    return Ok(response);
+                }
+
+                attempt += 1;
+                info!("Resending command after {rc:#x}, attempt {}", attempt + 1);
+                self.child.transmit(&command)?;
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::fault::fault::{FaultConfig, FaultTcti};
+        use crate::testing::testing::Scripted;
+        use crate::tpm::tpm::{response, TPM_RC_RETRY, TPM_RC_YIELDED};
+
+        const GET_RANDOM: &[u8] = b"\x80\x01\x00\x00\x00\x0c\x00\x00\x01\x7b\x00\x08";
+
+        fn rc_response(rc: ReturnCode) -> Vec<u8> {
+            response(rc, &[])
+        }
+
+        fn scripted(conf: &str, responses: &[Vec<u8>]) -> RetryTcti<Scripted> {
+            let child = Scripted::with_responses(responses.iter().cloned().map(Ok));
+            RetryTcti::with_child(child, RetryConfig::parse(conf).unwrap())
+        }
+
+        fn execute<T: Tcti>(tcti: &mut RetryTcti<T>, command: &[u8]) -> Vec<u8> {
+            tcti.transmit(command).unwrap();
+            tcti.receive().unwrap()
+        }
+
+        #[test]
+        fn test_backoff() {
+            let config = RetryConfig::parse("backoff_ms=10,max_backoff_ms=50").unwrap();
+            let backoff: Vec<u64> = (0..5)
+                .map(|attempt| config.backoff(attempt).as_millis() as u64)
+                .collect();
+            assert_eq!(backoff, [10, 20, 40, 50, 50]);
+            assert_eq!(config.backoff(100), Duration::from_millis(50));
+            assert!(RetryConfig::parse("max_attempts=0").is_err());
+        }
+
+        #[test]
+        fn test_warnings() {
+            let responses = [
+                rc_response(TPM_RC_RETRY),
+                rc_response(TPM_RC_YIELDED),
+                rc_response(TPM_RC_SUCCESS),
+            ];
+            let mut tcti = scripted("backoff_ms=0", &responses);
+            assert_eq!(execute(&mut tcti, GET_RANDOM), responses[2]);
+            assert_eq!(tcti.child().commands, vec![GET_RANDOM.to_vec(); 3]);
+
+            // the last response is returned
+            let mut tcti = scripted("backoff_ms=0,max_attempts=2", &responses);
+            assert_eq!(execute(&mut tcti, GET_RANDOM), responses[1]);
+            assert_eq!(tcti.child().commands.len(), 2);
+        }
+
+        #[test]
+        fn test_startup() {
+            let responses = [rc_response(TPM_RC_INITIALIZE), rc_response(TPM_RC_SUCCESS)];
+            let mut tcti = scripted("", &responses);
+            assert_eq!(execute(&mut tcti, GET_RANDOM), responses[0]);
+            assert_eq!(tcti.child().commands.len(), 1);
+
+            let mut tcti = scripted("startup=true", &responses);
+            assert_eq!(execute(&mut tcti, GET_RANDOM), rc_response(TPM_RC_SUCCESS));
+            assert_eq!(
+                tcti.child().commands,
+                [GET_RANDOM.to_vec(), startup(), GET_RANDOM.to_vec()]
+            );
+
+            // the TPM is not started, the response is returned
+            let responses = [rc_response(TPM_RC_INITIALIZE), rc_response(0x0101)];
+            let mut tcti = scripted("startup=true", &responses);
+            assert_eq!(execute(&mut tcti, GET_RANDOM), responses[0]);
+        }
+
+        #[test]
+        fn test_regap() {
+            let session = 0x02000000u32;
+            let saved = |sequence: u64| {
+                let mut context = sequence.to_be_bytes().to_vec();
+                context.extend_from_slice(&session.to_be_bytes());
+                context.extend_from_slice(&[0x40, 0, 0, 7, 0, 0]);
+                context
+            };
+            let success = |parameters: &[u8]| response(TPM_RC_SUCCESS, parameters);
+
+            let responses = [
+                success(&saved(1)),
+                rc_response(TPM_RC_CONTEXT_GAP),
+                success(&session.to_be_bytes()),
+                success(&saved(300)),
+                success(&[]),
+                success(&session.to_be_bytes()),
+            ];
+            let mut tcti = scripted("", &responses);
+            let context_save = tpm::command(CommandCode::ContextSave, &session.to_be_bytes());
+            assert_eq!(execute(&mut tcti, &context_save), responses[0]);
+            let start_auth_session = tpm::command(CommandCode::StartAuthSession, &[0; 8]);
+            assert_eq!(execute(&mut tcti, &start_auth_session), responses[4]);
+
+            // the stale context is replaced
+            let response = execute(
+                &mut tcti,
+                &tpm::command(CommandCode::ContextLoad, &saved(1)),
+            );
+            assert_eq!(response, responses[5]);
+            assert_eq!(
+                tcti.child().commands[1..],
+                [
+                    start_auth_session.clone(),
+                    tpm::command(CommandCode::ContextLoad, &saved(1)),
+                    context_save,
+                    start_auth_session,
+                    tpm::command(CommandCode::ContextLoad, &saved(300)),
+                ]
+            );
+            assert!(tcti.sessions.is_empty());
+        }
+
+        #[test]
+        fn test_with_faults() {
+            let config = FaultConfig::parse("seed=3,tpm_warning=0.5").unwrap();
+            let child = FaultTcti::with_child(Scripted::default(), config);
+            let config = RetryConfig::parse("max_attempts=30,backoff_ms=0").unwrap();
+            let mut tcti = RetryTcti::with_child(child, config);
+
+            for _ in 0..20 {
+                assert_eq!(execute(&mut tcti, GET_RANDOM), rc_response(TPM_RC_SUCCESS));
+            }
+            assert!(!tcti.child().injected().is_empty());
+            assert_eq!(tcti.child().child().commands.len(), 20);
+        }
+    }
+}
@@ -0,0 +1,170 @@
+/// Sharing a tcti between threads.
+pub mod shared {
+    use std::fmt;
+    use std::sync::{Arc, Mutex};
+
+    use tracing::warn;
+
+    use crate::tcti::error::TctiError;
+    use crate::tcti::tcti::Tcti;
+
+    /// Handle to a tcti which can be cloned and sent to other threads.
+    ///
+    /// Commands are executed as a whole, i.e. the tcti is locked from
+    /// transmitting the command until the response is received. Concurrent
+    /// callers wait for each other.
+    ///
+    /// If a thread panics while using the tcti, e.g. after transmitting a
+    /// command without receiving its response, the state of the tcti is
+    /// unknown and all further calls fail with [TctiError::GeneralFailure].
+    ///
+    /// # Examples
+    /// ```
+    /// use std::thread;
+    /// use tss2_tcti::shared::shared::SharedTcti;
+    /// use tss2_tcti::tcti::tcti::Tcti;
+    /// use tpm2_tcti_sim::lib::TctiSim;
+    ///
+    /// let tcti = SharedTcti::new(<TctiSim as Tcti>::new("seed=0").unwrap());
+    /// tcti.execute(b"\x80\x01\x00\x00\x00\x0c\x00\x00\x01\x44\x00\x00").unwrap();
+    ///
+    /// let threads: Vec<_> = (0..4)
+    ///     .map(|_| {
+    ///         let tcti = tcti.clone();
+    ///         thread::spawn(move || {
+    ///             tcti.execute(b"\x80\x01\x00\x00\x00\x0c\x00\x00\x01\x7b\x00\x08").unwrap()
+    ///         })
+    ///     })
+    ///     .collect();
+    /// for thread in threads {
+    ///     assert_eq!(thread.join().unwrap()[6..10], [0, 0, 0, 0]);
+    /// }
+    /// ```
+    pub struct SharedTcti<T: Tcti> {
+        tcti: Arc<Mutex<T>>,
+    }
+
+    impl<T: Tcti> Clone for SharedTcti<T> {
+        fn clone(&self) -> Self {
+            Self {
+                tcti: Arc::clone(&self.tcti),
+            }
+        }
+    }
+
+    impl<T: Tcti> fmt::Debug for SharedTcti<T> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_struct("SharedTcti")
+                .field("handles", &Arc::strong_count(&self.tcti))
+                .finish_non_exhaustive()
+        }
+    }
+
+    impl<T: Tcti> SharedTcti<T> {
+        pub fn new(tcti: T) -> Self {
+            Self {
+                tcti: Arc::new(Mutex::new(tcti)),
+            }
+        }
+
+        /// Transmit `command` and wait for its response.
+        pub fn execute(&self, command: &[u8]) -> Result<Vec<u8>, TctiError> {
+            self.with_tcti(|tcti| {
+                tcti.transmit(command)?;
+                tcti.receive()
+            })?
+        }
+
+        /// Run `f` with exclusive access to the tcti, e.g. to execute a
+        /// sequence of commands without other commands in between. `f`
+        /// has to receive the response of every command it transmits.
+        pub fn with_tcti<R>(&self, f: impl FnOnce(&mut T) -> R) -> Result<R, TctiError> {
+            let mut tcti = self.tcti.lock().map_err(|_| {
+                warn!("A thread panicked while using the shared tcti");
+                TctiError::GeneralFailure
+            })?;
+            Ok(f(&mut tcti))
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::tctildr::tcti_loader::TctiLoader;
+        use std::thread;
+        use std::time::Duration;
+
+        /// Responds with the command, slowly. Fails if commands interleave.
+        #[derive(Default)]
+        struct SlowEcho {
+            command: Option<Vec<u8>>,
+        }
+
+        impl Tcti for SlowEcho {
+            fn new(_conf: &str) -> Result<Self, TctiError> {
+                Ok(Self::default())
+            }
+
+            fn transmit(&mut self, command: &[u8]) -> Result<(), TctiError> {
+                if self.command.is_some() {
+                    return Err(TctiError::BadSequence);
+                }
+                self.command = Some(command.to_vec());
+                Ok(())
+            }
+
+            fn receive_with_timeout(&mut self, _timeout: Duration) -> Result<Vec<u8>, TctiError> {
+                thread::sleep(Duration::from_micros(10));
+                self.command.take().ok_or(TctiError::BadSequence)
+            }
+        }
+
+        fn assert_send_sync<T: Send + Sync>() {}
+
+        #[test]
+        fn test_concurrent_execute() {
+            assert_send_sync::<SharedTcti<SlowEcho>>();
+            assert_send_sync::<SharedTcti<TctiLoader>>();
+
+            let tcti = SharedTcti::new(SlowEcho::default());
+            let threads: Vec<_> = (0..8u32)
+                .map(|i| {
+                    let tcti = tcti.clone();
+                    thread::spawn(move || {
+                        for j in 0..50u32 {
+                            let command = [i.to_be_bytes(), j.to_be_bytes()].concat();
+                            assert_eq!(tcti.execute(&command).unwrap(), command);
+                        }
+                    })
+                })
+                .collect();
+            for thread in threads {
+                thread.join().unwrap();
+            }
+
+            let response = tcti.with_tcti(|tcti| {
+                tcti.transmit(b"a").unwrap();
+                tcti.receive().unwrap()
+            });
+            assert_eq!(response.unwrap(), b"a");
+        }
+
+        #[test]
+        fn test_poisoned() {
+            let tcti = SharedTcti::new(SlowEcho::default());
+            let clone = tcti.clone();
+            thread::spawn(move || {
+                clone.with_tcti(|tcti| {
+                    tcti.transmit(b"a").unwrap();
+                    panic!("test");
+                })
+            })
+            .join()
+            .unwrap_err();
+
+            // the response of the first command is still pending
+            assert_eq!(tcti.execute(b"b"), Err(TctiError::GeneralFailure));
+            assert_eq!(tcti.with_tcti(|_| ()), Err(TctiError::GeneralFailure));
+        }
+    }
+}
@@ -3,6 +3,8 @@ use strum_macros::EnumIter;
 use thiserror::Error;
 use tss2_tcti_sys::tpm2_tss;
 
+use super::rc::Tss2Rc;
+
 #[macro_export]
 macro_rules! tcti_layer_rc {
     ($x:expr) => {
@@ -78,6 +80,9 @@ pub enum TctiError {
         expected_magic: Option<u64>,
     } = tcti_layer_rc!(3),
 
+    #[error("ABI version mismatch")]
+    AbiMismatch = tcti_layer_rc!(4),
+
     #[error("BadReference")]
     BadReference = tcti_layer_rc!(5),
 
@@ -87,6 +92,9 @@ pub enum TctiError {
     #[error("BadSequence")]
     BadSequence = tcti_layer_rc!(7),
 
+    #[error("No connection to the TPM")]
+    NoConnection = tcti_layer_rc!(8),
+
     #[error("TryAgain")]
     TryAgain = tcti_layer_rc!(9),
 
@@ -106,12 +114,15 @@ pub enum TctiError {
     #[error("Out of memory")]
     Memory = tcti_layer_rc!(23),
 
+    // the specification defines no base error code for bad handles, so use the
+    // first implementation specific code
     #[error("A bad handle was passed")]
-    // TODO value not specified yet
-    BadHandle = tcti_layer_rc!(0xFFF),
+    BadHandle = tcti_layer_rc!(tpm2_tss::TSS2_LAYER_IMPLEMENTATION_SPECIFIC_OFFSET + 1),
 
-    #[error("Unknown error. TCTI seems not to be compliant to the specification")]
-    Unknown(u32) = tcti_layer_rc!(0xFFFFFFFF),
+    /// Codes of other layers (e.g. returned by a nested tcti) and tcti codes
+    /// without a variant. The code is retained when converting back.
+    #[error("Unknown error {0}")]
+    Unknown(Tss2Rc) = tcti_layer_rc!(0xFFFFFFFF),
 }
 
 impl TctiError {
@@ -123,21 +134,36 @@ impl TctiError {
     }
 }
 
-impl From<u32> for TctiError {
-    fn from(value: u32) -> Self {
+impl From<Tss2Rc> for TctiError {
+    fn from(value: Tss2Rc) -> Self {
         for variant in TctiError::iter() {
-            if value == variant.discriminant() {
+            if value == Tss2Rc::from(variant) {
                 return variant;
             }
         }
 
-        return TctiError::Unknown(value);
+        TctiError::Unknown(value)
+    }
+}
+
+impl From<TctiError> for Tss2Rc {
+    fn from(value: TctiError) -> Self {
+        match value {
+            TctiError::Unknown(rc) => rc,
+            error => Tss2Rc::from(error.discriminant()),
+        }
+    }
+}
+
+impl From<u32> for TctiError {
+    fn from(value: u32) -> Self {
+        Tss2Rc::from(value).into()
     }
 }
 
 impl From<TctiError> for u32 {
     fn from(value: TctiError) -> Self {
-        return value.discriminant();
+        Tss2Rc::from(value).into()
     }
 }
 
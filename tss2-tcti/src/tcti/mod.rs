@@ -1,12 +1,18 @@
 pub mod error;
+pub mod rc;
 
 pub mod tcti {
 
     use std::ffi::CStr;
+    use std::panic::{self, AssertUnwindSafe};
+    use std::time::Duration;
 
+    use tracing::{debug, error, warn, Span};
     use tss2_tcti_sys::tpm2_tss;
 
     use super::error::TctiError;
+    use super::rc::Tss2Rc;
+    use crate::telemetry::telemetry::{self, CommandSpan};
 
     #[macro_export]
     macro_rules! tcti_rc {
@@ -32,7 +38,7 @@ pub mod tcti {
                 'result: {
                     $(
                         if $ptr.is_null() {
-                            eprintln!("Error: unexpected null pointer: {:?}", stringify!($ptr));
+                            warn!("Unexpected null pointer: {:?}", stringify!($ptr));
                             break 'result Err(TctiError::BadReference);
                         }
                     )*
@@ -53,8 +59,8 @@ pub mod tcti {
                                 break 'result Ok(());
                             }
                         )*
-                        eprintln!(
-                            "Error: Expected tcti state to be one of {:?}, but state is {:?}",
+                        warn!(
+                            "Expected tcti state to be one of {:?}, but state is {:?}",
                             &[$($expected_state),*], state
                         );
                         Err($error)
@@ -68,11 +74,52 @@ pub mod tcti {
         ($result:expr) => {
             match $result {
                 Ok(x) => x,
-                Err(error) => return TctiError::from(error).discriminant(),
+                Err(error) => return Tss2Rc::from(TctiError::from(error)).into(),
             }
         };
     }
 
+    /// Upper bound for the response size. Returned by size queries of TCTIs which
+    /// cannot retain a response (see [TctiLib::get_pending_response()]).
+    pub const MAX_RESPONSE_SIZE: usize = 4096;
+
+    /// Wait indefinitely for the TPM response (`TSS2_TCTI_TIMEOUT_BLOCK`).
+    pub const TIMEOUT_BLOCK: Duration = Duration::MAX;
+
+    /// Converts a C timeout in milliseconds to a [Duration].
+    ///
+    /// `TSS2_TCTI_TIMEOUT_BLOCK` (-1) becomes [TIMEOUT_BLOCK], `0` means
+    /// polling. Other negative values are invalid.
+    ///
+    /// # Examples
+    /// ```
+    /// use std::time::Duration;
+    /// use tss2_tcti::tcti::tcti::{timeout_from_c, timeout_to_c, TIMEOUT_BLOCK};
+    ///
+    /// assert_eq!(timeout_from_c(-1).unwrap(), TIMEOUT_BLOCK);
+    /// assert_eq!(timeout_from_c(0).unwrap(), Duration::ZERO);
+    /// assert!(timeout_from_c(-2).is_err());
+    ///
+    /// assert_eq!(timeout_to_c(TIMEOUT_BLOCK), -1);
+    /// assert_eq!(timeout_to_c(Duration::from_millis(250)), 250);
+    /// ```
+    pub fn timeout_from_c(timeout: i32) -> Result<Duration, TctiError> {
+        match timeout {
+            tpm2_tss::TSS2_TCTI_TIMEOUT_BLOCK => Ok(TIMEOUT_BLOCK),
+            timeout if timeout >= 0 => Ok(Duration::from_millis(timeout as u64)),
+            _ => Err(TctiError::BadValue),
+        }
+    }
+
+    /// Converts a [Duration] to a C timeout in milliseconds. Durations which
+    /// do not fit into an `i32` block.
+    pub fn timeout_to_c(timeout: Duration) -> i32 {
+        match i32::try_from(timeout.as_millis()) {
+            Ok(timeout) => timeout,
+            Err(_) => tpm2_tss::TSS2_TCTI_TIMEOUT_BLOCK,
+        }
+    }
+
     /// Verifies that a bytes object is null-terminated and casts to *const i8
     const fn as_char_str(bytes: &[u8]) -> *const i8 {
         let last_byte = match bytes.last() {
@@ -141,6 +188,11 @@ pub mod tcti {
         Transmit,
         Receive,
         Finalized,
+        /// A call via the C interface panicked, so the context may be
+        /// inconsistent. All calls except finalize fail with
+        /// [TctiError::BadSequence]. Tctis without a [State] are not
+        /// poisoned.
+        Poisoned,
     }
 
     #[repr(C)]
@@ -153,15 +205,64 @@ pub mod tcti {
 
     pub type Api = tpm2_tss::TSS2_TCTI_CONTEXT_COMMON_V2;
 
-    pub trait Tcti: Sized {
+    /// A response which was received from the child/TPM but not yet handed
+    /// to the caller, e.g. because the caller only queried the response size.
+    /// See [TctiLib::get_pending_response()].
+    pub type PendingResponse = Option<Vec<u8>>;
+
+    /// Rust interface of a tcti.
+    ///
+    /// This trait is object-safe, i.e. tctis can be selected at runtime as
+    /// `Box<dyn Tcti>`, see [open()](crate::registry::registry::open).
+    pub trait Tcti {
         /// Creates a new [Tcti] object, initializing it.
-        fn new(conf: &str) -> Result<Self, TctiError>;
+        fn new(conf: &str) -> Result<Self, TctiError>
+        where
+            Self: Sized;
 
         /// Transmit TPM command.
         fn transmit(&mut self, command: &[u8]) -> Result<(), TctiError>;
 
-        /// Receive TPM response.
-        fn receive(&mut self) -> Result<Vec<u8>, TctiError>;
+        /// Receive TPM response. Blocks until the response is available.
+        fn receive(&mut self) -> Result<Vec<u8>, TctiError> {
+            self.receive_with_timeout(TIMEOUT_BLOCK)
+        }
+
+        /// Receive TPM response, waiting at most `timeout`.
+        ///
+        /// [TIMEOUT_BLOCK] waits indefinitely, [Duration::ZERO] polls. If the
+        /// response is not available in time, [TctiError::TryAgain] is
+        /// returned and the response can be received with a later call.
+        fn receive_with_timeout(&mut self, timeout: Duration) -> Result<Vec<u8>, TctiError>;
+
+        /// Query the size of the TPM response without consuming it. This is
+        /// the Rust counterpart of calling `receive` with a NULL buffer.
+        ///
+        /// The default implementation returns [MAX_RESPONSE_SIZE].
+        fn response_size(&mut self, _timeout: Duration) -> Result<usize, TctiError> {
+            Ok(MAX_RESPONSE_SIZE)
+        }
+
+        /// Receive TPM response into a caller-provided buffer. Returns the
+        /// number of bytes written.
+        ///
+        /// The default implementation is based on
+        /// [receive_with_timeout()](Tcti::receive_with_timeout) and discards
+        /// the response if `response` is too small
+        /// ([TctiError::InsufficientBuffer]).
+        fn receive_into(
+            &mut self,
+            response: &mut [u8],
+            timeout: Duration,
+        ) -> Result<usize, TctiError> {
+            let received = self.receive_with_timeout(timeout)?;
+            if received.len() > response.len() {
+                return Err(TctiError::InsufficientBuffer);
+            }
+
+            response[..received.len()].copy_from_slice(&received);
+            Ok(received.len())
+        }
 
         /// Finalizes the object. Called from ABI. Do not call from Rust code.
         fn finalize(&mut self) {}
@@ -171,7 +272,8 @@ pub mod tcti {
             Err(TctiError::NotImplemented)
         }
 
-        fn get_poll_handles(&mut self) -> Result<&[tpm2_tss::TSS2_TCTI_POLL_HANDLE], TctiError> {
+        /// Get the handles which can be polled for a TPM response.
+        fn get_poll_handles(&mut self) -> Result<Vec<tpm2_tss::TSS2_TCTI_POLL_HANDLE>, TctiError> {
             Err(TctiError::NotImplemented)
         }
 
@@ -179,7 +281,10 @@ pub mod tcti {
             Err(TctiError::NotImplemented)
         }
 
-        fn make_sticky(&mut self) -> Result<(), TctiError> {
+        /// Make a transient object or session sticky, i.e. keep it loaded in
+        /// a resource-managed TPM (`sticky == true`) or release it again
+        /// (`sticky == false`). The TCTI may update `handle`.
+        fn make_sticky(&mut self, _handle: &mut u32, _sticky: bool) -> Result<(), TctiError> {
             Err(TctiError::NotImplemented)
         }
     }
@@ -193,11 +298,118 @@ pub mod tcti {
             TctiLib::transmit(self, command)
         }
 
-        fn receive(&mut self) -> Result<Vec<u8>, TctiError> {
-            TctiLib::receive(self)
+        fn receive_with_timeout(&mut self, timeout: Duration) -> Result<Vec<u8>, TctiError> {
+            TctiLib::receive_with_timeout(self, timeout)
+        }
+
+        fn response_size(&mut self, timeout: Duration) -> Result<usize, TctiError> {
+            TctiLib::response_size(self, timeout)
+        }
+
+        fn receive_into(
+            &mut self,
+            response: &mut [u8],
+            timeout: Duration,
+        ) -> Result<usize, TctiError> {
+            TctiLib::receive_into(self, response, timeout)
+        }
+
+        fn get_poll_handles(&mut self) -> Result<Vec<tpm2_tss::TSS2_TCTI_POLL_HANDLE>, TctiError> {
+            TctiLib::get_poll_handles(self)
+        }
+
+        fn make_sticky(&mut self, handle: &mut u32, sticky: bool) -> Result<(), TctiError> {
+            TctiLib::make_sticky(self, handle, sticky)
+        }
+    }
+
+    /// Returns the pending response if there is one. Otherwise, receives the
+    /// response from [receive_inner()](TctiLib::receive_inner).
+    fn take_or_receive<T: TctiLib>(tcti: &mut T, timeout: Duration) -> Result<Vec<u8>, TctiError> {
+        let pending = tcti
+            .get_pending_response()
+            .and_then(|pending| pending.take());
+        match pending {
+            Some(response) => Ok(response),
+            None => tcti.receive_inner(timeout),
         }
     }
 
+    /// Name of the tcti without the terminating null byte.
+    fn tcti_name<T: TctiLib>() -> &'static str {
+        let name = T::INFO.name.strip_suffix(b"\0").unwrap_or(T::INFO.name);
+        std::str::from_utf8(name).unwrap_or("unknown")
+    }
+
+    /// Starts the span of `command`. Tctis without a [CommandSpan] member
+    /// get a span which ends after transmitting.
+    fn start_span<T: TctiLib>(tcti: &mut T, command: &[u8]) -> Span {
+        match tcti.get_command_span() {
+            Some(command_span) => command_span.start(tcti_name::<T>(), command),
+            None => telemetry::command_span(tcti_name::<T>(), command, None),
+        }
+    }
+
+    fn current_span<T: TctiLib>(tcti: &mut T) -> Span {
+        match tcti.get_command_span() {
+            Some(command_span) => command_span.current(),
+            None => Span::none(),
+        }
+    }
+
+    /// Records the response and ends the span of the current command. The
+    /// span is kept if the response is not available yet.
+    fn end_span<T: TctiLib>(tcti: &mut T, span: &Span, response: Result<&[u8], &TctiError>) {
+        match tcti.get_command_span() {
+            Some(command_span) => command_span.end(response),
+            None => telemetry::record_response(span, response),
+        }
+    }
+
+    /// State check of functions which work in any state, as long as the
+    /// context is not [State::Poisoned].
+    fn check_not_poisoned<T: TctiLib>(tcti: &T) -> Result<(), TctiError> {
+        if tcti.get_state() == Some(State::Poisoned) {
+            warn!("Tcti context is poisoned by a previous panic");
+            return Err(TctiError::BadSequence);
+        }
+        Ok(())
+    }
+
+    /// Runs the body `f` of a C interface function. Panics must not unwind
+    /// into the C caller: they are logged, the context is poisoned (see
+    /// [State::Poisoned]) and [TctiError::GeneralFailure] is returned.
+    ///
+    /// Poisoning needs the tcti to keep a [State]: if
+    /// [get_state()](TctiLib::get_state) returns [None], the context is used
+    /// as is by later calls.
+    ///
+    /// Only called by the C interface functions, whose callers guarantee
+    /// that `tcti_context` is valid (see [TctiLib::from_ptr()]).
+    fn catch_panic<T: TctiLib>(
+        tcti_context: *mut tpm2_tss::TSS2_TCTI_CONTEXT,
+        f: impl FnOnce() -> tpm2_tss::TSS2_RC,
+    ) -> tpm2_tss::TSS2_RC {
+        let payload = match panic::catch_unwind(AssertUnwindSafe(f)) {
+            Ok(return_code) => return return_code,
+            Err(payload) => payload,
+        };
+
+        let message = match payload.downcast_ref::<&str>() {
+            Some(message) => message,
+            None => match payload.downcast_ref::<String>() {
+                Some(message) => message.as_str(),
+                None => "unknown panic payload",
+            },
+        };
+        error!("Tcti panicked: {message}");
+
+        if let Ok(tcti) = unsafe { T::from_ptr(tcti_context) } {
+            tcti.set_state(State::Poisoned);
+        }
+        Tss2Rc::from(TctiError::GeneralFailure).into()
+    }
+
     /// Trait for implementing an ABI-compliant tcti.
     ///
     /// This trait can then be used by both Rust and C code (which expects a
@@ -207,8 +419,11 @@ pub mod tcti {
     /// Implementations need to fulfill these criteria:
     ///  1. [init_inner()](TctiLib::init_inner), [transmit_inner()](TctiLib::transmit_inner), [receive_inner()](TctiLib::receive_inner) must be implemented. These are called from both the ABI-layer and Rust.
     ///  1. First member must be of type [Api](crate::tcti::tcti::Api) (used internally).
-    ///  1. If you want [TctiLib] to run the state machine for you: there must be a member of type [State]; [get_state()](TctiLib::get_state), [set_state()](TctiLib::set_state) must be implemented.
+    ///  1. If you want [TctiLib] to run the state machine for you: there must be a member of type [State]; [get_state()](TctiLib::get_state), [set_state()](TctiLib::set_state) must be implemented. Without a [State], a context whose C interface call panicked is not poisoned (see [State::Poisoned]) and later calls run on possibly inconsistent data.
+    ///  1. If you want to support size queries and partial reads: there must be a member of type [PendingResponse]; [get_pending_response()](TctiLib::get_pending_response) must be implemented.
+    ///  1. If you want one span per command (see [telemetry](crate::telemetry::telemetry)): there must be a member of type [CommandSpan]; [get_command_span()](TctiLib::get_command_span) must be implemented.
     ///  1. [define_api_symbols] must be called on the type.
+    ///  1. Tests should run the ABI conformance checks, see [check_tcti()](crate::conformance::conformance::check_tcti).
     ///
     /// ```rust
     /// pub mod lib {
@@ -217,6 +432,7 @@ pub mod tcti {
     ///     use tss2_tcti::tctildr::tcti_loader::TctiLoader;
     ///     use tss2_tcti_sys::tpm2_tss;
     ///     use tss2_tcti::define_api_symbols;
+    ///     use std::time::Duration;
     ///
     ///     #[repr(C)]
     ///     #[derive(Debug)]
@@ -261,9 +477,9 @@ pub mod tcti {
     ///             todo!()
     ///         }
     ///
-    ///         fn receive_inner(&mut self) -> Result<Vec<u8>, TctiError> {
+    ///         fn receive_inner(&mut self, timeout: Duration) -> Result<Vec<u8>, TctiError> {
     ///             if let Some(child_tcti) = self.child_tcti.as_mut() {
-    ///                 return child_tcti.receive();
+    ///                 return child_tcti.receive_with_timeout(timeout);
     ///             }
     ///
     ///             todo!()
@@ -301,7 +517,7 @@ pub mod tcti {
         /// ```
         const INFO: Info<'static>;
         const INFO_RAW: tpm2_tss::TSS2_TCTI_INFO = tpm2_tss::TSS2_TCTI_INFO {
-            version: tpm2_tss::TCTI_VERSION, //Self::get_api_static().v1.version,
+            version: Self::VERSION,
             name: as_char_str(Self::INFO.name),
             description: as_char_str(Self::INFO.description),
             config_help: as_char_str(Self::INFO.config_help),
@@ -317,6 +533,9 @@ pub mod tcti {
         /// const MAGIC: u64 = 0x44a50b8745675fe5;
         /// ```
         const MAGIC: u64;
+        /// Version of the exposed API: `1` (without `makeSticky`) or `2`
+        /// ([tpm2_tss::TCTI_VERSION]).
+        const VERSION: u32 = tpm2_tss::TCTI_VERSION;
 
         /// Return the info struct which
         /// [define_api_symbols!()](define_api_symbols) will redefine as a
@@ -325,10 +544,13 @@ pub mod tcti {
             &Self::INFO_RAW as *const _
         }
 
-        /// Create new context (Rust only). Must internally call
+        /// Create new context. Must internally call
         /// [init()](TctiLib::init) (which calls
         /// [init_inner()](TctiLib::init_inner)).
         ///
+        /// Called from both ABI layer ([init_c()] moves the result into the
+        /// caller-provided context) and Rust.
+        ///
         /// # State machine
         /// If surrounding code is to handle the state machine, make sure to
         /// initialize the state member to [State::NotInitialized].
@@ -336,13 +558,15 @@ pub mod tcti {
 
         /// Wrapper for [init_inner()](TctiLib::init_inner).
         ///
-        /// Called from both ABI layer ([init_c()]) and Rust
-        /// ([TctiLib::init_inner()]). Do not call, call [new()](TctiLib::new)
-        /// instead.
+        /// Called from [new()](TctiLib::new). Do not call, call
+        /// [new()](TctiLib::new) instead.
         fn init(&mut self, conf: &str) -> Result<(), TctiError> {
-            // when memory is zeroized, state is State::NotInitialized = 0
             // TODO check_state!(self, TctiError::BadSequence, State::NotInitialized)?; // TODO panic?
 
+            if let Some(command_span) = self.get_command_span() {
+                *command_span = CommandSpan::default();
+            }
+
             let result = self.init_inner(conf);
 
             self.set_state(State::Transmit);
@@ -352,23 +576,88 @@ pub mod tcti {
         /// Wrapper for [transmit_inner()](TctiLib::transmit_inner).
         fn transmit(&mut self, command: &[u8]) -> Result<(), TctiError> {
             check_state!(self, TctiError::BadSequence, State::Transmit)?;
+            let span = start_span(self, command);
+            let _entered = span.enter();
 
             // TODO maybe there are error messages that should result in a state change?
-            self.transmit_inner(command)?;
+            let result = self.transmit_inner(command);
+            if let Err(error) = &result {
+                end_span(self, &span, Err(error));
+                return result;
+            }
 
             self.set_state(State::Receive);
             Ok(())
         }
-        /// Wrapper for [receive_inner()](TctiLib::receive_inner).
+        /// Wrapper for [receive_inner()](TctiLib::receive_inner). Blocks
+        /// until the response is available.
         fn receive(&mut self) -> Result<Vec<u8>, TctiError> {
+            self.receive_with_timeout(TIMEOUT_BLOCK)
+        }
+        /// Wrapper for [receive_inner()](TctiLib::receive_inner).
+        fn receive_with_timeout(&mut self, timeout: Duration) -> Result<Vec<u8>, TctiError> {
             check_state!(self, TctiError::BadSequence, State::Receive)?;
+            let span = current_span(self);
+            let _entered = span.enter();
 
             // TODO maybe there are error messages that should result in a state change?
-            let response = self.receive_inner()?;
+            let result = take_or_receive(self, timeout);
+            end_span(self, &span, result.as_deref());
+            let response = result?;
 
             self.set_state(State::Transmit);
             Ok(response)
         }
+        /// Wrapper for [receive_inner()](TctiLib::receive_inner). Receives
+        /// the response and keeps it as pending response, see
+        /// [get_pending_response()](TctiLib::get_pending_response).
+        ///
+        /// If there is no member for the pending response,
+        /// [MAX_RESPONSE_SIZE] is returned as an upper bound instead.
+        fn response_size(&mut self, timeout: Duration) -> Result<usize, TctiError> {
+            check_state!(self, TctiError::BadSequence, State::Receive)?;
+
+            if self.get_pending_response().is_none() {
+                return Ok(MAX_RESPONSE_SIZE);
+            }
+            let span = current_span(self);
+            let _entered = span.enter();
+
+            let response = take_or_receive(self, timeout)
+                .inspect_err(|error| end_span(self, &span, Err(error)))?;
+            let size = response.len();
+            if let Some(pending) = self.get_pending_response() {
+                *pending = Some(response);
+            }
+            Ok(size)
+        }
+        /// Wrapper for [receive_inner()](TctiLib::receive_inner). If
+        /// `response` is too small, [TctiError::InsufficientBuffer] is
+        /// returned and the response is kept as pending response (if
+        /// supported).
+        fn receive_into(
+            &mut self,
+            response: &mut [u8],
+            timeout: Duration,
+        ) -> Result<usize, TctiError> {
+            check_state!(self, TctiError::BadSequence, State::Receive)?;
+            let span = current_span(self);
+            let _entered = span.enter();
+
+            let received = take_or_receive(self, timeout)
+                .inspect_err(|error| end_span(self, &span, Err(error)))?;
+            if received.len() > response.len() {
+                if let Some(pending) = self.get_pending_response() {
+                    *pending = Some(received);
+                }
+                return Err(TctiError::InsufficientBuffer);
+            }
+            response[..received.len()].copy_from_slice(&received);
+            end_span(self, &span, Ok(&received));
+
+            self.set_state(State::Transmit);
+            Ok(received.len())
+        }
 
         /// Wrapper for [finalize_inner()](TctiLib::finalize_inner).
         /// Should not be called by the user.
@@ -378,6 +667,10 @@ pub mod tcti {
 
             self.finalize_inner();
 
+            if let Some(command_span) = self.get_command_span() {
+                // C callers free the context without dropping it
+                *command_span = CommandSpan::default();
+            }
             self.set_state(State::Finalized);
         }
 
@@ -385,40 +678,44 @@ pub mod tcti {
         fn cancel(&mut self) -> Result<(), TctiError> {
             check_state!(self, TctiError::BadSequence, State::Receive)?;
 
-            self.cancel_inner()
+            self.cancel_inner()?;
+            if let Some(command_span) = self.get_command_span() {
+                command_span.cancel();
+            }
+            Ok(())
         }
         /// Wrapper for [get_poll_handles_inner()](TctiLib::get_poll_handles_inner).
-        fn get_poll_handles(&mut self) -> Result<&[tpm2_tss::TSS2_TCTI_POLL_HANDLE], TctiError> {
-            // no state check
+        fn get_poll_handles(&mut self) -> Result<Vec<tpm2_tss::TSS2_TCTI_POLL_HANDLE>, TctiError> {
+            check_not_poisoned(self)?;
             self.get_poll_handles_inner()
         }
         /// Wrapper for [set_locality_inner()](TctiLib::set_locality_inner).
         fn set_locality(&mut self, locality: u8) -> Result<(), TctiError> {
             check_state!(self, TctiError::BadSequence, State::Transmit)?;
-            self.set_locality_inner(locality)
+            self.set_locality_inner(locality)?;
+            if let Some(command_span) = self.get_command_span() {
+                command_span.set_locality(locality);
+            }
+            Ok(())
         }
         /// Wrapper for [make_sticky_inner()](TctiLib::make_sticky_inner).
-        fn make_sticky(&mut self) -> Result<(), TctiError> {
-            // No state check
-            self.make_sticky_inner()
+        fn make_sticky(&mut self, handle: &mut u32, sticky: bool) -> Result<(), TctiError> {
+            check_not_poisoned(self)?;
+            self.make_sticky_inner(handle, sticky)
         }
 
         /// Initialize tcti.
         ///
-        /// Called from both ABI layer ([init_c()]) and Rust
-        /// ([new()](TctiLib::new) -> [init()](TctiLib::init) ->
+        /// Called from both ABI layer ([init_c()] -> [new()](TctiLib::new))
+        /// and Rust ([new()](TctiLib::new) -> [init()](TctiLib::init) ->
         /// [init_inner()](TctiLib::init_inner)).
         ///
         /// # State machine
         /// The following assumes that the surrounding code handles the state
         /// machine.
         ///
-        /// If called from C interface, memory is zeroized. That is, if there is
-        /// a member of type [State], it will default to
-        /// [State::NotInitialized].
-        ///
-        /// If called from Rust, [new()](TctiLib::new) should make sure that any
-        /// state is initialized to [State::NotInitialized].
+        /// [new()](TctiLib::new) should make sure that any state is
+        /// initialized to [State::NotInitialized].
         fn init_inner(&mut self, conf: &str) -> Result<(), TctiError>;
 
         /// Transmit TPM command.
@@ -426,10 +723,12 @@ pub mod tcti {
         /// Called from both ABI layer ([transmit_c()] -> [TctiLib::transmit()]) and Rust ([TctiLib::transmit()]).
         fn transmit_inner(&mut self, command: &[u8]) -> Result<(), TctiError>;
 
-        /// Receive TPM command.
+        /// Receive TPM command, waiting at most `timeout` (see
+        /// [Tcti::receive_with_timeout()]). Return [TctiError::TryAgain] if
+        /// the response is not available in time.
         ///
-        /// Called from both ABI layer ([receive_c()] -> [TctiLib::receive()]) and Rust ([TctiLib::receive()]).
-        fn receive_inner(&mut self) -> Result<Vec<u8>, TctiError>;
+        /// Called from both ABI layer ([receive_c()] -> [TctiLib::receive_into()]) and Rust ([TctiLib::receive()]).
+        fn receive_inner(&mut self, timeout: Duration) -> Result<Vec<u8>, TctiError>;
 
         /// Finalize tcti.
         ///
@@ -452,7 +751,7 @@ pub mod tcti {
         /// Called from both ABI layer ([get_poll_handles_c()] -> [TctiLib::get_poll_handles()]) and Rust ([TctiLib::get_poll_handles()]).
         fn get_poll_handles_inner(
             &mut self,
-        ) -> Result<&[tpm2_tss::TSS2_TCTI_POLL_HANDLE], TctiError> {
+        ) -> Result<Vec<tpm2_tss::TSS2_TCTI_POLL_HANDLE>, TctiError> {
             Err(TctiError::NotImplemented)
         }
 
@@ -465,12 +764,12 @@ pub mod tcti {
             Err(TctiError::NotImplemented)
         }
 
-        /// Make sticky.
+        /// Make sticky. See [Tcti::make_sticky()].
         ///
         /// The default implementation returns [Err](Err)([TctiError::NotImplemented]).
         ///
         /// Called from both ABI layer ([make_sticky_c()] -> [TctiLib::make_sticky()]) and Rust ([TctiLib::make_sticky()]).
-        fn make_sticky_inner(&mut self) -> Result<(), TctiError> {
+        fn make_sticky_inner(&mut self, _handle: &mut u32, _sticky: bool) -> Result<(), TctiError> {
             Err(TctiError::NotImplemented)
         }
 
@@ -487,7 +786,7 @@ pub mod tcti {
 
             let magic = unsafe { *(tcti_context as *mut u64) };
             if magic != Self::MAGIC {
-                eprintln!(
+                warn!(
                     "Magic number wrong. Expected {:#08x} but got {:#08x}",
                     Self::MAGIC,
                     magic,
@@ -507,7 +806,7 @@ pub mod tcti {
             Api {
                 v1: tpm2_tss::TSS2_TCTI_CONTEXT_COMMON_V1 {
                     magic: Self::MAGIC,
-                    version: tpm2_tss::TCTI_VERSION,
+                    version: Self::VERSION,
                     transmit: Some(transmit_c::<Self>),
                     receive: Some(receive_c::<Self>),
                     finalize: Some(finalize_c::<Self>),
@@ -516,7 +815,10 @@ pub mod tcti {
                     setLocality: Some(set_locality_c::<Self>),
                     ..Default::default()
                 },
-                makeSticky: Some(make_sticky_c::<Self>),
+                makeSticky: match Self::VERSION {
+                    1 => None,
+                    _ => Some(make_sticky_c::<Self>),
+                },
             }
         }
 
@@ -546,7 +848,7 @@ pub mod tcti {
         ///     # fn new(conf: &str) -> Result<Self, TctiError> {todo!()}
         ///     # fn init_inner(&mut self, conf: &str) -> Result<(), TctiError> {todo!()}
         ///     # fn transmit_inner(&mut self, command: &[u8]) -> Result<(), TctiError> {todo!()}
-        ///     # fn receive_inner(&mut self) -> Result<Vec<u8>, TctiError> {todo!()}
+        ///     # fn receive_inner(&mut self, timeout: std::time::Duration) -> Result<Vec<u8>, TctiError> {todo!()}
         ///     # fn set_state(&mut self, state: State) {todo!()}
         ///     fn get_state(&self) -> Option<State> {
         ///         Some(self.state)
@@ -575,7 +877,7 @@ pub mod tcti {
         ///     # fn new(conf: &str) -> Result<Self, TctiError> {todo!()}
         ///     # fn init_inner(&mut self, conf: &str) -> Result<(), TctiError> {todo!()}
         ///     # fn transmit_inner(&mut self, command: &[u8]) -> Result<(), TctiError> {todo!()}
-        ///     # fn receive_inner(&mut self) -> Result<Vec<u8>, TctiError> {todo!()}
+        ///     # fn receive_inner(&mut self, timeout: std::time::Duration) -> Result<Vec<u8>, TctiError> {todo!()}
         ///     # fn get_state(&self) -> Option<State> {todo!()}
         ///     fn set_state(&mut self, state: State) {
         ///         self.state = state;
@@ -585,6 +887,79 @@ pub mod tcti {
         /// }
         /// ```
         fn set_state(&mut self, state: State);
+
+        /// Return your member of type [PendingResponse] if the surrounding
+        /// code should support size queries and partial reads (i.e. `receive`
+        /// with a NULL or too small buffer). Return [None] (default) if you do
+        /// not want to keep responses.
+        ///
+        /// # Example
+        /// ```
+        /// # use tss2_tcti::tcti::tcti::{Api, Info, PendingResponse, State, TctiLib};
+        /// # use tss2_tcti::tcti::error::TctiError;
+        /// pub struct TctiFoobar {
+        ///    api: Api,
+        ///    pending_response: PendingResponse,
+        ///    // ...
+        /// }
+        ///
+        /// impl TctiLib for TctiFoobar {
+        ///     # const INFO: Info<'static> = todo!();
+        ///     # const MAGIC: u64 = todo!();
+        ///     # fn new(conf: &str) -> Result<Self, TctiError> {todo!()}
+        ///     # fn init_inner(&mut self, conf: &str) -> Result<(), TctiError> {todo!()}
+        ///     # fn transmit_inner(&mut self, command: &[u8]) -> Result<(), TctiError> {todo!()}
+        ///     # fn receive_inner(&mut self, timeout: std::time::Duration) -> Result<Vec<u8>, TctiError> {todo!()}
+        ///     # fn get_state(&self) -> Option<State> {todo!()}
+        ///     # fn set_state(&mut self, state: State) {todo!()}
+        ///     fn get_pending_response(&mut self) -> Option<&mut PendingResponse> {
+        ///         Some(&mut self.pending_response)
+        ///     }
+        ///
+        ///     // ...
+        /// }
+        /// ```
+        fn get_pending_response(&mut self) -> Option<&mut PendingResponse> {
+            None
+        }
+
+        /// Return your member of type [CommandSpan] if the surrounding code
+        /// should trace each command in a single span, from
+        /// [transmit()](TctiLib::transmit) until the response is received,
+        /// see [telemetry](crate::telemetry::telemetry). Return [None]
+        /// (default) to only trace transmitting.
+        ///
+        /// # Example
+        /// ```
+        /// # use tss2_tcti::tcti::tcti::{Api, Info, State, TctiLib};
+        /// # use tss2_tcti::tcti::error::TctiError;
+        /// use tss2_tcti::telemetry::telemetry::CommandSpan;
+        ///
+        /// pub struct TctiFoobar {
+        ///    api: Api,
+        ///    command_span: CommandSpan,
+        ///    // ...
+        /// }
+        ///
+        /// impl TctiLib for TctiFoobar {
+        ///     # const INFO: Info<'static> = todo!();
+        ///     # const MAGIC: u64 = todo!();
+        ///     # fn new(conf: &str) -> Result<Self, TctiError> {todo!()}
+        ///     # fn init_inner(&mut self, conf: &str) -> Result<(), TctiError> {todo!()}
+        ///     # fn transmit_inner(&mut self, command: &[u8]) -> Result<(), TctiError> {todo!()}
+        ///     # fn receive_inner(&mut self, timeout: std::time::Duration) -> Result<Vec<u8>, TctiError> {todo!()}
+        ///     # fn get_state(&self) -> Option<State> {todo!()}
+        ///     # fn set_state(&mut self, state: State) {todo!()}
+        ///     fn get_command_span(&mut self) -> Option<&mut CommandSpan> {
+        ///         Some(&mut self.command_span)
+        ///     }
+        ///
+        ///     // ...
+        /// }
+        /// ```
+        fn get_command_span(&mut self) -> Option<&mut CommandSpan> {
+            None
+        }
     }
 
     pub unsafe extern "C" fn init_c<T: TctiLib>(
@@ -592,35 +967,48 @@ pub mod tcti {
         size: *mut usize,
         config: *const ::std::os::raw::c_char,
     ) -> tpm2_tss::TSS2_RC {
-        if tcti_context.is_null() && size.is_null() {
-            eprintln!("TCTI context and size cannot both be NULL.");
-            return tcti_rc!(tpm2_tss::TSS2_BASE_RC_BAD_VALUE);
-        }
+        catch_panic::<T>(tcti_context, || {
+            #[cfg(feature = "subscriber")]
+            telemetry::init_from_env();
 
-        // if context is NULL, return size
-        if tcti_context.is_null() {
-            unsafe { *size = std::mem::size_of::<T>() };
-            eprintln!("TCTI context is NULL. Return size: {} bytes", unsafe {
-                *size
-            });
-            return 0;
-        }
+            if tcti_context.is_null() && size.is_null() {
+                warn!("TCTI context and size cannot both be NULL.");
+                return Tss2Rc::from(TctiError::BadValue).into();
+            }
 
-        let config = if config.is_null() {
-            ""
-        } else {
-            let config_c_str = unsafe { CStr::from_ptr(config) };
-            config_c_str.to_str().unwrap()
-        };
+            // if context is NULL, return size
+            if tcti_context.is_null() {
+                unsafe { *size = std::mem::size_of::<T>() };
+                debug!("TCTI context is NULL. Return size: {} bytes", unsafe {
+                    *size
+                });
+                return 0;
+            }
 
-        // cast and initialize context
-        let tcti = unsafe { &mut *(tcti_context as *mut T) };
-        // implicitly sets State to NotInitialized
-        std::ptr::write_bytes(tcti_context, 0, *size);
-        match tcti.init(config) {
-            Ok(()) => 0,
-            Err(error) => TctiError::from(error).discriminant(),
-        }
+            let config = if config.is_null() {
+                ""
+            } else {
+                let config_c_str = unsafe { CStr::from_ptr(config) };
+                return_if_error!(config_c_str.to_str().map_err(|_| {
+                    warn!("Config is not valid UTF-8");
+                    TctiError::BadValue
+                }))
+            };
+
+            if *size < std::mem::size_of::<T>() {
+                warn!(
+                    "TCTI context too small. Expected {} bytes but got {}",
+                    std::mem::size_of::<T>(),
+                    *size
+                );
+                return Tss2Rc::from(TctiError::InsufficientBuffer).into();
+            }
+
+            // the context is uninitialized memory, so it must not be dropped
+            let tcti = return_if_error!(T::new(config));
+            unsafe { std::ptr::write(tcti_context as *mut T, tcti) };
+            0
+        })
     }
 
     pub unsafe extern "C" fn transmit_c<T: TctiLib>(
@@ -628,68 +1016,79 @@ pub mod tcti {
         size: usize,
         command: *const u8,
     ) -> tpm2_tss::TSS2_RC {
-        return_if_error!(check_not_null!(command));
+        catch_panic::<T>(tcti_context, || {
+            return_if_error!(check_not_null!(command));
 
-        let tcti = return_if_error!(unsafe { T::from_ptr(tcti_context) });
-        let command = unsafe { std::slice::from_raw_parts(command, size) };
-        return_if_error!(tcti.transmit(command));
+            let tcti = return_if_error!(unsafe { T::from_ptr(tcti_context) });
+            let command = unsafe { std::slice::from_raw_parts(command, size) };
+            return_if_error!(tcti.transmit(command));
 
-        0
+            0
+        })
     }
 
     pub unsafe extern "C" fn receive_c<T: TctiLib>(
         tcti_context: *mut tpm2_tss::TSS2_TCTI_CONTEXT,
         size: *mut usize,
         response: *mut u8,
-        _timeout: i32, // TODO
+        timeout: i32,
     ) -> tpm2_tss::TSS2_RC {
-        return_if_error!(check_not_null!(size));
+        catch_panic::<T>(tcti_context, || {
+            return_if_error!(check_not_null!(size));
 
-        let tcti = return_if_error!(unsafe { T::from_ptr(tcti_context) });
+            let tcti = return_if_error!(unsafe { T::from_ptr(tcti_context) });
+            let timeout = return_if_error!(timeout_from_c(timeout));
 
-        if response.is_null() {
-            *size = 4096; // TODO get header/whole response and save in tcti for next call?
-            return 0;
-        }
-
-        let response = unsafe { std::slice::from_raw_parts_mut(response, *size) };
-
-        // TODO what if TRY_AGAIN (partial read/size not enough etc.
-        let child_response = return_if_error!(tcti.receive());
+            // size query
+            if response.is_null() {
+                *size = return_if_error!(tcti.response_size(timeout));
+                return 0;
+            }
 
-        eprintln!(
-            "response.len(): {:#?},  child_response.len(): {:#?}",
-            unsafe { *size },
-            child_response.len()
-        );
-        // TODO gracefully handle response.len() < child_response.len()
-        response[..child_response.len()].copy_from_slice(child_response.as_slice());
+            let response = unsafe { std::slice::from_raw_parts_mut(response, *size) };
+            let result = tcti.receive_into(response, timeout);
+            if result == Err(TctiError::InsufficientBuffer) {
+                // like the tpm2-tss tctis, report the size of the kept response
+                let pending = tcti
+                    .get_pending_response()
+                    .and_then(|pending| pending.as_ref());
+                if let Some(pending) = pending {
+                    *size = pending.len();
+                }
+            }
+            *size = return_if_error!(result);
 
-        0
+            0
+        })
     }
 
     pub unsafe extern "C" fn finalize_c<T: TctiLib>(
         tcti_context: *mut tpm2_tss::TSS2_TCTI_CONTEXT,
     ) {
-        let tcti = match unsafe { T::from_ptr(tcti_context) } {
-            Ok(tcti) => tcti,
-            Err(_) => {
-                // abort due to invalid magic
-                eprint!("Aborting finalization of TCTI since magic is invalid.");
-                return;
-            }
-        };
+        catch_panic::<T>(tcti_context, || {
+            let tcti = match unsafe { T::from_ptr(tcti_context) } {
+                Ok(tcti) => tcti,
+                Err(_) => {
+                    // abort due to invalid magic
+                    warn!("Aborting finalization of TCTI since magic is invalid.");
+                    return 0;
+                }
+            };
 
-        tcti.finalize()
+            tcti.finalize();
+            0
+        });
     }
 
     pub unsafe extern "C" fn cancel_c<T: TctiLib>(
         tcti_context: *mut tpm2_tss::TSS2_TCTI_CONTEXT,
     ) -> tpm2_tss::TSS2_RC {
-        let tcti = return_if_error!(unsafe { T::from_ptr(tcti_context) });
+        catch_panic::<T>(tcti_context, || {
+            let tcti = return_if_error!(unsafe { T::from_ptr(tcti_context) });
 
-        return_if_error!(tcti.cancel());
-        0
+            return_if_error!(tcti.cancel());
+            0
+        })
     }
 
     pub unsafe extern "C" fn get_poll_handles_c<T: TctiLib>(
@@ -697,42 +1096,56 @@ pub mod tcti {
         handles: *mut tpm2_tss::TSS2_TCTI_POLL_HANDLE,
         num_handles: *mut usize,
     ) -> tpm2_tss::TSS2_RC {
-        return_if_error!(check_not_null!(num_handles));
+        catch_panic::<T>(tcti_context, || {
+            return_if_error!(check_not_null!(num_handles));
 
-        let tcti = return_if_error!(unsafe { T::from_ptr(tcti_context) });
-        let handles_src = return_if_error!(tcti.get_poll_handles());
+            let tcti = return_if_error!(unsafe { T::from_ptr(tcti_context) });
+            let handles_src = return_if_error!(tcti.get_poll_handles());
 
-        if handles.is_null() {
-            *num_handles = handles_src.len();
-        } else {
-            // TODO gracefully handle *num_handles < handles_src.len()
+            // first call: query number of handles
+            if handles.is_null() {
+                *num_handles = handles_src.len();
+                return 0;
+            }
 
-            let handles = unsafe { std::slice::from_raw_parts_mut(handles, *num_handles) };
-            handles.copy_from_slice(handles_src);
-        };
+            // second call: fill caller-provided array
+            if *num_handles < handles_src.len() {
+                *num_handles = handles_src.len();
+                return Tss2Rc::from(TctiError::InsufficientBuffer).into();
+            }
+            let handles = unsafe { std::slice::from_raw_parts_mut(handles, handles_src.len()) };
+            handles.copy_from_slice(&handles_src);
+            *num_handles = handles_src.len();
 
-        0
+            0
+        })
     }
 
     pub unsafe extern "C" fn set_locality_c<T: TctiLib>(
         tcti_context: *mut tpm2_tss::TSS2_TCTI_CONTEXT,
         locality: u8,
     ) -> tpm2_tss::TSS2_RC {
-        let tcti = return_if_error!(unsafe { T::from_ptr(tcti_context) });
-        return_if_error!(tcti.set_locality(locality));
+        catch_panic::<T>(tcti_context, || {
+            let tcti = return_if_error!(unsafe { T::from_ptr(tcti_context) });
+            return_if_error!(tcti.set_locality(locality));
 
-        0
+            0
+        })
     }
 
     pub unsafe extern "C" fn make_sticky_c<T: TctiLib>(
         tcti_context: *mut tpm2_tss::TSS2_TCTI_CONTEXT,
-        _handle: *mut tpm2_tss::TPM2_HANDLE,
-        _sticky: u8,
+        handle: *mut tpm2_tss::TPM2_HANDLE,
+        sticky: u8,
     ) -> tpm2_tss::TSS2_RC {
-        let tcti = return_if_error!(unsafe { T::from_ptr(tcti_context) });
-        // TODO handle, sticky
-        return_if_error!(tcti.make_sticky());
+        catch_panic::<T>(tcti_context, || {
+            return_if_error!(check_not_null!(handle));
+
+            let tcti = return_if_error!(unsafe { T::from_ptr(tcti_context) });
+            let handle = unsafe { &mut *handle };
+            return_if_error!(tcti.make_sticky(handle, sticky != 0));
 
-        0
+            0
+        })
     }
 }
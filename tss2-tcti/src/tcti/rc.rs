@@ -0,0 +1,312 @@
+use std::fmt;
+
+use strum::IntoEnumIterator;
+use strum_macros::EnumIter;
+use tss2_tcti_sys::tpm2_tss;
+
+/// Layer of a [Tss2Rc], i.e. the component which returned it.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Layer {
+    /// Response code of the TPM, see TPM 2.0 Part 2, 6.6.
+    Tpm,
+    /// Feature API (FAPI)
+    Feature,
+    /// Enhanced System API (ESAPI)
+    Esapi,
+    /// System API (SAPI)
+    Sapi,
+    /// Marshaling/Unmarshaling
+    Mu,
+    Tcti,
+    /// Resource manager, e.g. tpm2-abrmd
+    ResMgr,
+    /// TPM response code generated by the resource manager on behalf of the TPM
+    ResMgrTpm,
+    Policy,
+    /// Layer not defined by the TSS specification
+    Other(u8),
+}
+
+impl From<u8> for Layer {
+    fn from(value: u8) -> Self {
+        match value {
+            0 => Layer::Tpm,
+            6 => Layer::Feature,
+            7 => Layer::Esapi,
+            8 => Layer::Sapi,
+            9 => Layer::Mu,
+            10 => Layer::Tcti,
+            11 => Layer::ResMgr,
+            12 => Layer::ResMgrTpm,
+            13 => Layer::Policy,
+            other => Layer::Other(other),
+        }
+    }
+}
+
+impl From<Layer> for u8 {
+    fn from(value: Layer) -> Self {
+        match value {
+            Layer::Tpm => 0,
+            Layer::Feature => 6,
+            Layer::Esapi => 7,
+            Layer::Sapi => 8,
+            Layer::Mu => 9,
+            Layer::Tcti => 10,
+            Layer::ResMgr => 11,
+            Layer::ResMgrTpm => 12,
+            Layer::Policy => 13,
+            Layer::Other(other) => other,
+        }
+    }
+}
+
+impl fmt::Display for Layer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Layer::Tpm => write!(f, "tpm"),
+            Layer::Feature => write!(f, "fapi"),
+            Layer::Esapi => write!(f, "esapi"),
+            Layer::Sapi => write!(f, "sys"),
+            Layer::Mu => write!(f, "mu"),
+            Layer::Tcti => write!(f, "tcti"),
+            Layer::ResMgr => write!(f, "rm"),
+            Layer::ResMgrTpm => write!(f, "rmt"),
+            Layer::Policy => write!(f, "policy"),
+            Layer::Other(other) => write!(f, "{other:#x}"),
+        }
+    }
+}
+
+macro_rules! base_rc {
+    ($($variant:ident = $constant:ident,)*) => {
+        /// Error codes shared by all layers except [Layer::Tpm] and
+        /// [Layer::ResMgrTpm] (`TSS2_BASE_RC_*`).
+        #[repr(u16)]
+        #[derive(Debug, EnumIter, Copy, Clone, PartialEq, Eq, Hash)]
+        pub enum BaseRc {
+            $($variant = tpm2_tss::$constant as u16,)*
+        }
+    };
+}
+
+base_rc!(
+    GeneralFailure = TSS2_BASE_RC_GENERAL_FAILURE,
+    NotImplemented = TSS2_BASE_RC_NOT_IMPLEMENTED,
+    BadContext = TSS2_BASE_RC_BAD_CONTEXT,
+    AbiMismatch = TSS2_BASE_RC_ABI_MISMATCH,
+    BadReference = TSS2_BASE_RC_BAD_REFERENCE,
+    InsufficientBuffer = TSS2_BASE_RC_INSUFFICIENT_BUFFER,
+    BadSequence = TSS2_BASE_RC_BAD_SEQUENCE,
+    NoConnection = TSS2_BASE_RC_NO_CONNECTION,
+    TryAgain = TSS2_BASE_RC_TRY_AGAIN,
+    IoError = TSS2_BASE_RC_IO_ERROR,
+    BadValue = TSS2_BASE_RC_BAD_VALUE,
+    NotPermitted = TSS2_BASE_RC_NOT_PERMITTED,
+    InvalidSessions = TSS2_BASE_RC_INVALID_SESSIONS,
+    NoDecryptParam = TSS2_BASE_RC_NO_DECRYPT_PARAM,
+    NoEncryptParam = TSS2_BASE_RC_NO_ENCRYPT_PARAM,
+    BadSize = TSS2_BASE_RC_BAD_SIZE,
+    MalformedResponse = TSS2_BASE_RC_MALFORMED_RESPONSE,
+    InsufficientContext = TSS2_BASE_RC_INSUFFICIENT_CONTEXT,
+    InsufficientResponse = TSS2_BASE_RC_INSUFFICIENT_RESPONSE,
+    IncompatibleTcti = TSS2_BASE_RC_INCOMPATIBLE_TCTI,
+    NotSupported = TSS2_BASE_RC_NOT_SUPPORTED,
+    BadTctiStructure = TSS2_BASE_RC_BAD_TCTI_STRUCTURE,
+    Memory = TSS2_BASE_RC_MEMORY,
+    BadTr = TSS2_BASE_RC_BAD_TR,
+    MultipleDecryptSessions = TSS2_BASE_RC_MULTIPLE_DECRYPT_SESSIONS,
+    MultipleEncryptSessions = TSS2_BASE_RC_MULTIPLE_ENCRYPT_SESSIONS,
+    RspAuthFailed = TSS2_BASE_RC_RSP_AUTH_FAILED,
+    NoConfig = TSS2_BASE_RC_NO_CONFIG,
+    BadPath = TSS2_BASE_RC_BAD_PATH,
+    NotDeletable = TSS2_BASE_RC_NOT_DELETABLE,
+    PathAlreadyExists = TSS2_BASE_RC_PATH_ALREADY_EXISTS,
+    KeyNotFound = TSS2_BASE_RC_KEY_NOT_FOUND,
+    SignatureVerificationFailed = TSS2_BASE_RC_SIGNATURE_VERIFICATION_FAILED,
+    HashMismatch = TSS2_BASE_RC_HASH_MISMATCH,
+    KeyNotDuplicable = TSS2_BASE_RC_KEY_NOT_DUPLICABLE,
+    PathNotFound = TSS2_BASE_RC_PATH_NOT_FOUND,
+    NoCert = TSS2_BASE_RC_NO_CERT,
+    NoPcr = TSS2_BASE_RC_NO_PCR,
+    PcrNotResettable = TSS2_BASE_RC_PCR_NOT_RESETTABLE,
+    BadTemplate = TSS2_BASE_RC_BAD_TEMPLATE,
+    AuthorizationFailed = TSS2_BASE_RC_AUTHORIZATION_FAILED,
+    AuthorizationUnknown = TSS2_BASE_RC_AUTHORIZATION_UNKNOWN,
+    NvNotReadable = TSS2_BASE_RC_NV_NOT_READABLE,
+    NvTooSmall = TSS2_BASE_RC_NV_TOO_SMALL,
+    NvNotWriteable = TSS2_BASE_RC_NV_NOT_WRITEABLE,
+    PolicyUnknown = TSS2_BASE_RC_POLICY_UNKNOWN,
+    NvWrongType = TSS2_BASE_RC_NV_WRONG_TYPE,
+    NameAlreadyExists = TSS2_BASE_RC_NAME_ALREADY_EXISTS,
+    NoTpm = TSS2_BASE_RC_NO_TPM,
+    BadKey = TSS2_BASE_RC_BAD_KEY,
+    NoHandle = TSS2_BASE_RC_NO_HANDLE,
+    NotProvisioned = TSS2_BASE_RC_NOT_PROVISIONED,
+    AlreadyProvisioned = TSS2_BASE_RC_ALREADY_PROVISIONED,
+    CallbackNull = TSS2_BASE_RC_CALLBACK_NULL,
+);
+
+impl TryFrom<u16> for BaseRc {
+    type Error = u16;
+
+    fn try_from(value: u16) -> Result<Self, Self::Error> {
+        BaseRc::iter()
+            .find(|base| *base as u16 == value)
+            .ok_or(value)
+    }
+}
+
+/// Raw `TSS2_RC` of any TSS layer. Converts from and into `u32` losslessly, so
+/// codes of unknown layers or implementation specific codes are retained.
+///
+/// # Examples
+/// ```
+/// use tss2_tcti::tcti::rc::{BaseRc, Layer, Tss2Rc};
+///
+/// let rc = Tss2Rc::from(0x000b0008);
+/// assert_eq!(rc.layer(), Layer::ResMgr);
+/// assert_eq!(rc.base(), Some(BaseRc::NoConnection));
+/// assert_eq!(rc.to_string(), "rm:NoConnection");
+/// assert_eq!(u32::from(rc), 0x000b0008);
+///
+/// let rc = Tss2Rc::new(Layer::Tpm, 0x101);
+/// assert_eq!(rc.base(), None);
+/// assert_eq!(rc.to_string(), "tpm:0x101");
+/// ```
+#[derive(Copy, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Tss2Rc(u32);
+
+impl Tss2Rc {
+    pub const SUCCESS: Self = Self(0);
+
+    pub fn new(layer: Layer, code: u16) -> Self {
+        Self((u8::from(layer) as u32) << tpm2_tss::TSS2_RC_LAYER_SHIFT | code as u32)
+    }
+
+    pub fn from_base(layer: Layer, base: BaseRc) -> Self {
+        Self::new(layer, base as u16)
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn layer(&self) -> Layer {
+        Layer::from((self.0 >> tpm2_tss::TSS2_RC_LAYER_SHIFT) as u8)
+    }
+
+    /// Layer specific part of the code, i.e. the lower 16 bits.
+    pub fn code(&self) -> u16 {
+        self.0 as u16
+    }
+
+    /// Base error of layers other than [Layer::Tpm] and [Layer::ResMgrTpm].
+    /// `None` for TPM response codes and codes outside of the base range,
+    /// e.g. implementation specific codes.
+    pub fn base(&self) -> Option<BaseRc> {
+        match self.layer() {
+            Layer::Tpm | Layer::ResMgrTpm => None,
+            _ => BaseRc::try_from(self.code()).ok(),
+        }
+    }
+
+    /// Whether the code lies in the implementation specific range of its
+    /// layer (`TSS2_LAYER_IMPLEMENTATION_SPECIFIC_OFFSET`).
+    pub fn is_implementation_specific(&self) -> bool {
+        !matches!(self.layer(), Layer::Tpm | Layer::ResMgrTpm)
+            && self.code() as u32 >= tpm2_tss::TSS2_LAYER_IMPLEMENTATION_SPECIFIC_OFFSET
+    }
+}
+
+impl From<u32> for Tss2Rc {
+    fn from(value: u32) -> Self {
+        Self(value)
+    }
+}
+
+impl From<Tss2Rc> for u32 {
+    fn from(value: Tss2Rc) -> Self {
+        value.0
+    }
+}
+
+impl fmt::Debug for Tss2Rc {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Tss2Rc({:#010x}: {self})", self.0)
+    }
+}
+
+impl fmt::Display for Tss2Rc {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_success() {
+            return write!(f, "success");
+        }
+        match self.base() {
+            Some(base) => write!(f, "{}:{base:?}", self.layer()),
+            None => write!(f, "{}:{:#x}", self.layer(), self.code()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tcti::error::TctiError;
+
+    #[test]
+    fn test_decode() {
+        for layer in 0..=u8::MAX {
+            assert_eq!(u8::from(Layer::from(layer)), layer);
+        }
+        for base in BaseRc::iter() {
+            assert_eq!(BaseRc::try_from(base as u16), Ok(base));
+        }
+        assert_eq!(BaseRc::try_from(0), Err(0));
+        assert_eq!(BaseRc::try_from(55), Err(55));
+
+        let rc = Tss2Rc::from_base(Layer::Esapi, BaseRc::BadTr);
+        assert_eq!(u32::from(rc), 0x00070018);
+        assert_eq!(rc.to_string(), "esapi:BadTr");
+
+        // FAPI uses the feature layer
+        let rc = Tss2Rc::from(0x00060020);
+        assert_eq!(rc.layer(), Layer::Feature);
+        assert_eq!(rc.base(), Some(BaseRc::KeyNotFound));
+
+        // response codes of the TPM are not decoded as base errors
+        let rc = Tss2Rc::from(0x000c0902);
+        assert_eq!(rc.layer(), Layer::ResMgrTpm);
+        assert_eq!(rc.base(), None);
+        assert_eq!(rc.to_string(), "rmt:0x902");
+
+        let rc = Tss2Rc::from(0x002af801);
+        assert_eq!(rc.layer(), Layer::Other(0x2a));
+        assert!(rc.is_implementation_specific());
+        assert_eq!(rc.to_string(), "0x2a:0xf801");
+        assert_eq!(u32::from(rc), 0x002af801);
+        assert_eq!(Tss2Rc::SUCCESS.to_string(), "success");
+    }
+
+    #[test]
+    fn test_tcti_error() {
+        for error in TctiError::iter().filter(|error| !matches!(error, TctiError::Unknown(_))) {
+            let rc = Tss2Rc::from(error);
+            assert_eq!(rc.layer(), Layer::Tcti);
+            assert_eq!(TctiError::from(rc), error);
+            assert_eq!(TctiError::from(u32::from(rc)), error);
+        }
+        assert_eq!(
+            Tss2Rc::from(TctiError::BadValue),
+            Tss2Rc::from_base(Layer::Tcti, BaseRc::BadValue)
+        );
+        assert!(Tss2Rc::from(TctiError::BadHandle).is_implementation_specific());
+
+        // codes of other layers and unassigned tcti codes are retained
+        for value in [0x000b0008, 0x00000101, 0x000a0010, 0x000a0fff, 0xffffffff] {
+            let error = TctiError::from(value);
+            assert_eq!(error, TctiError::Unknown(Tss2Rc::from(value)));
+            assert_eq!(u32::from(error), value);
+        }
+    }
+}
@@ -1,28 +1,151 @@
 pub mod tcti_loader {
+    use std::ffi::CString;
     use std::ptr::{null, null_mut};
+    use std::sync::Mutex;
+    use std::time::{Duration, Instant};
 
     use crate::tcti::{
         error::TctiError,
-        tcti::{Api, Tcti},
+        rc::Tss2Rc,
+        tcti::{timeout_to_c, Api, Tcti},
     };
-    use log::warn;
+    use libloading::Library;
+    use tracing::{debug, warn};
     use tss2_tcti_sys::tpm2_tss;
 
+    /// Returns the [tpm2_tss::TSS2_TCTI_INFO] of a tcti, e.g. `Tss2_Tcti_Info()` or
+    /// [TctiLib::info()](crate::tcti::tcti::TctiLib::info).
+    pub type InfoFn = fn() -> *const tpm2_tss::TSS2_TCTI_INFO;
+
+    /// Tctis which are probed in this order if no tcti name is given, together
+    /// with their default conf (same as libtss2-tctildr).
+    const DEFAULT_TCTIS: &[(&str, &str)] = &[
+        ("libtss2-tcti-default.so", ""),
+        ("libtss2-tcti-tabrmd.so.0", "bus_type=system"),
+        ("libtss2-tcti-device.so.0", "/dev/tpmrm0"),
+        ("libtss2-tcti-device.so.0", "/dev/tpm0"),
+        ("libtss2-tcti-device.so.0", "/dev/tcm0"),
+        ("libtss2-tcti-swtpm.so.0", ""),
+        ("libtss2-tcti-mssim.so.0", ""),
+    ];
+
+    /// How often [TctiLoader] queries the poll handles of its child if they
+    /// change in between querying their number and filling them.
+    const POLL_HANDLE_ATTEMPTS: usize = 3;
+
+    /// Statically linked tctis, see [register_static()].
+    static STATIC_TCTIS: Mutex<Vec<(String, InfoFn)>> = Mutex::new(Vec::new());
+
+    /// Makes a statically linked tcti available to [TctiLoader] under `name`.
+    /// Statically linked tctis take precedence over shared libraries.
+    pub fn register_static(name: &str, info: InfoFn) {
+        STATIC_TCTIS
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .push((name.to_string(), info));
+    }
+
+    fn find_static(name: &str) -> Option<InfoFn> {
+        STATIC_TCTIS
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .iter()
+            .rev()
+            .find(|(static_name, _)| static_name == name)
+            .map(|(_, info)| *info)
+    }
+
+    /// Splits a tctildr config string `<name>:<conf>` at the first colon.
+    ///
+    /// # Examples
+    /// ```
+    /// use tss2_tcti::tctildr::tcti_loader::parse_name_conf;
+    ///
+    /// assert_eq!(parse_name_conf("mssim:host=localhost,port=2321"), ("mssim", "host=localhost,port=2321"));
+    /// assert_eq!(parse_name_conf("device:/dev/tpmrm0"), ("device", "/dev/tpmrm0"));
+    /// assert_eq!(parse_name_conf("libtpms"), ("libtpms", ""));
+    /// assert_eq!(parse_name_conf(""), ("", ""));
+    /// ```
+    pub fn parse_name_conf(name_conf: &str) -> (&str, &str) {
+        match name_conf.split_once(':') {
+            Some((name, conf)) => (name, conf),
+            None => (name_conf, ""),
+        }
+    }
+
+    /// File names which are tried in this order when loading the tcti `name`.
+    ///
+    /// # Examples
+    /// ```
+    /// use tss2_tcti::tctildr::tcti_loader::library_candidates;
+    ///
+    /// assert_eq!(
+    ///     library_candidates("mssim"),
+    ///     ["mssim", "libtss2-tcti-mssim.so.0", "libtss2-tcti-mssim.so"],
+    /// );
+    /// ```
+    pub fn library_candidates(name: &str) -> Vec<String> {
+        vec![
+            name.to_string(),
+            format!("libtss2-tcti-{name}.so.0"),
+            format!("libtss2-tcti-{name}.so"),
+        ]
+    }
+
+    fn load_library(name: &str) -> Result<Library, TctiError> {
+        for file in library_candidates(name) {
+            match unsafe { Library::new(&file) } {
+                Ok(library) => return Ok(library),
+                Err(error) => debug!("Could not load {file}: {error}"),
+            }
+        }
+
+        warn!("Could not find tcti library for {name:?}");
+        Err(TctiError::NotSupported)
+    }
+
     /// Tcti for loading other tctis dynamically.
     ///
+    /// This is a Rust re-implementation of libtss2-tctildr: shared libraries
+    /// are loaded via `dlopen()`, statically linked tctis can be made available
+    /// via [register_static()]. The child is driven via its C ABI.
+    ///
     /// TctiLoader is special. Since this is a wrapper for C code, we do not need a C interface.
+    ///
+    /// Like the C tctis, TctiLoader can be moved to another thread but must
+    /// not be used by several threads at once. Use
+    /// [SharedTcti](crate::shared::shared::SharedTcti) to share it.
     #[repr(C)]
     #[derive(Debug)]
     pub struct TctiLoader {
-        ctx: Vec<u8>,
+        // u64 to guarantee the alignment of the child context
+        ctx: Vec<u64>,
+        // must outlive ctx, None for statically linked tctis
+        _library: Option<Library>,
     }
 
     impl TctiLoader {
-        fn get_api(&self) -> Api {
-            let api_ptr = self.ctx_ptr() as *const _ as *const Api;
+        /// API version of the child: `1` (without `makeSticky`) or `2`.
+        pub fn api_version(&self) -> u32 {
+            self.get_api().version
+        }
+
+        /// The part of the API which children of every version provide.
+        fn get_api(&self) -> tpm2_tss::TSS2_TCTI_CONTEXT_COMMON_V1 {
+            let api_ptr = self.ctx_ptr() as *const tpm2_tss::TSS2_TCTI_CONTEXT_COMMON_V1;
             unsafe { std::ptr::read(api_ptr) }
         }
 
+        /// `makeSticky`, which children of version 1 do not provide. Their
+        /// context may end before it.
+        fn get_make_sticky(&self) -> tpm2_tss::TSS2_TCTI_MAKE_STICKY_FCN {
+            if self.api_version() < 2 {
+                return None;
+            }
+            let api_ptr = self.ctx_ptr() as *const Api;
+            unsafe { std::ptr::read(api_ptr) }.makeSticky
+        }
+
         fn ctx_ptr(&self) -> *const tpm2_tss::TSS2_TCTI_OPAQUE_CONTEXT_BLOB {
             self.ctx.as_ptr() as *const _
         }
@@ -30,11 +153,119 @@ pub mod tcti_loader {
         fn ctx_mut_ptr(&mut self) -> *mut tpm2_tss::TSS2_TCTI_OPAQUE_CONTEXT_BLOB {
             self.ctx.as_mut_ptr() as *mut _
         }
+
+        /// Load tcti by name: statically linked tctis first, then shared
+        /// libraries (see [library_candidates()]).
+        fn new_by_name(name: &str, conf: &str) -> Result<Self, TctiError> {
+            if let Some(info) = find_static(name) {
+                return Self::from_info(info(), conf, None);
+            }
+
+            Self::from_library(load_library(name)?, conf)
+        }
+
+        /// Probe the default tctis (see libtss2-tctildr). `conf` overrides
+        /// the default conf, if not empty.
+        fn new_default(conf: &str) -> Result<Self, TctiError> {
+            for (file, default_conf) in DEFAULT_TCTIS {
+                let conf = if conf.is_empty() { default_conf } else { conf };
+
+                match load_library(file).and_then(|library| Self::from_library(library, conf)) {
+                    Ok(tcti_loader) => return Ok(tcti_loader),
+                    Err(error) => debug!("Could not initialize default tcti {file}: {error:?}"),
+                }
+            }
+
+            warn!("No default tcti could be loaded.");
+            Err(TctiError::IoError)
+        }
+
+        fn from_library(library: Library, conf: &str) -> Result<Self, TctiError> {
+            let info = {
+                let info_fn = unsafe {
+                    library.get::<unsafe extern "C" fn() -> *const tpm2_tss::TSS2_TCTI_INFO>(
+                        tpm2_tss::TSS2_TCTI_INFO_SYMBOL,
+                    )
+                }
+                .map_err(|error| {
+                    warn!("Library is not a tcti: {error}");
+                    TctiError::NotSupported
+                })?;
+                unsafe { info_fn() }
+            };
+
+            Self::from_info(info, conf, Some(library))
+        }
+
+        fn from_info(
+            info: *const tpm2_tss::TSS2_TCTI_INFO,
+            conf: &str,
+            library: Option<Library>,
+        ) -> Result<Self, TctiError> {
+            let init_fn = match unsafe { info.as_ref() }.and_then(|info| info.init) {
+                Some(init_fn) => init_fn,
+                None => {
+                    warn!("Tcti does not provide an init function.");
+                    return Err(TctiError::NotSupported);
+                }
+            };
+            let conf = CString::new(conf).map_err(|_| TctiError::BadValue)?;
+
+            let mut size: usize = 0;
+            let return_code = unsafe { init_fn(null_mut(), &mut size, null()) };
+            if return_code != 0 {
+                let error = TctiError::from(Tss2Rc::from(return_code));
+
+                warn!("Child tcti returned error: {error:?}");
+                return Err(error);
+            }
+            // the common part of the API is read from the context
+            if size < std::mem::size_of::<tpm2_tss::TSS2_TCTI_CONTEXT_COMMON_V1>() {
+                warn!("Child tcti context of {size} bytes cannot hold the tcti API.");
+                return Err(TctiError::NotSupported);
+            }
+
+            // only wrap into Self after successful initialization, the child
+            // must not be finalized otherwise
+            let mut ctx = vec![0u64; size.div_ceil(std::mem::size_of::<u64>())];
+            let return_code =
+                unsafe { init_fn(ctx.as_mut_ptr() as *mut _, &mut size, conf.as_ptr()) };
+            let error: TctiError = match return_code {
+                0 => {
+                    let tcti = Self {
+                        ctx,
+                        _library: library,
+                    };
+                    // the child is finalized on drop
+                    if tcti.api_version() >= 2 && size < std::mem::size_of::<Api>() {
+                        warn!("Child tcti context of {size} bytes cannot hold the tcti API version 2.");
+                        return Err(TctiError::NotSupported);
+                    }
+                    return Ok(tcti);
+                }
+                error_code => Tss2Rc::from(error_code).into(),
+            };
+
+            warn!("Child tcti returned error: {error:?}");
+            Err(error)
+        }
+    }
+
+    /// Returns the function of the child or [TctiError::NotImplemented] if
+    /// the child does not provide it.
+    fn child_fn<F>(function: Option<F>, name: &str) -> Result<F, TctiError> {
+        function.ok_or_else(|| {
+            warn!("Child tcti does not provide {name}");
+            TctiError::NotImplemented
+        })
     }
 
     impl Drop for TctiLoader {
         fn drop(&mut self) {
-            let finalize_fn = self.get_api().v1.finalize.unwrap();
+            let Some(finalize_fn) = self.get_api().finalize else {
+                warn!("Child tcti does not provide finalize");
+                return;
+            };
 
             unsafe {
                 finalize_fn(self.ctx_mut_ptr());
@@ -43,196 +274,469 @@ pub mod tcti_loader {
     }
 
     impl Tcti for TctiLoader {
+        /// Load and initialize a tcti from a tctildr config string
+        /// `<name>:<conf>` (see [parse_name_conf()]). If `name` is empty, the
+        /// default tctis are probed.
+        ///
         /// # Examples
         /// ```
-        /// use crate::tss2_tcti::tcti::tcti::Tcti;
-        /// use crate::tss2_tcti::tctildr::tcti_loader::TctiLoader;
+        /// use crate::tss2_tcti::tcti::tcti::{Tcti, TctiLib};
+        /// use crate::tss2_tcti::tctildr::tcti_loader::{register_static, TctiLoader};
+        /// use tpm2_tcti_sim::lib::TctiSim;
         ///
-        /// // Make sure that
-        /// //  a) libtpms is installed
-        /// //  b) libtss2‑tcti‑libtpms.so is installed (comes with tpm2-tss since v3.0)
-        /// let mut tcti = TctiLoader::new("libtpms").unwrap();
+        /// // Any installed tcti works, e.g. "libtpms" (libtss2-tcti-libtpms.so
+        /// // comes with tpm2-tss since v3.0). The in-process simulator needs
+        /// // no TPM at all.
+        /// register_static("sim", <TctiSim as TctiLib>::info);
+        /// let mut tcti = TctiLoader::new("sim:seed=0").unwrap();
         ///
         /// // TPM2_Startup
         /// tcti.transmit(b"\x80\x01\x00\x00\x00\x0c\x00\x00\x01\x44\x00\x00").unwrap();
         /// let response = tcti.receive().unwrap();
         /// assert_eq!(response, b"\x80\x01\x00\x00\x00\x0a\x00\x00\x00\x00");
         ///
-        /// // TPM2_GetCap (TPM_PT_MANUFACTURER: IBM)
+        /// // TPM2_GetCap (TPM_PT_MANUFACTURER: RUST)
         /// tcti.transmit(b"\x80\x01\x00\x00\x00\x16\x00\x00\x01\x7a\x00\x00\x00\x06\x00\x00\x01\x05\x00\x00\x00\x01").unwrap();
         /// let response = tcti.receive().unwrap();
-        /// assert_eq!(response, b"\x80\x01\x00\x00\x00\x1b\x00\x00\x00\x00\x01\x00\x00\x00\x06\x00\x00\x00\x01\x00\x00\x01\x05\x49\x42\x4d\x00");
+        /// assert_eq!(response, b"\x80\x01\x00\x00\x00\x1b\x00\x00\x00\x00\x01\x00\x00\x00\x06\x00\x00\x00\x01\x00\x00\x01\x05RUST");
         /// ```
         fn new(name_conf: &str) -> Result<Self, TctiError> {
-            let mut size: usize = 0;
-            let name_conf_cstr =
-                std::ffi::CString::new(name_conf).expect("Failed to convert to CString");
+            let (name, conf) = parse_name_conf(name_conf);
 
-            match unsafe { tpm2_tss::Tss2_Tcti_TctiLdr_Init(null_mut(), &mut size, null()) } {
-                0 => (),
-                _ => panic!("Unexpected Error."),
-            };
-            let mut tcti_loader = Self { ctx: vec![0; size] };
-
-            let return_code = unsafe {
-                tpm2_tss::Tss2_Tcti_TctiLdr_Init(
-                    tcti_loader.ctx_mut_ptr(),
-                    &mut size,
-                    name_conf_cstr.as_ptr(),
-                )
-            };
-
-            let error: TctiError = match return_code {
-                0 => return Ok(tcti_loader),
-                0xa001 => TctiError::GeneralFailure,
-                0xa002 => TctiError::NotImplemented,
-                0xa003 => TctiError::BadContext {
-                    magic: None,
-                    expected_magic: None,
-                },
-                0xa005 => TctiError::BadReference,
-                0xa006 => TctiError::InsufficientBuffer,
-                0xa007 => TctiError::BadSequence,
-                0xa009 => TctiError::TryAgain,
-                0xa010 => TctiError::IoError,
-                0xa011 => TctiError::BadValue,
-                0xa012 => TctiError::NotPermitted,
-                0xa021 => TctiError::NotSupported,
-                0xa023 => TctiError::Memory,
-                0xaFFF => TctiError::BadHandle,
-                error_code => TctiError::Unknown(error_code),
-            };
-
-            warn!("Child tcti returned error: {error:?}");
-            Err(error)
+            if name.is_empty() {
+                Self::new_default(conf)
+            } else {
+                Self::new_by_name(name, conf)
+            }
         }
 
         /// Transmit byte array to child tcti
         fn transmit(&mut self, command: &[u8]) -> Result<(), TctiError> {
-            let transmit_fn = self.get_api().v1.transmit.unwrap();
-
-            println!("{:#?}", self.ctx_ptr());
+            let transmit_fn = child_fn(self.get_api().transmit, "transmit")?;
 
             let return_code =
                 unsafe { transmit_fn(self.ctx_mut_ptr(), command.len(), command.as_ptr()) };
             let error: TctiError = match return_code {
                 0 => return Ok(()),
-                error_code => error_code
-                    .try_into()
-                    .expect("TCTI returned invalid return code."),
+                error_code => Tss2Rc::from(error_code).into(),
             };
 
             warn!("Child tcti returned error: {error:?}");
             Err(error)
         }
 
-        /// Transmit byte array from child tcti
-        fn receive(&mut self) -> Result<Vec<u8>, TctiError> {
-            let receive_fn = self.get_api().v1.receive.unwrap();
+        /// Receive byte array from child tcti. `timeout` covers both the size
+        /// query and receiving the response.
+        fn receive_with_timeout(&mut self, timeout: Duration) -> Result<Vec<u8>, TctiError> {
+            // None if the deadline overflows, e.g. for TIMEOUT_BLOCK
+            let deadline = Instant::now().checked_add(timeout);
+            let size = self.response_size(timeout)?;
 
-            let mut size = 0;
-            let timeout = 0;
+            let remaining = match deadline {
+                Some(deadline) => deadline.saturating_duration_since(Instant::now()),
+                None => timeout,
+            };
+            let mut response = vec![0; size];
+            let size = self.receive_into(&mut response, remaining)?;
+            response.truncate(size);
 
+            Ok(response)
+        }
+
+        /// Query response size from child tcti
+        fn response_size(&mut self, timeout: Duration) -> Result<usize, TctiError> {
+            let receive_fn = child_fn(self.get_api().receive, "receive")?;
+
+            let mut size = 0;
             let return_code = unsafe {
                 receive_fn(
                     self.ctx_mut_ptr(),
                     &mut size as *mut usize,
-                    std::ptr::null_mut(),
-                    timeout,
+                    null_mut(),
+                    timeout_to_c(timeout),
                 )
             };
-            if return_code != 0 {
-                let error: TctiError = return_code.into();
+            let error: TctiError = match return_code {
+                0 => return Ok(size),
+                error_code => Tss2Rc::from(error_code).into(),
+            };
 
-                warn!("Child tcti returned error: {error:?}");
-                return Err(error);
-            }
+            warn!("Child tcti returned error: {error:?}");
+            Err(error)
+        }
 
-            let mut response = Vec::with_capacity(size);
-            let mut size = response.capacity();
-            let timeout = 0;
+        /// Receive byte array from child tcti into caller-provided buffer
+        fn receive_into(
+            &mut self,
+            response: &mut [u8],
+            timeout: Duration,
+        ) -> Result<usize, TctiError> {
+            let receive_fn = child_fn(self.get_api().receive, "receive")?;
 
+            let mut size = response.len();
             let return_code = unsafe {
                 receive_fn(
                     self.ctx_mut_ptr(),
                     &mut size as *mut usize,
                     response.as_mut_ptr(),
-                    timeout,
+                    timeout_to_c(timeout),
                 )
             };
-            if return_code != 0 {
-                let error: TctiError = return_code.into();
-
-                warn!("Child tcti returned error: {error:?}");
-                return Err(error);
-            }
-
-            unsafe { response.set_len(size) };
+            let error: TctiError = match return_code {
+                0 => return Ok(size),
+                error_code => Tss2Rc::from(error_code).into(),
+            };
 
-            Ok(response)
+            warn!("Child tcti returned error: {error:?}");
+            Err(error)
         }
 
         fn cancel(&mut self) -> Result<(), TctiError> {
-            let cancel_fn = self.get_api().v1.cancel.unwrap();
+            let cancel_fn = child_fn(self.get_api().cancel, "cancel")?;
             let return_code = unsafe { cancel_fn(self.ctx_mut_ptr()) };
             let error: TctiError = match return_code {
                 0 => return Ok(()),
-                error_code => error_code.into(),
+                error_code => Tss2Rc::from(error_code).into(),
             };
 
             warn!("Child tcti returned error: {error:?}");
             Err(error)
         }
 
-        fn get_poll_handles(&mut self) -> Result<&[tpm2_tss::TSS2_TCTI_POLL_HANDLE], TctiError> {
-            // let get_poll_handles_fn = self.get_api().v1.getPollHandles.unwrap();
-            // match unsafe { get_poll_handles_fn(self.ctx_mut_ptr()) } {
-            //     0 => Ok(()),
-            //     error_code => Err(error_code),
-            // }
-            todo!()
+        /// Queries the number of handles, then fills them. The child is
+        /// trusted to write at most `*num_handles` handles, as the C
+        /// interface requires. If filling reports a different number, the
+        /// handles changed in between and are queried again.
+        fn get_poll_handles(&mut self) -> Result<Vec<tpm2_tss::TSS2_TCTI_POLL_HANDLE>, TctiError> {
+            let get_poll_handles_fn = child_fn(self.get_api().getPollHandles, "getPollHandles")?;
+            let call = |ctx, handles, num_handles: &mut usize| {
+                let return_code = unsafe { get_poll_handles_fn(ctx, handles, num_handles) };
+                match return_code {
+                    0 => Ok(()),
+                    error_code => Err(TctiError::from(Tss2Rc::from(error_code))),
+                }
+            };
+
+            for _ in 0..POLL_HANDLE_ATTEMPTS {
+                // first call: query number of handles
+                let mut num_handles: usize = 0;
+                call(self.ctx_mut_ptr(), null_mut(), &mut num_handles)
+                    .inspect_err(|error| warn!("Child tcti returned error: {error:?}"))?;
+
+                // second call: fill handles
+                let capacity = num_handles;
+                let mut handles = Vec::with_capacity(capacity);
+                match call(self.ctx_mut_ptr(), handles.as_mut_ptr(), &mut num_handles) {
+                    Ok(()) if num_handles == capacity => {
+                        unsafe { handles.set_len(num_handles) };
+                        return Ok(handles);
+                    }
+                    Ok(()) | Err(TctiError::InsufficientBuffer) => {
+                        debug!("Child tcti poll handles changed, querying again");
+                    }
+                    Err(error) => {
+                        warn!("Child tcti returned error: {error:?}");
+                        return Err(error);
+                    }
+                }
+            }
+
+            warn!("Child tcti poll handles changed while querying {POLL_HANDLE_ATTEMPTS} times");
+            Err(TctiError::InsufficientBuffer)
         }
 
         fn set_locality(&mut self, locality: u8) -> Result<(), TctiError> {
-            let set_locality_fn = self.get_api().v1.setLocality.unwrap();
+            let set_locality_fn = child_fn(self.get_api().setLocality, "setLocality")?;
             let return_code = unsafe { set_locality_fn(self.ctx_mut_ptr(), locality) };
             let error: TctiError = match return_code {
                 0 => return Ok(()),
-                error_code => error_code.into(),
+                error_code => Tss2Rc::from(error_code).into(),
             };
 
             warn!("Child tcti returned error: {error:?}");
             Err(error)
         }
 
-        fn make_sticky(&mut self) -> Result<(), TctiError> {
-            // let make_sticky_fn = self.get_api().v2.makeSticky.unwrap();
-            // match unsafe { make_sticky_fn(self.ctx_mut_ptr()) } {
-            //     0 => Ok(()),
-            //     error_code => Err(error_code),
-            // }
-            todo!()
+        fn make_sticky(&mut self, handle: &mut u32, sticky: bool) -> Result<(), TctiError> {
+            let make_sticky_fn = child_fn(self.get_make_sticky(), "makeSticky")?;
+            let return_code =
+                unsafe { make_sticky_fn(self.ctx_mut_ptr(), handle as *mut u32, sticky as u8) };
+            let error: TctiError = match return_code {
+                0 => return Ok(()),
+                error_code => Tss2Rc::from(error_code).into(),
+            };
+
+            warn!("Child tcti returned error: {error:?}");
+            Err(error)
         }
 
         /// Do not call. The TctiLoader object is finalized when it is dropped.
         fn finalize(&mut self) {}
     }
 
-    // #[cfg(test)]
-    // mod tests {
-    //     // Note this useful idiom: importing names from outer (for mod tests) scope.
-    //     use super::*;
-
-    //     #[test]
-    //     fn test_setup() {
-    //         assert_eq!(add(1, 2), 3);
-    //     }
-
-    //     #[test]
-    //     fn test_bad_add() {
-    //         // This assert would fire and test will fail.
-    //         // Please note, that private functions can be tested too!
-    //         assert_eq!(bad_add(1, 2), 3);
-    //     }
-    // }
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::conformance::conformance::check_tcti;
+        use crate::tcti::tcti::{Info, State, TctiLib};
+        use crate::testing::testing::Echo;
+        use std::sync::atomic::{AtomicBool, Ordering};
+
+        /// [Echo] as tcti of API version `V`.
+        #[repr(C)]
+        #[derive(Debug)]
+        struct TctiEcho<const V: u32 = 2> {
+            api: Api,
+            state: State,
+            echo: Echo,
+        }
+
+        impl<const V: u32> TctiLib for TctiEcho<V> {
+            const INFO: Info<'static> = Info {
+                name: b"tcti-echo\0",
+                description: b"Echoes commands.\0",
+                config_help: b"No config.\0",
+            };
+            const MAGIC: u64 = 0x3f1c8e0d2a6b7954;
+            const VERSION: u32 = V;
+
+            fn new(conf: &str) -> Result<Self, TctiError> {
+                let mut tcti = Self {
+                    api: Self::get_api_static(),
+                    state: State::NotInitialized,
+                    echo: Echo::default(),
+                };
+
+                tcti.init(conf)?;
+
+                Ok(tcti)
+            }
+
+            fn init_inner(&mut self, conf: &str) -> Result<(), TctiError> {
+                match conf {
+                    "fail" => return Err(TctiError::BadValue),
+                    // error of a nested tcti, e.g. a resource manager
+                    "nested" => return Err(TctiError::Unknown(Tss2Rc::from(0x000b0008))),
+                    "panic" => panic!("init panicked"),
+                    _ => (),
+                }
+                self.api = Self::get_api_static();
+                match conf {
+                    "poll-overflow" => self.api.v1.getPollHandles = Some(get_poll_handles_overflow),
+                    "poll-growing" => self.api.v1.getPollHandles = Some(get_poll_handles_growing),
+                    _ => (),
+                }
+                Ok(())
+            }
+
+            fn transmit_inner(&mut self, command: &[u8]) -> Result<(), TctiError> {
+                if command == b"panic" {
+                    panic!("transmit panicked");
+                }
+                self.echo.transmit(command)
+            }
+
+            fn receive_inner(&mut self, timeout: Duration) -> Result<Vec<u8>, TctiError> {
+                self.echo.receive_with_timeout(timeout)
+            }
+
+            fn get_state(&self) -> Option<State> {
+                Some(self.state)
+            }
+            fn set_state(&mut self, state: State) {
+                self.state = state;
+            }
+        }
+
+        /// Init function of a child whose context is `SIZE` bytes, of which
+        /// only the common part of API version `VERSION` is filled.
+        unsafe extern "C" fn init_sized<const SIZE: usize, const VERSION: u32>(
+            tcti_context: *mut tpm2_tss::TSS2_TCTI_CONTEXT,
+            size: *mut usize,
+            _config: *const std::os::raw::c_char,
+        ) -> tpm2_tss::TSS2_RC {
+            if tcti_context.is_null() {
+                *size = SIZE;
+                return 0;
+            }
+            let mut api = TctiEcho::<VERSION>::get_api_static().v1;
+            api.version = VERSION;
+            api.finalize = None;
+            std::ptr::write(tcti_context as *mut _, api);
+            0
+        }
+
+        fn load_sized(init: tpm2_tss::TSS2_TCTI_INIT_FUNC) -> Result<TctiLoader, TctiError> {
+            let info = tpm2_tss::TSS2_TCTI_INFO {
+                version: 2,
+                name: null(),
+                description: null(),
+                config_help: null(),
+                init,
+            };
+            TctiLoader::from_info(&info, "", None)
+        }
+
+        /// Reports one poll handle when queried, but two when filling.
+        unsafe extern "C" fn get_poll_handles_overflow(
+            _tcti_context: *mut tpm2_tss::TSS2_TCTI_CONTEXT,
+            handles: *mut tpm2_tss::TSS2_TCTI_POLL_HANDLE,
+            num_handles: *mut usize,
+        ) -> tpm2_tss::TSS2_RC {
+            *num_handles = if handles.is_null() { 1 } else { 2 };
+            0
+        }
+
+        /// One poll handle until they are filled for the first time, two
+        /// from then on. Fills at most `*num_handles` handles.
+        unsafe extern "C" fn get_poll_handles_growing(
+            _tcti_context: *mut tpm2_tss::TSS2_TCTI_CONTEXT,
+            handles: *mut tpm2_tss::TSS2_TCTI_POLL_HANDLE,
+            num_handles: *mut usize,
+        ) -> tpm2_tss::TSS2_RC {
+            static GROWN: AtomicBool = AtomicBool::new(false);
+
+            if handles.is_null() {
+                *num_handles = if GROWN.load(Ordering::SeqCst) { 2 } else { 1 };
+                return 0;
+            }
+            GROWN.store(true, Ordering::SeqCst);
+            for fd in 0..(*num_handles).min(2) {
+                let handle = tpm2_tss::TSS2_TCTI_POLL_HANDLE {
+                    fd: fd as i32,
+                    ..Default::default()
+                };
+                handles.add(fd).write(handle);
+            }
+            *num_handles = 2;
+            0
+        }
+
+        #[test]
+        fn test_load_static() {
+            register_static("echo", <TctiEcho as TctiLib>::info);
+
+            let mut tcti = <TctiLoader as Tcti>::new("echo:conf").unwrap();
+            assert_eq!(tcti.api_version(), 2);
+            tcti.transmit(b"\x80\x01\x00\x00\x00\x0c\x00\x00\x01\x44\x00\x00")
+                .unwrap();
+            assert_eq!(
+                tcti.receive().unwrap(),
+                b"\x80\x01\x00\x00\x00\x0c\x00\x00\x01\x44\x00\x00"
+            );
+        }
+
+        #[test]
+        fn test_load_static_v1() {
+            register_static("echo-v1", <TctiEcho<1> as TctiLib>::info);
+
+            let mut tcti = <TctiLoader as Tcti>::new("echo-v1:conf").unwrap();
+            assert_eq!(tcti.api_version(), 1);
+            assert_eq!(
+                tcti.make_sticky(&mut 0x80000000, true),
+                Err(TctiError::NotImplemented)
+            );
+            tcti.transmit(b"\x80\x01\x00\x00\x00\x0c\x00\x00\x01\x44\x00\x00")
+                .unwrap();
+            assert_eq!(
+                tcti.receive().unwrap(),
+                b"\x80\x01\x00\x00\x00\x0c\x00\x00\x01\x44\x00\x00"
+            );
+        }
+
+        #[test]
+        fn test_load_static_init_error() {
+            register_static("echo", <TctiEcho as TctiLib>::info);
+
+            assert_eq!(
+                <TctiLoader as Tcti>::new("echo:fail").unwrap_err(),
+                TctiError::BadValue
+            );
+        }
+
+        #[test]
+        fn test_load_static_nested_error() {
+            register_static("echo", <TctiEcho as TctiLib>::info);
+
+            let error = <TctiLoader as Tcti>::new("echo:nested").unwrap_err();
+            assert_eq!(error, TctiError::Unknown(Tss2Rc::from(0x000b0008)));
+            assert_eq!(u32::from(error), 0x000b0008);
+        }
+
+        #[test]
+        fn test_load_static_panic() {
+            register_static("echo", <TctiEcho as TctiLib>::info);
+
+            assert_eq!(
+                <TctiLoader as Tcti>::new("echo:panic").unwrap_err(),
+                TctiError::GeneralFailure
+            );
+
+            let mut tcti = <TctiLoader as Tcti>::new("echo:conf").unwrap();
+            assert_eq!(
+                tcti.transmit(b"panic").unwrap_err(),
+                TctiError::GeneralFailure
+            );
+            // the context is poisoned
+            assert_eq!(
+                tcti.transmit(b"\x80\x01\x00\x00\x00\x0c\x00\x00\x01\x44\x00\x00")
+                    .unwrap_err(),
+                TctiError::BadSequence
+            );
+            assert_eq!(
+                tcti.receive_with_timeout(Duration::ZERO).unwrap_err(),
+                TctiError::BadSequence
+            );
+            assert_eq!(tcti.get_poll_handles().unwrap_err(), TctiError::BadSequence);
+        }
+
+        #[test]
+        fn test_get_poll_handles_overflow() {
+            register_static("echo", <TctiEcho as TctiLib>::info);
+
+            let mut tcti = <TctiLoader as Tcti>::new("echo:poll-overflow").unwrap();
+            assert_eq!(
+                tcti.get_poll_handles().unwrap_err(),
+                TctiError::InsufficientBuffer
+            );
+
+            // queried again after the number changed
+            let mut tcti = <TctiLoader as Tcti>::new("echo:poll-growing").unwrap();
+            let handles = tcti.get_poll_handles().unwrap();
+            assert_eq!(
+                handles.iter().map(|handle| handle.fd).collect::<Vec<_>>(),
+                [0, 1]
+            );
+        }
+
+        #[test]
+        fn test_conformance() {
+            check_tcti::<TctiEcho<1>>("conf", b"\x80\x01\x00\x00\x00\x0c\x00\x00\x01\x44\x00\x00");
+            check_tcti::<TctiEcho>("conf", b"\x80\x01\x00\x00\x00\x0c\x00\x00\x01\x44\x00\x00");
+        }
+
+        #[test]
+        fn test_load_unknown() {
+            assert_eq!(
+                <TctiLoader as Tcti>::new("does-not-exist").unwrap_err(),
+                TctiError::NotSupported
+            );
+        }
+
+        #[test]
+        fn test_context_size() {
+            const V1_SIZE: usize = std::mem::size_of::<tpm2_tss::TSS2_TCTI_CONTEXT_COMMON_V1>();
+
+            assert_eq!(
+                load_sized(Some(init_sized::<8, 1>)).unwrap_err(),
+                TctiError::NotSupported
+            );
+            assert_eq!(
+                load_sized(Some(init_sized::<V1_SIZE, 2>)).unwrap_err(),
+                TctiError::NotSupported
+            );
+            let tcti = load_sized(Some(init_sized::<V1_SIZE, 1>)).unwrap();
+            assert_eq!(tcti.api_version(), 1);
+        }
+    }
 }
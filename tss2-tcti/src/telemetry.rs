@@ -0,0 +1,268 @@
+/// Structured tracing of tcti activity.
+///
+/// Tctis emit [tracing] events and one span per command, from transmitting
+/// the command until its response is received. Applications install their
+/// own subscriber or call `init_subscriber()`. Tctis loaded into C programs
+/// are silent unless [LOG_ENV](telemetry::LOG_ENV) is set, see
+/// `init_from_env()`. Both need the `subscriber` feature (enabled by
+/// default).
+pub mod telemetry {
+    #[cfg(feature = "subscriber")]
+    use std::fs::File;
+    #[cfg(feature = "subscriber")]
+    use std::sync::{Mutex, Once};
+
+    use tracing::field::Empty;
+    #[cfg(feature = "subscriber")]
+    use tracing::warn;
+    use tracing::{debug_span, Span};
+    #[cfg(feature = "subscriber")]
+    use tracing_subscriber::{fmt::format::FmtSpan, EnvFilter};
+
+    use crate::tcti::error::TctiError;
+    use crate::tcti::rc::Tss2Rc;
+    use crate::tpm::tpm::{command_code, command_name, response_code};
+
+    /// Environment variable with the filter of `init_from_env()`, e.g.
+    /// `debug` or `tss2_tcti=debug,tpm2_tcti_rm=info` (see
+    /// `tracing_subscriber::EnvFilter`).
+    pub const LOG_ENV: &str = "TSS2_TCTI_LOG";
+
+    /// Environment variable with a file to write to instead of stderr.
+    pub const LOG_FILE_ENV: &str = "TSS2_TCTI_LOG_FILE";
+
+    /// Name of the span of a command, see [command_span()].
+    pub const COMMAND_SPAN: &str = "tpm_command";
+
+    /// Install a global subscriber which writes events and closed command
+    /// spans to `file` (stderr if `None`), filtered by `filter`. Records of
+    /// the `log` crate are forwarded as well.
+    ///
+    /// Fails with [TctiError::BadValue] if `filter` is invalid and with
+    /// [TctiError::GeneralFailure] if a global subscriber (or logger) is
+    /// already installed.
+    #[cfg(feature = "subscriber")]
+    pub fn init_subscriber(filter: &str, file: Option<&str>) -> Result<(), TctiError> {
+        let filter = EnvFilter::try_new(filter).map_err(|error| {
+            warn!("Invalid log filter {filter:?}: {error}");
+            TctiError::BadValue
+        })?;
+        let builder = tracing_subscriber::fmt()
+            .with_env_filter(filter)
+            .with_span_events(FmtSpan::CLOSE);
+
+        let result = match file {
+            Some(path) => {
+                let file = File::options()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .map_err(|error| {
+                        warn!("Could not open log file {path:?}: {error}");
+                        TctiError::IoError
+                    })?;
+                builder
+                    .with_ansi(false)
+                    .with_writer(Mutex::new(file))
+                    .try_init()
+            }
+            None => builder.with_writer(std::io::stderr).try_init(),
+        };
+        result.map_err(|error| {
+            warn!("Could not install subscriber: {error}");
+            TctiError::GeneralFailure
+        })
+    }
+
+    /// Call [init_subscriber()] with the values of [LOG_ENV] and
+    /// [LOG_FILE_ENV] if [LOG_ENV] is set. Only the first call has an effect.
+    ///
+    /// Called when a tcti is initialized via the C interface. Existing
+    /// subscribers of the application are kept.
+    #[cfg(feature = "subscriber")]
+    pub fn init_from_env() {
+        static INIT: Once = Once::new();
+
+        INIT.call_once(|| {
+            let Ok(filter) = std::env::var(LOG_ENV) else {
+                return;
+            };
+            let file = std::env::var(LOG_FILE_ENV).ok();
+            // errors are logged to the existing subscriber, if any
+            let _ = init_subscriber(&filter, file.as_deref());
+        });
+    }
+
+    /// Create the span of `command` transmitted to the tcti `tcti`. The
+    /// response is recorded by [record_response()].
+    ///
+    /// Fields: `tcti`, `command_code`, `locality` (if known), `size`,
+    /// `response_size`, `response_code` and `result`.
+    pub fn command_span(tcti: &str, command: &[u8], locality: Option<u8>) -> Span {
+        let span = debug_span!(
+            COMMAND_SPAN,
+            tcti,
+            command_code = Empty,
+            locality,
+            size = command.len(),
+            response_size = Empty,
+            response_code = Empty,
+            result = Empty,
+        );
+        if let Some(cc) = command_code(command) {
+            span.record("command_code", command_name(cc));
+        }
+        span
+    }
+
+    /// Record the `response` (or error) of the command of `span`. Nothing is
+    /// recorded for [TctiError::TryAgain], the response is still to come.
+    pub fn record_response(span: &Span, response: Result<&[u8], &TctiError>) {
+        match response {
+            Err(TctiError::TryAgain) => (),
+            Ok(response) => {
+                span.record("response_size", response.len());
+                if let Some(rc) = response_code(response) {
+                    span.record("response_code", Tss2Rc::from(rc).to_string());
+                }
+                span.record("result", "success");
+            }
+            Err(error) => {
+                span.record("result", Tss2Rc::from(*error).to_string());
+            }
+        }
+    }
+
+    /// Span of the command which is currently processed by a tcti, see
+    /// [TctiLib::get_command_span()](crate::tcti::tcti::TctiLib::get_command_span).
+    /// Also remembers the locality for the next commands.
+    #[derive(Debug, Default)]
+    pub struct CommandSpan {
+        span: Option<Span>,
+        locality: Option<u8>,
+    }
+
+    impl CommandSpan {
+        /// Start the span of `command`, ending the span of a previous
+        /// command. Returns the new span.
+        pub fn start(&mut self, tcti: &str, command: &[u8]) -> Span {
+            let span = command_span(tcti, command, self.locality);
+            self.span = Some(span.clone());
+            span
+        }
+
+        /// The span of the current command or [Span::none()].
+        pub fn current(&self) -> Span {
+            self.span.clone().unwrap_or_else(Span::none)
+        }
+
+        /// Record the response and end the span of the current command. The
+        /// span is kept for [TctiError::TryAgain].
+        pub fn end(&mut self, response: Result<&[u8], &TctiError>) {
+            if response == Err(&TctiError::TryAgain) {
+                return;
+            }
+            if let Some(span) = self.span.take() {
+                record_response(&span, response);
+            }
+        }
+
+        /// End the span of the current command, which was cancelled.
+        pub fn cancel(&mut self) {
+            if let Some(span) = self.span.take() {
+                span.record("result", "cancelled");
+            }
+        }
+
+        pub fn set_locality(&mut self, locality: u8) {
+            self.locality = Some(locality);
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use std::collections::HashMap;
+        use std::sync::Arc;
+        use tracing::field::{Field, Visit};
+        use tracing::span::{Attributes, Id, Record};
+        use tracing::subscriber::with_default;
+        use tracing::{Event, Metadata, Subscriber};
+
+        type Fields = Arc<Mutex<HashMap<String, String>>>;
+
+        /// Collects the fields of all spans.
+        struct Collector(Fields);
+
+        impl Visit for Collector {
+            fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+                let mut fields = self.0.lock().unwrap();
+                fields.insert(field.name().to_string(), format!("{value:?}"));
+            }
+        }
+
+        impl Subscriber for Collector {
+            fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+                true
+            }
+            fn new_span(&self, span: &Attributes<'_>) -> Id {
+                span.record(&mut Collector(self.0.clone()));
+                Id::from_u64(1)
+            }
+            fn record(&self, _span: &Id, values: &Record<'_>) {
+                values.record(&mut Collector(self.0.clone()));
+            }
+            fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+            fn event(&self, _event: &Event<'_>) {}
+            fn enter(&self, _span: &Id) {}
+            fn exit(&self, _span: &Id) {}
+        }
+
+        #[test]
+        fn test_command_span() {
+            let fields = Fields::default();
+            with_default(Collector(fields.clone()), || {
+                let mut command_span = CommandSpan::default();
+                command_span.set_locality(3);
+                command_span.start(
+                    "tcti-test",
+                    b"\x80\x01\x00\x00\x00\x0c\x00\x00\x01\x7b\x00\x08",
+                );
+                command_span.end(Ok(b"\x80\x01\x00\x00\x00\x0a\x00\x00\x09\x01"));
+                assert!(command_span.current().is_none());
+            });
+
+            let fields = fields.lock().unwrap();
+            assert_eq!(fields["tcti"], "\"tcti-test\"");
+            assert_eq!(fields["command_code"], "\"GetRandom\"");
+            assert_eq!(fields["locality"], "3");
+            assert_eq!(fields["size"], "12");
+            assert_eq!(fields["response_size"], "10");
+            assert_eq!(fields["response_code"], "\"tpm:0x901\"");
+            assert_eq!(fields["result"], "\"success\"");
+        }
+
+        #[test]
+        fn test_error() {
+            let fields = Fields::default();
+            with_default(Collector(fields.clone()), || {
+                let span = command_span("tcti-test", b"\x80", None);
+                record_response(&span, Err(&TctiError::IoError));
+            });
+
+            let fields = fields.lock().unwrap();
+            assert!(!fields.contains_key("command_code"));
+            assert!(!fields.contains_key("locality"));
+            assert_eq!(fields["result"], "\"tcti:IoError\"");
+        }
+
+        #[test]
+        #[cfg(feature = "subscriber")]
+        fn test_invalid_filter() {
+            assert_eq!(
+                init_subscriber("tss2_tcti=nonsense", None),
+                Err(TctiError::BadValue)
+            );
+        }
+    }
+}
@@ -0,0 +1,76 @@
+/// Tctis for testing tcti users and middleware. Enabled by the `testing`
+/// feature.
+pub mod testing {
+    use std::collections::VecDeque;
+    use std::time::Duration;
+
+    use crate::tcti::error::TctiError;
+    use crate::tcti::tcti::Tcti;
+    use crate::tpm::tpm::{response, TPM_RC_SUCCESS};
+
+    /// Responds with the command. Fails to initialize with the conf string
+    /// `fail`.
+    #[derive(Debug, Default)]
+    pub struct Echo {
+        /// Command which was transmitted, but not received yet.
+        pub command: Option<Vec<u8>>,
+    }
+
+    impl Tcti for Echo {
+        fn new(conf: &str) -> Result<Self, TctiError> {
+            match conf {
+                "fail" => Err(TctiError::BadValue),
+                _ => Ok(Self::default()),
+            }
+        }
+
+        fn transmit(&mut self, command: &[u8]) -> Result<(), TctiError> {
+            self.command = Some(command.to_vec());
+            Ok(())
+        }
+
+        fn receive_with_timeout(&mut self, _timeout: Duration) -> Result<Vec<u8>, TctiError> {
+            self.command.take().ok_or(TctiError::BadSequence)
+        }
+
+        fn set_locality(&mut self, _locality: u8) -> Result<(), TctiError> {
+            Ok(())
+        }
+    }
+
+    /// Answers with scripted responses (or errors), then with
+    /// `TPM_RC_SUCCESS`, recording the commands.
+    #[derive(Debug, Default)]
+    pub struct Scripted {
+        pub responses: VecDeque<Result<Vec<u8>, TctiError>>,
+        pub commands: Vec<Vec<u8>>,
+    }
+
+    impl Scripted {
+        pub fn with_responses(
+            responses: impl IntoIterator<Item = Result<Vec<u8>, TctiError>>,
+        ) -> Self {
+            Self {
+                responses: responses.into_iter().collect(),
+                commands: Vec::new(),
+            }
+        }
+    }
+
+    impl Tcti for Scripted {
+        fn new(_conf: &str) -> Result<Self, TctiError> {
+            Ok(Self::default())
+        }
+
+        fn transmit(&mut self, command: &[u8]) -> Result<(), TctiError> {
+            self.commands.push(command.to_vec());
+            Ok(())
+        }
+
+        fn receive_with_timeout(&mut self, _timeout: Duration) -> Result<Vec<u8>, TctiError> {
+            self.responses
+                .pop_front()
+                .unwrap_or_else(|| Ok(response(TPM_RC_SUCCESS, &[])))
+        }
+    }
+}
@@ -0,0 +1,111 @@
+/// TPM command and response headers, for tctis which look into the commands
+/// passing through.
+pub mod tpm {
+    use tpm2_types::constants::{CommandCode, ReturnCode, StructureTag};
+
+    /// tag, commandSize/responseSize, commandCode/responseCode
+    pub const TPM_HEADER_SIZE: usize = 10;
+    pub const TPM_ST_NO_SESSIONS: u16 = StructureTag::NoSessions as u16;
+
+    pub const TPM_RC_SUCCESS: ReturnCode = 0x000;
+    pub const TPM_RC_INITIALIZE: ReturnCode = 0x100;
+    pub const TPM_RC_COMMAND_CODE: ReturnCode = 0x143;
+    pub const TPM_RC_CONTEXT_GAP: ReturnCode = 0x901;
+    pub const TPM_RC_YIELDED: ReturnCode = 0x908;
+    pub const TPM_RC_TESTING: ReturnCode = 0x90a;
+    pub const TPM_RC_RETRY: ReturnCode = 0x922;
+
+    /// Warnings after which the command can be resent unchanged.
+    pub const TPM_WARNINGS: [ReturnCode; 3] = [TPM_RC_RETRY, TPM_RC_YIELDED, TPM_RC_TESTING];
+
+    fn header(tag: u16, size: usize, code: u32) -> Vec<u8> {
+        let mut header = Vec::with_capacity(size);
+        header.extend_from_slice(&tag.to_be_bytes());
+        header.extend_from_slice(&(size as u32).to_be_bytes());
+        header.extend_from_slice(&code.to_be_bytes());
+        header
+    }
+
+    /// Command without sessions.
+    ///
+    /// # Examples
+    /// ```
+    /// use tpm2_types::constants::CommandCode;
+    /// use tss2_tcti::tpm::tpm::command;
+    ///
+    /// assert_eq!(
+    ///     command(CommandCode::Startup, &[0, 0]),
+    ///     b"\x80\x01\x00\x00\x00\x0c\x00\x00\x01\x44\x00\x00"
+    /// );
+    /// ```
+    pub fn command(command_code: CommandCode, body: &[u8]) -> Vec<u8> {
+        let size = TPM_HEADER_SIZE + body.len();
+        let mut command = header(TPM_ST_NO_SESSIONS, size, u32::from(command_code));
+        command.extend_from_slice(body);
+        command
+    }
+
+    /// Response without sessions.
+    pub fn response(rc: ReturnCode, parameters: &[u8]) -> Vec<u8> {
+        let size = TPM_HEADER_SIZE + parameters.len();
+        let mut response = header(TPM_ST_NO_SESSIONS, size, rc);
+        response.extend_from_slice(parameters);
+        response
+    }
+
+    /// Big endian [u32] at `offset`, e.g. a handle, [None] if `buf` is too
+    /// short.
+    pub fn u32_at(buf: &[u8], offset: usize) -> Option<u32> {
+        let bytes = buf.get(offset..offset.checked_add(4)?)?;
+        Some(u32::from_be_bytes(bytes.try_into().unwrap()))
+    }
+
+    fn code(buf: &[u8]) -> Option<u32> {
+        u32_at(buf, 6)
+    }
+
+    /// Command code of a command, [None] if the header is incomplete. This
+    /// is a [u32] since vendor specific command codes have no
+    /// [CommandCode].
+    pub fn command_code(command: &[u8]) -> Option<u32> {
+        code(command)
+    }
+
+    /// Response code of a response, [None] if the header is incomplete.
+    pub fn response_code(response: &[u8]) -> Option<ReturnCode> {
+        code(response)
+    }
+
+    /// Name of a command code, e.g. `GetRandom`, or its hex value for
+    /// unknown command codes.
+    pub fn command_name(cc: u32) -> String {
+        match CommandCode::try_from(cc) {
+            Ok(command_code) => format!("{command_code:?}"),
+            Err(_) => format!("{cc:#010x}"),
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_header() {
+            let get_random = command(CommandCode::GetRandom, &[0, 8]);
+            assert_eq!(
+                get_random,
+                b"\x80\x01\x00\x00\x00\x0c\x00\x00\x01\x7b\x00\x08"
+            );
+            assert_eq!(command_code(&get_random), Some(0x17b));
+            assert_eq!(command_name(0x17b), "GetRandom");
+            assert_eq!(command_name(0x20000001), "0x20000001");
+
+            let retry = response(TPM_RC_RETRY, &[]);
+            assert_eq!(retry, b"\x80\x01\x00\x00\x00\x0a\x00\x00\x09\x22");
+            assert_eq!(response_code(&retry), Some(TPM_RC_RETRY));
+            assert_eq!(response_code(&retry[..9]), None);
+            assert_eq!(u32_at(&get_random, 8), Some(0x017b0008));
+            assert_eq!(u32_at(&get_random, 9), None);
+        }
+    }
+}
@@ -0,0 +1,138 @@
+/// Logging of commands and responses.
+pub mod trace {
+    use std::time::Duration;
+
+    use tracing::{info, warn};
+
+    use crate::config::config::TctiConfig;
+    use crate::layer::layer::{forward_to_child, impl_layer};
+    use crate::tcti::error::TctiError;
+    use crate::tcti::tcti::Tcti;
+    use crate::telemetry::telemetry::CommandSpan;
+
+    #[derive(TctiConfig, Debug, Clone, Default, PartialEq)]
+    pub struct TraceConfig {
+        /// Conf string of the child tcti `T` which is traced, must be empty in a stack.
+        #[tcti_config(positional)]
+        pub child: String,
+        /// Prefix of the log messages, to tell apart several trace layers.
+        pub name: Option<String>,
+    }
+
+    /// Tcti which logs all commands and responses passing through to its
+    /// child, as well as errors. Each command is traced in a span, see
+    /// [command_span()](crate::telemetry::telemetry::command_span).
+    pub struct TraceTcti<T: Tcti> {
+        child: T,
+        name: String,
+        command_span: CommandSpan,
+    }
+
+    impl<T: Tcti> TraceTcti<T> {
+        /// Wrap `child`. [TraceConfig::child] is ignored.
+        pub fn with_child(child: T, config: TraceConfig) -> Self {
+            Self {
+                child,
+                name: config.name.unwrap_or_else(|| "trace".to_string()),
+                command_span: CommandSpan::default(),
+            }
+        }
+
+        fn log<R>(&self, what: &str, result: Result<R, TctiError>) -> Result<R, TctiError> {
+            if let Err(error) = &result {
+                // not receiving the response yet is part of the protocol
+                if *error != TctiError::TryAgain {
+                    warn!("{} {what}: {error:?}", self.name);
+                }
+            }
+            result
+        }
+    }
+
+    impl_layer!(TraceTcti, TraceConfig, [name]);
+
+    impl<T: Tcti> Tcti for TraceTcti<T> {
+        forward_to_child!(new(TraceConfig::from_conf), get_poll_handles);
+
+        fn transmit(&mut self, command: &[u8]) -> Result<(), TctiError> {
+            let span = self.command_span.start(&self.name, command);
+            let _entered = span.enter();
+
+            info!("{} TX: {:02x?}", self.name, command);
+            let result = self.child.transmit(command);
+            if let Err(error) = &result {
+                self.command_span.end(Err(error));
+            }
+            self.log("transmit", result)
+        }
+
+        fn receive_with_timeout(&mut self, timeout: Duration) -> Result<Vec<u8>, TctiError> {
+            let span = self.command_span.current();
+            let _entered = span.enter();
+
+            let result = self.child.receive_with_timeout(timeout);
+            self.command_span.end(result.as_deref());
+            let response = self.log("receive", result)?;
+            info!("{} RX: {:02x?}", self.name, response);
+            Ok(response)
+        }
+
+        fn response_size(&mut self, timeout: Duration) -> Result<usize, TctiError> {
+            let span = self.command_span.current();
+            let _entered = span.enter();
+
+            let result = self.child.response_size(timeout);
+            if let Err(error) = &result {
+                self.command_span.end(Err(error));
+            }
+            self.log("response_size", result)
+        }
+
+        fn receive_into(
+            &mut self,
+            response: &mut [u8],
+            timeout: Duration,
+        ) -> Result<usize, TctiError> {
+            let span = self.command_span.current();
+            let _entered = span.enter();
+
+            let result = self.child.receive_into(response, timeout);
+            match &result {
+                Ok(size) => self.command_span.end(Ok(&response[..*size])),
+                // the response is kept by the child
+                Err(TctiError::InsufficientBuffer) => (),
+                Err(error) => self.command_span.end(Err(error)),
+            }
+            let size = self.log("receive", result)?;
+            info!("{} RX: {:02x?}", self.name, &response[..size]);
+            Ok(size)
+        }
+
+        fn cancel(&mut self) -> Result<(), TctiError> {
+            let span = self.command_span.current();
+            let _entered = span.enter();
+
+            info!("{} cancel", self.name);
+            let result = self.child.cancel();
+            if result.is_ok() {
+                self.command_span.cancel();
+            }
+            self.log("cancel", result)
+        }
+
+        fn set_locality(&mut self, locality: u8) -> Result<(), TctiError> {
+            info!("{} locality: {locality}", self.name);
+            let result = self.child.set_locality(locality);
+            if result.is_ok() {
+                self.command_span.set_locality(locality);
+            }
+            self.log("set_locality", result)
+        }
+
+        fn make_sticky(&mut self, handle: &mut u32, sticky: bool) -> Result<(), TctiError> {
+            info!("{} make_sticky: {handle:08x} {sticky}", self.name);
+            let result = self.child.make_sticky(handle, sticky);
+            self.log("make_sticky", result)
+        }
+    }
+}